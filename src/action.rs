@@ -1,34 +1,101 @@
-use core_graphics::event::{CGEvent, CGEventFlags, CGEventType, EventField};
+use std::{
+    ops::RangeInclusive,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
-use crate::{mode::Mode, Layout};
+use anyhow::{anyhow, Result};
+use core_graphics::event::{CGEvent, CGEventFlags, CGEventType};
+use serde::Serialize;
 
-#[derive(Debug)]
+use crate::{
+    direction::Direction, keyboard::event_keycode, mode::Mode, palette::typed_text, Layout,
+};
+
+#[derive(Debug, Clone)]
 pub enum Action {
     ModeNormal,
     ModeInsert,
     ModeInsertNormal,
+    TogglePassthrough,
     RelayoutAll,
     LayoutFloating,
     LayoutCascade,
     LayoutTiling,
+    LayoutCustom(String),
+    SaveLayout(String),
+    RestoreLayout(String),
     WindowFull,
     WindowLeftHalf,
     WindowRightHalf,
+    WindowTopHalf,
+    WindowBottomHalf,
+    WindowTopLeftQuarter,
+    WindowTopRightQuarter,
+    WindowBottomLeftQuarter,
+    WindowBottomRightQuarter,
+    WindowCenter,
     WindowMinimize,
     WindowRestore,
+    MinimizeGroup,
+    RestoreGroup,
     WindowClose,
+    WindowRaise,
+    WindowLower,
+    CloseGroupWindows,
+    QuitApp,
+    ForceQuitApp,
+    TitleBarDoubleClick,
+    WindowZoom,
+    WindowFullScreen,
+    ToggleZoom,
+    TargetMouseWindow,
+    RepeatLast,
+    Spawn(String),
+    RunScript(String),
     NextWindow,
     PrevWindow,
+    FocusLeft,
+    FocusRight,
+    FocusUp,
+    FocusDown,
+    FocusLast,
+    FocusOlder,
+    FocusNewer,
+    FocusUrgent,
+    NextAppWindow,
+    PrevAppWindow,
     SwapNextWindow,
     SwapPrevWindow,
+    SwapLeft,
+    SwapRight,
+    SwapUp,
+    SwapDown,
+    Nudge(Direction),
+    ResizeFloating(Direction),
     IncrPrimaryColWidth,
     DecrPrimaryColWidth,
     IncrPrimaryColWindows,
     DecrPrimaryColWindows,
+    SetPrimaryColWidth(u8),
+    SetPrimaryColWindows(i32),
+    Balance,
+    GrowTile,
+    ShrinkTile,
+    SplitHorizontal,
+    SplitVertical,
+    StackWindow,
+    UnstackWindow,
+    StackNext,
+    StackPrev,
     NextDisplay,
     PrevDisplay,
     MoveWindowToNextDisplay { follow: bool },
     MoveWindowToPrevDisplay { follow: bool },
+    SwapWindowWithNextDisplay,
+    MoveGroupToNextDisplay { follow: bool },
+    MoveGroupToPrevDisplay { follow: bool },
+    SwapGroupWithNextDisplay,
+    IdentifyDisplays,
     MoveWindowToGroup { id: u8, follow: bool },
     ToggleWindowInGroup(u8),
     ShowGroup(u8),
@@ -36,6 +103,24 @@ pub enum Action {
     PrevGroup,
     MoveWindowToNextGroup { follow: bool },
     MoveWindowToPrevGroup { follow: bool },
+    MoveAppToGroup { id: u8, follow: bool },
+    ToggleAppFloating,
+    GatherApp,
+    ToggleBorder,
+    ToggleStats,
+    OpenSwitcher { all_groups: bool },
+    OpenMinimizedSwitcher,
+    SwitcherNext,
+    SwitcherPrev,
+    SwitcherSelect,
+    SwitcherCancel,
+    OpenPalette,
+    PaletteInput(String),
+    PaletteBackspace,
+    PaletteNext,
+    PalettePrev,
+    PaletteExecute,
+    PaletteCancel,
 }
 
 pub static HELP_TEXT: &str = "
@@ -45,27 +130,1082 @@ pub static HELP_TEXT: &str = "
 | I    | <opt>+<shift> (hold)   | transient mode (T)        |
 | T    | <opt>+<shift>+a        | normal mode (N)           |
 | N    | <esc>/q                | insert mode (I)           |
+| N    | <shift>+p              | passthrough mode (X)      |
+| I/X  | <ctrl>+<opt>+<shift>+p | toggle passthrough mode   |
 +------+-[layouts]--------------+---------------------------+
 | T/N  | t                      | tiling layout             |
 | T/N  | f                      | floating layout           |
 | T/N  | c                      | cascade layout            |
 +------+-[motions]--------------+---------------------------+
 | T/N  | j/k                    | window motion             |
+| T/N  | <arrows>               | focus window in direction |
+| T/N  | `                      | last focused window       |
+| N    | <ctrl>+o/i             | older/newer focus history |
+| N    | <ctrl>+u               | urgent window             |
+| I/N  | <cmd>+[<shift>]+`      | next/prev app window      |
 | T/N  | i/o/0-9                | group motion              |
 | T/N  | n/p                    | display motion            |
 +------+-[window commands]------+---------------------------+
 | N    | <opt>+[motion]         | move window               |
+| N    | <opt>+<arrows>         | swap window in direction  |
 | N    | <opt>+<shift>+[motion] | move window and follow    |
 | N    | <cmd>+[0-9]            | toggle window in group    |
+| N    | <ctrl>+n               | swap window with display  |
+| N    | <ctrl>+<opt>+n/p       | move group to display     |
+| N    | <ctrl>+<shift>+n       | swap group with display   |
+| N    | d                      | identify displays         |
 | T/N  | <ret>                  | maximize window           |
+| N    | <shift>+<ret>          | double-click title bar    |
+| N    | z                      | press zoom button         |
+| N    | <shift>+f              | toggle full screen        |
+| N    | <shift>+z              | zoom tile over display    |
+| N    | u                      | next action: mouse window |
+| T/N  | .                      | repeat last action        |
 | T/N  | m/M                    | minimize/restore window   |
+| N    | <opt>+m/M              | minimize/restore group    |
+| T/N  | x                      | close window              |
+| N    | X (twice)              | close group's windows     |
+| N    | <cmd>+q                | quit app                  |
+| N    | <cmd>+<opt>+q (twice)  | force quit app            |
+| N    | ]/[                    | raise/lower window        |
 | T/N  | h/l                    | window left/right half    |
+| N    | <shift>+k/j            | window top/bottom half    |
+| N    | <shift>+y/u/b/n        | window corner quarter     |
+| N    | <shift>+c              | center window             |
+| N    | b                      | toggle insert mode border |
+| N    | s                      | toggle focus statistics   |
++------+-[switcher]-------------+---------------------------+
+| I    | <opt>+<tab>            | open switcher (S)         |
+| N    | w/W                    | open switcher (S)         |
+| N    | <ctrl>+m               | pick minimized window (S) |
+| S    | <tab>/j                | next window               |
+| S    | <shift>+<tab>/k        | previous window           |
+| S    | <ret>/release <opt>    | focus selected window     |
+| S    | <esc>                  | close switcher            |
++------+-[command palette]------+---------------------------+
+| N    | :                      | open command palette (P)  |
+| P    | <tab>/<down>           | next command              |
+| P    | <shift>+<tab>/<up>     | previous command          |
+| P    | <ret>                  | run command               |
+| P    | <esc>                  | close command palette     |
 +------+-[tiling commands]------+---------------------------+
 | T/N  | h/l                    | adjust split width        |
 | T/N  | <opt>+h/l              | number of primary windows |
+| T/N  | =                      | make tiles the same size  |
+| N    | +/-                    | grow/shrink tile height   |
+| N    | v/V                    | split below/beside        |
+| N    | <opt>+s/S              | stack/unstack window      |
+| N    | }/{                    | next/prev stacked window  |
++------+-[floating commands]----+---------------------------+
+| N    | <ctrl>+hjkl/<arrows>   | nudge window              |
+| N    | <ctrl>+<shift>+hjkl    | resize window             |
 +------+------------------------+---------------------------+
 ";
 
+/// An argument to an action, as listed by `ActionInfo`.
+#[derive(Debug, Serialize)]
+pub struct ActionArg {
+    pub name: &'static str,
+    /// "bool", "group" (a group number 0-9), "number" or "text".
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+}
+
+/// Describes an action that can be triggered by id, e.g. over IPC.
+#[derive(Debug, Serialize)]
+pub struct ActionInfo {
+    pub id: &'static str,
+    pub args: &'static [ActionArg],
+    pub description: &'static str,
+}
+
+pub static ACTIONS: &[ActionInfo] = &[
+    ActionInfo {
+        id: "mode-normal",
+        args: &[],
+        description: "Enter normal mode",
+    },
+    ActionInfo {
+        id: "mode-insert",
+        args: &[],
+        description: "Enter insert mode",
+    },
+    ActionInfo {
+        id: "mode-insert-normal",
+        args: &[],
+        description: "Enter transient normal mode",
+    },
+    ActionInfo {
+        id: "toggle-passthrough",
+        args: &[],
+        description: "Turn the window manager off, or back on",
+    },
+    ActionInfo {
+        id: "relayout-all",
+        args: &[],
+        description: "Refresh the window list and re-apply all layouts",
+    },
+    ActionInfo {
+        id: "layout-floating",
+        args: &[],
+        description: "Use the floating layout for the active group",
+    },
+    ActionInfo {
+        id: "layout-cascade",
+        args: &[],
+        description: "Use the cascade layout for the active group",
+    },
+    ActionInfo {
+        id: "layout-tiling",
+        args: &[],
+        description: "Use the tiling layout for the active group",
+    },
+    ActionInfo {
+        id: "layout-custom",
+        args: &[ActionArg {
+            name: "name",
+            kind: "text",
+        }],
+        description: "Use a layout registered by the library user or script",
+    },
+    ActionInfo {
+        id: "save-layout",
+        args: &[ActionArg {
+            name: "name",
+            kind: "text",
+        }],
+        description: "Save the active group's windows, their frames and its layout under a name",
+    },
+    ActionInfo {
+        id: "restore-layout",
+        args: &[ActionArg {
+            name: "name",
+            kind: "text",
+        }],
+        description: "Arrange the active group as saved under a name, finding apps' windows again",
+    },
+    ActionInfo {
+        id: "window-full",
+        args: &[],
+        description: "Maximize the active window",
+    },
+    ActionInfo {
+        id: "window-left-half",
+        args: &[],
+        description: "Move the active window to the left half of the display",
+    },
+    ActionInfo {
+        id: "window-right-half",
+        args: &[],
+        description: "Move the active window to the right half of the display",
+    },
+    ActionInfo {
+        id: "window-top-half",
+        args: &[],
+        description: "Move the active window to the top half of the display",
+    },
+    ActionInfo {
+        id: "window-bottom-half",
+        args: &[],
+        description: "Move the active window to the bottom half of the display",
+    },
+    ActionInfo {
+        id: "window-top-left-quarter",
+        args: &[],
+        description: "Move the active window to the top left quarter of the display",
+    },
+    ActionInfo {
+        id: "window-top-right-quarter",
+        args: &[],
+        description: "Move the active window to the top right quarter of the display",
+    },
+    ActionInfo {
+        id: "window-bottom-left-quarter",
+        args: &[],
+        description: "Move the active window to the bottom left quarter of the display",
+    },
+    ActionInfo {
+        id: "window-bottom-right-quarter",
+        args: &[],
+        description: "Move the active window to the bottom right quarter of the display",
+    },
+    ActionInfo {
+        id: "window-center",
+        args: &[],
+        description: "Move the active window to the middle of the display",
+    },
+    ActionInfo {
+        id: "window-minimize",
+        args: &[],
+        description: "Minimize the active window",
+    },
+    ActionInfo {
+        id: "window-restore",
+        args: &[],
+        description: "Restore the window last minimized from the active group, or else the last minimized",
+    },
+    ActionInfo {
+        id: "minimize-group",
+        args: &[],
+        description: "Minimize every window in the active group, e.g. before sharing the screen",
+    },
+    ActionInfo {
+        id: "restore-group",
+        args: &[],
+        description: "Restore every window minimized from the active group, in their old order",
+    },
+    ActionInfo {
+        id: "window-close",
+        args: &[],
+        description: "Close the active window",
+    },
+    ActionInfo {
+        id: "window-raise",
+        args: &[],
+        description: "Put the active window at the top of its group's stacking order",
+    },
+    ActionInfo {
+        id: "window-lower",
+        args: &[],
+        description: "Put the active window at the bottom of its group's stacking order",
+    },
+    ActionInfo {
+        id: "close-group-windows",
+        args: &[],
+        description: "Close every window in the active group, once confirmed by doing it again",
+    },
+    ActionInfo {
+        id: "quit-app",
+        args: &[],
+        description: "Ask the active window's application to quit",
+    },
+    ActionInfo {
+        id: "force-quit-app",
+        args: &[],
+        description: "Kill the active window's application, once confirmed by doing it again",
+    },
+    ActionInfo {
+        id: "title-bar-double-click",
+        args: &[],
+        description: "Zoom or minimize the active window, as double-clicking its title bar would",
+    },
+    ActionInfo {
+        id: "window-zoom",
+        args: &[],
+        description: "Press the active window's zoom button",
+    },
+    ActionInfo {
+        id: "window-full-screen",
+        args: &[],
+        description: "Move the active window into, or out of, its own full-screen space",
+    },
+    ActionInfo {
+        id: "toggle-zoom",
+        args: &[],
+        description: "Expand the active tiled window over the display until toggled again or unfocused",
+    },
+    ActionInfo {
+        id: "target-mouse-window",
+        args: &[],
+        description: "Make the next action act on the window under the mouse, without focusing it",
+    },
+    ActionInfo {
+        id: "repeat-last",
+        args: &[],
+        description: "Do the last window or layout action again",
+    },
+    ActionInfo {
+        id: "spawn",
+        args: &[ActionArg {
+            name: "command",
+            kind: "text",
+        }],
+        description: "Run a shell command, e.g. \"open -na Terminal\"",
+    },
+    ActionInfo {
+        id: "run-script",
+        args: &[ActionArg {
+            name: "function",
+            kind: "text",
+        }],
+        description: "Run a function of the user's script",
+    },
+    ActionInfo {
+        id: "next-window",
+        args: &[],
+        description: "Focus the next window in the group",
+    },
+    ActionInfo {
+        id: "prev-window",
+        args: &[],
+        description: "Focus the previous window in the group",
+    },
+    ActionInfo {
+        id: "focus-left",
+        args: &[],
+        description: "Focus the nearest window to the left, on any display",
+    },
+    ActionInfo {
+        id: "focus-right",
+        args: &[],
+        description: "Focus the nearest window to the right, on any display",
+    },
+    ActionInfo {
+        id: "focus-up",
+        args: &[],
+        description: "Focus the nearest window above, on any display",
+    },
+    ActionInfo {
+        id: "focus-down",
+        args: &[],
+        description: "Focus the nearest window below, on any display",
+    },
+    ActionInfo {
+        id: "focus-last",
+        args: &[],
+        description: "Focus the previously focused window, in any group or display",
+    },
+    ActionInfo {
+        id: "focus-older",
+        args: &[],
+        description: "Step back through the windows focused before, in any group or display",
+    },
+    ActionInfo {
+        id: "focus-newer",
+        args: &[],
+        description: "Step forward again through the windows focused before",
+    },
+    ActionInfo {
+        id: "focus-urgent",
+        args: &[],
+        description: "Focus the window that most recently opened without taking focus",
+    },
+    ActionInfo {
+        id: "next-app-window",
+        args: &[],
+        description: "Focus the active app's next window, in any group or display",
+    },
+    ActionInfo {
+        id: "prev-app-window",
+        args: &[],
+        description: "Focus the active app's previous window, in any group or display",
+    },
+    ActionInfo {
+        id: "swap-next-window",
+        args: &[],
+        description: "Swap the active window with the next window",
+    },
+    ActionInfo {
+        id: "swap-prev-window",
+        args: &[],
+        description: "Swap the active window with the previous window",
+    },
+    ActionInfo {
+        id: "swap-left",
+        args: &[],
+        description: "Swap the active window with the one to its left, or move it to the display on the left",
+    },
+    ActionInfo {
+        id: "swap-right",
+        args: &[],
+        description: "Swap the active window with the one to its right, or move it to the display on the right",
+    },
+    ActionInfo {
+        id: "swap-up",
+        args: &[],
+        description: "Swap the active window with the one above it, or move it to the display above",
+    },
+    ActionInfo {
+        id: "swap-down",
+        args: &[],
+        description: "Swap the active window with the one below it, or move it to the display below",
+    },
+    ActionInfo {
+        id: "nudge-left",
+        args: &[],
+        description: "Move the active floating window left a step",
+    },
+    ActionInfo {
+        id: "nudge-right",
+        args: &[],
+        description: "Move the active floating window right a step",
+    },
+    ActionInfo {
+        id: "nudge-up",
+        args: &[],
+        description: "Move the active floating window up a step",
+    },
+    ActionInfo {
+        id: "nudge-down",
+        args: &[],
+        description: "Move the active floating window down a step",
+    },
+    ActionInfo {
+        id: "resize-floating-left",
+        args: &[],
+        description: "Make the active floating window narrower by a step",
+    },
+    ActionInfo {
+        id: "resize-floating-right",
+        args: &[],
+        description: "Make the active floating window wider by a step",
+    },
+    ActionInfo {
+        id: "resize-floating-up",
+        args: &[],
+        description: "Make the active floating window shorter by a step",
+    },
+    ActionInfo {
+        id: "resize-floating-down",
+        args: &[],
+        description: "Make the active floating window taller by a step",
+    },
+    ActionInfo {
+        id: "incr-primary-col-width",
+        args: &[],
+        description: "Widen the primary column",
+    },
+    ActionInfo {
+        id: "decr-primary-col-width",
+        args: &[],
+        description: "Narrow the primary column",
+    },
+    ActionInfo {
+        id: "incr-primary-col-windows",
+        args: &[],
+        description: "Add a window to the primary column",
+    },
+    ActionInfo {
+        id: "decr-primary-col-windows",
+        args: &[],
+        description: "Remove a window from the primary column",
+    },
+    ActionInfo {
+        id: "set-primary-col-width",
+        args: &[ActionArg {
+            name: "pct",
+            kind: "number",
+        }],
+        description: "Set the primary column to a percentage (10-90) of the display width",
+    },
+    ActionInfo {
+        id: "set-primary-col-windows",
+        args: &[ActionArg {
+            name: "count",
+            kind: "number",
+        }],
+        description: "Set how many windows the primary column holds",
+    },
+    ActionInfo {
+        id: "balance",
+        args: &[],
+        description: "Resize the active group's tiles so that they are all the same size",
+    },
+    ActionInfo {
+        id: "grow-tile",
+        args: &[],
+        description: "Make the active window's tile taller than the others in its column",
+    },
+    ActionInfo {
+        id: "shrink-tile",
+        args: &[],
+        description: "Make the active window's tile shorter than the others in its column",
+    },
+    ActionInfo {
+        id: "split-horizontal",
+        args: &[],
+        description: "Put the next window beside the active window, in a split tree like i3's",
+    },
+    ActionInfo {
+        id: "split-vertical",
+        args: &[],
+        description: "Put the next window below the active window, in a split tree like i3's",
+    },
+    ActionInfo {
+        id: "stack-window",
+        args: &[],
+        description: "Share the active window's tile with the next tile's windows, like i3's tabs",
+    },
+    ActionInfo {
+        id: "unstack-window",
+        args: &[],
+        description: "Give the active window a tile of its own again",
+    },
+    ActionInfo {
+        id: "stack-next",
+        args: &[],
+        description: "Show the next window sharing the active window's tile",
+    },
+    ActionInfo {
+        id: "stack-prev",
+        args: &[],
+        description: "Show the previous window sharing the active window's tile",
+    },
+    ActionInfo {
+        id: "next-display",
+        args: &[],
+        description: "Focus the next display",
+    },
+    ActionInfo {
+        id: "prev-display",
+        args: &[],
+        description: "Focus the previous display",
+    },
+    ActionInfo {
+        id: "move-window-to-next-display",
+        args: &[ActionArg {
+            name: "follow",
+            kind: "bool",
+        }],
+        description: "Move the active window to the next display",
+    },
+    ActionInfo {
+        id: "move-window-to-prev-display",
+        args: &[ActionArg {
+            name: "follow",
+            kind: "bool",
+        }],
+        description: "Move the active window to the previous display",
+    },
+    ActionInfo {
+        id: "swap-window-with-next-display",
+        args: &[],
+        description: "Swap the active window with the same slot on the next display",
+    },
+    ActionInfo {
+        id: "move-group-to-next-display",
+        args: &[ActionArg {
+            name: "follow",
+            kind: "bool",
+        }],
+        description: "Move the active group and all its windows to the next display",
+    },
+    ActionInfo {
+        id: "move-group-to-prev-display",
+        args: &[ActionArg {
+            name: "follow",
+            kind: "bool",
+        }],
+        description: "Move the active group and all its windows to the previous display",
+    },
+    ActionInfo {
+        id: "swap-group-with-next-display",
+        args: &[],
+        description: "Swap the active group with the active group of the next display",
+    },
+    ActionInfo {
+        id: "identify-displays",
+        args: &[],
+        description: "Show each display's number and name on it for a few seconds",
+    },
+    ActionInfo {
+        id: "move-window-to-group",
+        args: &[
+            ActionArg {
+                name: "group",
+                kind: "group",
+            },
+            ActionArg {
+                name: "follow",
+                kind: "bool",
+            },
+        ],
+        description: "Move the active window to a group",
+    },
+    ActionInfo {
+        id: "toggle-window-in-group",
+        args: &[ActionArg {
+            name: "group",
+            kind: "group",
+        }],
+        description: "Add the active window to, or remove it from, a group",
+    },
+    ActionInfo {
+        id: "show-group",
+        args: &[ActionArg {
+            name: "group",
+            kind: "group",
+        }],
+        description: "Show a group on the active display",
+    },
+    ActionInfo {
+        id: "next-group",
+        args: &[],
+        description: "Show the next group with windows",
+    },
+    ActionInfo {
+        id: "prev-group",
+        args: &[],
+        description: "Show the previous group with windows",
+    },
+    ActionInfo {
+        id: "move-window-to-next-group",
+        args: &[ActionArg {
+            name: "follow",
+            kind: "bool",
+        }],
+        description: "Move the active window to the next group",
+    },
+    ActionInfo {
+        id: "move-window-to-prev-group",
+        args: &[ActionArg {
+            name: "follow",
+            kind: "bool",
+        }],
+        description: "Move the active window to the previous group",
+    },
+    ActionInfo {
+        id: "move-app-to-group",
+        args: &[
+            ActionArg {
+                name: "group",
+                kind: "group",
+            },
+            ActionArg {
+                name: "follow",
+                kind: "bool",
+            },
+        ],
+        description: "Move all of the active window's app's windows to a group on this display",
+    },
+    ActionInfo {
+        id: "toggle-app-floating",
+        args: &[],
+        description: "Float all of the active window's app's windows, or tile them if they all float",
+    },
+    ActionInfo {
+        id: "gather-app",
+        args: &[],
+        description: "Bring all of the active window's app's windows into the active group",
+    },
+    ActionInfo {
+        id: "toggle-border",
+        args: &[],
+        description: "Show or hide the border around the focused window in insert mode",
+    },
+    ActionInfo {
+        id: "toggle-stats",
+        args: &[],
+        description: "Show or hide focus time per group and app in the status window",
+    },
+    ActionInfo {
+        id: "open-switcher",
+        args: &[ActionArg {
+            name: "all_groups",
+            kind: "bool",
+        }],
+        description: "List the active group's windows (or all windows) with thumbnails",
+    },
+    ActionInfo {
+        id: "open-minimized-switcher",
+        args: &[],
+        description: "Pick a minimized window to restore to the group it came from",
+    },
+    ActionInfo {
+        id: "switcher-next",
+        args: &[],
+        description: "Select the next window in the switcher",
+    },
+    ActionInfo {
+        id: "switcher-prev",
+        args: &[],
+        description: "Select the previous window in the switcher",
+    },
+    ActionInfo {
+        id: "switcher-select",
+        args: &[],
+        description: "Focus the window selected in the switcher",
+    },
+    ActionInfo {
+        id: "switcher-cancel",
+        args: &[],
+        description: "Close the switcher",
+    },
+    ActionInfo {
+        id: "open-palette",
+        args: &[],
+        description: "Open a prompt to run any action by name",
+    },
+    ActionInfo {
+        id: "palette-input",
+        args: &[ActionArg {
+            name: "text",
+            kind: "text",
+        }],
+        description: "Type into the command palette",
+    },
+    ActionInfo {
+        id: "palette-backspace",
+        args: &[],
+        description: "Delete the last character in the command palette",
+    },
+    ActionInfo {
+        id: "palette-next",
+        args: &[],
+        description: "Select the next command in the command palette",
+    },
+    ActionInfo {
+        id: "palette-prev",
+        args: &[],
+        description: "Select the previous command in the command palette",
+    },
+    ActionInfo {
+        id: "palette-execute",
+        args: &[],
+        description: "Run the command in the command palette",
+    },
+    ActionInfo {
+        id: "palette-cancel",
+        args: &[],
+        description: "Close the command palette",
+    },
+];
+
+fn bool_arg(args: &[String], idx: usize) -> Result<bool> {
+    match args.get(idx).map(|s| s.as_str()) {
+        Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        Some(arg) => Err(anyhow!("Expected true or false, got {:?}", arg)),
+        None => Err(anyhow!("Missing argument {}", idx + 1)),
+    }
+}
+
+fn group_arg(args: &[String], idx: usize) -> Result<u8> {
+    match args.get(idx).map(|s| s.parse::<u8>()) {
+        Some(Ok(g_id)) if g_id <= 9 => Ok(g_id),
+        Some(_) => Err(anyhow!("Expected a group number 0-9, got {:?}", args[idx])),
+        None => Err(anyhow!("Missing argument {}", idx + 1)),
+    }
+}
+
+fn number_arg(args: &[String], idx: usize, range: RangeInclusive<u32>) -> Result<u32> {
+    match args.get(idx).map(|s| s.parse::<u32>()) {
+        Some(Ok(n)) if range.contains(&n) => Ok(n),
+        Some(_) => Err(anyhow!(
+            "Expected a number {}-{}, got {:?}",
+            range.start(),
+            range.end(),
+            args[idx]
+        )),
+        None => Err(anyhow!("Missing argument {}", idx + 1)),
+    }
+}
+
+fn text_arg(args: &[String], idx: usize) -> Result<String> {
+    args.get(idx)
+        .cloned()
+        .ok_or_else(|| anyhow!("Missing argument {}", idx + 1))
+}
+
+impl Action {
+    /// Whether this moves focus to another window, which holding its key
+    /// repeats.
+    pub fn is_motion(&self) -> bool {
+        use Action::*;
+        matches!(
+            self,
+            NextWindow | PrevWindow | FocusLeft | FocusRight | FocusUp | FocusDown
+        )
+    }
+
+    /// Whether `RepeatLast` can do this action again. Mode changes and
+    /// actions driving the switcher or palette aren't repeated.
+    pub fn is_repeatable(&self) -> bool {
+        use Action::*;
+        !matches!(
+            self,
+            ModeNormal
+                | ModeInsert
+                | ModeInsertNormal
+                | TogglePassthrough
+                | TargetMouseWindow
+                | RepeatLast
+                | OpenSwitcher { .. }
+                | OpenMinimizedSwitcher
+                | SwitcherNext
+                | SwitcherPrev
+                | SwitcherSelect
+                | SwitcherCancel
+                | OpenPalette
+                | PaletteInput(_)
+                | PaletteBackspace
+                | PaletteNext
+                | PalettePrev
+                | PaletteExecute
+                | PaletteCancel
+        )
+    }
+
+    /// The id of this action in `ACTIONS`.
+    pub fn id(&self) -> &'static str {
+        use Action::*;
+        match self {
+            ModeNormal => "mode-normal",
+            ModeInsert => "mode-insert",
+            ModeInsertNormal => "mode-insert-normal",
+            TogglePassthrough => "toggle-passthrough",
+            RelayoutAll => "relayout-all",
+            LayoutFloating => "layout-floating",
+            LayoutCascade => "layout-cascade",
+            LayoutTiling => "layout-tiling",
+            LayoutCustom(_) => "layout-custom",
+            SaveLayout(_) => "save-layout",
+            RestoreLayout(_) => "restore-layout",
+            WindowFull => "window-full",
+            WindowLeftHalf => "window-left-half",
+            WindowRightHalf => "window-right-half",
+            WindowTopHalf => "window-top-half",
+            WindowBottomHalf => "window-bottom-half",
+            WindowTopLeftQuarter => "window-top-left-quarter",
+            WindowTopRightQuarter => "window-top-right-quarter",
+            WindowBottomLeftQuarter => "window-bottom-left-quarter",
+            WindowBottomRightQuarter => "window-bottom-right-quarter",
+            WindowCenter => "window-center",
+            WindowMinimize => "window-minimize",
+            WindowRestore => "window-restore",
+            MinimizeGroup => "minimize-group",
+            RestoreGroup => "restore-group",
+            WindowClose => "window-close",
+            WindowRaise => "window-raise",
+            WindowLower => "window-lower",
+            CloseGroupWindows => "close-group-windows",
+            QuitApp => "quit-app",
+            ForceQuitApp => "force-quit-app",
+            TitleBarDoubleClick => "title-bar-double-click",
+            WindowZoom => "window-zoom",
+            WindowFullScreen => "window-full-screen",
+            ToggleZoom => "toggle-zoom",
+            TargetMouseWindow => "target-mouse-window",
+            RepeatLast => "repeat-last",
+            Spawn(_) => "spawn",
+            RunScript(_) => "run-script",
+            NextWindow => "next-window",
+            PrevWindow => "prev-window",
+            FocusLeft => "focus-left",
+            FocusRight => "focus-right",
+            FocusUp => "focus-up",
+            FocusDown => "focus-down",
+            FocusLast => "focus-last",
+            FocusOlder => "focus-older",
+            FocusNewer => "focus-newer",
+            FocusUrgent => "focus-urgent",
+            NextAppWindow => "next-app-window",
+            PrevAppWindow => "prev-app-window",
+            SwapNextWindow => "swap-next-window",
+            SwapPrevWindow => "swap-prev-window",
+            SwapLeft => "swap-left",
+            SwapRight => "swap-right",
+            SwapUp => "swap-up",
+            SwapDown => "swap-down",
+            Nudge(Direction::Left) => "nudge-left",
+            Nudge(Direction::Right) => "nudge-right",
+            Nudge(Direction::Up) => "nudge-up",
+            Nudge(Direction::Down) => "nudge-down",
+            ResizeFloating(Direction::Left) => "resize-floating-left",
+            ResizeFloating(Direction::Right) => "resize-floating-right",
+            ResizeFloating(Direction::Up) => "resize-floating-up",
+            ResizeFloating(Direction::Down) => "resize-floating-down",
+            IncrPrimaryColWidth => "incr-primary-col-width",
+            DecrPrimaryColWidth => "decr-primary-col-width",
+            IncrPrimaryColWindows => "incr-primary-col-windows",
+            DecrPrimaryColWindows => "decr-primary-col-windows",
+            SetPrimaryColWidth(_) => "set-primary-col-width",
+            SetPrimaryColWindows(_) => "set-primary-col-windows",
+            Balance => "balance",
+            GrowTile => "grow-tile",
+            ShrinkTile => "shrink-tile",
+            SplitHorizontal => "split-horizontal",
+            SplitVertical => "split-vertical",
+            StackWindow => "stack-window",
+            UnstackWindow => "unstack-window",
+            StackNext => "stack-next",
+            StackPrev => "stack-prev",
+            NextDisplay => "next-display",
+            PrevDisplay => "prev-display",
+            MoveWindowToNextDisplay { .. } => "move-window-to-next-display",
+            MoveWindowToPrevDisplay { .. } => "move-window-to-prev-display",
+            SwapWindowWithNextDisplay => "swap-window-with-next-display",
+            MoveGroupToNextDisplay { .. } => "move-group-to-next-display",
+            MoveGroupToPrevDisplay { .. } => "move-group-to-prev-display",
+            SwapGroupWithNextDisplay => "swap-group-with-next-display",
+            IdentifyDisplays => "identify-displays",
+            MoveWindowToGroup { .. } => "move-window-to-group",
+            ToggleWindowInGroup(_) => "toggle-window-in-group",
+            ShowGroup(_) => "show-group",
+            NextGroup => "next-group",
+            PrevGroup => "prev-group",
+            MoveWindowToNextGroup { .. } => "move-window-to-next-group",
+            MoveWindowToPrevGroup { .. } => "move-window-to-prev-group",
+            MoveAppToGroup { .. } => "move-app-to-group",
+            ToggleAppFloating => "toggle-app-floating",
+            GatherApp => "gather-app",
+            ToggleBorder => "toggle-border",
+            ToggleStats => "toggle-stats",
+            OpenSwitcher { .. } => "open-switcher",
+            OpenMinimizedSwitcher => "open-minimized-switcher",
+            SwitcherNext => "switcher-next",
+            SwitcherPrev => "switcher-prev",
+            SwitcherSelect => "switcher-select",
+            SwitcherCancel => "switcher-cancel",
+            OpenPalette => "open-palette",
+            PaletteInput(_) => "palette-input",
+            PaletteBackspace => "palette-backspace",
+            PaletteNext => "palette-next",
+            PalettePrev => "palette-prev",
+            PaletteExecute => "palette-execute",
+            PaletteCancel => "palette-cancel",
+        }
+    }
+
+    /// Build an action from its id in `ACTIONS` and its arguments.
+    pub fn parse(id: &str, args: &[String]) -> Result<Self> {
+        use Action::*;
+        let action = match id {
+            "mode-normal" => ModeNormal,
+            "mode-insert" => ModeInsert,
+            "mode-insert-normal" => ModeInsertNormal,
+            "toggle-passthrough" => TogglePassthrough,
+            "relayout-all" => RelayoutAll,
+            "layout-floating" => LayoutFloating,
+            "layout-cascade" => LayoutCascade,
+            "layout-tiling" => LayoutTiling,
+            "layout-custom" => LayoutCustom(text_arg(args, 0)?),
+            "save-layout" => SaveLayout(text_arg(args, 0)?),
+            "restore-layout" => RestoreLayout(text_arg(args, 0)?),
+            "window-full" => WindowFull,
+            "window-left-half" => WindowLeftHalf,
+            "window-right-half" => WindowRightHalf,
+            "window-top-half" => WindowTopHalf,
+            "window-bottom-half" => WindowBottomHalf,
+            "window-top-left-quarter" => WindowTopLeftQuarter,
+            "window-top-right-quarter" => WindowTopRightQuarter,
+            "window-bottom-left-quarter" => WindowBottomLeftQuarter,
+            "window-bottom-right-quarter" => WindowBottomRightQuarter,
+            "window-center" => WindowCenter,
+            "window-minimize" => WindowMinimize,
+            "window-restore" => WindowRestore,
+            "minimize-group" => MinimizeGroup,
+            "restore-group" => RestoreGroup,
+            "window-close" => WindowClose,
+            "window-raise" => WindowRaise,
+            "window-lower" => WindowLower,
+            "close-group-windows" => CloseGroupWindows,
+            "quit-app" => QuitApp,
+            "force-quit-app" => ForceQuitApp,
+            "title-bar-double-click" => TitleBarDoubleClick,
+            "window-zoom" => WindowZoom,
+            "window-full-screen" => WindowFullScreen,
+            "toggle-zoom" => ToggleZoom,
+            "target-mouse-window" => TargetMouseWindow,
+            "repeat-last" => RepeatLast,
+            // The whole rest of the line is the command.
+            "spawn" => Spawn(text_arg(args, 0).map(|_| args.join(" "))?),
+            "run-script" => RunScript(text_arg(args, 0)?),
+            "next-window" => NextWindow,
+            "prev-window" => PrevWindow,
+            "focus-left" => FocusLeft,
+            "focus-right" => FocusRight,
+            "focus-up" => FocusUp,
+            "focus-down" => FocusDown,
+            "focus-last" => FocusLast,
+            "focus-older" => FocusOlder,
+            "focus-newer" => FocusNewer,
+            "focus-urgent" => FocusUrgent,
+            "next-app-window" => NextAppWindow,
+            "prev-app-window" => PrevAppWindow,
+            "swap-next-window" => SwapNextWindow,
+            "swap-prev-window" => SwapPrevWindow,
+            "swap-left" => SwapLeft,
+            "swap-right" => SwapRight,
+            "swap-up" => SwapUp,
+            "swap-down" => SwapDown,
+            "nudge-left" => Nudge(Direction::Left),
+            "nudge-right" => Nudge(Direction::Right),
+            "nudge-up" => Nudge(Direction::Up),
+            "nudge-down" => Nudge(Direction::Down),
+            "resize-floating-left" => ResizeFloating(Direction::Left),
+            "resize-floating-right" => ResizeFloating(Direction::Right),
+            "resize-floating-up" => ResizeFloating(Direction::Up),
+            "resize-floating-down" => ResizeFloating(Direction::Down),
+            "incr-primary-col-width" => IncrPrimaryColWidth,
+            "decr-primary-col-width" => DecrPrimaryColWidth,
+            "incr-primary-col-windows" => IncrPrimaryColWindows,
+            "decr-primary-col-windows" => DecrPrimaryColWindows,
+            "set-primary-col-width" => SetPrimaryColWidth(number_arg(args, 0, 10..=90)? as u8),
+            "balance" => Balance,
+            "grow-tile" => GrowTile,
+            "shrink-tile" => ShrinkTile,
+            "split-horizontal" => SplitHorizontal,
+            "split-vertical" => SplitVertical,
+            "stack-window" => StackWindow,
+            "unstack-window" => UnstackWindow,
+            "stack-next" => StackNext,
+            "stack-prev" => StackPrev,
+            "set-primary-col-windows" => {
+                SetPrimaryColWindows(number_arg(args, 0, 1..=i32::MAX as u32)? as i32)
+            }
+            "next-display" => NextDisplay,
+            "prev-display" => PrevDisplay,
+            "move-window-to-next-display" => MoveWindowToNextDisplay {
+                follow: bool_arg(args, 0)?,
+            },
+            "move-window-to-prev-display" => MoveWindowToPrevDisplay {
+                follow: bool_arg(args, 0)?,
+            },
+            "swap-window-with-next-display" => SwapWindowWithNextDisplay,
+            "move-group-to-next-display" => MoveGroupToNextDisplay {
+                follow: bool_arg(args, 0)?,
+            },
+            "move-group-to-prev-display" => MoveGroupToPrevDisplay {
+                follow: bool_arg(args, 0)?,
+            },
+            "swap-group-with-next-display" => SwapGroupWithNextDisplay,
+            "identify-displays" => IdentifyDisplays,
+            "move-window-to-group" => MoveWindowToGroup {
+                id: group_arg(args, 0)?,
+                follow: bool_arg(args, 1)?,
+            },
+            "toggle-window-in-group" => ToggleWindowInGroup(group_arg(args, 0)?),
+            "show-group" => ShowGroup(group_arg(args, 0)?),
+            "next-group" => NextGroup,
+            "prev-group" => PrevGroup,
+            "move-window-to-next-group" => MoveWindowToNextGroup {
+                follow: bool_arg(args, 0)?,
+            },
+            "move-window-to-prev-group" => MoveWindowToPrevGroup {
+                follow: bool_arg(args, 0)?,
+            },
+            "move-app-to-group" => MoveAppToGroup {
+                id: group_arg(args, 0)?,
+                follow: bool_arg(args, 1)?,
+            },
+            "toggle-app-floating" => ToggleAppFloating,
+            "gather-app" => GatherApp,
+            "toggle-border" => ToggleBorder,
+            "toggle-stats" => ToggleStats,
+            "open-switcher" => OpenSwitcher {
+                all_groups: bool_arg(args, 0)?,
+            },
+            "open-minimized-switcher" => OpenMinimizedSwitcher,
+            "switcher-next" => SwitcherNext,
+            "switcher-prev" => SwitcherPrev,
+            "switcher-select" => SwitcherSelect,
+            "switcher-cancel" => SwitcherCancel,
+            "open-palette" => OpenPalette,
+            "palette-input" => PaletteInput(text_arg(args, 0)?),
+            "palette-backspace" => PaletteBackspace,
+            "palette-next" => PaletteNext,
+            "palette-prev" => PalettePrev,
+            "palette-execute" => PaletteExecute,
+            "palette-cancel" => PaletteCancel,
+            _ => return Err(anyhow!("Unknown action {:?}", id)),
+        };
+        Ok(action)
+    }
+}
+
+// Key codes on a US keyboard. Events are translated to these by the character
+// they type (see `keyboard::layout_keycode`).
 const KEYCODE_0: i64 = 29;
 const KEYCODE_1: i64 = 18;
 const KEYCODE_2: i64 = 19;
@@ -77,7 +1217,9 @@ const KEYCODE_7: i64 = 26;
 const KEYCODE_8: i64 = 28;
 const KEYCODE_9: i64 = 25;
 const KEYCODE_A: i64 = 0;
+const KEYCODE_B: i64 = 11;
 const KEYCODE_C: i64 = 8;
+const KEYCODE_D: i64 = 2;
 const KEYCODE_F: i64 = 3;
 const KEYCODE_H: i64 = 4;
 const KEYCODE_I: i64 = 34;
@@ -90,42 +1232,146 @@ const KEYCODE_O: i64 = 31;
 const KEYCODE_P: i64 = 35;
 const KEYCODE_Q: i64 = 12;
 const KEYCODE_R: i64 = 15;
+const KEYCODE_S: i64 = 1;
 const KEYCODE_T: i64 = 17;
+const KEYCODE_U: i64 = 32;
+const KEYCODE_V: i64 = 9;
+const KEYCODE_W: i64 = 13;
 const KEYCODE_X: i64 = 7;
+const KEYCODE_Y: i64 = 16;
+const KEYCODE_Z: i64 = 6;
+const KEYCODE_SEMICOLON: i64 = 41;
+const KEYCODE_PERIOD: i64 = 47;
+const KEYCODE_EQUAL: i64 = 24;
+const KEYCODE_MINUS: i64 = 27;
+const KEYCODE_GRAVE: i64 = 50;
+const KEYCODE_LEFT_BRACKET: i64 = 33;
+const KEYCODE_RIGHT_BRACKET: i64 = 30;
 const KEYCODE_ENT: i64 = 36;
+const KEYCODE_DEL: i64 = 51;
 const KEYCODE_ESC: i64 = 53;
+const KEYCODE_TAB: i64 = 48;
+const KEYCODE_LEFT: i64 = 123;
+const KEYCODE_RIGHT: i64 = 124;
+const KEYCODE_DOWN: i64 = 125;
+const KEYCODE_UP: i64 = 126;
 const FLG_NULL: CGEventFlags = CGEventFlags::CGEventFlagNull;
 const FLG_CTRL: CGEventFlags = CGEventFlags::CGEventFlagControl;
 const FLG_ALT: CGEventFlags = CGEventFlags::CGEventFlagAlternate;
 const FLG_SHIFT: CGEventFlags = CGEventFlags::CGEventFlagShift;
 const FLG_CMD: CGEventFlags = CGEventFlags::CGEventFlagCommand;
 
+/// The modifiers held for `Mode::InsertNormal`, <opt>+<shift> by default.
+/// None turns the mode's chord off.
+static INSERT_NORMAL_FLAGS: AtomicU64 = AtomicU64::new(FLG_ALT.bits() | FLG_SHIFT.bits());
+
+pub fn set_insert_normal_chord(flags: CGEventFlags) {
+    INSERT_NORMAL_FLAGS.store(flags.bits(), Ordering::Relaxed);
+}
+
+/// Whether `flags`, as returned by `modifier_flags`, are the modifiers held
+/// for `Mode::InsertNormal`.
+pub(crate) fn holds_insert_normal_chord(flags: CGEventFlags) -> bool {
+    let chord = INSERT_NORMAL_FLAGS.load(Ordering::Relaxed);
+    chord != 0 && flags.bits() == chord
+}
+
+/// The direction of an arrow key or of h/j/k/l.
+fn direction_of_keycode(keycode: i64) -> Option<Direction> {
+    match keycode {
+        KEYCODE_LEFT | KEYCODE_H => Some(Direction::Left),
+        KEYCODE_RIGHT | KEYCODE_L => Some(Direction::Right),
+        KEYCODE_UP | KEYCODE_K => Some(Direction::Up),
+        KEYCODE_DOWN | KEYCODE_J => Some(Direction::Down),
+        _ => None,
+    }
+}
+
 impl Action {
     pub fn of_cg_event(event: &CGEvent, mode: &Mode, layout: Option<&Layout>) -> Option<Self> {
         // Extract only relevant flags so we can use (==)
         let flags = event
             .get_flags()
             .intersection(FLG_CTRL | FLG_ALT | FLG_SHIFT | FLG_CMD);
-        let nml_mode_flgs: CGEventFlags = FLG_ALT | FLG_SHIFT;
         match event.get_type() {
             CGEventType::FlagsChanged => {
                 // eprintln!("FlagsChanged ({:?}) {:?}", mode, flags);
                 match mode {
-                    Mode::Insert if flags == nml_mode_flgs => Some(Self::ModeInsertNormal),
-                    Mode::InsertNormal if flags != nml_mode_flgs => Some(Self::ModeInsert),
+                    Mode::Insert if holds_insert_normal_chord(flags) => {
+                        Some(Self::ModeInsertNormal)
+                    }
+                    Mode::InsertNormal if !holds_insert_normal_chord(flags) => {
+                        Some(Self::ModeInsert)
+                    }
+                    Mode::Switcher {
+                        release_to_select: true,
+                    } if !flags.contains(FLG_ALT) => Some(Self::SwitcherSelect),
                     _ => None,
                 }
             }
             CGEventType::KeyDown => {
-                let keycode = event.get_integer_value_field(EventField::KEYBOARD_EVENT_KEYCODE);
+                let keycode = event_keycode(event);
                 // eprintln!("KeyDown ({:?}) {}", mode, keycode);
                 use Action::*;
                 match (mode, flags, keycode, layout) {
+                    (Mode::Insert, FLG_ALT, KEYCODE_TAB, _) => {
+                        Some(OpenSwitcher { all_groups: false })
+                    }
+                    (Mode::Normal, FLG_NULL, KEYCODE_W, _) => {
+                        Some(OpenSwitcher { all_groups: false })
+                    }
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_W, _) => {
+                        Some(OpenSwitcher { all_groups: true })
+                    }
+                    (Mode::Switcher { .. }, _, KEYCODE_TAB | KEYCODE_K, _)
+                        if flags.contains(FLG_SHIFT) || keycode == KEYCODE_K =>
+                    {
+                        Some(SwitcherPrev)
+                    }
+                    (Mode::Switcher { .. }, _, KEYCODE_TAB | KEYCODE_J, _) => Some(SwitcherNext),
+                    (Mode::Switcher { .. }, _, KEYCODE_ENT, _) => Some(SwitcherSelect),
+                    (Mode::Switcher { .. }, _, KEYCODE_ESC, _) => Some(SwitcherCancel),
+                    (Mode::Insert | Mode::Passthrough, _, KEYCODE_P, _)
+                        if flags == FLG_CTRL | FLG_ALT | FLG_SHIFT =>
+                    {
+                        Some(TogglePassthrough)
+                    }
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_P, _) => Some(TogglePassthrough),
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_SEMICOLON, _) => Some(OpenPalette),
+                    (Mode::Palette, _, KEYCODE_TAB, _) if flags.contains(FLG_SHIFT) => {
+                        Some(PalettePrev)
+                    }
+                    (Mode::Palette, _, KEYCODE_TAB | KEYCODE_DOWN, _) => Some(PaletteNext),
+                    (Mode::Palette, _, KEYCODE_UP, _) => Some(PalettePrev),
+                    (Mode::Palette, _, KEYCODE_ENT, _) => Some(PaletteExecute),
+                    (Mode::Palette, _, KEYCODE_ESC, _) => Some(PaletteCancel),
+                    (Mode::Palette, _, KEYCODE_DEL, _) => Some(PaletteBackspace),
+                    (Mode::Palette, _, _, _) => typed_text(event).map(PaletteInput),
                     (Mode::InsertNormal, _, KEYCODE_A, _) => Some(ModeNormal),
+                    (Mode::Normal, FLG_NULL, KEYCODE_B, _) => Some(ToggleBorder),
                     (Mode::Normal, FLG_NULL, KEYCODE_C, _) => Some(LayoutCascade),
                     (Mode::InsertNormal, _, KEYCODE_C, _) => Some(LayoutCascade),
+                    (Mode::Normal, FLG_NULL, KEYCODE_D, _) => Some(IdentifyDisplays),
                     (Mode::Normal, FLG_NULL, KEYCODE_F, _) => Some(LayoutFloating),
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_F, _) => Some(WindowFullScreen),
+                    (Mode::Normal, FLG_NULL, KEYCODE_Z, _) => Some(WindowZoom),
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_Z, _) => Some(ToggleZoom),
+                    (Mode::Normal, FLG_NULL, KEYCODE_U, _) => Some(TargetMouseWindow),
+                    (Mode::Normal, FLG_NULL, KEYCODE_PERIOD, _) => Some(RepeatLast),
+                    (Mode::InsertNormal, _, KEYCODE_PERIOD, _) => Some(RepeatLast),
                     (Mode::InsertNormal, _, KEYCODE_F, _) => Some(LayoutFloating),
+                    (Mode::Normal, _, _, Some(Layout::Floating))
+                        if flags.contains(FLG_CTRL) && direction_of_keycode(keycode).is_some() =>
+                    {
+                        let direction = direction_of_keycode(keycode)?;
+                        if flags == FLG_CTRL {
+                            Some(Nudge(direction))
+                        } else if flags == FLG_CTRL | FLG_SHIFT {
+                            Some(ResizeFloating(direction))
+                        } else {
+                            None
+                        }
+                    }
                     (Mode::Normal, FLG_ALT, KEYCODE_H, Some(Layout::TileHorizontal(_))) => {
                         Some(IncrPrimaryColWindows)
                     }
@@ -144,15 +1390,48 @@ impl Action {
                     (Mode::InsertNormal, _, KEYCODE_L, Some(Layout::TileHorizontal(_))) => {
                         Some(IncrPrimaryColWidth)
                     }
+                    (Mode::Normal, FLG_NULL, KEYCODE_EQUAL, Some(Layout::TileHorizontal(_))) => {
+                        Some(Balance)
+                    }
+                    (Mode::InsertNormal, _, KEYCODE_EQUAL, Some(Layout::TileHorizontal(_))) => {
+                        Some(Balance)
+                    }
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_EQUAL, Some(Layout::TileHorizontal(_))) => {
+                        Some(GrowTile)
+                    }
+                    (Mode::Normal, FLG_NULL, KEYCODE_MINUS, Some(Layout::TileHorizontal(_))) => {
+                        Some(ShrinkTile)
+                    }
+                    (Mode::Normal, FLG_NULL, KEYCODE_V, _) => Some(SplitVertical),
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_V, _) => Some(SplitHorizontal),
+                    (Mode::Normal, FLG_ALT, KEYCODE_S, _) => Some(StackWindow),
+                    (Mode::Normal, _, KEYCODE_S, _) if flags == FLG_ALT | FLG_SHIFT => {
+                        Some(UnstackWindow)
+                    }
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_RIGHT_BRACKET, _) => Some(StackNext),
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_LEFT_BRACKET, _) => Some(StackPrev),
                     (Mode::Normal, FLG_NULL, KEYCODE_H, _) => Some(WindowLeftHalf),
                     (Mode::InsertNormal, _, KEYCODE_H, _) => Some(WindowLeftHalf),
                     (Mode::Normal, FLG_NULL, KEYCODE_L, _) => Some(WindowRightHalf),
                     (Mode::InsertNormal, _, KEYCODE_L, _) => Some(WindowRightHalf),
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_K, _) => Some(WindowTopHalf),
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_J, _) => Some(WindowBottomHalf),
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_Y, _) => Some(WindowTopLeftQuarter),
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_U, _) => Some(WindowTopRightQuarter),
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_B, _) => Some(WindowBottomLeftQuarter),
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_N, _) => Some(WindowBottomRightQuarter),
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_C, _) => Some(WindowCenter),
                     (Mode::Normal, FLG_NULL, KEYCODE_M, _) => Some(WindowMinimize),
                     (Mode::InsertNormal, _, KEYCODE_M, _) => Some(WindowMinimize),
                     (Mode::Normal, FLG_SHIFT, KEYCODE_M, _) => Some(WindowRestore),
+                    (Mode::Normal, FLG_CTRL, KEYCODE_M, _) => Some(OpenMinimizedSwitcher),
+                    (Mode::Normal, FLG_ALT, KEYCODE_M, _) => Some(MinimizeGroup),
+                    (Mode::Normal, _, KEYCODE_M, _) if flags == FLG_ALT | FLG_SHIFT => {
+                        Some(RestoreGroup)
+                    }
                     (Mode::Normal, FLG_NULL, KEYCODE_R, _) => Some(RelayoutAll),
                     (Mode::InsertNormal, _, KEYCODE_R, _) => Some(RelayoutAll),
+                    (Mode::Normal, FLG_NULL, KEYCODE_S, _) => Some(ToggleStats),
                     (Mode::Normal, FLG_NULL, KEYCODE_T, _) => Some(LayoutTiling),
                     (Mode::InsertNormal, _, KEYCODE_T, _) => Some(LayoutTiling),
                     (Mode::Normal, FLG_ALT, KEYCODE_J, _) => Some(SwapNextWindow),
@@ -161,10 +1440,41 @@ impl Action {
                     (Mode::InsertNormal, _, KEYCODE_J, _) => Some(NextWindow),
                     (Mode::Normal, FLG_NULL, KEYCODE_K, _) => Some(PrevWindow),
                     (Mode::InsertNormal, _, KEYCODE_K, _) => Some(PrevWindow),
+                    (Mode::Normal, FLG_ALT, KEYCODE_LEFT, _) => Some(SwapLeft),
+                    (Mode::Normal, FLG_ALT, KEYCODE_RIGHT, _) => Some(SwapRight),
+                    (Mode::Normal, FLG_ALT, KEYCODE_UP, _) => Some(SwapUp),
+                    (Mode::Normal, FLG_ALT, KEYCODE_DOWN, _) => Some(SwapDown),
+                    (Mode::Normal, FLG_NULL, KEYCODE_LEFT, _) => Some(FocusLeft),
+                    (Mode::InsertNormal, _, KEYCODE_LEFT, _) => Some(FocusLeft),
+                    (Mode::Normal, FLG_NULL, KEYCODE_RIGHT, _) => Some(FocusRight),
+                    (Mode::InsertNormal, _, KEYCODE_RIGHT, _) => Some(FocusRight),
+                    (Mode::Normal, FLG_NULL, KEYCODE_UP, _) => Some(FocusUp),
+                    (Mode::InsertNormal, _, KEYCODE_UP, _) => Some(FocusUp),
+                    (Mode::Normal, FLG_NULL, KEYCODE_DOWN, _) => Some(FocusDown),
+                    (Mode::InsertNormal, _, KEYCODE_DOWN, _) => Some(FocusDown),
+                    (Mode::Normal, FLG_NULL, KEYCODE_GRAVE, _) => Some(FocusLast),
+                    (Mode::InsertNormal, _, KEYCODE_GRAVE, _) => Some(FocusLast),
+                    (Mode::Normal, FLG_CTRL, KEYCODE_O, _) => Some(FocusOlder),
+                    (Mode::Normal, FLG_CTRL, KEYCODE_I, _) => Some(FocusNewer),
+                    (Mode::Normal, FLG_CTRL, KEYCODE_U, _) => Some(FocusUrgent),
+                    (Mode::Insert | Mode::Normal, FLG_CMD, KEYCODE_GRAVE, _) => Some(NextAppWindow),
+                    (Mode::Insert | Mode::Normal, _, KEYCODE_GRAVE, _)
+                        if flags == FLG_CMD | FLG_SHIFT =>
+                    {
+                        Some(PrevAppWindow)
+                    }
                     (Mode::Normal, FLG_NULL, KEYCODE_ENT, _) => Some(WindowFull),
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_ENT, _) => Some(TitleBarDoubleClick),
                     (Mode::InsertNormal, _, KEYCODE_ENT, _) => Some(WindowFull),
                     (Mode::Normal, FLG_NULL, KEYCODE_X, _) => Some(WindowClose),
                     (Mode::InsertNormal, _, KEYCODE_X, _) => Some(WindowClose),
+                    (Mode::Normal, FLG_NULL, KEYCODE_RIGHT_BRACKET, _) => Some(WindowRaise),
+                    (Mode::Normal, FLG_NULL, KEYCODE_LEFT_BRACKET, _) => Some(WindowLower),
+                    (Mode::Normal, FLG_SHIFT, KEYCODE_X, _) => Some(CloseGroupWindows),
+                    (Mode::Normal, FLG_CMD, KEYCODE_Q, _) => Some(QuitApp),
+                    (Mode::Normal, _, KEYCODE_Q, _) if flags == FLG_CMD | FLG_ALT => {
+                        Some(ForceQuitApp)
+                    }
                     (Mode::Normal, FLG_NULL, KEYCODE_N, _) => Some(NextDisplay),
                     (Mode::InsertNormal, _, KEYCODE_N, _) => Some(NextDisplay),
                     (Mode::Normal, FLG_NULL, KEYCODE_P, _) => Some(PrevDisplay),
@@ -175,6 +1485,16 @@ impl Action {
                     (Mode::Normal, _, KEYCODE_N, _) if flags == FLG_ALT | FLG_SHIFT => {
                         Some(MoveWindowToNextDisplay { follow: false })
                     }
+                    (Mode::Normal, FLG_CTRL, KEYCODE_N, _) => Some(SwapWindowWithNextDisplay),
+                    (Mode::Normal, _, KEYCODE_N, _) if flags == FLG_CTRL | FLG_ALT => {
+                        Some(MoveGroupToNextDisplay { follow: true })
+                    }
+                    (Mode::Normal, _, KEYCODE_P, _) if flags == FLG_CTRL | FLG_ALT => {
+                        Some(MoveGroupToPrevDisplay { follow: true })
+                    }
+                    (Mode::Normal, _, KEYCODE_N, _) if flags == FLG_CTRL | FLG_SHIFT => {
+                        Some(SwapGroupWithNextDisplay)
+                    }
                     (Mode::Normal, FLG_ALT, KEYCODE_P, _) => {
                         Some(MoveWindowToPrevDisplay { follow: true })
                     }
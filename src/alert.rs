@@ -0,0 +1,92 @@
+//! Notification Center alerts for problems that would otherwise only be
+//! logged, e.g. losing Accessibility permission.
+//!
+//! `UNUserNotificationCenter` only works for apps in a bundle: run as a bare
+//! binary, alerts are skipped and only the log has the problem.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use block::ConcreteBlock;
+use cocoa::{
+    base::{id, nil, BOOL, NO},
+    foundation::NSString,
+};
+use objc::{class, msg_send, sel, sel_impl};
+
+#[link(name = "UserNotifications", kind = "framework")]
+extern "C" {}
+
+/// `UNAuthorizationOptionSound | UNAuthorizationOptionAlert`.
+const AUTHORIZATION_OPTIONS: usize = (1 << 1) | (1 << 2);
+
+static ALERTS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_alerts_enabled(enabled: bool) {
+    ALERTS_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// The notification center, unless we aren't in a bundle.
+unsafe fn notification_center() -> Option<id> {
+    let bundle: id = msg_send![class!(NSBundle), mainBundle];
+    let bundle_id: id = msg_send![bundle, bundleIdentifier];
+    if bundle_id == nil {
+        return None;
+    }
+    Some(msg_send![
+        class!(UNUserNotificationCenter),
+        currentNotificationCenter
+    ])
+}
+
+/// Ask to show alerts, which macOS only does the first time.
+pub fn request_alert_permission() {
+    if !ALERTS_ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    unsafe {
+        let center = match notification_center() {
+            Some(center) => center,
+            None => return,
+        };
+        let handler = ConcreteBlock::new(|granted: BOOL, _error: id| {
+            if granted == NO {
+                eprintln!("Notifications are not allowed, problems will only be logged");
+            }
+        })
+        .copy();
+        let () = msg_send![
+            center,
+            requestAuthorizationWithOptions: AUTHORIZATION_OPTIONS
+            completionHandler: &*handler
+        ];
+    }
+}
+
+/// Show an alert in Notification Center. An alert with the same title
+/// replaces the last one, so a recurring problem doesn't pile up.
+pub fn alert(title: &str, body: &str) {
+    if !ALERTS_ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    unsafe {
+        let center = match notification_center() {
+            Some(center) => center,
+            None => return,
+        };
+        let content: id = msg_send![class!(UNMutableNotificationContent), new];
+        let title = NSString::alloc(nil).init_str(title);
+        let body = NSString::alloc(nil).init_str(body);
+        let () = msg_send![content, setTitle: title];
+        let () = msg_send![content, setBody: body];
+        let request: id = msg_send![
+            class!(UNNotificationRequest),
+            requestWithIdentifier: title
+            content: content
+            trigger: nil
+        ];
+        let () = msg_send![center, addNotificationRequest: request withCompletionHandler: nil];
+        let () = msg_send![content, release];
+        let () = msg_send![title, release];
+        let () = msg_send![body, release];
+    }
+}
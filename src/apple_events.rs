@@ -0,0 +1,183 @@
+//! AppleScript commands, so that
+//! `osascript -e 'tell app "awesome-rs" to show group 2'` works.
+//!
+//! The commands' terminology is in `res/awesome-rs.sdef`, which the app
+//! bundle must include and name in its `Info.plist`'s
+//! `OSAScriptingDefinition`, as `make bundle` does. Each command is an Apple
+//! Event in the `AwRs` class, handled here.
+//!
+//! Arguments that contain spaces go in a list:
+//! `perform action "spawn" with arguments {"open", "-a", "Google Chrome"}`.
+
+use std::{ffi::c_void, sync::Once};
+
+use anyhow::{anyhow, Result};
+use cocoa::{
+    base::{id, nil},
+    foundation::NSString,
+};
+use objc::{
+    class,
+    declare::ClassDecl,
+    msg_send,
+    runtime::{Class, Object, Sel},
+    sel, sel_impl,
+};
+
+use crate::{window::nsstring_to_string, Action};
+
+/// The event class of every awesome-rs command: 'AwRs'.
+const EVENT_CLASS: u32 = 0x4177_5273;
+/// `perform action "show-group 2"`: 'actn'.
+const PERFORM_ACTION: u32 = 0x6163_746e;
+/// `show group 2`: 'shgp'.
+const SHOW_GROUP: u32 = 0x7368_6770;
+const EVENT_IDS: [u32; 2] = [PERFORM_ACTION, SHOW_GROUP];
+/// `keyDirectObject`: '----'.
+const DIRECT_OBJECT: u32 = 0x2d2d_2d2d;
+/// `perform action`'s `with arguments`: 'args'.
+const ARGUMENTS: u32 = 0x6172_6773;
+/// `typeAEList`: 'list'.
+const LIST_TYPE: u32 = 0x6c69_7374;
+/// `keyErrorString`: 'errs'.
+const ERROR_STRING: u32 = 0x6572_7273;
+
+type Callback = Box<dyn Fn(Action) -> Result<()>>;
+
+const CALLBACK_IVAR: &str = "callback";
+
+/// The strings in a list descriptor, or a single string.
+unsafe fn strings_of(descriptor: id) -> Result<Vec<String>> {
+    let descriptor_type: u32 = msg_send![descriptor, descriptorType];
+    let items: Vec<id> = if descriptor_type == LIST_TYPE {
+        let count: isize = msg_send![descriptor, numberOfItems];
+        // Indices start at 1.
+        (1..=count)
+            .map(|i| msg_send![descriptor, descriptorAtIndex: i])
+            .collect()
+    } else {
+        vec![descriptor]
+    };
+    items
+        .into_iter()
+        .map(|item| {
+            let s: id = msg_send![item, stringValue];
+            nsstring_to_string(s).ok_or_else(|| anyhow!("Expected text arguments"))
+        })
+        .collect()
+}
+
+/// The action a command asks for.
+unsafe fn action_of_event(event: id) -> Result<Action> {
+    let event_id: u32 = msg_send![event, eventID];
+    let descriptor: id = msg_send![event, paramDescriptorForKeyword: DIRECT_OBJECT];
+    if descriptor == nil {
+        return Err(anyhow!("Missing direct parameter"));
+    }
+    match event_id {
+        PERFORM_ACTION => {
+            let command: id = msg_send![descriptor, stringValue];
+            let command = nsstring_to_string(command)
+                .ok_or_else(|| anyhow!("Expected an action, e.g. \"show-group 2\""))?;
+            let arguments: id = msg_send![event, paramDescriptorForKeyword: ARGUMENTS];
+            if arguments != nil {
+                return Action::parse(command.trim(), &strings_of(arguments)?);
+            }
+            let mut words = command.split_whitespace();
+            let id = words.next().unwrap_or("");
+            let args: Vec<String> = words.map(|w| w.to_string()).collect();
+            Action::parse(id, &args)
+        }
+        SHOW_GROUP => {
+            let group: i32 = msg_send![descriptor, int32Value];
+            Action::parse("show-group", &[group.to_string()])
+        }
+        _ => Err(anyhow!("Unknown command {:#x}", event_id)),
+    }
+}
+
+extern "C" fn apple_event_received(this: &Object, _cmd: Sel, event: id, reply: id) {
+    unsafe {
+        let callback = *this.get_ivar::<*mut c_void>(CALLBACK_IVAR) as *const Callback;
+        if let Err(e) = action_of_event(event).and_then(|action| (*callback)(action)) {
+            // Shown by osascript and Script Editor as the command's error.
+            let message = NSString::alloc(nil).init_str(&format!("{:#}", e));
+            let descriptor: id =
+                msg_send![class!(NSAppleEventDescriptor), descriptorWithString: message];
+            let () = msg_send![message, release];
+            let () = msg_send![reply, setParamDescriptor: descriptor forKeyword: ERROR_STRING];
+        }
+    }
+}
+
+/// An Objective-C class whose instances forward
+/// `handleAppleEvent:withReplyEvent:` to a Rust callback.
+fn handler_class() -> &'static Class {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let mut decl = ClassDecl::new("AwesomeAppleEventHandler", class!(NSObject)).unwrap();
+        decl.add_ivar::<*mut c_void>(CALLBACK_IVAR);
+        unsafe {
+            decl.add_method(
+                sel!(handleAppleEvent:withReplyEvent:),
+                apple_event_received as extern "C" fn(&Object, Sel, id, id),
+            );
+        }
+        decl.register();
+    });
+    Class::get("AwesomeAppleEventHandler").unwrap()
+}
+
+unsafe fn event_manager() -> id {
+    msg_send![class!(NSAppleEventManager), sharedAppleEventManager]
+}
+
+/// Calls back on the main thread with the action each AppleScript command
+/// asks for, until dropped. An error the callback returns is reported to the
+/// script.
+pub struct AppleEventHandler {
+    handler: id,
+    callback: *mut Callback,
+}
+
+impl AppleEventHandler {
+    pub fn new(f: impl Fn(Action) -> Result<()> + 'static) -> Self {
+        let callback: *mut Callback = Box::into_raw(Box::new(Box::new(f)));
+        unsafe {
+            let handler: id = msg_send![handler_class(), new];
+            (*handler).set_ivar::<*mut c_void>(CALLBACK_IVAR, callback as *mut c_void);
+            for event_id in EVENT_IDS {
+                let () = msg_send![
+                    event_manager(),
+                    setEventHandler: handler
+                    andSelector: sel!(handleAppleEvent:withReplyEvent:)
+                    forEventClass: EVENT_CLASS
+                    andEventID: event_id
+                ];
+            }
+            Self { handler, callback }
+        }
+    }
+}
+
+impl Drop for AppleEventHandler {
+    fn drop(&mut self) {
+        unsafe {
+            for event_id in EVENT_IDS {
+                let () = msg_send![
+                    event_manager(),
+                    removeEventHandlerForEventClass: EVENT_CLASS
+                    andEventID: event_id
+                ];
+            }
+            let () = msg_send![self.handler, release];
+            drop(Box::from_raw(self.callback));
+        }
+    }
+}
+
+impl std::fmt::Debug for AppleEventHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AppleEventHandler")
+    }
+}
@@ -0,0 +1,160 @@
+use anyhow::{anyhow, Result};
+use awesome_rs::{send_request, ActionCall, Request, Response, ACTIONS, PROTOCOL_VERSION};
+
+const USAGE: &str = "Usage:
+  awesomectl actions [--json]       List available actions
+  awesomectl action <id> [args...]  Perform an action
+  awesomectl batch '<id> [args...]; ...'
+                                    Perform several actions, updating the
+                                    screen once at the end
+  awesomectl name-group <n> [name]  Name group n, or clear its name
+  awesomectl group <n> --set <setting> <value>...
+                                    Change group n's settings on every
+                                    display: primary-pct (10-90) or
+                                    primary-windows (at least 1)
+  awesomectl ping                   Check that awesome-rs is running
+  awesomectl stats [--json]         Show focus time per group and app
+  awesomectl frame-changes [--json] Show frames windows would have been
+                                    given since the last call (--dry-run)
+  awesomectl version                Show the IPC protocol version
+
+Set AWESOME_RS_TCP=127.0.0.1:<port> and AWESOME_RS_TOKEN=<token> to connect
+over TCP (see ipc.tcp_port and ipc.token in the config).";
+
+/// Bump when the `actions --json` output changes incompatibly.
+const ACTIONS_SCHEMA_VERSION: u32 = 1;
+
+fn print_actions(json: bool) -> Result<()> {
+    if json {
+        let value = serde_json::json!({
+            "schema_version": ACTIONS_SCHEMA_VERSION,
+            "actions": ACTIONS,
+        });
+        println!("{}", serde_json::to_string_pretty(&value)?);
+    } else {
+        for info in ACTIONS {
+            let args: Vec<_> = info.args.iter().map(|a| format!("<{}>", a.name)).collect();
+            let usage = format!("{} {}", info.id, args.join(" "));
+            println!("{:<40} {}", usage, info.description);
+        }
+    }
+    Ok(())
+}
+
+fn send(request: Request) -> Result<()> {
+    match send_request(&request)? {
+        Response::Error { message } => Err(anyhow!(message)),
+        response => {
+            println!("{:?}", response);
+            Ok(())
+        }
+    }
+}
+
+/// Parse `"show-group 2; layout-tiling"` into action calls.
+fn parse_batch(batch: &str) -> Vec<ActionCall> {
+    batch
+        .split(';')
+        .filter_map(|call| {
+            let mut words = call.split_whitespace();
+            words.next().map(|id| ActionCall {
+                id: id.to_string(),
+                args: words.map(|w| w.to_string()).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Parse `<n> --set primary-pct 66 --set primary-windows 2`.
+fn parse_group_settings(args: &[String]) -> Result<Request> {
+    let group = match args.first().map(|g| g.parse::<u8>()) {
+        Some(Ok(group)) => group,
+        _ => return Err(anyhow!("{}", USAGE)),
+    };
+    let (mut pct, mut max_windows) = (None, None);
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        let (setting, value) = match (arg.as_str(), rest.next(), rest.next()) {
+            ("--set", Some(setting), Some(value)) => (setting, value),
+            _ => return Err(anyhow!("{}", USAGE)),
+        };
+        match setting.as_str() {
+            "primary-pct" => pct = Some(value.parse()?),
+            "primary-windows" => max_windows = Some(value.parse()?),
+            _ => return Err(anyhow!("Unknown group setting {:?}", setting)),
+        }
+    }
+    if pct.is_none() && max_windows.is_none() {
+        return Err(anyhow!("{}", USAGE));
+    }
+    Ok(Request::SetGroupPrimary {
+        group,
+        pct,
+        max_windows,
+    })
+}
+
+fn print_stats(json: bool) -> Result<()> {
+    match send_request(&Request::Stats)? {
+        Response::Stats(report) if json => {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            Ok(())
+        }
+        Response::Stats(report) => {
+            print!("{}", report);
+            Ok(())
+        }
+        Response::Error { message } => Err(anyhow!(message)),
+        response => Err(anyhow!("Unexpected response {:?}", response)),
+    }
+}
+
+fn print_frame_changes(json: bool) -> Result<()> {
+    match send_request(&Request::FrameChanges)? {
+        Response::FrameChanges(changes) if json => {
+            println!("{}", serde_json::to_string_pretty(&changes)?);
+            Ok(())
+        }
+        Response::FrameChanges(changes) => {
+            for change in changes {
+                println!("{}", change);
+            }
+            Ok(())
+        }
+        Response::Error { message } => Err(anyhow!(message)),
+        response => Err(anyhow!("Unexpected response {:?}", response)),
+    }
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(|s| s.as_str()) {
+        Some("actions") => print_actions(args.iter().any(|a| a == "--json")),
+        Some("action") => match args.get(1) {
+            Some(id) => send(Request::Action {
+                id: id.clone(),
+                args: args[2..].to_vec(),
+            }),
+            None => Err(anyhow!("{}", USAGE)),
+        },
+        Some("batch") if args.len() > 1 => send(Request::Batch {
+            actions: parse_batch(&args[1..].join(" ")),
+        }),
+        Some("name-group") => match args.get(1).map(|g| g.parse::<u8>()) {
+            Some(Ok(group)) => send(Request::SetGroupName {
+                group,
+                name: (args.len() > 2).then(|| args[2..].join(" ")),
+            }),
+            _ => Err(anyhow!("{}", USAGE)),
+        },
+        Some("group") => parse_group_settings(&args[1..]).and_then(send),
+        Some("ping") => send(Request::Ping),
+        Some("frame-changes") => print_frame_changes(args.iter().any(|a| a == "--json")),
+        Some("stats") => print_stats(args.iter().any(|a| a == "--json")),
+        Some("version") => {
+            println!("IPC protocol version {}", PROTOCOL_VERSION);
+            Ok(())
+        }
+        _ => Err(anyhow!("{}", USAGE)),
+    }
+}
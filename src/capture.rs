@@ -0,0 +1,141 @@
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    time::{Duration, Instant},
+};
+
+use accessibility::AXUIElement;
+use anyhow::{anyhow, Result};
+use cocoa::{base::id, foundation::NSSize};
+use core_foundation::base::TCFType;
+use core_graphics::{
+    geometry::{CGPoint, CGRect, CGSize},
+    image::CGImage,
+    window::{
+        create_image, kCGWindowImageBoundsIgnoreFraming, kCGWindowImageNominalResolution,
+        kCGWindowListOptionIncludingWindow, CGWindowID,
+    },
+};
+use foreign_types::ForeignType;
+use objc::{class, msg_send, sel, sel_impl};
+
+use crate::{
+    config::ThumbnailsConfig,
+    window_system::{DynWindowSystem, WindowHandle},
+};
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    /// Private, but widely used: the only way to get from an AX window to its
+    /// CGWindowID.
+    fn _AXUIElementGetWindow(element: accessibility_sys::AXUIElementRef, id: *mut u32) -> i32;
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGPreflightScreenCaptureAccess() -> bool;
+    fn CGRequestScreenCaptureAccess() -> bool;
+}
+
+/// The CoreGraphics id of an AX window, as used by `CGWindowList*`.
+pub(crate) fn element_window_id(element: &AXUIElement) -> Result<CGWindowID> {
+    let mut window_id: u32 = 0;
+    let err = unsafe { _AXUIElementGetWindow(element.as_concrete_TypeRef(), &mut window_id) };
+    if err != 0 || window_id == 0 {
+        return Err(anyhow!("_AXUIElementGetWindow failed: {}", err));
+    }
+    Ok(window_id)
+}
+
+/// Capture the current contents of the window with id `wid`, even if it is
+/// covered by other windows. Requires the screen recording permission to see
+/// other apps' content.
+pub fn window_image(wid: CGWindowID) -> Result<CGImage> {
+    // CGRectNull: capture the window's own bounds.
+    let null_rect = CGRect::new(
+        &CGPoint::new(f64::INFINITY, f64::INFINITY),
+        &CGSize::new(0., 0.),
+    );
+    create_image(
+        null_rect,
+        kCGWindowListOptionIncludingWindow,
+        wid,
+        kCGWindowImageBoundsIgnoreFraming | kCGWindowImageNominalResolution,
+    )
+    .ok_or_else(|| anyhow!("CGWindowListCreateImage returned nothing"))
+}
+
+/// Wrap a captured image in a retained `NSImage`.
+pub(crate) unsafe fn ns_image(image: &CGImage) -> id {
+    let ns_image: id = msg_send![class!(NSImage), alloc];
+    msg_send![
+        ns_image,
+        initWithCGImage: image.as_ptr() as *mut c_void
+        size: NSSize::new(0., 0.)
+    ]
+}
+
+/// Window thumbnails for the switcher and group previews. Each is captured
+/// when first asked for, then reused until it is `max_age` seconds old.
+/// Without the screen recording permission captures would only show the
+/// desktop, so none are taken.
+#[derive(Debug)]
+pub struct ThumbnailCache {
+    enabled: bool,
+    max_age: Duration,
+    /// Whether we have asked for the screen recording permission yet.
+    requested_access: bool,
+    thumbnails: HashMap<uuid::Uuid, (CGImage, Instant)>,
+}
+
+impl ThumbnailCache {
+    pub fn new(config: &ThumbnailsConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            max_age: Duration::from_secs_f64(config.max_age),
+            requested_access: false,
+            thumbnails: HashMap::new(),
+        }
+    }
+
+    fn has_access(&mut self) -> bool {
+        if unsafe { CGPreflightScreenCaptureAccess() } {
+            return true;
+        }
+        // Ask once per run: macOS shows the prompt at most once anyway.
+        if !self.requested_access {
+            self.requested_access = true;
+            unsafe { CGRequestScreenCaptureAccess() };
+        }
+        false
+    }
+
+    /// A recent thumbnail of the window, or the last one taken if it can't
+    /// be captured now.
+    pub fn get(&mut self, system: &DynWindowSystem, w: &WindowHandle) -> Option<CGImage> {
+        if !self.enabled || !self.has_access() {
+            return None;
+        }
+        if let Some((image, taken)) = self.thumbnails.get(w.id()) {
+            if taken.elapsed() < self.max_age {
+                return Some(image.clone());
+            }
+        }
+        match system.window_number(w).and_then(window_image) {
+            Ok(image) => {
+                self.thumbnails
+                    .insert(*w.id(), (image.clone(), Instant::now()));
+                Some(image)
+            }
+            Err(e) => {
+                eprintln!("While capturing window image: {:?}", e);
+                self.thumbnails.get(w.id()).map(|(image, _)| image.clone())
+            }
+        }
+    }
+
+    /// Forget the thumbnails of windows for which `f` returns false.
+    pub fn retain(&mut self, f: impl Fn(&uuid::Uuid) -> bool) {
+        self.thumbnails.retain(|id, _| f(id));
+    }
+}
@@ -0,0 +1,32 @@
+//! Moving windows through the window server's private API, for windows that
+//! ignore the accessibility API (some games and Java apps).
+//!
+//! The window server only lets a connection move windows it doesn't own
+//! under some system configurations, so this is a best effort: it is behind
+//! the `cgs-move` feature and only tried once the accessibility API fails.
+
+use accessibility::AXUIElement;
+use anyhow::{anyhow, Result};
+use core_graphics::geometry::CGPoint;
+
+use crate::capture::element_window_id;
+
+type CGSConnectionID = i32;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGSMainConnectionID() -> CGSConnectionID;
+    fn CGSMoveWindow(cid: CGSConnectionID, wid: u32, point: *const CGPoint) -> i32;
+}
+
+/// Move the window `element`'s top-left corner to `pos`, in the same coordinates as
+/// the accessibility API. The window can't be resized this way.
+pub fn move_window(element: &AXUIElement, pos: CGPoint) -> Result<()> {
+    let wid = element_window_id(element)?;
+    let err = unsafe { CGSMoveWindow(CGSMainConnectionID(), wid, &pos) };
+    if err != 0 {
+        return Err(anyhow!("CGSMoveWindow failed: {}", err));
+    }
+    println!("CGSMoveWindow window:{} desired:{:?}", wid, pos);
+    Ok(())
+}
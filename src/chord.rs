@@ -0,0 +1,230 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use core_graphics::event::{CGEvent, CGEventFlags};
+
+use crate::{
+    action::Action,
+    config::{modifier_flags, ChordsConfig},
+    keyboard::{event_keycode, keycode_of_name},
+    mode::Mode,
+};
+
+/// A modifier's flag, or all four for "hyper".
+fn modifier_flag(name: &str) -> Option<CGEventFlags> {
+    Some(match name.to_ascii_lowercase().as_str() {
+        "ctrl" => CGEventFlags::CGEventFlagControl,
+        "alt" | "opt" => CGEventFlags::CGEventFlagAlternate,
+        "shift" => CGEventFlags::CGEventFlagShift,
+        "cmd" => CGEventFlags::CGEventFlagCommand,
+        "hyper" => {
+            CGEventFlags::CGEventFlagControl
+                | CGEventFlags::CGEventFlagAlternate
+                | CGEventFlags::CGEventFlagShift
+                | CGEventFlags::CGEventFlagCommand
+        }
+        _ => return None,
+    })
+}
+
+/// Modifiers without a key, written e.g. `"alt+shift"` or `"hyper"`. The
+/// empty string is no modifiers.
+pub fn parse_modifiers(s: &str) -> Result<CGEventFlags> {
+    let mut flags = CGEventFlags::CGEventFlagNull;
+    for modifier in s.split('+').filter(|m| !m.is_empty()) {
+        flags |= modifier_flag(modifier)
+            .ok_or_else(|| anyhow!("Unknown modifier {:?} in {:?}", modifier, s))?;
+    }
+    Ok(flags)
+}
+
+/// A key and the modifiers held with it, written e.g. `"ctrl+space"`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyPress {
+    keycode: i64,
+    flags: CGEventFlags,
+}
+
+impl KeyPress {
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut parts: Vec<&str> = s.split('+').collect();
+        let key = parts
+            .pop()
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| anyhow!("Missing key in {:?}", s))?;
+        let keycode =
+            keycode_of_name(key).ok_or_else(|| anyhow!("Unknown key {:?} in {:?}", key, s))?;
+        let mut flags = CGEventFlags::CGEventFlagNull;
+        for modifier in parts {
+            flags |= modifier_flag(modifier)
+                .ok_or_else(|| anyhow!("Unknown modifier {:?} in {:?}", modifier, s))?;
+        }
+        Ok(Self { keycode, flags })
+    }
+
+    pub fn of_event(event: &CGEvent) -> Self {
+        Self {
+            keycode: event_keycode(event),
+            flags: modifier_flags(event.get_flags()),
+        }
+    }
+}
+
+/// What a key press did to the chord in progress.
+#[derive(Debug)]
+pub enum ChordKey {
+    /// No chord is in progress and the key isn't the leader.
+    NotChord,
+    /// The key started or continued a chord.
+    Pending,
+    /// The key completed a chord.
+    Complete(Action),
+    /// The key doesn't continue any chord, so the chord was abandoned.
+    Abandoned,
+}
+
+/// A sequence of keys after the leader and the action it runs.
+#[derive(Debug)]
+struct Sequence {
+    keys: Vec<KeyPress>,
+    id: String,
+    args: Vec<String>,
+}
+
+/// Multi-key bindings: the leader key followed by a sequence of keys, e.g.
+/// `<space> w m`.
+#[derive(Debug, Default)]
+pub struct Chords {
+    leader: Option<KeyPress>,
+    timeout: Duration,
+    sequences: Vec<Sequence>,
+    /// Keys pressed since the leader, and when the last one was pressed.
+    pending: Option<(Vec<KeyPress>, Instant)>,
+}
+
+impl Chords {
+    pub fn new(config: &ChordsConfig) -> Result<Self> {
+        let leader = config
+            .leader
+            .as_deref()
+            .map(KeyPress::parse)
+            .transpose()
+            .context("In chords.leader")?;
+        let mut sequences = vec![];
+        for (keys, command) in config.sequences.iter() {
+            let keys = keys
+                .split_whitespace()
+                .map(KeyPress::parse)
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| format!("In chord {:?}", keys))?;
+            let (id, args) =
+                split_command(command).with_context(|| format!("In chord action {:?}", command))?;
+            sequences.push(Sequence { keys, id, args });
+        }
+        Ok(Self {
+            leader,
+            timeout: Duration::from_secs_f64(config.timeout),
+            sequences,
+            pending: None,
+        })
+    }
+
+    /// A leader with modifiers can start chords without getting in the way of
+    /// typing.
+    pub fn leader_has_modifiers(&self) -> bool {
+        self.leader
+            .map_or(false, |l| l.flags != CGEventFlags::CGEventFlagNull)
+    }
+
+    /// Feed a key press to the chord in progress, or start one if `key` is
+    /// the leader and `can_start`. A sequence runs as soon as it is complete,
+    /// even if a longer one starts with it.
+    pub fn key_down(&mut self, key: KeyPress, can_start: bool) -> ChordKey {
+        if let Some((mut keys, since)) = self.pending.take() {
+            if since.elapsed() <= self.timeout {
+                keys.push(key);
+                if let Some(s) = self.sequences.iter().find(|s| s.keys == keys) {
+                    return match Action::parse(&s.id, &s.args) {
+                        Ok(action) => ChordKey::Complete(action),
+                        Err(e) => {
+                            eprintln!("While parsing chord action: {:?}", e);
+                            ChordKey::Abandoned
+                        }
+                    };
+                }
+                if self.sequences.iter().any(|s| s.keys.starts_with(&keys)) {
+                    self.pending = Some((keys, Instant::now()));
+                    return ChordKey::Pending;
+                }
+                return ChordKey::Abandoned;
+            }
+        }
+        if can_start && Some(key) == self.leader {
+            self.pending = Some((vec![], Instant::now()));
+            return ChordKey::Pending;
+        }
+        ChordKey::NotChord
+    }
+}
+
+/// Split an action command, e.g. "show-group 3", into its id and arguments.
+/// Fails if it doesn't parse, so that config mistakes show up at startup
+/// rather than when the key is pressed.
+fn split_command(command: &str) -> Result<(String, Vec<String>)> {
+    let mut words = command.split_whitespace();
+    let id = words.next().unwrap_or("").to_string();
+    let args: Vec<String> = words.map(|w| w.to_string()).collect();
+    Action::parse(&id, &args)?;
+    Ok((id, args))
+}
+
+/// Single keys bound to actions in the config.
+#[derive(Debug, Default)]
+pub struct KeyBindings {
+    bindings: Vec<(KeyPress, String, Vec<String>)>,
+    /// Enters normal mode from insert mode, with or without modifiers.
+    normal_mode: Option<KeyPress>,
+}
+
+impl KeyBindings {
+    pub fn new(keys: &HashMap<String, String>, normal_mode: Option<&str>) -> Result<Self> {
+        let normal_mode = normal_mode
+            .map(KeyPress::parse)
+            .transpose()
+            .context("In modes.normal")?;
+        let mut bindings = vec![];
+        for (key, command) in keys.iter() {
+            let key = KeyPress::parse(key).with_context(|| format!("In key binding {:?}", key))?;
+            let (id, args) = split_command(command)
+                .with_context(|| format!("In key binding action {:?}", command))?;
+            bindings.push((key, id, args));
+        }
+        Ok(Self {
+            bindings,
+            normal_mode,
+        })
+    }
+
+    /// The action bound to `key` in `mode`. Keys without modifiers are only
+    /// bound in normal mode, so that they don't get in the way of typing.
+    pub fn action(&self, key: KeyPress, mode: Mode) -> Option<Action> {
+        if matches!(mode, Mode::Insert | Mode::InsertNormal) && self.normal_mode == Some(key) {
+            return Some(Action::ModeNormal);
+        }
+        let bound = match mode {
+            Mode::Normal => true,
+            Mode::Insert => key.flags != CGEventFlags::CGEventFlagNull,
+            _ => false,
+        };
+        if !bound {
+            return None;
+        }
+        let (_, id, args) = self.bindings.iter().find(|(k, _, _)| *k == key)?;
+        Action::parse(id, args)
+            .map_err(|e| eprintln!("While parsing key binding action: {:?}", e))
+            .ok()
+    }
+}
@@ -0,0 +1,856 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use core_graphics::{event::CGEventFlags, geometry::CGSize};
+use serde::Deserialize;
+
+use crate::{
+    action::{Action, ACTIONS},
+    chord::{parse_modifiers, Chords, KeyBindings},
+    double_tap::DoubleTaps,
+    window::{ActivationStep, DEFAULT_ACTIVATION},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Modifier {
+    Ctrl,
+    Alt,
+    Shift,
+    Cmd,
+}
+
+impl Modifier {
+    pub fn flag(&self) -> CGEventFlags {
+        match self {
+            Modifier::Ctrl => CGEventFlags::CGEventFlagControl,
+            Modifier::Alt => CGEventFlags::CGEventFlagAlternate,
+            Modifier::Shift => CGEventFlags::CGEventFlagShift,
+            Modifier::Cmd => CGEventFlags::CGEventFlagCommand,
+        }
+    }
+}
+
+/// Keep only the modifier flags we care about, so flags can be compared with
+/// (==).
+pub fn modifier_flags(flags: CGEventFlags) -> CGEventFlags {
+    flags.intersection(
+        CGEventFlags::CGEventFlagControl
+            | CGEventFlags::CGEventFlagAlternate
+            | CGEventFlags::CGEventFlagShift
+            | CGEventFlags::CGEventFlagCommand,
+    )
+}
+
+fn flags_of_modifiers(modifiers: &[Modifier]) -> CGEventFlags {
+    modifiers
+        .iter()
+        .fold(CGEventFlags::CGEventFlagNull, |flags, m| flags | m.flag())
+}
+
+/// A mouse gesture performed while holding `modifiers`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MouseBindingConfig {
+    pub enabled: bool,
+    pub modifiers: Vec<Modifier>,
+}
+
+impl Default for MouseBindingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            modifiers: vec![Modifier::Alt],
+        }
+    }
+}
+
+impl MouseBindingConfig {
+    /// True if the binding is enabled and exactly the configured modifiers are
+    /// held.
+    pub fn matches(&self, flags: CGEventFlags) -> bool {
+        self.enabled && modifier_flags(flags) == flags_of_modifiers(&self.modifiers)
+    }
+}
+
+/// An action performed on the window under the mouse when it is
+/// middle-clicked while holding `modifiers`, without focusing that window.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MiddleClickConfig {
+    pub enabled: bool,
+    pub modifiers: Vec<Modifier>,
+    /// An action id and its arguments, e.g. "window-minimize".
+    pub action: String,
+}
+
+impl Default for MiddleClickConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            modifiers: vec![Modifier::Alt],
+            action: "window-minimize".to_string(),
+        }
+    }
+}
+
+impl MiddleClickConfig {
+    pub fn matches(&self, flags: CGEventFlags) -> bool {
+        self.enabled && modifier_flags(flags) == flags_of_modifiers(&self.modifiers)
+    }
+
+    pub fn action(&self) -> Result<Action> {
+        let mut words = self.action.split_whitespace();
+        let id = words.next().unwrap_or("");
+        let args: Vec<String> = words.map(|w| w.to_string()).collect();
+        Action::parse(id, &args)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SnapConfig {
+    /// Snap floating windows when they are dropped after a drag.
+    pub enabled: bool,
+    /// How close (in points) an edge or the mouse must be to snap.
+    pub threshold: f64,
+    /// Fractions of the display a half or quarter snap steps through when its
+    /// key is pressed again, before the window is pushed onto the next
+    /// display.
+    pub cycle: Vec<f64>,
+}
+
+impl Default for SnapConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 10.,
+            cycle: vec![1. / 2., 1. / 3., 2. / 3.],
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct StickyEdgesConfig {
+    /// How far (in points) the mouse must go past the edge of a display
+    /// before a dragged window follows it onto the next display. 0 turns this
+    /// off.
+    pub resistance: f64,
+}
+
+/// An sRGB color, written as `"#rrggbb"` or `"#rrggbbaa"`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+    pub alpha: f64,
+}
+
+impl TryFrom<String> for Color {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        let hex = s
+            .strip_prefix('#')
+            .ok_or_else(|| anyhow!("Color {:?} should start with '#'", s))?;
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+            return Err(anyhow!("Color {:?} should be #rrggbb or #rrggbbaa", s));
+        }
+        let component = |i: usize| -> Result<f64> {
+            match hex.get(i..i + 2) {
+                Some(c) => Ok(u8::from_str_radix(c, 16)? as f64 / 255.),
+                None => Ok(1.),
+            }
+        };
+        Ok(Self {
+            red: component(0)?,
+            green: component(2)?,
+            blue: component(4)?,
+            alpha: component(6)?,
+        })
+    }
+}
+
+/// The border drawn around the active window.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HighlightConfig {
+    /// Border color when the active window has keyboard focus.
+    pub color: Color,
+    /// Border color when another window has keyboard focus.
+    pub unfocused_color: Color,
+    /// Keep the border around the focused window in insert mode too.
+    pub persistent: bool,
+    /// Width of the border in points.
+    pub width: f64,
+    /// Distance between the window and the border in points. Defaults to
+    /// `width`.
+    pub outset: Option<f64>,
+    pub corner_radius: f64,
+    /// Also draw a border around the active group's other windows.
+    pub group: bool,
+    pub group_color: Color,
+    pub group_width: f64,
+}
+
+impl HighlightConfig {
+    pub fn outset(&self) -> f64 {
+        self.outset.unwrap_or(self.width)
+    }
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        Self {
+            color: Color {
+                red: 1.,
+                green: 0.23,
+                blue: 0.19,
+                alpha: 0.9,
+            },
+            unfocused_color: Color {
+                red: 0.56,
+                green: 0.56,
+                blue: 0.58,
+                alpha: 0.7,
+            },
+            persistent: false,
+            width: 4.,
+            outset: None,
+            corner_radius: 10.,
+            group: false,
+            group_color: Color {
+                red: 1.,
+                green: 0.23,
+                blue: 0.19,
+                alpha: 0.35,
+            },
+            group_width: 2.,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct FloatingConfig {
+    /// Put floating windows back where they were the last time a display of
+    /// the same size was connected.
+    pub remember_frames: bool,
+    /// How far (in points) the nudge actions move a floating window.
+    pub nudge_step: f64,
+    /// How much (in points) the resize actions grow or shrink a floating
+    /// window.
+    pub resize_step: f64,
+}
+
+impl Default for FloatingConfig {
+    fn default() -> Self {
+        Self {
+            remember_frames: true,
+            nudge_step: 10.,
+            resize_step: 20.,
+        }
+    }
+}
+
+/// Window thumbnails in the switcher and group previews. Needs the screen
+/// recording permission.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ThumbnailsConfig {
+    pub enabled: bool,
+    /// Seconds before a thumbnail is captured again.
+    pub max_age: f64,
+}
+
+impl Default for ThumbnailsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_age: 2.,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ActivationConfig {
+    /// Seconds to wait after the last window motion before activating and
+    /// highlighting the new active window, so that moving quickly through a
+    /// group doesn't stop at every window. 0 activates straight away.
+    pub debounce: f64,
+    /// Keep focus where it is when a window opens in another app than the
+    /// active window's, and mark the new window urgent instead. This includes
+    /// apps launched from outside awesome-rs, e.g. from Spotlight.
+    pub prevent_focus_stealing: bool,
+}
+
+impl Default for ActivationConfig {
+    fn default() -> Self {
+        Self {
+            debounce: 0.,
+            prevent_focus_stealing: false,
+        }
+    }
+}
+
+/// The temporary normal mode entered while <opt>+<shift> are held.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TransientModeConfig {
+    /// Seconds without an action after which to go back to insert mode even
+    /// if the modifiers still seem held. 0 waits for them to be released.
+    pub timeout: f64,
+}
+
+impl Default for TransientModeConfig {
+    fn default() -> Self {
+        Self { timeout: 10. }
+    }
+}
+
+/// Notification Center alerts for problems, e.g. losing Accessibility
+/// permission or a display being disconnected.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AlertsConfig {
+    pub enabled: bool,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// Float newly adopted windows no larger than `max_width` x `max_height`,
+/// e.g. pickers, palettes and utility panels, instead of tiling them.
+/// Dialogs, i.e. modal windows and windows with one of `subroles`, float
+/// whatever their size, centered over their app's window. Off unless
+/// `enabled` is set, so windows tile as they always have.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AutoFloatConfig {
+    pub enabled: bool,
+    pub max_width: f64,
+    pub max_height: f64,
+    pub subroles: Vec<String>,
+}
+
+impl Default for AutoFloatConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_width: 400.,
+            max_height: 300.,
+            subroles: vec![
+                "AXDialog".to_string(),
+                "AXSystemDialog".to_string(),
+                "AXFloatingWindow".to_string(),
+            ],
+        }
+    }
+}
+
+impl AutoFloatConfig {
+    pub fn should_float(&self, size: &CGSize) -> bool {
+        self.enabled && size.width <= self.max_width && size.height <= self.max_height
+    }
+
+    /// Whether a window with this subrole, and which is modal or not, is a
+    /// dialog.
+    pub fn is_dialog(&self, subrole: Option<&str>, modal: bool) -> bool {
+        self.enabled && (modal || subrole.map_or(false, |s| self.subroles.iter().any(|r| r == s)))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct BindingsConfig {
+    /// Ids of actions whose key events still reach the focused app after the
+    /// action is performed.
+    pub pass_through: Vec<String>,
+    /// Match keys by where they are on a US keyboard rather than by the
+    /// character they type in the current keyboard layout.
+    pub by_position: bool,
+    /// Extra keys, e.g. "alt+return", and the action each runs with its
+    /// arguments, e.g. "spawn open -na Terminal". Keys without modifiers are
+    /// only bound in normal mode. These win over the built-in bindings.
+    pub keys: HashMap<String, String>,
+    /// Seconds between window motions while their key is held: key repeats
+    /// coming faster are dropped. 0 performs every repeat.
+    pub repeat_interval: f64,
+}
+
+impl Default for BindingsConfig {
+    fn default() -> Self {
+        Self {
+            pass_through: vec![],
+            by_position: false,
+            keys: HashMap::new(),
+            repeat_interval: 0.05,
+        }
+    }
+}
+
+impl BindingsConfig {
+    pub fn passes_through(&self, action: &Action) -> bool {
+        self.pass_through.iter().any(|id| id == action.id())
+    }
+}
+
+/// How to enter normal and transient mode.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ModesConfig {
+    /// A key that enters normal mode from insert mode, e.g. "f3" or
+    /// "hyper+space", as well as <opt>+<shift>+a in transient mode.
+    pub normal: Option<String>,
+    /// The modifiers held for transient mode, e.g. "ctrl+alt" or "hyper"
+    /// (<ctrl>+<opt>+<shift>+<cmd>). Empty turns transient mode off.
+    pub transient: String,
+}
+
+impl Default for ModesConfig {
+    fn default() -> Self {
+        Self {
+            normal: None,
+            transient: "alt+shift".to_string(),
+        }
+    }
+}
+
+/// Actions run by tapping a modifier key twice on its own.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DoubleTapConfig {
+    /// Seconds each tap, and the gap between them, may last.
+    pub interval: f64,
+    /// Modifier keys, e.g. "right_cmd" or "left_shift", and the action each
+    /// runs with its arguments, e.g. "mode-normal".
+    pub keys: HashMap<String, String>,
+}
+
+impl Default for DoubleTapConfig {
+    fn default() -> Self {
+        Self {
+            interval: 0.3,
+            keys: HashMap::new(),
+        }
+    }
+}
+
+/// Multi-key bindings started by a leader key.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ChordsConfig {
+    /// The key that starts a chord, e.g. "space" or "ctrl+space". A leader
+    /// without modifiers only starts chords in normal mode.
+    pub leader: Option<String>,
+    /// Seconds to wait for the next key before abandoning a chord.
+    pub timeout: f64,
+    /// The keys to press after the leader, e.g. "w m", and the action to run
+    /// with its arguments, e.g. "window-minimize" or "show-group 3".
+    pub sequences: HashMap<String, String>,
+}
+
+impl Default for ChordsConfig {
+    fn default() -> Self {
+        Self {
+            leader: None,
+            timeout: 1.,
+            sequences: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct GroupsConfig {
+    /// Names shown next to group numbers, e.g. `1 = "web"`. A group has the
+    /// same name on every display.
+    pub names: HashMap<String, String>,
+    /// While in transient mode, show the windows of the group picked with a
+    /// number key, and only switch to it when the modifiers are released.
+    pub preview: bool,
+    /// Whether `next-group` and `prev-group` skip groups with no windows.
+    /// Otherwise they step through every group number, creating groups as
+    /// they go.
+    pub skip_empty: bool,
+    /// Settings a group starts with when it is created, under
+    /// `[groups.defaults.<group>]` where `<group>` is a group number or one of
+    /// the names above.
+    pub defaults: HashMap<String, GroupDefaults>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutName {
+    Tiling,
+    Cascade,
+    Floating,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct GroupDefaults {
+    pub layout: Option<LayoutName>,
+    pub primary_column_pct: Option<u8>,
+    pub primary_column_max_windows: Option<i32>,
+    /// Space in points around and between tiled windows.
+    pub gap: Option<f64>,
+}
+
+impl GroupDefaults {
+    fn validate(&self) -> Result<()> {
+        if let Some(pct) = self.primary_column_pct {
+            if !(10..=90).contains(&pct) {
+                return Err(anyhow!("primary_column_pct must be 10-90, got {}", pct));
+            }
+        }
+        if let Some(n) = self.primary_column_max_windows {
+            if n < 1 {
+                return Err(anyhow!("primary_column_max_windows must be at least 1"));
+            }
+        }
+        if let Some(gap) = self.gap {
+            if gap < 0. || gap.is_nan() {
+                return Err(anyhow!("gap must not be negative"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for GroupsConfig {
+    fn default() -> Self {
+        Self {
+            names: HashMap::new(),
+            preview: false,
+            skip_empty: true,
+            defaults: HashMap::new(),
+        }
+    }
+}
+
+impl GroupsConfig {
+    pub fn names(&self) -> HashMap<u8, String> {
+        self.names
+            .iter()
+            .filter_map(|(g_id, name)| Some((g_id.parse().ok()?, name.clone())))
+            .collect()
+    }
+
+    /// The group a key of `defaults` refers to.
+    fn group_of_key(&self, key: &str) -> Option<u8> {
+        match key.parse() {
+            Ok(g_id @ 0..=9) => Some(g_id),
+            Ok(_) => None,
+            Err(_) => self
+                .names()
+                .into_iter()
+                .find_map(|(g_id, name)| (name == key).then(|| g_id)),
+        }
+    }
+
+    /// The settings each group starts with, by group number.
+    pub fn defaults(&self) -> HashMap<u8, GroupDefaults> {
+        self.defaults
+            .iter()
+            .filter_map(|(key, defaults)| Some((self.group_of_key(key)?, defaults.clone())))
+            .collect()
+    }
+}
+
+/// Remote control, in addition to the Unix socket.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct IpcConfig {
+    /// Also accept requests on this port on 127.0.0.1, e.g. to forward over
+    /// SSH or reach from a container.
+    pub tcp_port: Option<u16>,
+    /// Required for TCP connections.
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MinimizeConfig {
+    /// Instead of minimizing windows into the Dock, which plays its animation
+    /// for each window, park them in the bottom-right corner of their display
+    /// with only a corner showing. `WindowRestore` puts them back at once.
+    /// They stay open as far as their apps are concerned.
+    pub suppress_animation: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SpacesConfig {
+    /// Give each group its own Mission Control Space: group 1 is a display's
+    /// first Space, group 2 its second and so on, and group 0 its tenth.
+    /// Showing a group switches Space, and switching Space shows its group.
+    /// Only used when built with the `spaces` feature, see src/spaces.rs.
+    pub enabled: bool,
+}
+
+impl SpacesConfig {
+    /// The index among a display's Spaces of group `g_id`'s Space.
+    pub fn space_index(g_id: u8) -> usize {
+        match g_id {
+            0 => 9,
+            g_id => g_id as usize - 1,
+        }
+    }
+
+    /// The group living on the display's Space at `idx`.
+    pub fn group_of_space(idx: usize) -> Option<u8> {
+        match idx {
+            0..=8 => Some(idx as u8 + 1),
+            9 => Some(0),
+            _ => None,
+        }
+    }
+}
+
+/// Settings for the windows of one application.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Matches the application's bundle identifier or its name.
+    pub app: String,
+    /// How to bring the app's windows to the front, overriding
+    /// `["set-main", "activate-app"]`.
+    pub activation: Option<Vec<ActivationStep>>,
+    /// Leave the keyboard alone while this app is frontmost, e.g. for virtual
+    /// machines and games that need every key.
+    pub passthrough: Option<bool>,
+    /// Put the app's new windows in this group (0-9).
+    pub group: Option<u8>,
+    /// Float the app's new windows, or never float them, whatever their
+    /// size.
+    pub floating: Option<bool>,
+}
+
+impl Rule {
+    pub fn matches(&self, bundle_id: Option<&str>, app_name: Option<&str>) -> bool {
+        bundle_id == Some(self.app.as_str()) || app_name == Some(self.app.as_str())
+    }
+}
+
+/// Shell commands run when something changes. Each is given the event as
+/// JSON on stdin and in `$AWESOME_EVENT_JSON`, and its name in
+/// `$AWESOME_EVENT`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// A window appeared.
+    pub window_created: Vec<String>,
+    /// The active window changed.
+    pub focus_changed: Vec<String>,
+    /// A display's active group changed.
+    pub group_shown: Vec<String>,
+    /// A display was connected.
+    pub display_added: Vec<String>,
+    /// A group's layout changed.
+    pub layout_changed: Vec<String>,
+    pub mode_changed: Vec<String>,
+    /// Also post each event as a distributed notification named
+    /// `com.github.mattjbray.awesome-rs.<event>`, with the event's fields in
+    /// its `userInfo`, e.g. for sketchybar's `--add event`.
+    pub distributed_notifications: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Move windows by dragging with the left mouse button.
+    pub drag_window: MouseBindingConfig,
+    /// Resize windows by dragging with the right mouse button.
+    pub resize_window: MouseBindingConfig,
+    pub middle_click: MiddleClickConfig,
+    pub snap: SnapConfig,
+    pub sticky_edges: StickyEdgesConfig,
+    pub highlight: HighlightConfig,
+    pub auto_float: AutoFloatConfig,
+    pub floating: FloatingConfig,
+    pub activation: ActivationConfig,
+    pub alerts: AlertsConfig,
+    pub transient_mode: TransientModeConfig,
+    pub thumbnails: ThumbnailsConfig,
+    pub minimize: MinimizeConfig,
+    pub ipc: IpcConfig,
+    pub bindings: BindingsConfig,
+    pub modes: ModesConfig,
+    pub double_tap: DoubleTapConfig,
+    pub chords: ChordsConfig,
+    pub groups: GroupsConfig,
+    pub hooks: HooksConfig,
+    pub spaces: SpacesConfig,
+    /// A Rhai script defining bindings and event handlers. Only used when
+    /// built with the `scripting` feature, see src/script.rs.
+    pub script: Option<PathBuf>,
+    /// Per-application settings. When several rules match an app, the first
+    /// one that sets an option wins.
+    pub rules: Vec<Rule>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/awesome-rs/config.toml`, defaulting to
+    /// `~/.config/awesome-rs/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_home.join("awesome-rs").join("config.toml"))
+    }
+
+    pub fn matching_rules<'a>(
+        &'a self,
+        bundle_id: Option<&'a str>,
+        app_name: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a Rule> {
+        self.rules
+            .iter()
+            .filter(move |r| r.matches(bundle_id, app_name))
+    }
+
+    pub fn activation_for(
+        &self,
+        bundle_id: Option<&str>,
+        app_name: Option<&str>,
+    ) -> &[ActivationStep] {
+        self.matching_rules(bundle_id, app_name)
+            .find_map(|r| r.activation.as_deref())
+            .unwrap_or(DEFAULT_ACTIVATION)
+    }
+
+    pub fn passthrough_for(&self, bundle_id: Option<&str>, app_name: Option<&str>) -> bool {
+        self.matching_rules(bundle_id, app_name)
+            .find_map(|r| r.passthrough)
+            .unwrap_or(false)
+    }
+
+    pub fn group_for(&self, bundle_id: Option<&str>, app_name: Option<&str>) -> Option<u8> {
+        self.matching_rules(bundle_id, app_name)
+            .find_map(|r| r.group)
+    }
+
+    /// Whether a new window of the app should float, if a rule says.
+    pub fn floating_for(&self, bundle_id: Option<&str>, app_name: Option<&str>) -> Option<bool> {
+        self.matching_rules(bundle_id, app_name)
+            .find_map(|r| r.floating)
+    }
+
+    /// Load the config from the default path, or use the defaults if there is
+    /// no config file.
+    pub fn load() -> Result<Self> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load_from(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read config file {}", path.display()))?;
+        let mut config: Self = toml::from_str(&content)
+            .with_context(|| format!("Could not parse config file {}", path.display()))?;
+        // An action renamed or removed since the config was written is
+        // skipped rather than losing the whole config.
+        config.bindings.pass_through.retain(|id| {
+            let known = ACTIONS.iter().any(|info| info.id == id);
+            if !known {
+                eprintln!("Ignoring unknown action {:?} in bindings.pass_through", id);
+            }
+            known
+        });
+        for g_id in config.groups.names.keys() {
+            if !matches!(g_id.parse::<u8>(), Ok(0..=9)) {
+                return Err(anyhow!(
+                    "Expected a group number 0-9 in groups.names, got {:?}",
+                    g_id
+                ));
+            }
+        }
+        for (key, defaults) in config.groups.defaults.iter() {
+            if config.groups.group_of_key(key).is_none() {
+                return Err(anyhow!(
+                    "Expected a group number 0-9 or name in groups.defaults, got {:?}",
+                    key
+                ));
+            }
+            defaults
+                .validate()
+                .with_context(|| format!("In groups.defaults.{}", key))?;
+        }
+        if config.snap.cycle.is_empty() {
+            return Err(anyhow!("Expected at least one size in snap.cycle"));
+        }
+        for size in config.snap.cycle.iter() {
+            if !(*size > 0. && *size <= 1.) {
+                return Err(anyhow!(
+                    "Expected sizes between 0 and 1 in snap.cycle, got {}",
+                    size
+                ));
+            }
+        }
+        for rule in config.rules.iter() {
+            if matches!(rule.group, Some(g_id) if g_id > 9) {
+                return Err(anyhow!(
+                    "Expected a group number 0-9 in the rule for {:?}",
+                    rule.app
+                ));
+            }
+        }
+        config
+            .middle_click
+            .action()
+            .context("In middle_click.action")?;
+        Chords::new(&config.chords)?;
+        KeyBindings::new(&config.bindings.keys, config.modes.normal.as_deref())?;
+        parse_modifiers(&config.modes.transient).context("In modes.transient")?;
+        DoubleTaps::new(&config.double_tap)?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(id: &str, args: &[&str]) -> Action {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        Action::parse(id, &args).unwrap()
+    }
+
+    #[test]
+    fn pass_through_matches_actions_by_id() {
+        let config: Config = toml::from_str(
+            r#"
+            [bindings]
+            pass_through = ["focus-left", "show-group"]
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.bindings.passes_through(&action("focus-left", &[])));
+        assert!(config
+            .bindings
+            .passes_through(&action("show-group", &["2"])));
+        assert!(!config.bindings.passes_through(&action("focus-right", &[])));
+    }
+
+    #[test]
+    fn nothing_passes_through_by_default() {
+        let config = Config::default();
+
+        assert!(!config.bindings.passes_through(&action("focus-left", &[])));
+    }
+}
@@ -0,0 +1,73 @@
+use core_graphics::display::CGRect;
+
+/// A direction on screen, for moving between windows by where they are rather
+/// than by their order in a group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Direction {
+    /// How far `to` is from `from` in this direction, and how far off to the
+    /// side, or `None` if `to` isn't in this direction. Screen coordinates
+    /// increase to the right and down.
+    fn distance(self, from: &CGRect, to: &CGRect) -> Option<(f64, f64)> {
+        let (fx, fy) = center(from);
+        let (tx, ty) = center(to);
+        let (along, across) = match self {
+            Direction::Left => (fx - tx, ty - fy),
+            Direction::Right => (tx - fx, ty - fy),
+            Direction::Up => (fy - ty, tx - fx),
+            Direction::Down => (ty - fy, tx - fx),
+        };
+        if along > 0. {
+            Some((along, across.abs()))
+        } else {
+            None
+        }
+    }
+
+    /// Whether `to` is level with `from`, i.e. some of it is straight ahead.
+    fn overlaps(self, from: &CGRect, to: &CGRect) -> bool {
+        let ((from_min, from_max), (to_min, to_max)) = match self {
+            Direction::Left | Direction::Right => (
+                (from.origin.y, from.origin.y + from.size.height),
+                (to.origin.y, to.origin.y + to.size.height),
+            ),
+            Direction::Up | Direction::Down => (
+                (from.origin.x, from.origin.x + from.size.width),
+                (to.origin.x, to.origin.x + to.size.width),
+            ),
+        };
+        to_min < from_max && from_min < to_max
+    }
+}
+
+fn center(r: &CGRect) -> (f64, f64) {
+    (
+        r.origin.x + r.size.width / 2.,
+        r.origin.y + r.size.height / 2.,
+    )
+}
+
+/// The index of the frame nearest to `from` in `direction`. Frames straight
+/// ahead win over ones off to the side, then the closest wins.
+pub fn nearest_in_direction(
+    from: &CGRect,
+    frames: &[CGRect],
+    direction: Direction,
+) -> Option<usize> {
+    frames
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, to)| {
+            direction
+                .distance(from, to)
+                .map(|(along, across)| (!direction.overlaps(from, to), along + across, idx))
+        })
+        .min_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)))
+        .map(|(_, _, idx)| idx)
+}
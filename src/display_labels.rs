@@ -0,0 +1,158 @@
+use std::{cell::RefCell, rc::Rc};
+
+use cocoa::{
+    appkit::{
+        NSBackingStoreType::NSBackingStoreBuffered, NSColor, NSView, NSWindow, NSWindowStyleMask,
+    },
+    base::{id, nil, NO, YES},
+    foundation::{NSArray, NSInteger, NSPoint, NSRect, NSSize, NSString},
+};
+use core_graphics::display::{CGDisplay, CGRect};
+use objc::{class, msg_send, sel, sel_impl};
+
+use crate::{
+    overlay::{overlay_scale, POPUP_WINDOW_LEVEL},
+    timer::Timer,
+    window::nsstring_to_string,
+};
+
+const WIDTH: f64 = 320.;
+const HEIGHT: f64 = 200.;
+const NUMBER_FONT_SIZE: f64 = 120.;
+const NAME_FONT_SIZE: f64 = 18.;
+const NAME_HEIGHT: f64 = 30.;
+/// NSTextAlignmentCenter
+const TEXT_ALIGNMENT_CENTER: NSInteger = 1;
+
+/// A large number and name shown in the middle of each display for a few
+/// seconds, like the Displays preference pane does. The labels are closed
+/// when they time out or when this is dropped, whichever comes first.
+pub struct DisplayLabels {
+    panels: Rc<RefCell<Vec<id>>>,
+    timer: Timer,
+}
+
+impl DisplayLabels {
+    /// Show each `(bounds, number, name)` label centered on the display with
+    /// those bounds.
+    pub fn show(labels: &[(CGRect, String, String)], duration: f64) -> Self {
+        let panels: Vec<id> = labels
+            .iter()
+            .map(|(bounds, number, name)| unsafe { make_panel(bounds, number, name) })
+            .collect();
+        let panels = Rc::new(RefCell::new(panels));
+        let timer = {
+            let panels = panels.clone();
+            Timer::once(duration, move || close_all(&panels))
+        };
+        Self { panels, timer }
+    }
+}
+
+/// The name macOS gives a display, e.g. "Built-in Retina Display".
+pub fn display_name(display_id: u32) -> Option<String> {
+    unsafe {
+        let screens: id = msg_send![class!(NSScreen), screens];
+        for i in 0..screens.count() {
+            let screen = screens.objectAtIndex(i);
+            let description: id = msg_send![screen, deviceDescription];
+            let number: id = msg_send![
+                description,
+                objectForKey: NSString::alloc(nil).init_str("NSScreenNumber")
+            ];
+            if number == nil {
+                continue;
+            }
+            let number: u32 = msg_send![number, unsignedIntValue];
+            if number == display_id {
+                let name: id = msg_send![screen, localizedName];
+                return nsstring_to_string(name);
+            }
+        }
+        None
+    }
+}
+
+fn close_all(panels: &RefCell<Vec<id>>) {
+    for panel in panels.borrow_mut().drain(..) {
+        unsafe { panel.close() };
+    }
+}
+
+unsafe fn make_panel(bounds: &CGRect, number: &str, name: &str) -> id {
+    let scale = overlay_scale(bounds);
+    let width = WIDTH * scale;
+    let height = HEIGHT * scale;
+    let name_height = NAME_HEIGHT * scale;
+    // (0,0) is bottom-left of main display, y increases up the screen
+    let m = CGDisplay::main().bounds();
+    let x = bounds.origin.x + (bounds.size.width - width) / 2.;
+    let y =
+        m.size.height - bounds.origin.y - bounds.size.height + (bounds.size.height - height) / 2.;
+
+    let panel: id = msg_send![class!(NSPanel), alloc];
+    panel.initWithContentRect_styleMask_backing_defer_(
+        NSRect::new(NSPoint::new(x, y), NSSize::new(width, height)),
+        NSWindowStyleMask::NSBorderlessWindowMask | NSWindowStyleMask::NSNonactivatingPanelMask,
+        NSBackingStoreBuffered,
+        NO,
+    );
+    panel.setOpaque_(NO);
+    panel.setBackgroundColor_(NSColor::colorWithSRGBRed_green_blue_alpha_(
+        nil, 0.1, 0.1, 0.1, 0.8,
+    ));
+    panel.setIgnoresMouseEvents_(YES);
+    panel.setLevel_(POPUP_WINDOW_LEVEL);
+
+    let content = panel.contentView();
+    let number_label = make_label(
+        NSRect::new(
+            NSPoint::new(0., name_height),
+            NSSize::new(width, height - name_height),
+        ),
+        number,
+        NUMBER_FONT_SIZE * scale,
+    );
+    content.addSubview_(number_label);
+    let () = msg_send![number_label, release];
+    let name_label = make_label(
+        NSRect::new(NSPoint::new(0., 0.), NSSize::new(width, name_height)),
+        name,
+        NAME_FONT_SIZE * scale,
+    );
+    content.addSubview_(name_label);
+    let () = msg_send![name_label, release];
+
+    panel.orderFrontRegardless();
+    panel
+}
+
+/// Returns a retained view.
+unsafe fn make_label(frame: NSRect, text: &str, font_size: f64) -> id {
+    let label: id = msg_send![class!(NSTextField), alloc];
+    let label: id = msg_send![label, initWithFrame: frame];
+    let () = msg_send![label, setEditable: NO];
+    let () = msg_send![label, setSelectable: NO];
+    let () = msg_send![label, setBezeled: NO];
+    let () = msg_send![label, setDrawsBackground: NO];
+    let () = msg_send![label, setAlignment: TEXT_ALIGNMENT_CENTER];
+    let white: id = msg_send![class!(NSColor), whiteColor];
+    let () = msg_send![label, setTextColor: white];
+    let font: id = msg_send![class!(NSFont), boldSystemFontOfSize: font_size];
+    let () = msg_send![label, setFont: font];
+    let () = msg_send![label, setStringValue: NSString::alloc(nil).init_str(text)];
+    label
+}
+
+impl Drop for DisplayLabels {
+    fn drop(&mut self) {
+        self.timer.invalidate();
+        close_all(&self.panels);
+    }
+}
+
+impl std::fmt::Debug for DisplayLabels {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DisplayLabels")
+    }
+}
@@ -0,0 +1,98 @@
+use std::ffi::c_void;
+
+use core_foundation::{
+    base::TCFType,
+    string::{CFString, CFStringRef},
+};
+
+type CGDisplayChangeSummaryFlags = u32;
+type Callback = Box<dyn FnMut()>;
+
+const K_CG_DISPLAY_BEGIN_CONFIGURATION_FLAG: CGDisplayChangeSummaryFlags = 1 << 0;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGDisplayRegisterReconfigurationCallback(
+        callback: extern "C" fn(u32, CGDisplayChangeSummaryFlags, *mut c_void),
+        user_info: *mut c_void,
+    ) -> i32;
+    fn CGDisplayRemoveReconfigurationCallback(
+        callback: extern "C" fn(u32, CGDisplayChangeSummaryFlags, *mut c_void),
+        user_info: *mut c_void,
+    ) -> i32;
+    fn CGDisplayCreateUUIDFromDisplayID(display: u32) -> *const c_void;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFUUIDCreateString(allocator: *const c_void, uuid: *const c_void) -> CFStringRef;
+    fn CFRelease(cf: *const c_void);
+}
+
+extern "C" fn reconfiguration_callback(
+    _display: u32,
+    flags: CGDisplayChangeSummaryFlags,
+    user_info: *mut c_void,
+) {
+    // Each change is reported twice: before and after it happens.
+    if flags & K_CG_DISPLAY_BEGIN_CONFIGURATION_FLAG != 0 {
+        return;
+    }
+    let callback = unsafe { &mut *(user_info as *mut Callback) };
+    callback();
+}
+
+/// Calls back on the main thread's run loop after displays are connected,
+/// disconnected or rearranged. A single change may call back several times,
+/// once per display it affects.
+pub struct DisplayWatcher {
+    callback: *mut Callback,
+}
+
+impl DisplayWatcher {
+    pub fn new(f: impl FnMut() + 'static) -> Self {
+        let callback: *mut Callback = Box::into_raw(Box::new(Box::new(f)));
+        unsafe {
+            CGDisplayRegisterReconfigurationCallback(
+                reconfiguration_callback,
+                callback as *mut c_void,
+            );
+        }
+        Self { callback }
+    }
+}
+
+impl Drop for DisplayWatcher {
+    fn drop(&mut self) {
+        unsafe {
+            CGDisplayRemoveReconfigurationCallback(
+                reconfiguration_callback,
+                self.callback as *mut c_void,
+            );
+            drop(Box::from_raw(self.callback));
+        }
+    }
+}
+
+impl std::fmt::Debug for DisplayWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DisplayWatcher")
+    }
+}
+
+/// A display's UUID, which unlike its id stays the same when it is
+/// reconnected. Only known while the display is connected.
+pub fn display_uuid(display_id: u32) -> Option<String> {
+    unsafe {
+        let uuid = CGDisplayCreateUUIDFromDisplayID(display_id);
+        if uuid.is_null() {
+            return None;
+        }
+        let s = CFUUIDCreateString(std::ptr::null(), uuid);
+        CFRelease(uuid);
+        if s.is_null() {
+            return None;
+        }
+        Some(CFString::wrap_under_create_rule(s).to_string())
+    }
+}
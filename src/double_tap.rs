@@ -0,0 +1,127 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use core_graphics::event::{CGEvent, CGEventFlags, EventField};
+
+use crate::{
+    action::Action,
+    config::{modifier_flags, DoubleTapConfig},
+};
+
+/// The key code of a modifier key by name, and the flag it sets.
+fn modifier_key(name: &str) -> Option<(i64, CGEventFlags)> {
+    use CGEventFlags::*;
+    Some(match name.to_ascii_lowercase().as_str() {
+        "right_cmd" => (54, CGEventFlagCommand),
+        "left_cmd" => (55, CGEventFlagCommand),
+        "left_shift" => (56, CGEventFlagShift),
+        "left_alt" | "left_opt" => (58, CGEventFlagAlternate),
+        "left_ctrl" => (59, CGEventFlagControl),
+        "right_shift" => (60, CGEventFlagShift),
+        "right_alt" | "right_opt" => (61, CGEventFlagAlternate),
+        "right_ctrl" => (62, CGEventFlagControl),
+        _ => return None,
+    })
+}
+
+/// A modifier key bound to an action when tapped twice.
+#[derive(Debug)]
+struct Binding {
+    keycode: i64,
+    flag: CGEventFlags,
+    id: String,
+    args: Vec<String>,
+}
+
+/// Actions run by tapping a modifier key twice on its own, e.g. right
+/// <cmd>. Each tap, and the gap between them, must be shorter than the
+/// interval.
+#[derive(Debug, Default)]
+pub struct DoubleTaps {
+    interval: Duration,
+    bindings: Vec<Binding>,
+    /// The binding whose key is held on its own, and since when.
+    down: Option<(usize, Instant)>,
+    /// The binding whose key was just tapped, and when it was released.
+    tapped: Option<(usize, Instant)>,
+}
+
+impl DoubleTaps {
+    pub fn new(config: &DoubleTapConfig) -> Result<Self> {
+        let mut bindings = vec![];
+        for (key, command) in config.keys.iter() {
+            let (keycode, flag) = modifier_key(key).ok_or_else(|| {
+                anyhow!(
+                    "Expected a modifier key, e.g. \"right_cmd\", in double_tap.keys, got {:?}",
+                    key
+                )
+            })?;
+            let mut words = command.split_whitespace();
+            let id = words.next().unwrap_or("").to_string();
+            let args: Vec<String> = words.map(|w| w.to_string()).collect();
+            Action::parse(&id, &args)
+                .with_context(|| format!("In double-tap action {:?}", command))?;
+            bindings.push(Binding {
+                keycode,
+                flag,
+                id,
+                args,
+            });
+        }
+        Ok(Self {
+            interval: Duration::from_secs_f64(config.interval),
+            bindings,
+            down: None,
+            tapped: None,
+        })
+    }
+
+    /// Feed a `FlagsChanged` event. Returns the action bound to the key if
+    /// this completes a double tap.
+    pub fn flags_changed(&mut self, event: &CGEvent) -> Option<Action> {
+        if self.bindings.is_empty() {
+            return None;
+        }
+        let keycode = event.get_integer_value_field(EventField::KEYBOARD_EVENT_KEYCODE);
+        let flags = modifier_flags(event.get_flags());
+        let idx = self.bindings.iter().position(|b| b.keycode == keycode);
+        match (idx, self.down.take()) {
+            // Pressed on its own.
+            (Some(idx), _) if flags == self.bindings[idx].flag => {
+                self.down = Some((idx, Instant::now()));
+                None
+            }
+            // Released quickly, with nothing else held in between.
+            (Some(idx), Some((down_idx, since)))
+                if idx == down_idx
+                    && flags == CGEventFlags::CGEventFlagNull
+                    && since.elapsed() <= self.interval =>
+            {
+                match self.tapped.take() {
+                    Some((tapped_idx, at))
+                        if tapped_idx == idx && at.elapsed() <= self.interval =>
+                    {
+                        let b = &self.bindings[idx];
+                        Action::parse(&b.id, &b.args)
+                            .map_err(|e| eprintln!("While parsing double-tap action: {:?}", e))
+                            .ok()
+                    }
+                    _ => {
+                        self.tapped = Some((idx, Instant::now()));
+                        None
+                    }
+                }
+            }
+            _ => {
+                self.tapped = None;
+                None
+            }
+        }
+    }
+
+    /// A key press or click between taps means they weren't a double tap.
+    pub fn interrupt(&mut self) {
+        self.down = None;
+        self.tapped = None;
+    }
+}
@@ -1,58 +1,114 @@
-use accessibility::AXUIElement;
+use std::{cell::Cell, rc::Rc};
+
 use anyhow::{anyhow, Result};
 use core_graphics::{
-    event::CGEvent,
+    event::{CGEvent, CGEventFlags},
     event_source::{CGEventSource, CGEventSourceStateID},
-    geometry::CGPoint,
+    geometry::{CGPoint, CGRect},
 };
 
-use crate::window::{Window, WindowWrapper};
+use crate::window_system::{DynWindowSystem, WindowHandle};
 
 #[derive(Debug)]
 pub struct DragWindow {
-    window: WindowWrapper<AXUIElement>,
+    system: Rc<DynWindowSystem>,
+    window: WindowHandle,
     mouse_offset: CGPoint,
+    /// How far past the edge of `display` the mouse must go before the
+    /// window follows it onto another display.
+    resistance: f64,
+    /// Bounds of the display the window is being dragged on.
+    display: Cell<Option<CGRect>>,
+}
+
+fn display_bounds_at(system: &DynWindowSystem, point: &CGPoint) -> Option<CGRect> {
+    let display_id = system.display_at_point(point).ok()??;
+    Some(system.display_bounds(display_id))
+}
+
+/// How far `point` is outside `bounds`, horizontally or vertically.
+fn distance_outside(bounds: &CGRect, point: &CGPoint) -> f64 {
+    let dx = f64::max(
+        bounds.origin.x - point.x,
+        point.x - (bounds.origin.x + bounds.size.width),
+    );
+    let dy = f64::max(
+        bounds.origin.y - point.y,
+        point.y - (bounds.origin.y + bounds.size.height),
+    );
+    f64::max(f64::max(dx, dy), 0.)
 }
 
-fn get_mouse_location() -> Result<CGPoint> {
+pub(crate) fn get_mouse_location() -> Result<CGPoint> {
     let event_source = CGEventSource::new(CGEventSourceStateID::CombinedSessionState)
         .map_err(|()| anyhow!("Failed to create CGEventSource"))?;
     let event = CGEvent::new(event_source).map_err(|()| anyhow!("Failed to create GCEvent"))?;
     Ok(event.location())
 }
 
+/// The modifier keys held now.
+pub(crate) fn get_modifier_flags() -> Result<CGEventFlags> {
+    let event_source = CGEventSource::new(CGEventSourceStateID::CombinedSessionState)
+        .map_err(|()| anyhow!("Failed to create CGEventSource"))?;
+    let event = CGEvent::new(event_source).map_err(|()| anyhow!("Failed to create GCEvent"))?;
+    Ok(event.get_flags())
+}
+
 impl DragWindow {
-    fn new(window: WindowWrapper<AXUIElement>, mouse_offset: CGPoint) -> Self {
-        Self {
+    pub(crate) fn new(
+        system: Rc<DynWindowSystem>,
+        window: WindowHandle,
+        mouse_location: &CGPoint,
+        resistance: f64,
+    ) -> Result<Self> {
+        let window_pos = system.frame(&window)?.origin;
+        let mouse_offset = CGPoint::new(
+            mouse_location.x - window_pos.x,
+            mouse_location.y - window_pos.y,
+        );
+        let display = Cell::new(display_bounds_at(&*system, mouse_location));
+        Ok(Self {
+            system,
             window,
             mouse_offset,
-        }
+            resistance,
+            display,
+        })
     }
 
-    pub fn at_mouse_location() -> Result<Option<Self>> {
-        let mouse_location = get_mouse_location()?;
-        let window = WindowWrapper::at_point(&mouse_location)?;
-        match window {
-            None => Ok(None),
-            Some(window) => {
-                let window_pos: CGPoint = window.position()?;
-                let mouse_offset = CGPoint::new(
-                    mouse_location.x - window_pos.x,
-                    mouse_location.y - window_pos.y,
-                );
-                Ok(Some(Self::new(window, mouse_offset)))
+    /// Where the window should follow the mouse to: the mouse is held at the
+    /// edge of the display the window is on until it has gone `resistance`
+    /// past it, so that the window doesn't hop displays by accident.
+    pub fn resisted_point(&self, point: &CGPoint) -> CGPoint {
+        let d = match self.display.get() {
+            Some(d) => d,
+            None => return *point,
+        };
+        let outside = distance_outside(&d, point);
+        if outside == 0. {
+            return *point;
+        }
+        if outside > self.resistance {
+            if let Some(bounds) = display_bounds_at(&*self.system, point) {
+                self.display.set(Some(bounds));
+                return *point;
             }
         }
+        CGPoint::new(
+            point.x.clamp(d.origin.x, d.origin.x + d.size.width - 1.),
+            point.y.clamp(d.origin.y, d.origin.y + d.size.height - 1.),
+        )
+    }
+
+    pub(crate) fn window(&self) -> &WindowHandle {
+        &self.window
     }
 
     pub fn set_position_around(&self, point: &CGPoint) -> Result<()> {
+        let point = self.resisted_point(point);
         let x = point.x - self.mouse_offset.x;
         let y = point.y - self.mouse_offset.y;
 
-        self.window.set_position(CGPoint::new(x, y))
-    }
-
-    pub fn activate_window(&self) -> Result<()> {
-        self.window.activate()
+        self.system.set_position(&self.window, CGPoint::new(x, y))
     }
 }
@@ -0,0 +1,335 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    mem,
+};
+
+use anyhow::{anyhow, Result};
+use core_graphics::{
+    geometry::{CGPoint, CGRect},
+    window::CGWindowID,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    window::ActivationStep,
+    window_system::{DynWindowSystem, PendingWindows, WindowHandle, WindowSystem},
+};
+
+/// How many frame changes are kept for IPC to fetch, when nothing fetches
+/// them. The oldest are dropped first.
+const MAX_FRAME_CHANGES: usize = 1000;
+
+/// A frame a window would have been given, outside of dry-run mode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameChange {
+    pub pid: i32,
+    pub title: Option<String>,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl std::fmt::Display for FrameChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {:?}: ({}, {}) {}x{}",
+            self.pid,
+            self.title.as_deref().unwrap_or(""),
+            self.x,
+            self.y,
+            self.width,
+            self.height
+        )
+    }
+}
+
+/// Wraps a window system so that windows are never modified: they are read
+/// from it as usual, but changes to them are only logged. In read-only
+/// (spectator) mode that is all. In dry-run mode the frames windows would
+/// have been given are also remembered and read back as their frames, so
+/// layouts behave as if the windows had moved, and the changes can be
+/// fetched over IPC.
+#[derive(Debug)]
+pub struct DryRunWindowSystem {
+    inner: Box<DynWindowSystem>,
+    simulate: bool,
+    /// The frames windows were given, by window number.
+    simulated_frames: RefCell<HashMap<CGWindowID, CGRect>>,
+    frame_changes: RefCell<Vec<FrameChange>>,
+}
+
+impl DryRunWindowSystem {
+    /// Only log changes to the windows of `inner`.
+    pub fn read_only(inner: Box<DynWindowSystem>) -> Self {
+        Self::new(inner, false)
+    }
+
+    /// Log changes to the windows of `inner`, and read back the frames they
+    /// would have been given.
+    pub fn dry_run(inner: Box<DynWindowSystem>) -> Self {
+        Self::new(inner, true)
+    }
+
+    fn new(inner: Box<DynWindowSystem>, simulate: bool) -> Self {
+        Self {
+            inner,
+            simulate,
+            simulated_frames: RefCell::new(HashMap::new()),
+            frame_changes: RefCell::new(vec![]),
+        }
+    }
+
+    /// The frame `window` was last given in dry-run mode.
+    fn simulated_frame(&self, window: &WindowHandle) -> Option<CGRect> {
+        let window_id = self.inner.window_number(window).ok()?;
+        self.simulated_frames.borrow().get(&window_id).copied()
+    }
+
+    /// Remember that `window` would have been given `frame`.
+    fn record_frame(&self, window: &WindowHandle, frame: CGRect) -> Result<()> {
+        let change = FrameChange {
+            pid: self.inner.pid(window)?,
+            title: self.inner.title(window).ok(),
+            x: frame.origin.x,
+            y: frame.origin.y,
+            width: frame.size.width,
+            height: frame.size.height,
+        };
+        println!("[dry-run] {}", change);
+        if let Ok(window_id) = self.inner.window_number(window) {
+            self.simulated_frames.borrow_mut().insert(window_id, frame);
+        }
+        let mut changes = self.frame_changes.borrow_mut();
+        changes.push(change);
+        if changes.len() > MAX_FRAME_CHANGES {
+            let excess = changes.len() - MAX_FRAME_CHANGES;
+            changes.drain(..excess);
+        }
+        Ok(())
+    }
+}
+
+impl WindowSystem for DryRunWindowSystem {
+    type Window = WindowHandle;
+
+    fn windows(&self) -> Result<(Vec<Self::Window>, Vec<Self::Window>)> {
+        self.inner.windows()
+    }
+
+    fn start_windows(&self) -> Result<PendingWindows<Self::Window>> {
+        self.inner.start_windows()
+    }
+
+    fn finish_windows(
+        &self,
+        pending: &PendingWindows<Self::Window>,
+    ) -> Option<Result<(Vec<Self::Window>, Vec<Self::Window>)>> {
+        self.inner.finish_windows(pending)
+    }
+
+    fn unresponsive_pids(&self) -> HashSet<i32> {
+        self.inner.unresponsive_pids()
+    }
+
+    fn focused_window(&self) -> Result<Self::Window> {
+        self.inner.focused_window()
+    }
+
+    fn window_at_point(&self, point: &CGPoint) -> Result<Option<Self::Window>> {
+        self.inner.window_at_point(point)
+    }
+
+    fn is_same_window(&self, a: &Self::Window, b: &Self::Window) -> Result<bool> {
+        self.inner.is_same_window(a, b)
+    }
+
+    fn frame(&self, window: &Self::Window) -> Result<CGRect> {
+        match self.simulated_frame(window) {
+            Some(frame) => Ok(frame),
+            None => self.inner.frame(window),
+        }
+    }
+
+    fn set_frame(&self, window: &Self::Window, frame: CGRect) -> Result<()> {
+        if self.simulate {
+            return self.record_frame(window, frame);
+        }
+        println!("[read-only] set_frame {:?}", frame);
+        Ok(())
+    }
+
+    fn set_position(&self, window: &Self::Window, position: CGPoint) -> Result<()> {
+        if self.simulate {
+            let size = self.frame(window)?.size;
+            return self.record_frame(window, CGRect::new(&position, &size));
+        }
+        println!("[read-only] set_position {:?}", position);
+        Ok(())
+    }
+
+    fn activate(&self, _window: &Self::Window, steps: &[ActivationStep]) -> Result<()> {
+        println!("[read-only] activate {:?}", steps);
+        Ok(())
+    }
+
+    fn pid(&self, window: &Self::Window) -> Result<i32> {
+        self.inner.pid(window)
+    }
+
+    fn title(&self, window: &Self::Window) -> Result<String> {
+        self.inner.title(window)
+    }
+
+    fn bundle_identifier(&self, window: &Self::Window) -> Result<Option<String>> {
+        self.inner.bundle_identifier(window)
+    }
+
+    fn app_name(&self, window: &Self::Window) -> Result<Option<String>> {
+        self.inner.app_name(window)
+    }
+
+    fn subrole(&self, window: &Self::Window) -> Option<String> {
+        self.inner.subrole(window)
+    }
+
+    fn is_modal(&self, window: &Self::Window) -> bool {
+        self.inner.is_modal(window)
+    }
+
+    fn is_main(&self, window: &Self::Window) -> Result<bool> {
+        self.inner.is_main(window)
+    }
+
+    fn is_frontmost_and_main(&self, window: &Self::Window) -> Result<bool> {
+        self.inner.is_frontmost_and_main(window)
+    }
+
+    fn window_number(&self, window: &Self::Window) -> Result<CGWindowID> {
+        self.inner.window_number(window)
+    }
+
+    fn close(&self, window: &Self::Window) -> Result<()> {
+        println!("[read-only] close {:?}", self.inner.title(window));
+        Ok(())
+    }
+
+    fn zoom(&self, window: &Self::Window) -> Result<()> {
+        println!("[read-only] zoom {:?}", self.inner.title(window));
+        Ok(())
+    }
+
+    fn toggle_full_screen(&self, window: &Self::Window) -> Result<()> {
+        println!(
+            "[read-only] toggle full screen {:?}",
+            self.inner.title(window)
+        );
+        Ok(())
+    }
+
+    fn set_minimized(&self, _window: &Self::Window, minimized: bool) -> Result<()> {
+        println!("[read-only] set_minimized {}", minimized);
+        Ok(())
+    }
+
+    fn quit_app(&self, window: &Self::Window) -> Result<()> {
+        println!("[read-only] quit app {}", self.inner.pid(window)?);
+        Ok(())
+    }
+
+    fn force_quit_app(&self, pid: i32) -> Result<()> {
+        println!("[read-only] force quit app {}", pid);
+        Ok(())
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn is_dry_run(&self) -> bool {
+        self.simulate
+    }
+
+    fn take_frame_changes(&self) -> Vec<FrameChange> {
+        mem::take(&mut *self.frame_changes.borrow_mut())
+    }
+
+    fn display_ids(&self) -> Result<Vec<u32>> {
+        self.inner.display_ids()
+    }
+
+    fn main_display_id(&self) -> u32 {
+        self.inner.main_display_id()
+    }
+
+    fn display_uuid(&self, display_id: u32) -> Option<String> {
+        self.inner.display_uuid(display_id)
+    }
+
+    fn display_bounds(&self, display_id: u32) -> CGRect {
+        self.inner.display_bounds(display_id)
+    }
+
+    fn display_of(&self, window: &Self::Window) -> Result<u32> {
+        match self.simulated_frame(window) {
+            Some(frame) => self
+                .inner
+                .display_at_point(&frame.origin)?
+                .ok_or_else(|| anyhow!("Window is off-screen")),
+            None => self.inner.display_of(window),
+        }
+    }
+
+    fn display_at_point(&self, point: &CGPoint) -> Result<Option<u32>> {
+        self.inner.display_at_point(point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core_graphics::geometry::CGSize;
+
+    use super::*;
+    use crate::{layout::same_frame, window_system::InMemoryWindowSystem};
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> CGRect {
+        CGRect::new(&CGPoint::new(x, y), &CGSize::new(width, height))
+    }
+
+    #[test]
+    fn dry_run_reads_back_frames_without_moving_windows() {
+        let inner = InMemoryWindowSystem::new(&[(1, rect(0., 0., 1000., 800.))]);
+        let pid = inner.open_window(rect(100., 100., 600., 400.));
+        let system = DryRunWindowSystem::dry_run(Box::new(inner.clone()));
+        let window = system.focused_window().unwrap();
+        system.set_frame(&window, rect(0., 0., 500., 800.)).unwrap();
+
+        let before = rect(100., 100., 600., 400.);
+        assert!(same_frame(
+            &system.frame(&window).unwrap(),
+            &rect(0., 0., 500., 800.)
+        ));
+        assert!(same_frame(&inner.frame_of(pid).unwrap(), &before));
+        let changes = system.take_frame_changes();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].pid, pid);
+        assert!(system.take_frame_changes().is_empty());
+    }
+
+    #[test]
+    fn read_only_leaves_frames_and_windows_alone() {
+        let inner = InMemoryWindowSystem::new(&[(1, rect(0., 0., 1000., 800.))]);
+        let pid = inner.open_window(rect(100., 100., 600., 400.));
+        let system = DryRunWindowSystem::read_only(Box::new(inner.clone()));
+        let window = system.focused_window().unwrap();
+        system.set_frame(&window, rect(0., 0., 500., 800.)).unwrap();
+        system.close(&window).unwrap();
+
+        let before = rect(100., 100., 600., 400.);
+        assert!(same_frame(&inner.frame_of(pid).unwrap(), &before));
+        assert!(same_frame(&system.frame(&window).unwrap(), &before));
+        assert!(system.take_frame_changes().is_empty());
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    process::{Command, Stdio},
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::{config::HooksConfig, notification_observer::post_distributed_notification};
+
+/// Prefixed to event names to name their distributed notifications.
+const NOTIFICATION_PREFIX: &str = "com.github.mattjbray.awesome-rs.";
+
+/// Something that changed in the window manager, passed to hook commands as
+/// JSON.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum HookEvent {
+    WindowCreated {
+        window_id: uuid::Uuid,
+        app: Option<String>,
+        title: Option<String>,
+    },
+    FocusChanged {
+        app: Option<String>,
+        title: Option<String>,
+        display_id: Option<u32>,
+        group: Option<u8>,
+    },
+    GroupShown {
+        display_id: u32,
+        group: u8,
+    },
+    DisplayAdded {
+        display_id: u32,
+    },
+    LayoutChanged {
+        display_id: u32,
+        group: u8,
+        layout: String,
+    },
+    ModeChanged {
+        mode: &'static str,
+    },
+}
+
+impl HookEvent {
+    /// The window the event is about, if it is about one.
+    pub fn window_id(&self) -> Option<uuid::Uuid> {
+        match self {
+            HookEvent::WindowCreated { window_id, .. } => Some(*window_id),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::WindowCreated { .. } => "window-created",
+            HookEvent::FocusChanged { .. } => "focus-changed",
+            HookEvent::GroupShown { .. } => "group-shown",
+            HookEvent::DisplayAdded { .. } => "display-added",
+            HookEvent::LayoutChanged { .. } => "layout-changed",
+            HookEvent::ModeChanged { .. } => "mode-changed",
+        }
+    }
+}
+
+/// What hooks are told about, as of one moment.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HookState {
+    pub mode: &'static str,
+    /// The active window's id, app and title.
+    pub focused: Option<(uuid::Uuid, Option<String>, Option<String>)>,
+    pub active_display_id: Option<u32>,
+    /// Each display's active group.
+    pub active_groups: HashMap<u32, u8>,
+    /// The layout of each display's groups.
+    pub layouts: HashMap<(u32, u8), String>,
+    pub display_ids: Vec<u32>,
+    /// The id, app and title of windows that appeared since the last state.
+    pub created: Vec<(uuid::Uuid, Option<String>, Option<String>)>,
+}
+
+impl HookState {
+    /// The events that take the window manager from `self` to `next`.
+    fn events_to(&self, next: &HookState) -> Vec<HookEvent> {
+        let mut events = vec![];
+        let known: HashSet<&u32> = self.display_ids.iter().collect();
+        for display_id in next.display_ids.iter() {
+            if !known.contains(display_id) {
+                events.push(HookEvent::DisplayAdded {
+                    display_id: *display_id,
+                });
+            }
+        }
+        for (window_id, app, title) in next.created.iter() {
+            events.push(HookEvent::WindowCreated {
+                window_id: *window_id,
+                app: app.clone(),
+                title: title.clone(),
+            });
+        }
+        if next.mode != self.mode {
+            events.push(HookEvent::ModeChanged { mode: next.mode });
+        }
+        for (display_id, group) in next.active_groups.iter() {
+            if self.active_groups.get(display_id) != Some(group) {
+                events.push(HookEvent::GroupShown {
+                    display_id: *display_id,
+                    group: *group,
+                });
+            }
+        }
+        for ((display_id, group), layout) in next.layouts.iter() {
+            // New groups aren't a change of layout.
+            if matches!(self.layouts.get(&(*display_id, *group)), Some(l) if l != layout) {
+                events.push(HookEvent::LayoutChanged {
+                    display_id: *display_id,
+                    group: *group,
+                    layout: layout.clone(),
+                });
+            }
+        }
+        let focused_id = |s: &HookState| s.focused.as_ref().map(|(id, _, _)| *id);
+        if focused_id(next) != focused_id(self) {
+            let (app, title) = match &next.focused {
+                Some((_, app, title)) => (app.clone(), title.clone()),
+                None => (None, None),
+            };
+            events.push(HookEvent::FocusChanged {
+                app,
+                title,
+                display_id: next.active_display_id,
+                group: next
+                    .active_display_id
+                    .and_then(|d_id| next.active_groups.get(&d_id).copied()),
+            });
+        }
+        events
+    }
+}
+
+/// Runs the configured commands when the window manager's state changes.
+#[derive(Debug, Default)]
+pub struct Hooks {
+    commands: HashMap<&'static str, Vec<String>>,
+    /// Post events as distributed notifications.
+    distributed: bool,
+    last: Option<HookState>,
+}
+
+impl Hooks {
+    pub fn new(config: &HooksConfig) -> Self {
+        let commands = [
+            ("window-created", &config.window_created),
+            ("focus-changed", &config.focus_changed),
+            ("group-shown", &config.group_shown),
+            ("display-added", &config.display_added),
+            ("layout-changed", &config.layout_changed),
+            ("mode-changed", &config.mode_changed),
+        ]
+        .into_iter()
+        .filter(|(_, commands)| !commands.is_empty())
+        .map(|(name, commands)| (name, commands.clone()))
+        .collect();
+        Self {
+            commands,
+            distributed: config.distributed_notifications,
+            last: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty() && !self.distributed
+    }
+
+    /// Run the hooks for whatever changed since the last call, and return
+    /// what changed. The first call only records the state.
+    pub fn update(&mut self, state: HookState) -> Vec<HookEvent> {
+        let events = match &self.last {
+            Some(last) => last.events_to(&state),
+            None => vec![],
+        };
+        for event in events.iter() {
+            for command in self.commands.get(event.name()).into_iter().flatten() {
+                run(command, event)
+                    .unwrap_or_else(|e| eprintln!("While running {} hook: {:?}", event.name(), e));
+            }
+            if self.distributed {
+                post(event).unwrap_or_else(|e| {
+                    eprintln!("While posting {} notification: {:?}", event.name(), e)
+                });
+            }
+        }
+        self.last = Some(state);
+        events
+    }
+}
+
+/// Run `command` with `sh -c`, without waiting for it. The event is written
+/// to its stdin and is also in `$AWESOME_EVENT_JSON`, with its name in
+/// `$AWESOME_EVENT`.
+fn run(command: &str, event: &HookEvent) -> Result<()> {
+    let json = serde_json::to_string(event)?;
+    let mut child = Command::new("/bin/sh")
+        .arg("-c")
+        .arg(command)
+        .env("AWESOME_EVENT", event.name())
+        .env("AWESOME_EVENT_JSON", &json)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("Could not run {:?}: {}", command, e))?;
+    let stdin = child.stdin.take();
+    thread::spawn(move || {
+        if let Some(mut stdin) = stdin {
+            // The command may not read it.
+            let _ = stdin.write_all(json.as_bytes());
+        }
+        child.wait()
+    });
+    Ok(())
+}
+
+/// Post the event as a distributed notification, with its fields as strings
+/// in `userInfo`. Missing values are left out.
+fn post(event: &HookEvent) -> Result<()> {
+    let fields = match serde_json::to_value(event)? {
+        serde_json::Value::Object(fields) => fields,
+        value => return Err(anyhow!("Expected an object, got {}", value)),
+    };
+    let user_info: Vec<(&str, String)> = fields
+        .iter()
+        .filter_map(|(key, value)| match value {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(s) => Some((key.as_str(), s.clone())),
+            value => Some((key.as_str(), value.to_string())),
+        })
+        .collect();
+    post_distributed_notification(
+        &format!("{}{}", NOTIFICATION_PREFIX, event.name()),
+        &user_info,
+    );
+    Ok(())
+}
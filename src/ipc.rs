@@ -0,0 +1,360 @@
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::IpcConfig, dry_run::FrameChange, state::SavedState, stats::FocusReport};
+
+/// The version of the request and response schema. It is only bumped for
+/// incompatible changes, such as removing or renaming a command or field, or
+/// changing what one means. New commands, new responses and new optional
+/// fields don't bump it, so clients must ignore fields they don't know.
+///
+/// Clients send it as a `"version"` field next to `"command"`, and every
+/// response carries it as a `"version"` field next to `"response"`, e.g.
+///
+/// ```text
+/// > {"version": 1, "command": "action", "id": "show-group", "args": ["2"]}
+/// < {"version": 1, "response": "ok"}
+/// ```
+///
+/// A request with a different version is answered with an error rather than
+/// risk being misunderstood. A request without one is assumed to be current,
+/// for scripts written by hand.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Requests are sent as one JSON object per line, and each is answered with
+/// one JSON `Response` line.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    /// Must be the first request on a TCP connection.
+    Auth {
+        token: String,
+    },
+    Ping,
+    /// Perform an action by its id in `ACTIONS`.
+    Action {
+        id: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    /// Perform several actions, updating layouts, focus and the highlight
+    /// once at the end. If any action is unknown none are performed.
+    Batch {
+        actions: Vec<ActionCall>,
+    },
+    /// Name a group on every display, or clear its name if `name` is
+    /// missing.
+    SetGroupName {
+        group: u8,
+        #[serde(default)]
+        name: Option<String>,
+    },
+    /// Shut down, replying with the current state so a new instance can take
+    /// over.
+    Shutdown,
+    /// Set a group's primary column on every display, leaving settings that
+    /// are missing as they are. A group that isn't tiled uses them once it
+    /// is, and one that doesn't exist yet starts with them.
+    SetGroupPrimary {
+        group: u8,
+        /// The column's width, 10-90% of the display's.
+        #[serde(default)]
+        pct: Option<u8>,
+        /// How many windows the column holds, at least 1.
+        #[serde(default)]
+        max_windows: Option<i32>,
+    },
+    /// Time spent focused on each group and app this session.
+    Stats,
+    /// The groups and their windows, as handed over to a new instance.
+    State,
+    /// The frames windows would have been given since the last request, in
+    /// dry-run mode, up to the last 1000.
+    FrameChanges,
+}
+
+/// An action in a `Request::Batch`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionCall {
+    pub id: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "kebab-case")]
+pub enum Response {
+    Ok,
+    Pong,
+    State(SavedState),
+    Stats(FocusReport),
+    FrameChanges(Vec<FrameChange>),
+    Error { message: String },
+}
+
+/// A request or response with the protocol version it was written for.
+#[derive(Debug, Serialize, Deserialize)]
+struct Versioned<T> {
+    /// Missing in responses from servers older than versioning, which are
+    /// treated as version 0.
+    #[serde(default)]
+    version: Option<u32>,
+    #[serde(flatten)]
+    body: T,
+}
+
+impl<T> Versioned<T> {
+    fn current(body: T) -> Self {
+        Self {
+            version: Some(PROTOCOL_VERSION),
+            body,
+        }
+    }
+}
+
+pub type Reply = mpsc::Sender<Response>;
+
+pub fn socket_path() -> PathBuf {
+    let user = std::env::var("USER").unwrap_or_default();
+    std::env::temp_dir().join(format!("awesome-rs-{}.sock", user))
+}
+
+fn write_request(stream: &mut impl Write, request: &Request) -> Result<()> {
+    serde_json::to_writer(&mut *stream, &Versioned::current(request))?;
+    stream.write_all(b"\n")?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn read_response(reader: &mut impl BufRead) -> Result<Response> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let response: Versioned<Response> = serde_json::from_str(&line)?;
+    let version = response.version.unwrap_or(0);
+    if version != PROTOCOL_VERSION {
+        return Err(anyhow!(
+            "awesome-rs speaks IPC version {}, but this client speaks version {}; \
+             restart awesome-rs or update the client",
+            version,
+            PROTOCOL_VERSION
+        ));
+    }
+    Ok(response.body)
+}
+
+/// Send a single request to the running instance.
+///
+/// If `AWESOME_RS_TCP` is set (e.g. to `127.0.0.1:7475`), connect there and
+/// authenticate with `AWESOME_RS_TOKEN` instead of using the Unix socket.
+pub fn send_request(request: &Request) -> Result<Response> {
+    match std::env::var("AWESOME_RS_TCP") {
+        Ok(addr) => {
+            let token = std::env::var("AWESOME_RS_TOKEN")
+                .map_err(|_| anyhow!("AWESOME_RS_TOKEN must be set with AWESOME_RS_TCP"))?;
+            let mut stream = TcpStream::connect(addr)?;
+            let mut reader = BufReader::new(stream.try_clone()?);
+            write_request(&mut stream, &Request::Auth { token })?;
+            if let Response::Error { message } = read_response(&mut reader)? {
+                return Err(anyhow!(message));
+            }
+            write_request(&mut stream, request)?;
+            read_response(&mut reader)
+        }
+        Err(_) => {
+            let mut stream = UnixStream::connect(socket_path())?;
+            write_request(&mut stream, request)?;
+            read_response(&mut BufReader::new(stream))
+        }
+    }
+}
+
+/// Accepts connections on background threads. Requests must be handled on the
+/// main thread: poll them with `try_recv` and answer through the `Reply`.
+pub struct IpcServer {
+    sender: mpsc::Sender<(Request, Reply)>,
+    receiver: mpsc::Receiver<(Request, Reply)>,
+    /// Set once the reply to a `Shutdown` request has been sent.
+    shutdown: Arc<AtomicBool>,
+}
+
+impl IpcServer {
+    pub fn bind() -> Result<Self> {
+        let path = socket_path();
+        // Nobody answered on this socket, so it was left behind by an instance
+        // that didn't shut down cleanly.
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        let (sender, receiver) = mpsc::channel();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let client_sender = sender.clone();
+        let client_shutdown = shutdown.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let sender = client_sender.clone();
+                        let shutdown = client_shutdown.clone();
+                        thread::spawn(move || {
+                            let reader = match stream.try_clone() {
+                                Ok(reader) => reader,
+                                Err(e) => return eprintln!("IPC client error: {:?}", e),
+                            };
+                            handle_client(reader, stream, sender, &shutdown, None)
+                                .unwrap_or_else(|e| eprintln!("IPC client error: {:?}", e))
+                        });
+                    }
+                    Err(e) => eprintln!("IPC connection failed: {:?}", e),
+                }
+            }
+        });
+        Ok(Self {
+            sender,
+            receiver,
+            shutdown,
+        })
+    }
+
+    /// Also accept requests on a localhost TCP port, if configured. Each TCP
+    /// connection must authenticate with the configured token first.
+    pub fn bind_tcp(&self, config: &IpcConfig) -> Result<()> {
+        let port = match config.tcp_port {
+            Some(port) => port,
+            None => return Ok(()),
+        };
+        let token = match &config.token {
+            Some(token) if !token.is_empty() => token.clone(),
+            _ => return Err(anyhow!("ipc.token must be set to use ipc.tcp_port")),
+        };
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        println!("Listening for IPC on 127.0.0.1:{}", port);
+        let sender = self.sender.clone();
+        let shutdown = self.shutdown.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let sender = sender.clone();
+                        let shutdown = shutdown.clone();
+                        let token = token.clone();
+                        thread::spawn(move || {
+                            let reader = match stream.try_clone() {
+                                Ok(reader) => reader,
+                                Err(e) => return eprintln!("IPC client error: {:?}", e),
+                            };
+                            handle_client(reader, stream, sender, &shutdown, Some(&token))
+                                .unwrap_or_else(|e| eprintln!("IPC client error: {:?}", e))
+                        });
+                    }
+                    Err(e) => eprintln!("IPC connection failed: {:?}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    pub fn try_recv(&self) -> Option<(Request, Reply)> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Whether a client asked us to shut down and has been answered, so
+    /// that the main thread should quit.
+    pub fn shutdown_requested(&self) -> bool {
+        self.shutdown.load(Ordering::Relaxed)
+    }
+}
+
+/// Compare tokens without returning early on the first mismatched byte.
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// Answer requests from one client. If `token` is set, the client must send a
+/// matching `Auth` request before anything else. Once a `Shutdown` request is
+/// answered, `shutdown` is set.
+fn handle_client(
+    reader: impl Read,
+    mut writer: impl Write,
+    sender: mpsc::Sender<(Request, Reply)>,
+    shutdown: &AtomicBool,
+    token: Option<&str>,
+) -> Result<()> {
+    let reader = BufReader::new(reader);
+    let mut authenticated = token.is_none();
+    for line in reader.lines() {
+        let line = line?;
+        let mut is_shutdown = false;
+        let response = match serde_json::from_str::<Versioned<Request>>(&line) {
+            Ok(Versioned {
+                version: Some(version),
+                ..
+            }) if version != PROTOCOL_VERSION => {
+                // Answer anyway, so the client can report the mismatch.
+                Response::Error {
+                    message: format!(
+                        "Client speaks IPC version {}, but awesome-rs speaks version {}",
+                        version, PROTOCOL_VERSION
+                    ),
+                }
+            }
+            Ok(Versioned {
+                body: Request::Auth { token: given },
+                ..
+            }) => {
+                authenticated = token.map_or(true, |token| tokens_match(token, &given));
+                if authenticated {
+                    Response::Ok
+                } else {
+                    Response::Error {
+                        message: "Invalid token".to_string(),
+                    }
+                }
+            }
+            Ok(_) if !authenticated => Response::Error {
+                message: "Not authenticated".to_string(),
+            },
+            Ok(Versioned { body: request, .. }) => {
+                is_shutdown = matches!(request, Request::Shutdown);
+                let (reply, response) = mpsc::channel();
+                sender
+                    .send((request, reply))
+                    .map_err(|_| anyhow!("Window manager is not running"))?;
+                response.recv()?
+            }
+            Err(e) => Response::Error {
+                message: e.to_string(),
+            },
+        };
+        let rejected = !authenticated;
+        serde_json::to_writer(&mut writer, &Versioned::current(response))?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        if rejected {
+            return Ok(());
+        }
+
+        if is_shutdown {
+            shutdown.store(true, Ordering::Relaxed);
+            return Ok(());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,228 @@
+use std::{
+    cell::RefCell,
+    ffi::c_void,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use core_foundation::{
+    base::{CFRelease, CFTypeRef},
+    data::{CFDataGetBytePtr, CFDataRef},
+    string::CFStringRef,
+};
+use core_graphics::event::{CGEvent, EventField};
+
+#[link(name = "Carbon", kind = "framework")]
+extern "C" {
+    static kTISPropertyUnicodeKeyLayoutData: CFStringRef;
+    fn TISCopyCurrentKeyboardLayoutInputSource() -> CFTypeRef;
+    fn TISGetInputSourceProperty(source: CFTypeRef, key: CFStringRef) -> CFDataRef;
+    fn LMGetKbdType() -> u8;
+    fn UCKeyTranslate(
+        layout: *const c_void,
+        virtual_key_code: u16,
+        key_action: u16,
+        modifier_key_state: u32,
+        keyboard_type: u32,
+        key_translate_options: u32,
+        dead_key_state: *mut u32,
+        max_string_length: usize,
+        actual_string_length: *mut usize,
+        unicode_string: *mut u16,
+    ) -> i32;
+}
+
+const K_UC_KEY_ACTION_DOWN: u16 = 0;
+/// kUCKeyTranslateNoDeadKeysMask
+const K_UC_KEY_TRANSLATE_NO_DEAD_KEYS: u32 = 1;
+/// Key codes below this are the main keyboard, keypad and function keys.
+const NUM_KEYCODES: usize = 128;
+
+/// Key names and their key codes on a US (ANSI) keyboard.
+static KEY_NAMES: &[(&str, i64)] = &[
+    ("a", 0),
+    ("s", 1),
+    ("d", 2),
+    ("f", 3),
+    ("h", 4),
+    ("g", 5),
+    ("z", 6),
+    ("x", 7),
+    ("c", 8),
+    ("v", 9),
+    ("b", 11),
+    ("q", 12),
+    ("w", 13),
+    ("e", 14),
+    ("r", 15),
+    ("y", 16),
+    ("t", 17),
+    ("1", 18),
+    ("2", 19),
+    ("3", 20),
+    ("4", 21),
+    ("6", 22),
+    ("5", 23),
+    ("=", 24),
+    ("9", 25),
+    ("7", 26),
+    ("-", 27),
+    ("8", 28),
+    ("0", 29),
+    ("]", 30),
+    ("o", 31),
+    ("u", 32),
+    ("[", 33),
+    ("i", 34),
+    ("p", 35),
+    ("return", 36),
+    ("enter", 36),
+    ("l", 37),
+    ("j", 38),
+    ("'", 39),
+    ("k", 40),
+    (";", 41),
+    ("\\", 42),
+    (",", 43),
+    ("/", 44),
+    ("n", 45),
+    ("m", 46),
+    (".", 47),
+    ("tab", 48),
+    ("space", 49),
+    ("`", 50),
+    ("delete", 51),
+    ("backspace", 51),
+    ("escape", 53),
+    ("esc", 53),
+    ("f1", 122),
+    ("f2", 120),
+    ("f3", 99),
+    ("f4", 118),
+    ("f5", 96),
+    ("f6", 97),
+    ("f7", 98),
+    ("f8", 100),
+    ("f9", 101),
+    ("f10", 109),
+    ("f11", 103),
+    ("f12", 111),
+    ("f13", 105),
+    ("f14", 107),
+    ("f15", 113),
+    ("f16", 106),
+    ("f17", 64),
+    ("f18", 79),
+    ("f19", 80),
+    ("f20", 90),
+    ("left", 123),
+    ("right", 124),
+    ("down", 125),
+    ("up", 126),
+];
+
+static MATCH_BY_POSITION: AtomicBool = AtomicBool::new(false);
+
+/// Match key bindings by where keys are on a US keyboard, rather than by the
+/// character they type in the current keyboard layout.
+pub fn set_match_by_position(by_position: bool) {
+    MATCH_BY_POSITION.store(by_position, Ordering::Relaxed);
+}
+
+/// The key code of a key name, e.g. "j" or "space", on a US keyboard.
+pub fn keycode_of_name(name: &str) -> Option<i64> {
+    KEY_NAMES
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, keycode)| *keycode)
+}
+
+/// Maps the key codes of the current keyboard layout to the US key codes
+/// that type the same characters, for one layout.
+struct Translation {
+    layout: CFDataRef,
+    keycodes: [i64; NUM_KEYCODES],
+}
+
+thread_local! {
+    static TRANSLATION: RefCell<Option<Translation>> = RefCell::new(None);
+}
+
+/// The character `keycode` types in `layout` without modifiers.
+unsafe fn character(layout: CFDataRef, keycode: u16) -> Option<String> {
+    let mut dead_key_state = 0;
+    let mut buf = [0u16; 4];
+    let mut len = 0;
+    let status = UCKeyTranslate(
+        CFDataGetBytePtr(layout) as *const c_void,
+        keycode,
+        K_UC_KEY_ACTION_DOWN,
+        0,
+        LMGetKbdType() as u32,
+        K_UC_KEY_TRANSLATE_NO_DEAD_KEYS,
+        &mut dead_key_state,
+        buf.len(),
+        &mut len,
+        buf.as_mut_ptr(),
+    );
+    if status != 0 || len == 0 {
+        return None;
+    }
+    Some(String::from_utf16_lossy(&buf[..usize::min(len, buf.len())]))
+}
+
+fn translation(layout: CFDataRef) -> Translation {
+    let mut keycodes = [0; NUM_KEYCODES];
+    for (keycode, translated) in keycodes.iter_mut().enumerate() {
+        *translated = keycode as i64;
+        // Only keys that type a character move between layouts; return,
+        // escape, the arrows and so on stay put.
+        let c = match unsafe { character(layout, keycode as u16) } {
+            Some(c) if c.chars().count() == 1 && !c.chars().any(|c| c.is_control()) => c,
+            _ => continue,
+        };
+        if let Some(us_keycode) = KEY_NAMES
+            .iter()
+            .find(|(name, _)| name.chars().count() == 1 && *name == c.to_lowercase())
+            .map(|(_, keycode)| *keycode)
+        {
+            *translated = us_keycode;
+        }
+    }
+    Translation { layout, keycodes }
+}
+
+/// The US key code that types the same character as `keycode` does in the
+/// current keyboard layout, so that bindings follow the characters printed on
+/// the keys. Keys that don't type a character in both layouts are unchanged.
+pub fn layout_keycode(keycode: i64) -> i64 {
+    if MATCH_BY_POSITION.load(Ordering::Relaxed) || keycode < 0 || keycode >= NUM_KEYCODES as i64 {
+        return keycode;
+    }
+    unsafe {
+        let source = TISCopyCurrentKeyboardLayoutInputSource();
+        if source.is_null() {
+            return keycode;
+        }
+        let layout = TISGetInputSourceProperty(source, kTISPropertyUnicodeKeyLayoutData);
+        let translated = if layout.is_null() {
+            keycode
+        } else {
+            TRANSLATION.with(|t| {
+                let mut t = t.borrow_mut();
+                // The layout data belongs to the input source, which stays
+                // alive while it is selected.
+                if t.as_ref().map_or(true, |t| t.layout != layout) {
+                    *t = Some(translation(layout));
+                }
+                t.as_ref().unwrap().keycodes[keycode as usize]
+            })
+        };
+        CFRelease(source);
+        translated
+    }
+}
+
+/// The key code of a key press, translated for the current keyboard layout.
+pub fn event_keycode(event: &CGEvent) -> i64 {
+    layout_keycode(event.get_integer_value_field(EventField::KEYBOARD_EVENT_KEYCODE))
+}
@@ -0,0 +1,126 @@
+//! A LaunchAgent that starts awesome-rs at login and restarts it if it
+//! crashes, installed with `awesome-rs install-service`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, Context, Result};
+
+const LABEL: &str = "com.github.mattjbray.awesome-rs";
+
+extern "C" {
+    fn getuid() -> u32;
+}
+
+fn home() -> Result<PathBuf> {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow!("HOME is not set"))
+}
+
+/// `~/Library/LaunchAgents/com.github.mattjbray.awesome-rs.plist`.
+pub fn plist_path() -> Result<PathBuf> {
+    Ok(home()?
+        .join("Library/LaunchAgents")
+        .join(format!("{}.plist", LABEL)))
+}
+
+/// Where the agent's stdout and stderr go.
+fn log_dir() -> Result<PathBuf> {
+    Ok(home()?.join("Library/Logs/awesome-rs"))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn plist(exe: &Path, log_dir: &Path) -> String {
+    let path = |p: &Path| escape(&p.display().to_string());
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{exe}</string>
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>ProcessType</key>
+    <string>Interactive</string>
+    <key>LimitLoadToSessionType</key>
+    <string>Aqua</string>
+    <key>StandardOutPath</key>
+    <string>{stdout}</string>
+    <key>StandardErrorPath</key>
+    <string>{stderr}</string>
+</dict>
+</plist>
+"#,
+        label = LABEL,
+        exe = path(exe),
+        stdout = path(&log_dir.join("awesome-rs.log")),
+        stderr = path(&log_dir.join("awesome-rs.err.log")),
+    )
+}
+
+fn launchctl(args: &[&str]) -> Result<()> {
+    let status = Command::new("launchctl")
+        .args(args)
+        .status()
+        .context("Could not run launchctl")?;
+    if !status.success() {
+        return Err(anyhow!("launchctl {} failed: {}", args.join(" "), status));
+    }
+    Ok(())
+}
+
+fn domain() -> String {
+    format!("gui/{}", unsafe { getuid() })
+}
+
+/// Write the LaunchAgent for this executable and load it, replacing any
+/// installed before. Returns the plist's path.
+pub fn install_service() -> Result<PathBuf> {
+    let exe = std::env::current_exe().context("Could not find the awesome-rs executable")?;
+    let plist_path = plist_path()?;
+    let log_dir = log_dir()?;
+    fs::create_dir_all(&log_dir)
+        .with_context(|| format!("Could not create {}", log_dir.display()))?;
+    if let Some(dir) = plist_path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Could not create {}", dir.display()))?;
+    }
+    if plist_path.exists() {
+        // Not loaded if the user stopped it: nothing to unload then.
+        let _ = launchctl(&["bootout", &domain(), &plist_path.display().to_string()]);
+    }
+    fs::write(&plist_path, plist(&exe, &log_dir))
+        .with_context(|| format!("Could not write {}", plist_path.display()))?;
+    launchctl(&["bootstrap", &domain(), &plist_path.display().to_string()])?;
+    Ok(plist_path)
+}
+
+/// Unload the LaunchAgent and remove its plist. Logs are left behind.
+pub fn uninstall_service() -> Result<()> {
+    let plist_path = plist_path()?;
+    if !plist_path.exists() {
+        return Err(anyhow!("No LaunchAgent at {}", plist_path.display()));
+    }
+    launchctl(&["bootout", &domain(), &plist_path.display().to_string()])
+        .unwrap_or_else(|e| eprintln!("While unloading the LaunchAgent: {:?}", e));
+    fs::remove_file(&plist_path)
+        .with_context(|| format!("Could not remove {}", plist_path.display()))
+}
@@ -1,23 +1,132 @@
-use accessibility::AXUIElement;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
 use anyhow::Result;
-use core_graphics::display::{CGDisplay, CGPoint, CGRect, CGSize};
+use core_graphics::display::{CGPoint, CGRect, CGSize};
+use serde::{Deserialize, Serialize};
 
-use crate::{window::WindowWrapper, Window};
+use crate::window_system::{DynWindowSystem, WindowHandle};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TileHorizontalOpts {
     pub max_num_left: i32,
     pub primary_column_pct: u8,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Layout {
     Floating,
     Cascade,
     TileHorizontal(TileHorizontalOpts),
+    /// A layout registered with `register_layout`.
+    Custom(String),
+}
+
+/// Computes where windows go in a layout that isn't built in.
+pub trait LayoutAlgorithm {
+    /// The frames for `num_windows` windows on a display with these bounds,
+    /// in window order. The bounds leave out the menu bar.
+    fn compute(&self, bounds: &CGRect, num_windows: usize) -> Vec<CGRect>;
+}
+
+impl<F: Fn(&CGRect, usize) -> Vec<CGRect>> LayoutAlgorithm for F {
+    fn compute(&self, bounds: &CGRect, num_windows: usize) -> Vec<CGRect> {
+        self(bounds, num_windows)
+    }
+}
+
+thread_local! {
+    // Layouts are only used on the main thread.
+    static CUSTOM_LAYOUTS: RefCell<HashMap<String, Rc<dyn LayoutAlgorithm>>> =
+        RefCell::new(HashMap::new());
 }
 
-type Windows = Vec<WindowWrapper<AXUIElement>>;
+/// Make `algorithm` available as `Layout::Custom(name)`, replacing any layout
+/// registered with that name before.
+pub fn register_layout(name: &str, algorithm: impl LayoutAlgorithm + 'static) {
+    CUSTOM_LAYOUTS.with(|layouts| {
+        layouts
+            .borrow_mut()
+            .insert(name.to_string(), Rc::new(algorithm))
+    });
+}
+
+pub fn is_registered_layout(name: &str) -> bool {
+    CUSTOM_LAYOUTS.with(|layouts| layouts.borrow().contains_key(name))
+}
+
+fn custom_layout(name: &str) -> Option<Rc<dyn LayoutAlgorithm>> {
+    CUSTOM_LAYOUTS.with(|layouts| layouts.borrow().get(name).cloned())
+}
+
+type Windows = Vec<WindowHandle>;
+
+/// Floating windows' frames relative to their display, remembered per display
+/// size so they can be put back when that display configuration returns (e.g.
+/// after docking a laptop again).
+#[derive(Debug, Default)]
+pub struct FloatingFrames {
+    frames: HashMap<(uuid::Uuid, i64, i64), CGRect>,
+}
+
+impl FloatingFrames {
+    fn key(w: &WindowHandle, display: &CGRect) -> (uuid::Uuid, i64, i64) {
+        (
+            *w.id(),
+            display.size.width as i64,
+            display.size.height as i64,
+        )
+    }
+
+    /// Remember the window's frame, if it is fully on its display.
+    pub fn remember(&mut self, system: &DynWindowSystem, w: &WindowHandle) -> Result<()> {
+        let d = system.display_bounds(system.display_of(w)?);
+        let f = system.frame(w)?;
+        if same_frame(&clamp_to_display(&f, &d), &f) {
+            let relative = CGRect::new(
+                &CGPoint::new(f.origin.x - d.origin.x, f.origin.y - d.origin.y),
+                &f.size,
+            );
+            self.frames.insert(Self::key(w, &d), relative);
+        }
+        Ok(())
+    }
+
+    /// The window's remembered frame on a display of this size.
+    pub fn recall(&self, w: &WindowHandle, display: &CGRect) -> Option<CGRect> {
+        self.frames.get(&Self::key(w, display)).map(|r| {
+            CGRect::new(
+                &CGPoint::new(r.origin.x + display.origin.x, r.origin.y + display.origin.y),
+                &r.size,
+            )
+        })
+    }
+
+    pub fn retain(&mut self, f: impl Fn(&uuid::Uuid) -> bool) {
+        self.frames.retain(|(id, _, _), _| f(id));
+    }
+}
+
+pub(crate) fn same_frame(a: &CGRect, b: &CGRect) -> bool {
+    a.origin.x == b.origin.x
+        && a.origin.y == b.origin.y
+        && a.size.width == b.size.width
+        && a.size.height == b.size.height
+}
+
+/// Move (and if needed shrink) a frame so that it is fully on the display.
+fn clamp_to_display(frame: &CGRect, d: &CGRect) -> CGRect {
+    let width = f64::min(frame.size.width, d.size.width);
+    let height = f64::min(frame.size.height, d.size.height);
+    let x = frame
+        .origin
+        .x
+        .clamp(d.origin.x, d.origin.x + d.size.width - width);
+    let y = frame
+        .origin
+        .y
+        .clamp(d.origin.y, d.origin.y + d.size.height - height);
+    CGRect::new(&CGPoint::new(x, y), &CGSize::new(width, height))
+}
 
 impl Layout {
     pub fn floating() -> Self {
@@ -33,112 +142,223 @@ impl Layout {
         })
     }
 
-    pub fn apply(&self, display_id: u32, windows: &Windows) -> Result<()> {
-        let display = CGDisplay::new(display_id);
-        match self {
-            Layout::Floating => self.apply_floating(&display, windows),
-            Layout::Cascade => self.apply_cascade(&display, windows),
-            Layout::TileHorizontal(opts) => self.apply_tile_horizontal(&display, windows, &opts),
+    /// Lay out `windows` on the display, leaving `gap` points around and
+    /// between them. `weights` are the windows' relative heights, see
+    /// `compute`. Floating windows are put back at their frame in `frames`
+    /// for this display size, if there is one.
+    pub fn apply(
+        &self,
+        system: &DynWindowSystem,
+        display_id: u32,
+        windows: &Windows,
+        weights: &[f64],
+        frames: Option<&FloatingFrames>,
+        gap: f64,
+    ) -> Result<()> {
+        let d = system.display_bounds(display_id);
+        let inputs: Vec<WindowFrame> = windows
+            .iter()
+            .map(|w| WindowFrame {
+                frame: system.frame(w).ok(),
+                display: system
+                    .display_of(w)
+                    .ok()
+                    .map(|display_id| system.display_bounds(display_id)),
+                remembered: frames.and_then(|frames| frames.recall(w, &d)),
+            })
+            .collect();
+        let targets = self.target_frames(&d, &inputs, weights, gap);
+        let mut moves: Vec<_> = windows.iter().zip(targets).collect();
+        if matches!(self, Layout::Floating) {
+            // Bottom to top.
+            moves.reverse();
         }
-    }
-
-    fn apply_floating(&self, display: &CGDisplay, windows: &Windows) -> Result<()> {
-        let d = display.bounds();
-        for w in windows.iter().rev() {
-            let window_display = w.display()?;
-            let window_pos = w.position()?;
-            if window_display.id != display.id {
-                let x = window_pos.x - window_display.bounds().origin.x + d.origin.x;
-                let y = window_pos.y - window_display.bounds().origin.y + d.origin.y;
-                w.set_position(CGPoint::new(x, y)).unwrap_or_else(|e| {
-                    eprintln!("Could not set_position on window {:?}: {:?}", w, e)
+        for (w, target) in moves {
+            if let Some(frame) = target {
+                system.set_frame(w, frame).unwrap_or_else(|e| {
+                    eprintln!("Could not set_frame on window {:?}: {:?}", w, e)
                 });
             }
         }
         Ok(())
     }
 
-    fn apply_cascade(&self, display: &CGDisplay, windows: &Windows) -> Result<()> {
-        let d = display.bounds();
-        for (i, w) in windows.iter().rev().enumerate() {
-            let rect = CGRect::new(
-                &CGPoint::new(
-                    d.origin.x + i as f64 * 32.,
-                    d.origin.y + 38. + i as f64 * 32.,
-                ),
-                &CGSize::new(d.size.width * 2. / 3., d.size.height * 2. / 3.),
-            );
-            w.set_frame(rect)
-                .unwrap_or_else(|e| eprintln!("Could not set_frame on window {:?}: {:?}", w, e));
+    /// Where each of `windows` goes on a display with these bounds, in
+    /// window order, or `None` where a window should stay put. Unlike
+    /// `apply`, this doesn't touch any windows.
+    pub fn target_frames(
+        &self,
+        display: &CGRect,
+        windows: &[WindowFrame],
+        weights: &[f64],
+        gap: f64,
+    ) -> Vec<Option<CGRect>> {
+        match self {
+            Layout::Floating => windows.iter().map(|w| floating_frame(display, w)).collect(),
+            Layout::Cascade | Layout::TileHorizontal(_) | Layout::Custom(_) => {
+                // Half the gap around the display plus half around each
+                // window makes the same gap everywhere.
+                let bounds = inset(display, gap / 2.);
+                let rects = self.compute(&bounds, weights);
+                windows
+                    .iter()
+                    .zip(rects)
+                    .map(|(w, rect)| {
+                        let rect = inset(&rect, gap / 2.);
+                        match w.frame {
+                            Some(frame) if same_frame(&frame, &rect) => None,
+                            _ => Some(rect),
+                        }
+                    })
+                    .collect()
+            }
         }
-        Ok(())
     }
 
-    fn apply_tile_horizontal(
-        &self,
-        display: &CGDisplay,
-        windows: &Windows,
-        opts: &TileHorizontalOpts,
-    ) -> Result<()> {
-        let num_windows = windows.len() as i32;
+    /// The frames this layout gives a window for each of `weights` on a
+    /// display with these bounds, in window order. Tiles in the same column
+    /// get heights in proportion to their weights. The floating layout
+    /// leaves windows where they are, so it gives none.
+    pub fn compute(&self, bounds: &CGRect, weights: &[f64]) -> Vec<CGRect> {
+        match self {
+            Layout::Floating => vec![],
+            Layout::Cascade => compute_cascade(bounds, weights.len()),
+            Layout::TileHorizontal(opts) => compute_tile_horizontal(bounds, weights, opts),
+            Layout::Custom(name) => match custom_layout(name) {
+                Some(algorithm) => {
+                    let below_menu_bar = CGRect::new(
+                        &CGPoint::new(bounds.origin.x, bounds.origin.y + 38.),
+                        &CGSize::new(bounds.size.width, bounds.size.height - 38.),
+                    );
+                    let mut rects = algorithm.compute(&below_menu_bar, weights.len());
+                    rects.truncate(weights.len());
+                    rects
+                }
+                None => {
+                    eprintln!("No layout named {:?}", name);
+                    vec![]
+                }
+            },
+        }
+    }
+}
 
-        if num_windows == 0 {
-            return Ok(());
-        };
+/// What a layout needs to know about a window it places.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WindowFrame {
+    /// Where the window is now, if that could be read.
+    pub frame: Option<CGRect>,
+    /// The bounds of the display the window is on. Windows stranded
+    /// off-screen have none.
+    pub display: Option<CGRect>,
+    /// Where the window was last left floating on a display of this size.
+    pub remembered: Option<CGRect>,
+}
 
-        let d = display.bounds();
+/// Where the floating layout puts a window on the display `d`: back where it
+/// was remembered, or where it is, moved over from another display and onto
+/// `d` if needed.
+fn floating_frame(d: &CGRect, w: &WindowFrame) -> Option<CGRect> {
+    let current = w.frame?;
+    if let Some(frame) = w.remembered {
+        return (!same_frame(&current, &frame)).then_some(frame);
+    }
+    let mut frame = current;
+    if let Some(wd) = w.display {
+        if !same_frame(&wd, d) {
+            frame.origin.x = frame.origin.x - wd.origin.x + d.origin.x;
+            frame.origin.y = frame.origin.y - wd.origin.y + d.origin.y;
+        }
+    }
+    let clamped = clamp_to_display(&frame, d);
+    (!same_frame(&clamped, &current)).then_some(clamped)
+}
 
-        let num_left = i32::min(num_windows, opts.max_num_left);
-        let num_right = if num_windows > num_left {
-            num_windows - num_left
-        } else {
-            0
-        };
+pub(crate) fn inset(r: &CGRect, by: f64) -> CGRect {
+    CGRect::new(
+        &CGPoint::new(r.origin.x + by, r.origin.y + by),
+        &CGSize::new(
+            f64::max(r.size.width - 2. * by, 0.),
+            f64::max(r.size.height - 2. * by, 0.),
+        ),
+    )
+}
 
-        // Left column
+fn compute_cascade(d: &CGRect, n: usize) -> Vec<CGRect> {
+    // The last window is at the top left, each earlier one a step further in.
+    (0..n)
+        .map(|idx| {
+            let i = (n - 1 - idx) as f64;
+            CGRect::new(
+                &CGPoint::new(d.origin.x + i * 32., d.origin.y + 38. + i * 32.),
+                &CGSize::new(d.size.width * 2. / 3., d.size.height * 2. / 3.),
+            )
+        })
+        .collect()
+}
 
-        let left_width = if num_right == 0 {
-            d.size.width
-        } else {
-            d.size.width * (opts.primary_column_pct as f64 / 100.)
-        };
+/// Stack tiles with these weights from the top of a column.
+fn compute_column(x: f64, top: f64, width: f64, height: f64, weights: &[f64]) -> Vec<CGRect> {
+    let total: f64 = weights.iter().sum();
+    let mut y = top;
+    weights
+        .iter()
+        .map(|weight| {
+            let h = height * weight / total;
+            let rect = CGRect::new(&CGPoint::new(x, y), &CGSize::new(width, h));
+            y += h;
+            rect
+        })
+        .collect()
+}
 
-        let left_height = (d.size.height - 38.) / num_left as f64;
-        let left_size = CGSize::new(left_width, left_height);
+fn compute_tile_horizontal(d: &CGRect, weights: &[f64], opts: &TileHorizontalOpts) -> Vec<CGRect> {
+    let num_windows = weights.len() as i32;
 
-        for (i, w) in windows.iter().take(num_left as usize).enumerate() {
-            let rect = CGRect::new(
-                &CGPoint::new(d.origin.x, d.origin.y + 38. + i as f64 * left_height),
-                &left_size,
-            );
-            w.set_frame(rect)
-                .unwrap_or_else(|e| eprintln!("Could not set_frame on window {:?}: {:?}", w, e));
-        }
+    if num_windows == 0 {
+        return vec![];
+    };
 
-        if num_right == 0 {
-            return Ok(());
-        };
+    let num_left = i32::min(num_windows, opts.max_num_left);
+    let num_right = if num_windows > num_left {
+        num_windows - num_left
+    } else {
+        0
+    };
 
-        // Right column
+    // Left column
 
-        let right_width = d.size.width * ((100 - opts.primary_column_pct) as f64 / 100.);
-        let right_height = (d.size.height - 38.) / num_right as f64;
-        let right_size = CGSize::new(right_width, right_height);
+    let left_width = if num_right == 0 {
+        d.size.width
+    } else {
+        d.size.width * (opts.primary_column_pct as f64 / 100.)
+    };
 
-        for (i, w) in windows.iter().skip(num_left as usize).enumerate() {
-            let rect = CGRect::new(
-                &CGPoint::new(
-                    d.origin.x + left_width,
-                    d.origin.y + 38. + i as f64 * right_height,
-                ),
-                &right_size,
-            );
-            w.set_frame(rect)
-                .unwrap_or_else(|e| eprintln!("Could not set_frame on window {:?}: {:?}", w, e));
-        }
+    let (left_weights, right_weights) = weights.split_at(num_left as usize);
+    let mut rects = compute_column(
+        d.origin.x,
+        d.origin.y + 38.,
+        left_width,
+        d.size.height - 38.,
+        left_weights,
+    );
 
-        Ok(())
-    }
+    if num_right == 0 {
+        return rects;
+    };
+
+    // Right column
+
+    let right_width = d.size.width * ((100 - opts.primary_column_pct) as f64 / 100.);
+    rects.extend(compute_column(
+        d.origin.x + left_width,
+        d.origin.y + 38.,
+        right_width,
+        d.size.height - 38.,
+        right_weights,
+    ));
+
+    rects
 }
 
 impl std::fmt::Display for Layout {
@@ -147,7 +367,154 @@ impl std::fmt::Display for Layout {
             Layout::Cascade => "cascade",
             Layout::Floating => "floating",
             Layout::TileHorizontal(_) => "tiling",
+            Layout::Custom(name) => name,
         };
         write!(f, "{}", str)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> CGRect {
+        CGRect::new(&CGPoint::new(x, y), &CGSize::new(width, height))
+    }
+
+    /// A 1000x838 display, which leaves 800 points below the menu bar.
+    fn display() -> CGRect {
+        rect(0., 0., 1000., 838.)
+    }
+
+    fn assert_frames(actual: &[CGRect], expected: &[CGRect]) {
+        let matches = actual.len() == expected.len()
+            && actual.iter().zip(expected).all(|(a, b)| same_frame(a, b));
+        assert!(matches, "Expected frames {:?}, got {:?}", expected, actual);
+    }
+
+    #[test]
+    fn a_single_window_fills_the_display_below_the_menu_bar() {
+        let frames = Layout::tile_horizontal(1, 50).compute(&display(), &[1.]);
+
+        assert_frames(&frames, &[rect(0., 38., 1000., 800.)]);
+    }
+
+    #[test]
+    fn tiling_splits_the_display_into_columns() {
+        let frames = Layout::tile_horizontal(1, 60).compute(&display(), &[1., 1., 1.]);
+
+        assert_frames(
+            &frames,
+            &[
+                rect(0., 38., 600., 800.),
+                rect(600., 38., 400., 400.),
+                rect(600., 438., 400., 400.),
+            ],
+        );
+    }
+
+    #[test]
+    fn tiles_share_a_column_by_weight() {
+        let frames = Layout::tile_horizontal(2, 50).compute(&display(), &[1., 3., 1.]);
+
+        assert_frames(
+            &frames,
+            &[
+                rect(0., 38., 500., 200.),
+                rect(0., 238., 500., 600.),
+                rect(500., 38., 500., 800.),
+            ],
+        );
+    }
+
+    #[test]
+    fn no_windows_get_no_frames() {
+        assert!(Layout::tile_horizontal(1, 50)
+            .compute(&display(), &[])
+            .is_empty());
+        assert!(Layout::floating().compute(&display(), &[1.]).is_empty());
+    }
+
+    #[test]
+    fn cascade_puts_the_last_window_at_the_top_left() {
+        let frames = Layout::cascade().compute(&rect(0., 0., 900., 600.), &[1., 1.]);
+
+        assert_frames(
+            &frames,
+            &[rect(32., 70., 600., 400.), rect(0., 38., 600., 400.)],
+        );
+    }
+
+    #[test]
+    fn gaps_are_the_same_around_and_between_windows() {
+        let windows = [WindowFrame::default(), WindowFrame::default()];
+        let targets =
+            Layout::tile_horizontal(1, 50).target_frames(&display(), &windows, &[1., 1.], 10.);
+        let targets: Vec<CGRect> = targets.into_iter().flatten().collect();
+
+        assert_frames(
+            &targets,
+            &[rect(10., 48., 485., 780.), rect(505., 48., 485., 780.)],
+        );
+    }
+
+    #[test]
+    fn windows_already_in_place_stay_put() {
+        let windows = [
+            WindowFrame {
+                frame: Some(rect(0., 38., 500., 800.)),
+                ..Default::default()
+            },
+            WindowFrame::default(),
+        ];
+        let targets =
+            Layout::tile_horizontal(1, 50).target_frames(&display(), &windows, &[1., 1.], 0.);
+
+        assert!(targets[0].is_none());
+        assert_frames(&[targets[1].unwrap()], &[rect(500., 38., 500., 800.)]);
+    }
+
+    #[test]
+    fn floating_windows_follow_their_display() {
+        let other_display = rect(1000., 0., 1000., 838.);
+        let windows = [
+            WindowFrame {
+                frame: Some(rect(1100., 100., 400., 300.)),
+                display: Some(other_display),
+                remembered: None,
+            },
+            WindowFrame {
+                frame: Some(rect(100., 100., 400., 300.)),
+                display: Some(display()),
+                remembered: None,
+            },
+        ];
+        let targets = Layout::floating().target_frames(&display(), &windows, &[1., 1.], 0.);
+
+        assert_frames(&[targets[0].unwrap()], &[rect(100., 100., 400., 300.)]);
+        assert!(targets[1].is_none());
+    }
+
+    #[test]
+    fn custom_layouts_get_the_display_below_the_menu_bar() {
+        register_layout("test-halves", |bounds: &CGRect, _: usize| {
+            let half = bounds.size.width / 2.;
+            vec![
+                rect(bounds.origin.x, bounds.origin.y, half, bounds.size.height),
+                rect(
+                    bounds.origin.x + half,
+                    bounds.origin.y,
+                    half,
+                    bounds.size.height,
+                ),
+                rect(0., 0., 1., 1.),
+            ]
+        });
+        let frames = Layout::Custom("test-halves".to_string()).compute(&display(), &[1., 1.]);
+
+        assert_frames(
+            &frames,
+            &[rect(0., 38., 500., 800.), rect(500., 38., 500., 800.)],
+        );
+    }
+}
@@ -1,12 +1,96 @@
 mod action;
+mod alert;
+mod apple_events;
+mod capture;
+#[cfg(feature = "cgs-move")]
+mod cgs;
+mod chord;
+mod config;
+mod direction;
+mod display_labels;
+mod display_watcher;
+mod double_tap;
 mod drag_window;
+mod dry_run;
+mod hooks;
+mod ipc;
+mod keyboard;
+mod launch_agent;
 mod layout;
+mod menu_bar;
 mod mode;
+mod notification_observer;
+mod observer;
+mod overlay;
+mod palette;
+mod permission;
+mod resize_window;
+#[cfg(feature = "scripting")]
+mod script;
+mod snap;
+#[cfg(feature = "spaces")]
+mod spaces;
+mod split_tree;
+mod state;
+mod stats;
+mod switcher;
+mod tasklist;
+mod timer;
+mod url_handler;
 mod window;
 mod window_manager;
+mod window_system;
 
-pub use crate::action::{Action, HELP_TEXT};
+pub use crate::action::{
+    set_insert_normal_chord, Action, ActionArg, ActionInfo, ACTIONS, HELP_TEXT,
+};
+pub use crate::alert::{alert, request_alert_permission, set_alerts_enabled};
+pub use crate::apple_events::AppleEventHandler;
+pub use crate::capture::{window_image, ThumbnailCache};
+pub use crate::chord::{parse_modifiers, ChordKey, Chords, KeyBindings, KeyPress};
+pub use crate::config::{
+    ActivationConfig, AlertsConfig, AutoFloatConfig, BindingsConfig, ChordsConfig, Color, Config,
+    DoubleTapConfig, FloatingConfig, GroupDefaults, GroupsConfig, HighlightConfig, HooksConfig,
+    IpcConfig, LayoutName, MiddleClickConfig, MinimizeConfig, ModesConfig, Modifier,
+    MouseBindingConfig, Rule, SnapConfig, SpacesConfig, StickyEdgesConfig, ThumbnailsConfig,
+    TransientModeConfig,
+};
+pub use crate::direction::{nearest_in_direction, Direction};
+pub use crate::display_labels::{display_name, DisplayLabels};
+pub use crate::display_watcher::{display_uuid, DisplayWatcher};
+pub use crate::double_tap::DoubleTaps;
 pub use crate::drag_window::DragWindow;
-pub use crate::layout::Layout;
-pub use crate::window::{CGErrorWrapper, Window};
-pub use crate::window_manager::WindowManager;
+pub use crate::dry_run::{DryRunWindowSystem, FrameChange};
+pub use crate::hooks::{HookEvent, HookState, Hooks};
+pub use crate::ipc::{
+    send_request, socket_path, ActionCall, IpcServer, Reply, Request, Response, PROTOCOL_VERSION,
+};
+pub use crate::keyboard::{keycode_of_name, layout_keycode, set_match_by_position};
+pub use crate::launch_agent::{install_service, plist_path, uninstall_service};
+pub use crate::layout::{
+    is_registered_layout, register_layout, FloatingFrames, Layout, LayoutAlgorithm, WindowFrame,
+};
+pub use crate::menu_bar::{MenuBar, MenuGroup, MenuTarget};
+pub use crate::notification_observer::{NotificationCenter, NotificationObserver};
+pub use crate::observer::AppObserver;
+pub use crate::overlay::overlay_scale;
+pub use crate::palette::Palette;
+pub use crate::permission::{is_trusted, prompt_for_trust, PermissionWindow};
+pub use crate::resize_window::ResizeWindow;
+#[cfg(feature = "scripting")]
+pub use crate::script::Script;
+pub use crate::state::{
+    LayoutSnapshot, SavedDisplay, SavedGroup, SavedState, SavedWindow, SnapshotWindow,
+};
+pub use crate::stats::{AppFocusTime, FocusReport, FocusStats, GroupFocusTime};
+pub use crate::switcher::{Switcher, SwitcherEntry};
+pub use crate::tasklist::{Tasklist, TasklistEntry, TasklistHandler};
+pub use crate::timer::Timer;
+pub use crate::url_handler::{action_of_url, UrlHandler, URL_SCHEME};
+pub use crate::window::{
+    forget_frames, ActivationStep, CGErrorWrapper, TitleBarDoubleClickAction, Window,
+};
+pub use crate::window_manager::{WakeHandler, WindowManager};
+pub use crate::window_system::{
+    AXWindowSystem, DynWindowSystem, PendingWindows, WindowHandle, WindowSystem,
+};
@@ -1,97 +1,819 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::{thread, time::Duration};
 
-use accessibility::AXUIElement;
-use awesome_rs::{Action, DragWindow, WindowManager, HELP_TEXT};
-use cocoa::appkit::{NSApp, NSApplication};
-use core_foundation::runloop::{kCFRunLoopCommonModes, CFRunLoop};
+use accessibility_sys::{
+    kAXApplicationActivatedNotification, kAXFocusedWindowChangedNotification,
+    kAXWindowMovedNotification, kAXWindowResizedNotification,
+};
+use anyhow::{anyhow, Result};
+use awesome_rs::{
+    action_of_url, alert, forget_frames, install_service, is_trusted, parse_modifiers,
+    prompt_for_trust, request_alert_permission, send_request, set_alerts_enabled,
+    set_insert_normal_chord, set_match_by_position, socket_path, uninstall_service, AXWindowSystem,
+    Action, AppObserver, AppleEventHandler, ChordKey, Config, DisplayWatcher, DryRunWindowSystem,
+    DynWindowSystem, IpcServer, MenuBar, NotificationCenter, NotificationObserver,
+    PermissionWindow, Request, Response, SavedState, TasklistHandler, Timer, UrlHandler,
+    WakeHandler, WindowManager, HELP_TEXT,
+};
+use clap::{Args, Parser, Subcommand};
+use cocoa::{
+    appkit::{NSApp, NSApplication},
+    base::nil,
+};
+use core_foundation::{
+    base::TCFType,
+    mach_port::{CFMachPortInvalidate, CFMachPortIsValid, CFMachPortRef},
+    runloop::{kCFRunLoopCommonModes, CFRunLoop, CFRunLoopSource},
+};
 use core_graphics::event::{
-    CGEvent, CGEventFlags, CGEventTap, CGEventTapCallbackResult, CGEventTapLocation,
-    CGEventTapOptions, CGEventTapPlacement, CGEventType,
+    CGEvent, CGEventTap, CGEventTapCallbackResult, CGEventTapLocation, CGEventTapOptions,
+    CGEventTapPlacement, CGEventType, EventField,
 };
 
-const AWESOME_ENABLE_DRAG_WINDOW: bool = false;
+/// A tiling window manager for macOS, after awesome.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Read the config from this file instead of the default path.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Log each action performed.
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// For running without a subcommand.
+    #[command(flatten)]
+    run: RunArgs,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-// <ALT>
-fn awesome_normal_mode_drag_window_flags() -> CGEventFlags {
-    CGEventFlags::CGEventFlagAlternate
+#[derive(Args)]
+struct RunArgs {
+    /// Track windows, groups and focus as usual, but never modify any window.
+    #[arg(long)]
+    spectator: bool,
+    /// Like --spectator, but act as if windows were moved, logging the
+    /// frames they would have been given.
+    #[arg(long)]
+    dry_run: bool,
+    /// Take over from a running instance, keeping its groups.
+    #[arg(long)]
+    replace: bool,
+    /// Don't move or resize windows by dragging with the mouse.
+    #[arg(long)]
+    no_drag: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Manage windows (the default).
+    Run(RunArgs),
+    /// Check that the config file parses and is valid.
+    CheckConfig,
+    /// Print the running instance's groups and windows as JSON.
+    Query,
+    /// Ask the running instance to perform an action.
+    Action {
+        /// The action's id, as listed by `awesomectl actions`.
+        name: String,
+        args: Vec<String>,
+    },
+    /// Install a LaunchAgent that starts awesome-rs at login.
+    InstallService,
+    /// Stop and remove the LaunchAgent.
+    UninstallService,
 }
 
 fn main() {
-    let wm = WindowManager::new();
-    let state: RefCell<WindowManager> = RefCell::new(wm);
-
-    let event_tap = {
-        use CGEventType::*;
-        CGEventTap::new(
-            CGEventTapLocation::HID,
-            CGEventTapPlacement::HeadInsertEventTap,
-            CGEventTapOptions::Default,
-            vec![MouseMoved, FlagsChanged, KeyDown],
-            mk_event_tap_callback(&state),
-        )
-        .unwrap()
+    let cli = Cli::parse();
+    let result = match &cli.command {
+        None => {
+            run(&cli, &cli.run);
+            Ok(())
+        }
+        Some(Command::Run(args)) => {
+            run(&cli, args);
+            Ok(())
+        }
+        Some(Command::CheckConfig) => check_config(cli.config.as_deref()),
+        Some(Command::Query) => query(),
+        Some(Command::Action { name, args }) => perform_action(name, args),
+        Some(Command::InstallService) => install_service().map(|path| {
+            println!("Installed and started LaunchAgent {}", path.display());
+        }),
+        Some(Command::UninstallService) => {
+            uninstall_service().map(|()| println!("Stopped and removed LaunchAgent"))
+        }
     };
+    if let Err(e) = result {
+        eprintln!("{:?}", e);
+        std::process::exit(1);
+    }
+}
 
-    let current = CFRunLoop::get_current();
-    let loop_source = event_tap.mach_port.create_runloop_source(0).unwrap();
-    unsafe {
-        current.add_source(&loop_source, kCFRunLoopCommonModes);
+fn load_config(path: Option<&Path>) -> Result<Config> {
+    match path {
+        Some(path) => Config::load_from(path),
+        None => Config::load(),
+    }
+}
+
+fn check_config(path: Option<&Path>) -> Result<()> {
+    load_config(path)?;
+    match path.map(PathBuf::from).or_else(Config::default_path) {
+        Some(path) if path.exists() => println!("{} is valid", path.display()),
+        _ => println!("No config file: using the defaults"),
+    }
+    Ok(())
+}
+
+fn query() -> Result<()> {
+    match send_request(&Request::State)? {
+        Response::State(state) => {
+            println!("{}", serde_json::to_string_pretty(&state)?);
+            Ok(())
+        }
+        Response::Error { message } => Err(anyhow!(message)),
+        response => Err(anyhow!("Unexpected response: {:?}", response)),
+    }
+}
+
+fn perform_action(name: &str, args: &[String]) -> Result<()> {
+    // Catch typos before bothering the running instance.
+    Action::parse(name, args)?;
+    match send_request(&Request::Action {
+        id: name.to_string(),
+        args: args.to_vec(),
+    })? {
+        Response::Ok => Ok(()),
+        Response::Error { message } => Err(anyhow!(message)),
+        response => Err(anyhow!("Unexpected response: {:?}", response)),
+    }
+}
+
+/// The macOS window system, wrapped so that windows are left alone in
+/// spectator and dry-run modes.
+fn window_system(args: &RunArgs) -> Rc<DynWindowSystem> {
+    let system: Box<DynWindowSystem> = Box::new(AXWindowSystem::default());
+    if args.dry_run {
+        println!("Dry run: window frame changes will be logged instead of made");
+        Rc::new(DryRunWindowSystem::dry_run(system))
+    } else if args.spectator {
+        println!("Spectator mode: windows will not be modified");
+        Rc::new(DryRunWindowSystem::read_only(system))
+    } else {
+        Rc::from(system)
+    }
+}
+
+fn run(cli: &Cli, args: &RunArgs) {
+    let system = window_system(args);
+
+    let mut config = load_config(cli.config.as_deref()).unwrap_or_else(|e| {
+        eprintln!("While loading config: {:?}", e);
+        alert(
+            "Could not load the config",
+            &format!("{:#}. Using the defaults.", e),
+        );
+        Config::default()
+    });
+    set_alerts_enabled(config.alerts.enabled);
+    request_alert_permission();
+    if args.no_drag {
+        config.drag_window.enabled = false;
+        config.resize_window.enabled = false;
+    }
+
+    set_match_by_position(config.bindings.by_position);
+    match parse_modifiers(&config.modes.transient) {
+        Ok(flags) => set_insert_normal_chord(flags),
+        Err(e) => eprintln!("While parsing modes.transient: {:?}", e),
     }
-    event_tap.enable();
 
-    println!(
-        "Starting app. Trusted: {}",
-        AXUIElement::application_is_trusted()
+    let saved_state = take_over_running_instance(args.replace);
+
+    let ipc_server = IpcServer::bind().unwrap_or_else(|e| {
+        eprintln!("Could not listen on {}: {:?}", socket_path().display(), e);
+        std::process::exit(1);
+    });
+    ipc_server
+        .bind_tcp(&config.ipc)
+        .unwrap_or_else(|e| eprintln!("While listening for IPC over TCP: {:?}", e));
+
+    let mut wm = WindowManager::with_system(config, system);
+    wm.set_verbose(cli.verbose);
+    if let Some(saved_state) = saved_state {
+        wm.restore_state(&saved_state)
+            .unwrap_or_else(|e| eprintln!("While restoring state: {:?}", e));
+    }
+    // The state lives for as long as the app runs, and is shared by the event
+    // tap and timer callbacks on the main thread.
+    let state: &'static RefCell<WindowManager> = Box::leak(Box::new(RefCell::new(wm)));
+
+    // The shared application must exist before we add a menu bar item.
+    let app = unsafe { NSApp() };
+    let menu_bar = MenuBar::new(move |target| {
+        state
+            .borrow_mut()
+            .focus_menu_target(&target)
+            .unwrap_or_else(|e| eprintln!("While focusing menu item {:?}: {:?}", target, e));
+    });
+    state.borrow_mut().set_menu_bar(menu_bar);
+    state
+        .borrow_mut()
+        .set_tasklist_handler(TasklistHandler::new(move |target| {
+            state
+                .borrow_mut()
+                .focus_menu_target(&target)
+                .unwrap_or_else(|e| eprintln!("While focusing window {:?}: {:?}", target, e));
+        }));
+    // Keep the status window's thumbnails live.
+    let _tasklist_timer = Timer::repeating(TASKLIST_REFRESH_INTERVAL, move || {
+        if let Ok(mut wm) = state.try_borrow_mut() {
+            wm.update_status_tasklist();
+        }
+    });
+
+    // Debounced activations and relayouts, and background refreshes.
+    state
+        .borrow_mut()
+        .set_wake_handler(WakeHandler::new(move |delay| {
+            Timer::once(delay, move || wake_up(state))
+        }));
+
+    // In case the release of the temporary normal mode's modifiers is missed.
+    let _transient_mode_timer = Timer::repeating(TRANSIENT_MODE_POLL_INTERVAL, move || {
+        if let Ok(mut wm) = state.try_borrow_mut() {
+            wm.check_transient_mode()
+                .unwrap_or_else(|e| eprintln!("While checking modifiers: {:?}", e));
+        }
+    });
+
+    let _display_watcher = DisplayWatcher::new(move || {
+        // Wait for the displays to settle: one change is reported per display
+        // it affects. Handling it more than once is harmless.
+        Timer::once(DISPLAY_SETTLE_DELAY, move || {
+            if let Ok(mut wm) = state.try_borrow_mut() {
+                wm.displays_changed()
+                    .unwrap_or_else(|e| eprintln!("While handling display change: {:?}", e));
+            }
+        });
+    });
+
+    let _wake_observer = NotificationObserver::new(
+        &[
+            (
+                NotificationCenter::Workspace,
+                "NSWorkspaceDidWakeNotification",
+            ),
+            (
+                NotificationCenter::Workspace,
+                "NSWorkspaceScreensDidWakeNotification",
+            ),
+            (
+                NotificationCenter::Workspace,
+                "NSWorkspaceSessionDidBecomeActiveNotification",
+            ),
+            (
+                NotificationCenter::Distributed,
+                "com.apple.screenIsUnlocked",
+            ),
+        ],
+        move |name| {
+            println!("{}: resynchronizing windows", name);
+            // Apps and displays are still waking up, and keep moving windows
+            // for a moment.
+            Timer::once(WAKE_SETTLE_DELAY, move || {
+                if let Ok(mut wm) = state.try_borrow_mut() {
+                    wm.resync()
+                        .unwrap_or_else(|e| eprintln!("While resynchronizing: {:?}", e));
+                }
+            });
+        },
+    );
+
+    let _space_observer = NotificationObserver::new(
+        &[(
+            NotificationCenter::Workspace,
+            "NSWorkspaceActiveSpaceDidChangeNotification",
+        )],
+        move |_| {
+            if let Ok(mut wm) = state.try_borrow_mut() {
+                wm.space_changed()
+                    .unwrap_or_else(|e| eprintln!("While following Space change: {:?}", e));
+            }
+        },
     );
 
+    // awesome-rs:// URLs, from Shortcuts and launchers.
+    let _url_handler = UrlHandler::new(move |url| match action_of_url(url) {
+        Ok(action) => state
+            .borrow_mut()
+            .do_action(&action)
+            .unwrap_or_else(|e| eprintln!("While performing {:?}: {:?}", action, e)),
+        Err(e) => eprintln!("While handling URL {}: {:?}", url, e),
+    });
+
+    // AppleScript commands, e.g. `tell app "awesome-rs" to show group 2`.
+    let _apple_event_handler =
+        AppleEventHandler::new(move |action| state.borrow_mut().do_action(&action));
+
+    let _ipc_timer = Timer::repeating(0.05, move || {
+        while let Some((request, reply)) = ipc_server.try_recv() {
+            let response = state.borrow_mut().handle_request(&request);
+            let _ = reply.send(response);
+        }
+        // Another instance is taking over, and has our state.
+        if ipc_server.shutdown_requested() {
+            let _ = std::fs::remove_file(socket_path());
+            unsafe { app.terminate_(nil) };
+        }
+    });
+
+    // The event tap and app observers need Accessibility permission.
+    let mut permission_watch = PermissionWatch::new(state);
+    permission_watch.check();
+    let _permission_timer = Timer::repeating(PERMISSION_POLL_INTERVAL, move || {
+        permission_watch.check();
+    });
+
     println!("{}", HELP_TEXT);
 
     unsafe {
         // let _pool = NSAutoreleasePool::new(nil);
-        let app = NSApp();
         app.run();
     }
 }
 
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGEventTapIsEnabled(tap: CFMachPortRef) -> bool;
+    fn CGEventTapEnable(tap: CFMachPortRef, enable: bool);
+}
+
+thread_local! {
+    // The installed tap's port, so the callback can re-enable the tap when
+    // macOS disables it.
+    static EVENT_TAP_PORT: Cell<CFMachPortRef> = Cell::new(std::ptr::null_mut());
+}
+
+/// How often to check whether Accessibility permission was granted or
+/// revoked.
+const PERMISSION_POLL_INTERVAL: f64 = 1.;
+
+/// Starts handling events once we have Accessibility permission, and stops,
+/// explaining how to grant it again, if it is revoked while we run.
+struct PermissionWatch {
+    state: &'static RefCell<WindowManager>,
+    running: Option<Running>,
+    // Shown while we wait for permission.
+    _window: Option<PermissionWindow>,
+}
+
+impl PermissionWatch {
+    fn new(state: &'static RefCell<WindowManager>) -> Self {
+        let window = if prompt_for_trust() {
+            None
+        } else {
+            println!("Waiting for Accessibility permission");
+            Some(PermissionWindow::show())
+        };
+        Self {
+            state,
+            running: None,
+            _window: window,
+        }
+    }
+
+    fn check(&mut self) {
+        let trusted = is_trusted();
+        if trusted && self.running.is_none() {
+            match Running::start(self.state) {
+                Ok(running) => {
+                    println!("Accessibility permission granted, handling events");
+                    self.running = Some(running);
+                    self._window = None;
+                    self.state.borrow_mut().set_event_tap_warning(None);
+                }
+                Err(()) => eprintln!("Could not create event tap, will retry"),
+            }
+        } else if !trusted && self.running.is_some() {
+            eprintln!("Accessibility permission was revoked, waiting for it again");
+            alert(
+                "Accessibility permission was revoked",
+                "Windows won't be managed until it is granted again.",
+            );
+            self.running = None;
+            self._window = Some(PermissionWindow::show());
+            self.state
+                .borrow_mut()
+                .set_event_tap_warning(Some("Accessibility permission was revoked".to_string()));
+        }
+    }
+}
+
+/// The event tap and app observers, which stop when this is dropped.
+struct Running {
+    event_tap_watchdog: Timer,
+    app_observers: Timer,
+}
+
+impl Running {
+    fn start(state: &'static RefCell<WindowManager>) -> Result<Self, ()> {
+        let event_tap = InstalledEventTap::install(state)?;
+        Ok(Self {
+            event_tap_watchdog: watch_event_tap(state, event_tap),
+            app_observers: observe_apps(state),
+        })
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        // Dropping the timers' callbacks uninstalls the tap and observers.
+        self.event_tap_watchdog.invalidate();
+        self.app_observers.invalidate();
+    }
+}
+
+/// How often to check that macOS hasn't silently disabled or invalidated the
+/// event tap (e.g. after some security prompts).
+const EVENT_TAP_WATCHDOG_INTERVAL: f64 = 5.;
+
+/// An event tap and the run loop source that delivers its events.
+struct InstalledEventTap {
+    tap: CGEventTap<'static>,
+    loop_source: CFRunLoopSource,
+}
+
+impl InstalledEventTap {
+    fn install(state: &'static RefCell<WindowManager>) -> Result<Self, ()> {
+        let tap = {
+            use CGEventType::*;
+            CGEventTap::new(
+                CGEventTapLocation::HID,
+                CGEventTapPlacement::HeadInsertEventTap,
+                CGEventTapOptions::Default,
+                vec![
+                    LeftMouseDown,
+                    LeftMouseDragged,
+                    LeftMouseUp,
+                    RightMouseDown,
+                    RightMouseDragged,
+                    RightMouseUp,
+                    OtherMouseDown,
+                    FlagsChanged,
+                    KeyDown,
+                    KeyUp,
+                ],
+                mk_event_tap_callback(state),
+            )?
+        };
+        let loop_source = tap.mach_port.create_runloop_source(0)?;
+        unsafe {
+            CFRunLoop::get_current().add_source(&loop_source, kCFRunLoopCommonModes);
+        }
+        tap.enable();
+        EVENT_TAP_PORT.with(|port| port.set(tap.mach_port.as_concrete_TypeRef()));
+        Ok(Self { tap, loop_source })
+    }
+
+    fn is_valid(&self) -> bool {
+        unsafe { CFMachPortIsValid(self.tap.mach_port.as_concrete_TypeRef()) != 0 }
+    }
+
+    fn is_attached(&self) -> bool {
+        unsafe {
+            CFRunLoop::get_current().contains_source(&self.loop_source, kCFRunLoopCommonModes)
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        unsafe { CGEventTapIsEnabled(self.tap.mach_port.as_concrete_TypeRef()) }
+    }
+
+    fn uninstall(&self) {
+        unsafe {
+            CFRunLoop::get_current().remove_source(&self.loop_source, kCFRunLoopCommonModes);
+            CFMachPortInvalidate(self.tap.mach_port.as_concrete_TypeRef());
+        }
+    }
+}
+
+impl Drop for InstalledEventTap {
+    fn drop(&mut self) {
+        self.uninstall();
+        let port = self.tap.mach_port.as_concrete_TypeRef();
+        // A replacement tap may already be installed.
+        EVENT_TAP_PORT.with(|p| {
+            if p.get() == port {
+                p.set(std::ptr::null_mut());
+            }
+        });
+    }
+}
+
+/// Periodically re-enable the event tap, or re-create it if it was
+/// invalidated.
+fn watch_event_tap(
+    state: &'static RefCell<WindowManager>,
+    mut event_tap: InstalledEventTap,
+) -> Timer {
+    Timer::repeating(EVENT_TAP_WATCHDOG_INTERVAL, move || {
+        if !event_tap.is_valid() || !event_tap.is_attached() {
+            eprintln!("Event tap was invalidated, re-creating it");
+            alert(
+                "macOS disabled keyboard shortcuts",
+                "awesome-rs is re-enabling them.",
+            );
+            match InstalledEventTap::install(state) {
+                Ok(new_tap) => {
+                    event_tap = new_tap;
+                    println!("Event tap re-created");
+                }
+                Err(()) => eprintln!("Could not re-create event tap, will retry"),
+            }
+        } else if !event_tap.is_enabled() {
+            eprintln!("Event tap was disabled, re-enabling it");
+            event_tap.tap.enable();
+            alert(
+                "macOS disabled keyboard shortcuts",
+                "awesome-rs re-enabled them.",
+            );
+            state
+                .borrow_mut()
+                .set_event_tap_warning(Some("macOS disabled keyboard shortcuts".to_string()));
+        }
+    })
+}
+
+/// How long to wait to wake the window manager up again while it is
+/// handling an event.
+const WAKE_RETRY_DELAY: f64 = 0.01;
+
+/// Do the window manager's debounced work, or try again shortly if it is
+/// busy.
+fn wake_up(state: &'static RefCell<WindowManager>) {
+    match state.try_borrow_mut() {
+        Ok(mut wm) => wm.wake_up(),
+        Err(_) => {
+            Timer::once(WAKE_RETRY_DELAY, move || wake_up(state));
+        }
+    }
+}
+
+/// How often to check that the temporary normal mode's modifiers are still
+/// held.
+const TRANSIENT_MODE_POLL_INTERVAL: f64 = 0.25;
+
+/// How often to capture the status window's thumbnails again.
+const TASKLIST_REFRESH_INTERVAL: f64 = 1.;
+
+/// How long to wait after displays change before moving windows.
+const DISPLAY_SETTLE_DELAY: f64 = 0.5;
+
+/// How long to wait after waking or unlocking before moving windows.
+const WAKE_SETTLE_DELAY: f64 = 1.;
+
+/// How often to start observing newly managed apps.
+const APP_OBSERVER_REFRESH_INTERVAL: f64 = 2.;
+
+/// Observe focus changes and window moves in every app with managed windows,
+/// so the border can follow the focused window.
+fn observe_apps(state: &'static RefCell<WindowManager>) -> Timer {
+    let mut observers: HashMap<i32, AppObserver> = HashMap::new();
+    let notifications = [
+        kAXApplicationActivatedNotification,
+        kAXFocusedWindowChangedNotification,
+        kAXWindowMovedNotification,
+        kAXWindowResizedNotification,
+    ];
+    Timer::repeating(APP_OBSERVER_REFRESH_INTERVAL, move || {
+        let pids = state.borrow().window_pids();
+        observers.retain(|pid, _| pids.contains(pid));
+        for pid in pids {
+            if observers.contains_key(&pid) {
+                continue;
+            }
+            let observer = AppObserver::new(pid, &notifications, move |notification| {
+                if notification == kAXWindowMovedNotification
+                    || notification == kAXWindowResizedNotification
+                {
+                    forget_frames(pid);
+                }
+                // Skip notifications that arrive while we are handling an event.
+                if let Ok(mut wm) = state.try_borrow_mut() {
+                    wm.focus_changed()
+                        .unwrap_or_else(|e| eprintln!("While handling focus change: {:?}", e));
+                }
+            });
+            match observer {
+                Ok(observer) => {
+                    observers.insert(pid, observer);
+                }
+                Err(e) => eprintln!("While observing pid {}: {:?}", pid, e),
+            }
+        }
+    })
+}
+
+/// Exit successfully if another instance is running, so launching it twice,
+/// e.g. from a login item, isn't an error, unless `replace` is set, in which
+/// case ask it to shut down and return the state it hands over.
+fn take_over_running_instance(replace: bool) -> Option<SavedState> {
+    if send_request(&Request::Ping).is_err() {
+        return None;
+    }
+    if !replace {
+        eprintln!("awesome-rs is already running. Use --replace to take over from it.");
+        std::process::exit(0);
+    }
+
+    println!("Replacing running instance");
+    let saved_state = match send_request(&Request::Shutdown) {
+        Ok(Response::State(saved_state)) => Some(saved_state),
+        Ok(response) => {
+            eprintln!("Unexpected response to shutdown: {:?}", response);
+            None
+        }
+        Err(e) => {
+            eprintln!("While shutting down running instance: {:?}", e);
+            None
+        }
+    };
+    // Give the old instance a moment to exit and release its event tap.
+    for _ in 0..20 {
+        if !socket_path().exists() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    saved_state
+}
+
+/// The button number of `OtherMouseDown` events from the middle button.
+const MIDDLE_MOUSE_BUTTON: i64 = 2;
+
+/// What to do with the event that performed `action`: it still reaches the
+/// focused app if the action is in `bindings.pass_through`.
+fn performed_event_result(config: &Config, action: &Action) -> CGEventTapCallbackResult {
+    if config.bindings.passes_through(action) {
+        CGEventTapCallbackResult::Keep
+    } else {
+        CGEventTapCallbackResult::Drop
+    }
+}
+
 fn mk_event_tap_callback<'a>(
     state: &'a RefCell<WindowManager>,
 ) -> impl Fn(*const c_void, CGEventType, &CGEvent) -> CGEventTapCallbackResult + 'a {
     use CGEventType::*;
     |_, event_type, event| -> CGEventTapCallbackResult {
         let mut s = state.borrow_mut();
+        if matches!(event_type, TapDisabledByTimeout | TapDisabledByUserInput) {
+            // macOS disables the tap if we are too slow to handle an event,
+            // or while Secure Input is on, e.g. in a password field.
+            eprintln!("Event tap was disabled ({:?}), re-enabling it", event_type);
+            EVENT_TAP_PORT.with(|port| unsafe { CGEventTapEnable(port.get(), true) });
+            let warning = match event_type {
+                TapDisabledByTimeout => "Keyboard shortcuts were disabled: too slow to respond",
+                _ => "Keyboard shortcuts are disabled while Secure Input is on",
+            };
+            if matches!(event_type, TapDisabledByTimeout) {
+                // Secure Input comes and goes with every password field.
+                alert(warning, "awesome-rs re-enabled them.");
+            }
+            s.set_event_tap_warning(Some(warning.to_string()));
+            return CGEventTapCallbackResult::Keep;
+        }
+        // The tap is working again.
+        s.set_event_tap_warning(None);
+        if s.is_passthrough() {
+            // Only watch for the key that turns passthrough off.
+            return match Action::of_cg_event(&event, &s.mode(), s.layout()) {
+                Some(action @ Action::TogglePassthrough) => {
+                    s.do_action(&action)
+                        .unwrap_or_else(|e| eprintln!("While performing {:?}: {:?}", action, e));
+                    CGEventTapCallbackResult::Drop
+                }
+                _ => CGEventTapCallbackResult::Keep,
+            };
+        }
+        if matches!(event_type, KeyDown | FlagsChanged) && s.frontmost_app_passes_through() {
+            return CGEventTapCallbackResult::Keep;
+        }
         match event_type {
-            MouseMoved => {
+            FlagsChanged => {
+                if let Some(action) = s.double_tap_flags_changed(&event) {
+                    s.do_action(&action)
+                        .unwrap_or_else(|e| eprintln!("While performing {:?}: {:?}", action, e));
+                    // Other apps should see the modifier released.
+                    return CGEventTapCallbackResult::Keep;
+                }
+            }
+            KeyDown | LeftMouseDown | RightMouseDown | OtherMouseDown => s.interrupt_double_tap(),
+            _ => (),
+        }
+        match event_type {
+            LeftMouseDown => {
+                if s.config().drag_window.matches(event.get_flags()) {
+                    s.start_drag(&event.location())
+                        .unwrap_or_else(|e| eprintln!("While starting drag: {:?}", e));
+                    if s.drag_window().is_some() {
+                        return CGEventTapCallbackResult::Drop;
+                    }
+                }
+                s.set_active_window_at_point(&event.location())
+                    .unwrap_or_else(|e| eprintln!("While focusing clicked window: {:?}", e));
+            }
+            LeftMouseDragged => {
                 if let Some(dw) = s.drag_window() {
-                    dw.set_position_around(&event.location()).unwrap()
+                    dw.set_position_around(&event.location())
+                        .unwrap_or_else(|e| eprintln!("While dragging window: {:?}", e));
+                    return CGEventTapCallbackResult::Drop;
+                }
+            }
+            LeftMouseUp => {
+                if s.drag_window().is_some() {
+                    s.end_drag(&event.location())
+                        .unwrap_or_else(|e| eprintln!("While ending drag: {:?}", e));
+                    return CGEventTapCallbackResult::Drop;
+                }
+            }
+            RightMouseDown => {
+                if s.config().resize_window.matches(event.get_flags()) {
+                    s.start_resize(&event.location())
+                        .unwrap_or_else(|e| eprintln!("While starting resize: {:?}", e));
+                    if s.resize_window().is_some() {
+                        return CGEventTapCallbackResult::Drop;
+                    }
+                }
+            }
+            RightMouseDragged => {
+                if let Some(rw) = s.resize_window() {
+                    rw.resize_to(&event.location())
+                        .unwrap_or_else(|e| eprintln!("While resizing window: {:?}", e));
+                    return CGEventTapCallbackResult::Drop;
+                }
+            }
+            RightMouseUp => {
+                if s.resize_window().is_some() {
+                    s.end_resize(&event.location())
+                        .unwrap_or_else(|e| eprintln!("While ending resize: {:?}", e));
+                    return CGEventTapCallbackResult::Drop;
                 }
             }
-            FlagsChanged if AWESOME_ENABLE_DRAG_WINDOW => {
-                if event
-                    .get_flags()
-                    .contains(awesome_normal_mode_drag_window_flags())
-                    && s.is_normal_mode()
+            OtherMouseDown => {
+                let button = event.get_integer_value_field(EventField::MOUSE_EVENT_BUTTON_NUMBER);
+                if button == MIDDLE_MOUSE_BUTTON
+                    && s.config().middle_click.matches(event.get_flags())
                 {
-                    let ws = DragWindow::at_mouse_location().unwrap_or_else(|e| {
-                        eprintln!("While getting window at mouse location: {}", e);
-                        None
-                    });
-                    s.set_drag_window(ws);
-                    if let Some(drag_window) = s.drag_window() {
-                        drag_window
-                            .activate_window()
-                            .unwrap_or_else(|e| eprintln!("While activating drag window: {:?}", e))
+                    match s.config().middle_click.action() {
+                        Ok(action) => s
+                            .do_action_at_point(&event.location(), &action)
+                            .unwrap_or_else(|e| {
+                                eprintln!("While performing {:?}: {:?}", action, e)
+                            }),
+                        Err(e) => eprintln!("While parsing middle_click.action: {:?}", e),
                     }
-                } else {
-                    s.set_drag_window(None);
+                    return CGEventTapCallbackResult::Drop;
                 }
             }
+            KeyDown => {
+                let action = match s.chord_key_down(&event) {
+                    ChordKey::NotChord => s.key_binding(&event),
+                    ChordKey::Pending | ChordKey::Abandoned => {
+                        return CGEventTapCallbackResult::Drop
+                    }
+                    ChordKey::Complete(action) => Some(action),
+                };
+                if let Some(action) = action {
+                    s.do_key_action(&action, &event)
+                        .unwrap_or_else(|e| eprintln!("While performing {:?}: {:?}", action, e));
+                    return performed_event_result(s.config(), &action);
+                }
+            }
+            KeyUp => {
+                s.end_key_repeat()
+                    .unwrap_or_else(|e| eprintln!("While ending key repeat: {:?}", e));
+                return CGEventTapCallbackResult::Keep;
+            }
             _ => (),
         };
         match Action::of_cg_event(&event, &s.mode(), s.layout()) {
             Some(action) => {
-                s.do_action(&action)
-                    .unwrap_or_else(|e| eprintln!("While performing {:?}: {:?}", action, e));
+                let result = match event_type {
+                    KeyDown => s.do_key_action(&action, &event),
+                    _ => s.do_action(&action),
+                };
+                result.unwrap_or_else(|e| eprintln!("While performing {:?}: {:?}", action, e));
+                performed_event_result(s.config(), &action)
+            }
+            None if matches!(event_type, KeyDown) && s.captures_keyboard() => {
                 CGEventTapCallbackResult::Drop
             }
             None => CGEventTapCallbackResult::Keep,
@@ -0,0 +1,207 @@
+use std::{cell::RefCell, ffi::c_void, rc::Rc, sync::Once};
+
+use cocoa::{
+    appkit::{NSMenu, NSMenuItem, NSStatusBar, NSStatusItem, NSVariableStatusItemLength},
+    base::{id, nil, NO, YES},
+    foundation::{NSInteger, NSString},
+};
+use objc::{
+    class,
+    declare::ClassDecl,
+    msg_send,
+    runtime::{Class, Object, Sel},
+    sel, sel_impl,
+};
+
+use crate::window_manager::DisplayID;
+
+/// What a menu item focuses when it is clicked.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MenuTarget {
+    Group {
+        display_id: DisplayID,
+        group: u8,
+    },
+    Window {
+        display_id: DisplayID,
+        group: u8,
+        window_id: uuid::Uuid,
+    },
+}
+
+/// A group as listed in the menu.
+#[derive(Debug)]
+pub struct MenuGroup {
+    pub display_id: DisplayID,
+    pub group: u8,
+    pub name: Option<String>,
+    pub is_active: bool,
+    /// Window ids and titles.
+    pub windows: Vec<(uuid::Uuid, String)>,
+}
+
+type Callback = Box<dyn Fn(isize)>;
+
+const CALLBACK_IVAR: &str = "callback";
+
+extern "C" fn item_selected(this: &Object, _cmd: Sel, sender: id) {
+    unsafe {
+        let callback = *this.get_ivar::<*mut c_void>(CALLBACK_IVAR) as *const Callback;
+        let tag: NSInteger = msg_send![sender, tag];
+        (*callback)(tag as isize);
+    }
+}
+
+/// An Objective-C class whose instances forward `itemSelected:` to a Rust
+/// callback.
+fn menu_target_class() -> &'static Class {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let mut decl = ClassDecl::new("AwesomeMenuTarget", class!(NSObject)).unwrap();
+        decl.add_ivar::<*mut c_void>(CALLBACK_IVAR);
+        unsafe {
+            decl.add_method(
+                sel!(itemSelected:),
+                item_selected as extern "C" fn(&Object, Sel, id),
+            );
+        }
+        decl.register();
+    });
+    Class::get("AwesomeMenuTarget").unwrap()
+}
+
+/// The target of menu items and buttons whose action is `itemSelected:`,
+/// calling back with the sender's tag.
+pub(crate) struct TagTarget {
+    target: id,
+    callback: *mut Callback,
+}
+
+impl TagTarget {
+    pub(crate) fn new(f: impl Fn(isize) + 'static) -> Self {
+        let callback: *mut Callback = Box::into_raw(Box::new(Box::new(f)));
+        unsafe {
+            let target: id = msg_send![menu_target_class(), new];
+            (*target).set_ivar::<*mut c_void>(CALLBACK_IVAR, callback as *mut c_void);
+            Self { target, callback }
+        }
+    }
+
+    pub(crate) fn id(&self) -> id {
+        self.target
+    }
+}
+
+impl Drop for TagTarget {
+    fn drop(&mut self) {
+        unsafe {
+            let () = msg_send![self.target, release];
+            drop(Box::from_raw(self.callback));
+        }
+    }
+}
+
+/// A menu bar item showing the mode and active group, with a menu listing the
+/// groups and their windows.
+pub struct MenuBar {
+    status_item: id,
+    target: TagTarget,
+    /// The target of each menu item, indexed by the item's tag.
+    targets: Rc<RefCell<Vec<MenuTarget>>>,
+}
+
+impl MenuBar {
+    /// `on_select` is called on the main thread when a menu item is clicked.
+    pub fn new(on_select: impl Fn(MenuTarget) + 'static) -> Self {
+        let targets: Rc<RefCell<Vec<MenuTarget>>> = Rc::new(RefCell::new(vec![]));
+        let target = {
+            let targets = targets.clone();
+            TagTarget::new(move |tag| {
+                let target = targets.borrow().get(tag as usize).cloned();
+                if let Some(target) = target {
+                    on_select(target);
+                }
+            })
+        };
+        unsafe {
+            let status_item =
+                NSStatusBar::systemStatusBar(nil).statusItemWithLength_(NSVariableStatusItemLength);
+            let () = msg_send![status_item, retain];
+            Self {
+                status_item,
+                target,
+                targets,
+            }
+        }
+    }
+
+    pub fn update(&self, title: &str, groups: &[MenuGroup]) {
+        let mut targets = self.targets.borrow_mut();
+        targets.clear();
+        unsafe {
+            let button: id = msg_send![self.status_item, button];
+            let () = msg_send![button, setTitle: NSString::alloc(nil).init_str(title)];
+
+            let menu = NSMenu::new(nil);
+            let () = msg_send![menu, setAutoenablesItems: NO];
+            for (i, g) in groups.iter().enumerate() {
+                if i > 0 {
+                    menu.addItem_(NSMenuItem::separatorItem(nil));
+                }
+                let title = match &g.name {
+                    Some(name) => format!("Group {} {} (display {})", g.group, name, g.display_id),
+                    None => format!("Group {} (display {})", g.group, g.display_id),
+                };
+                let item = self.menu_item(&title, targets.len());
+                let () = msg_send![item, setState: if g.is_active { 1 as NSInteger } else { 0 }];
+                menu.addItem_(item);
+                let () = msg_send![item, release];
+                targets.push(MenuTarget::Group {
+                    display_id: g.display_id,
+                    group: g.group,
+                });
+                for (window_id, window_title) in g.windows.iter() {
+                    let item = self.menu_item(window_title, targets.len());
+                    let () = msg_send![item, setIndentationLevel: 1 as NSInteger];
+                    menu.addItem_(item);
+                    let () = msg_send![item, release];
+                    targets.push(MenuTarget::Window {
+                        display_id: g.display_id,
+                        group: g.group,
+                        window_id: *window_id,
+                    });
+                }
+            }
+            self.status_item.setMenu_(menu);
+            let () = msg_send![menu, release];
+        }
+    }
+
+    /// Returns a retained item.
+    unsafe fn menu_item(&self, title: &str, tag: usize) -> id {
+        let item = NSMenuItem::alloc(nil).initWithTitle_action_keyEquivalent_(
+            NSString::alloc(nil).init_str(title),
+            sel!(itemSelected:),
+            NSString::alloc(nil).init_str(""),
+        );
+        item.setTarget_(self.target.id());
+        let () = msg_send![item, setTag: tag as NSInteger];
+        let () = msg_send![item, setEnabled: YES];
+        item
+    }
+}
+
+impl Drop for MenuBar {
+    fn drop(&mut self) {
+        unsafe {
+            NSStatusBar::systemStatusBar(nil).removeStatusItem_(self.status_item);
+            let () = msg_send![self.status_item, release];
+        }
+    }
+}
+
+impl std::fmt::Debug for MenuBar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MenuBar")
+    }
+}
@@ -1,6 +1,30 @@
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Mode {
     Normal,
     Insert,
     InsertNormal, // Temporary normal mode while keybinding held
+    /// The window switcher is open. If `release_to_select`, releasing <opt>
+    /// focuses the selected window.
+    Switcher {
+        release_to_select: bool,
+    },
+    /// The command palette is open and takes all key presses.
+    Palette,
+    /// The window manager is off: keys and clicks go straight to apps and
+    /// nothing is laid out, until passthrough is toggled off.
+    Passthrough,
+}
+
+impl Mode {
+    /// The mode's name in hook events.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Normal => "normal",
+            Mode::Insert => "insert",
+            Mode::InsertNormal => "insert-normal",
+            Mode::Switcher { .. } => "switcher",
+            Mode::Palette => "palette",
+            Mode::Passthrough => "passthrough",
+        }
+    }
 }
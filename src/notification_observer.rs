@@ -0,0 +1,150 @@
+use std::{ffi::c_void, sync::Once};
+
+use cocoa::{
+    base::{id, nil, YES},
+    foundation::NSString,
+};
+use objc::{
+    class,
+    declare::ClassDecl,
+    msg_send,
+    runtime::{Class, Object, Sel},
+    sel, sel_impl,
+};
+
+use crate::window::nsstring_to_string;
+
+type Callback = Box<dyn Fn(&str)>;
+
+const CALLBACK_IVAR: &str = "callback";
+
+/// Where a notification is posted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationCenter {
+    /// `NSWorkspace`'s center, e.g. for sleep and wake.
+    Workspace,
+    /// The center for notifications between processes, e.g. for the screen
+    /// locking.
+    Distributed,
+}
+
+impl NotificationCenter {
+    unsafe fn get(self) -> id {
+        match self {
+            NotificationCenter::Workspace => {
+                let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+                msg_send![workspace, notificationCenter]
+            }
+            NotificationCenter::Distributed => {
+                msg_send![class!(NSDistributedNotificationCenter), defaultCenter]
+            }
+        }
+    }
+}
+
+extern "C" fn notification_received(this: &Object, _cmd: Sel, notification: id) {
+    unsafe {
+        let callback = *this.get_ivar::<*mut c_void>(CALLBACK_IVAR) as *const Callback;
+        let name: id = msg_send![notification, name];
+        if let Some(name) = nsstring_to_string(name) {
+            (*callback)(&name);
+        }
+    }
+}
+
+/// An Objective-C class whose instances forward `notificationReceived:` to a
+/// Rust callback.
+fn observer_class() -> &'static Class {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let mut decl = ClassDecl::new("AwesomeNotificationObserver", class!(NSObject)).unwrap();
+        decl.add_ivar::<*mut c_void>(CALLBACK_IVAR);
+        unsafe {
+            decl.add_method(
+                sel!(notificationReceived:),
+                notification_received as extern "C" fn(&Object, Sel, id),
+            );
+        }
+        decl.register();
+    });
+    Class::get("AwesomeNotificationObserver").unwrap()
+}
+
+/// Calls back on the main thread with the notification's name when any of
+/// the given notifications is posted, until dropped.
+pub struct NotificationObserver {
+    observer: id,
+    centers: Vec<NotificationCenter>,
+    callback: *mut Callback,
+}
+
+impl NotificationObserver {
+    pub fn new(notifications: &[(NotificationCenter, &str)], f: impl Fn(&str) + 'static) -> Self {
+        let callback: *mut Callback = Box::into_raw(Box::new(Box::new(f)));
+        let mut centers = vec![];
+        unsafe {
+            let observer: id = msg_send![observer_class(), new];
+            (*observer).set_ivar::<*mut c_void>(CALLBACK_IVAR, callback as *mut c_void);
+            for (center, name) in notifications {
+                let name = NSString::alloc(nil).init_str(name);
+                let () = msg_send![
+                    center.get(),
+                    addObserver: observer
+                    selector: sel!(notificationReceived:)
+                    name: name
+                    object: nil
+                ];
+                if !centers.contains(center) {
+                    centers.push(*center);
+                }
+            }
+            Self {
+                observer,
+                centers,
+                callback,
+            }
+        }
+    }
+}
+
+impl Drop for NotificationObserver {
+    fn drop(&mut self) {
+        unsafe {
+            for center in self.centers.iter() {
+                let () = msg_send![center.get(), removeObserver: self.observer];
+            }
+            let () = msg_send![self.observer, release];
+            drop(Box::from_raw(self.callback));
+        }
+    }
+}
+
+impl std::fmt::Debug for NotificationObserver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NotificationObserver")
+    }
+}
+
+/// Post a notification to other processes, with string values in its
+/// `userInfo`.
+pub fn post_distributed_notification(name: &str, user_info: &[(&str, String)]) {
+    unsafe {
+        let info: id = msg_send![class!(NSMutableDictionary), dictionary];
+        for (key, value) in user_info {
+            let key = NSString::alloc(nil).init_str(key);
+            let value = NSString::alloc(nil).init_str(value);
+            let () = msg_send![info, setObject: value forKey: key];
+            let () = msg_send![key, release];
+            let () = msg_send![value, release];
+        }
+        let name = NSString::alloc(nil).init_str(name);
+        let () = msg_send![
+            NotificationCenter::Distributed.get(),
+            postNotificationName: name
+            object: nil
+            userInfo: info
+            deliverImmediately: YES
+        ];
+        let () = msg_send![name, release];
+    }
+}
@@ -0,0 +1,95 @@
+use std::{ffi::c_void, ptr};
+
+use accessibility::AXUIElement;
+use accessibility_sys::{
+    kAXErrorSuccess, pid_t, AXObserverAddNotification, AXObserverCreate,
+    AXObserverGetRunLoopSource, AXObserverRef, AXUIElementRef,
+};
+use anyhow::{anyhow, Result};
+use core_foundation::{
+    base::{CFRelease, TCFType},
+    runloop::{kCFRunLoopCommonModes, CFRunLoop, CFRunLoopSource},
+    string::{CFString, CFStringRef},
+};
+
+type Callback = Box<dyn FnMut(&str)>;
+
+unsafe extern "C" fn observer_callback(
+    _observer: AXObserverRef,
+    _element: AXUIElementRef,
+    notification: CFStringRef,
+    refcon: *mut c_void,
+) {
+    let callback = &mut *(refcon as *mut Callback);
+    let notification = CFString::wrap_under_get_rule(notification).to_string();
+    callback(&notification);
+}
+
+/// Calls back on the current thread's run loop when an application posts one
+/// of the given accessibility notifications, with the notification's name.
+pub struct AppObserver {
+    observer: AXObserverRef,
+    loop_source: CFRunLoopSource,
+    callback: *mut Callback,
+}
+
+impl AppObserver {
+    pub fn new(
+        pid: pid_t,
+        notifications: &[&'static str],
+        f: impl FnMut(&str) + 'static,
+    ) -> Result<Self> {
+        let mut observer: AXObserverRef = ptr::null_mut();
+        let err = unsafe { AXObserverCreate(pid, observer_callback, &mut observer) };
+        if err != kAXErrorSuccess {
+            return Err(anyhow!("AXObserverCreate failed for pid {}: {}", pid, err));
+        }
+
+        let callback: *mut Callback = Box::into_raw(Box::new(Box::new(f)));
+        let app = AXUIElement::application(pid);
+        for notification in notifications {
+            let notification = CFString::from_static_string(notification);
+            let err = unsafe {
+                AXObserverAddNotification(
+                    observer,
+                    app.as_concrete_TypeRef(),
+                    notification.as_concrete_TypeRef(),
+                    callback as *mut c_void,
+                )
+            };
+            if err != kAXErrorSuccess {
+                eprintln!(
+                    "Could not observe {} for pid {}: {}",
+                    notification, pid, err
+                );
+            }
+        }
+
+        let loop_source =
+            unsafe { CFRunLoopSource::wrap_under_get_rule(AXObserverGetRunLoopSource(observer)) };
+        unsafe {
+            CFRunLoop::get_current().add_source(&loop_source, kCFRunLoopCommonModes);
+        }
+        Ok(Self {
+            observer,
+            loop_source,
+            callback,
+        })
+    }
+}
+
+impl Drop for AppObserver {
+    fn drop(&mut self) {
+        unsafe {
+            CFRunLoop::get_current().remove_source(&self.loop_source, kCFRunLoopCommonModes);
+            CFRelease(self.observer as *const c_void);
+            drop(Box::from_raw(self.callback));
+        }
+    }
+}
+
+impl std::fmt::Debug for AppObserver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AppObserver")
+    }
+}
@@ -0,0 +1,80 @@
+use cocoa::{
+    appkit::NSWindow,
+    base::{id, NO, YES},
+    foundation::{NSPoint, NSRect},
+};
+use core_graphics::display::{CGDisplay, CGRect};
+use objc::{msg_send, sel, sel_impl};
+
+/// Height in points of the display overlays were sized for: a laptop panel
+/// at its default resolution.
+const REFERENCE_HEIGHT: f64 = 900.;
+const MAX_SCALE: f64 = 2.5;
+
+/// NSPopUpMenuWindowLevel: panels shown at this level, like the window
+/// switcher, go above the highlight overlay.
+pub const POPUP_WINDOW_LEVEL: i64 = 101;
+
+/// How much to enlarge overlays shown on the display with these bounds, so
+/// that their text is readable on a large display running at a high
+/// resolution. Bounds are in points, so Retina displays are already taken
+/// care of.
+pub fn overlay_scale(bounds: &CGRect) -> f64 {
+    (bounds.size.height / REFERENCE_HEIGHT).clamp(1., MAX_SCALE)
+}
+
+/// Move a window to the middle of the display with these bounds.
+pub unsafe fn center_on(window: id, bounds: &CGRect) {
+    // (0,0) is bottom-left of main display, y increases up the screen
+    let m = CGDisplay::main().bounds();
+    let frame: NSRect = NSWindow::frame(window);
+    let x = bounds.origin.x + (bounds.size.width - frame.size.width) / 2.;
+    let y = m.size.height - bounds.origin.y - bounds.size.height
+        + (bounds.size.height - frame.size.height) / 2.;
+    window.setFrameOrigin_(NSPoint::new(x, y));
+}
+
+/// A window we created to draw on top of other apps. It is closed and
+/// released when dropped, so it can neither leak nor be used after it is
+/// closed. Like `id`, it is neither `Send` nor `Sync`, so it stays on the main
+/// thread.
+pub struct OverlayWindow {
+    window: id,
+}
+
+impl OverlayWindow {
+    /// Take ownership of a window returned by `alloc`/`init`.
+    pub unsafe fn new(window: id) -> Self {
+        // Panels aren't released when closed by default, windows are. Either
+        // way, we release it ourselves.
+        window.setReleasedWhenClosed_(NO);
+        Self { window }
+    }
+
+    pub fn content_view(&self) -> id {
+        unsafe { self.window.contentView() }
+    }
+
+    pub fn set_frame(&self, rect: NSRect) {
+        unsafe { self.window.setFrame_display_(rect, YES) };
+    }
+
+    pub fn order_front(&self) {
+        unsafe { self.window.orderFrontRegardless() };
+    }
+}
+
+impl Drop for OverlayWindow {
+    fn drop(&mut self) {
+        unsafe {
+            self.window.close();
+            let () = msg_send![self.window, release];
+        }
+    }
+}
+
+impl std::fmt::Debug for OverlayWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "OverlayWindow({:p})", self.window)
+    }
+}
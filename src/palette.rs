@@ -0,0 +1,265 @@
+use std::ffi::c_void;
+
+use cocoa::{
+    appkit::{
+        NSBackingStoreType::NSBackingStoreBuffered, NSColor, NSView, NSWindow, NSWindowStyleMask,
+    },
+    base::{id, nil, NO},
+    foundation::{NSPoint, NSRect, NSSize, NSString},
+};
+use core_graphics::{display::CGRect, event::CGEvent};
+use foreign_types::ForeignType;
+use objc::{class, msg_send, sel, sel_impl};
+
+use crate::{
+    action::{ActionInfo, ACTIONS},
+    overlay::{center_on, overlay_scale, POPUP_WINDOW_LEVEL},
+};
+
+const WIDTH: f64 = 640.;
+const ROW_HEIGHT: f64 = 18.;
+const MAX_ROWS: usize = 12;
+const PADDING: f64 = 12.;
+const FONT_SIZE: f64 = 13.;
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn CGEventKeyboardGetUnicodeString(
+        event: *mut c_void,
+        max_len: usize,
+        actual_len: *mut usize,
+        buf: *mut u16,
+    );
+}
+
+/// The text a key press would type, if it types any.
+pub fn typed_text(event: &CGEvent) -> Option<String> {
+    let mut buf = [0u16; 8];
+    let mut len = 0;
+    unsafe {
+        CGEventKeyboardGetUnicodeString(
+            event.as_ptr() as *mut c_void,
+            buf.len(),
+            &mut len,
+            buf.as_mut_ptr(),
+        )
+    };
+    let text = String::from_utf16_lossy(&buf[..usize::min(len, buf.len())]);
+    if text.is_empty() || text.chars().any(|c| c.is_control()) {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+/// Score `candidate` if it contains the characters of `pattern` in order.
+/// Higher is better: consecutive matches and matches at the start of a word
+/// score more, and shorter candidates win ties.
+fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i64> {
+    let mut chars = candidate.char_indices();
+    let mut last: Option<usize> = None;
+    let mut score = 0;
+    for p in pattern.chars().map(|c| c.to_ascii_lowercase()) {
+        let (idx, _) = chars.by_ref().find(|(_, c)| c.to_ascii_lowercase() == p)?;
+        score += match last {
+            Some(last) if idx == last + 1 => 5,
+            _ => 1,
+        };
+        if idx == 0 || candidate[..idx].ends_with('-') {
+            score += 3;
+        }
+        last = Some(idx);
+    }
+    Some(score * 100 - candidate.len() as i64)
+}
+
+fn describe(info: &ActionInfo) -> String {
+    let mut line = info.id.to_string();
+    for arg in info.args.iter() {
+        line.push_str(&format!(" <{}>", arg.name));
+    }
+    format!("{:<40} {}", line, info.description)
+}
+
+/// A `:` prompt listing every action, filtered by what has been typed. The
+/// first word of the query picks the action, any further words are its
+/// arguments. The panel is closed when the palette is dropped.
+pub struct Palette {
+    panel: id,
+    prompt: id,
+    list: id,
+    query: String,
+    /// Indices into `ACTIONS`, best match first.
+    matches: Vec<usize>,
+    selected: usize,
+    error: Option<String>,
+}
+
+impl Palette {
+    /// Open the palette in the middle of the display with these bounds.
+    pub fn open(bounds: &CGRect) -> Self {
+        let scale = overlay_scale(bounds);
+        let width = WIDTH * scale;
+        let row_height = ROW_HEIGHT * scale;
+        let padding = PADDING * scale;
+        let list_height = MAX_ROWS as f64 * row_height;
+        let height = row_height + list_height + 3. * padding;
+        unsafe {
+            let panel: id = msg_send![class!(NSPanel), alloc];
+            panel.initWithContentRect_styleMask_backing_defer_(
+                NSRect::new(NSPoint::new(0., 0.), NSSize::new(width, height)),
+                NSWindowStyleMask::NSBorderlessWindowMask
+                    | NSWindowStyleMask::NSNonactivatingPanelMask,
+                NSBackingStoreBuffered,
+                NO,
+            );
+            panel.setOpaque_(NO);
+            panel.setBackgroundColor_(NSColor::colorWithSRGBRed_green_blue_alpha_(
+                nil, 0.1, 0.1, 0.1, 0.9,
+            ));
+            panel.setLevel_(POPUP_WINDOW_LEVEL);
+            center_on(panel, bounds);
+
+            let content = panel.contentView();
+            let prompt = make_label(
+                NSRect::new(
+                    NSPoint::new(padding, height - padding - row_height),
+                    NSSize::new(width - 2. * padding, row_height),
+                ),
+                scale,
+            );
+            content.addSubview_(prompt);
+            let list = make_label(
+                NSRect::new(
+                    NSPoint::new(padding, padding),
+                    NSSize::new(width - 2. * padding, list_height),
+                ),
+                scale,
+            );
+            content.addSubview_(list);
+
+            let mut palette = Self {
+                panel,
+                prompt,
+                list,
+                query: String::new(),
+                matches: vec![],
+                selected: 0,
+                error: None,
+            };
+            palette.refresh();
+            panel.orderFrontRegardless();
+            palette
+        }
+    }
+
+    pub fn input(&mut self, text: &str) {
+        self.query.push_str(text);
+        self.refresh();
+    }
+
+    pub fn backspace(&mut self) {
+        self.query.pop();
+        self.refresh();
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.matches.is_empty() {
+            self.selected = (self.selected + 1) % self.matches.len();
+            self.render();
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if !self.matches.is_empty() {
+            self.selected = (self.selected + self.matches.len() - 1) % self.matches.len();
+            self.render();
+        }
+    }
+
+    /// The action id and arguments to run: the first word of the query if it
+    /// is an action id, otherwise the selected match.
+    pub fn command(&self) -> Option<(&'static str, Vec<String>)> {
+        let mut words = self.query.split_whitespace();
+        let first = words.next().unwrap_or("");
+        let args = words.map(|w| w.to_string()).collect();
+        ACTIONS
+            .iter()
+            .find(|info| info.id == first)
+            .or_else(|| self.matches.get(self.selected).map(|idx| &ACTIONS[*idx]))
+            .map(|info| (info.id, args))
+    }
+
+    /// Keep the palette open and show why the command failed.
+    pub fn show_error(&mut self, message: String) {
+        self.error = Some(message);
+        self.render();
+    }
+
+    fn refresh(&mut self) {
+        let pattern = self.query.split_whitespace().next().unwrap_or("");
+        let mut scored: Vec<(i64, usize)> = ACTIONS
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, info)| fuzzy_score(pattern, info.id).map(|score| (score, idx)))
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        self.matches = scored.into_iter().map(|(_, idx)| idx).collect();
+        self.selected = 0;
+        self.error = None;
+        self.render();
+    }
+
+    fn render(&self) {
+        let mut lines = vec![];
+        if let Some(error) = &self.error {
+            lines.push(format!("! {}", error));
+        }
+        let rows = MAX_ROWS - lines.len();
+        let first = (self.selected + 1).saturating_sub(rows);
+        for (i, idx) in self.matches.iter().enumerate().skip(first).take(rows) {
+            let marker = if i == self.selected { ">" } else { " " };
+            lines.push(format!("{} {}", marker, describe(&ACTIONS[*idx])));
+        }
+        unsafe {
+            let prompt = format!(":{}", self.query);
+            let () = msg_send![self.prompt, setStringValue: NSString::alloc(nil).init_str(&prompt)];
+            let list = lines.join("\n");
+            let () = msg_send![self.list, setStringValue: NSString::alloc(nil).init_str(&list)];
+        }
+    }
+}
+
+/// A non-editable, monospaced white label. Returns a retained view.
+unsafe fn make_label(frame: NSRect, scale: f64) -> id {
+    let label: id = msg_send![class!(NSTextField), alloc];
+    let label: id = msg_send![label, initWithFrame: frame];
+    let () = msg_send![label, setEditable: NO];
+    let () = msg_send![label, setSelectable: NO];
+    let () = msg_send![label, setBezeled: NO];
+    let () = msg_send![label, setDrawsBackground: NO];
+    let white: id = msg_send![class!(NSColor), whiteColor];
+    let () = msg_send![label, setTextColor: white];
+    let font: id = msg_send![class!(NSFont), userFixedPitchFontOfSize: FONT_SIZE * scale];
+    let () = msg_send![label, setFont: font];
+    label
+}
+
+impl Drop for Palette {
+    fn drop(&mut self) {
+        unsafe {
+            let () = msg_send![self.prompt, release];
+            let () = msg_send![self.list, release];
+            self.panel.close();
+        }
+    }
+}
+
+impl std::fmt::Debug for Palette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Palette")
+            .field("query", &self.query)
+            .field("selected", &self.selected)
+            .finish()
+    }
+}
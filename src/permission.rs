@@ -0,0 +1,87 @@
+use accessibility::AXUIElement;
+use cocoa::{
+    appkit::{NSBackingStoreType::NSBackingStoreBuffered, NSView, NSWindow, NSWindowStyleMask},
+    base::{id, nil, NO},
+    foundation::{NSPoint, NSRect, NSSize, NSString},
+};
+use core_graphics::display::CGDisplay;
+use objc::{class, msg_send, sel, sel_impl};
+
+use crate::overlay::{center_on, OverlayWindow};
+
+const WIDTH: f64 = 460.;
+const HEIGHT: f64 = 190.;
+const MARGIN: f64 = 20.;
+const FONT_SIZE: f64 = 13.;
+/// NSFloatingWindowLevel, so it isn't lost behind System Settings.
+const PERMISSION_WINDOW_LEVEL: i64 = 3;
+
+const EXPLANATION: &str = "awesome-rs needs Accessibility permission to move \
+windows and handle keyboard shortcuts.
+
+Open System Settings > Privacy & Security > Accessibility, and turn on \
+awesome-rs (or the terminal you started it from). If it is already on, turn \
+it off and on again.
+
+This window closes by itself once permission is granted.";
+
+/// Whether we may use the Accessibility API and tap keyboard events.
+pub fn is_trusted() -> bool {
+    AXUIElement::application_is_trusted()
+}
+
+/// Like `is_trusted`, but if we aren't, ask macOS to show its prompt that
+/// offers to open System Settings.
+pub fn prompt_for_trust() -> bool {
+    AXUIElement::application_is_trusted_with_prompt()
+}
+
+/// A window explaining how to grant Accessibility permission, shown until it
+/// is dropped.
+pub struct PermissionWindow {
+    _window: OverlayWindow,
+}
+
+impl PermissionWindow {
+    pub fn show() -> Self {
+        let window = unsafe {
+            let window: id = msg_send![class!(NSWindow), alloc];
+            window.initWithContentRect_styleMask_backing_defer_(
+                NSRect::new(NSPoint::new(0., 0.), NSSize::new(WIDTH, HEIGHT)),
+                NSWindowStyleMask::NSTitledWindowMask,
+                NSBackingStoreBuffered,
+                NO,
+            );
+            window.setTitle_(NSString::alloc(nil).init_str("awesome-rs needs permission"));
+            window.setLevel_(PERMISSION_WINDOW_LEVEL);
+
+            let label: id = msg_send![class!(NSTextField), alloc];
+            let label: id = msg_send![
+                label,
+                initWithFrame: NSRect::new(
+                    NSPoint::new(MARGIN, MARGIN),
+                    NSSize::new(WIDTH - 2. * MARGIN, HEIGHT - 2. * MARGIN),
+                )
+            ];
+            let () = msg_send![label, setEditable: NO];
+            let () = msg_send![label, setBezeled: NO];
+            let () = msg_send![label, setDrawsBackground: NO];
+            let font: id = msg_send![class!(NSFont), systemFontOfSize: FONT_SIZE];
+            let () = msg_send![label, setFont: font];
+            let () = msg_send![label, setStringValue: NSString::alloc(nil).init_str(EXPLANATION)];
+            window.contentView().addSubview_(label);
+            let () = msg_send![label, release];
+
+            center_on(window, &CGDisplay::main().bounds());
+            OverlayWindow::new(window)
+        };
+        window.order_front();
+        Self { _window: window }
+    }
+}
+
+impl std::fmt::Debug for PermissionWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PermissionWindow")
+    }
+}
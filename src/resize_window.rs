@@ -0,0 +1,88 @@
+use std::rc::Rc;
+
+use anyhow::Result;
+use core_graphics::geometry::{CGPoint, CGRect, CGSize};
+
+use crate::window_system::{DynWindowSystem, WindowHandle};
+
+const MIN_SIZE: f64 = 100.;
+
+#[derive(Debug, Clone, Copy)]
+enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// A window being resized with the mouse, from the corner nearest to where
+/// the drag started.
+#[derive(Debug)]
+pub struct ResizeWindow {
+    system: Rc<DynWindowSystem>,
+    window: WindowHandle,
+    start_frame: CGRect,
+    start_mouse: CGPoint,
+    corner: Corner,
+}
+
+impl ResizeWindow {
+    pub(crate) fn new(
+        system: Rc<DynWindowSystem>,
+        window: WindowHandle,
+        mouse_location: &CGPoint,
+    ) -> Result<Self> {
+        let start_frame = system.frame(&window)?;
+        let mid_x = start_frame.origin.x + start_frame.size.width / 2.;
+        let mid_y = start_frame.origin.y + start_frame.size.height / 2.;
+        let corner = match (mouse_location.x < mid_x, mouse_location.y < mid_y) {
+            (true, true) => Corner::TopLeft,
+            (false, true) => Corner::TopRight,
+            (true, false) => Corner::BottomLeft,
+            (false, false) => Corner::BottomRight,
+        };
+        Ok(Self {
+            system,
+            window,
+            start_frame,
+            start_mouse: *mouse_location,
+            corner,
+        })
+    }
+
+    /// The frame of the window with its corner moved by the mouse's offset
+    /// from where the drag started.
+    pub fn frame_for(&self, point: &CGPoint) -> CGRect {
+        let f = self.start_frame;
+        let dx = point.x - self.start_mouse.x;
+        let dy = point.y - self.start_mouse.y;
+
+        let (x, width) = match self.corner {
+            Corner::TopLeft | Corner::BottomLeft => {
+                let width = f64::max(f.size.width - dx, MIN_SIZE);
+                (f.origin.x + f.size.width - width, width)
+            }
+            Corner::TopRight | Corner::BottomRight => {
+                (f.origin.x, f64::max(f.size.width + dx, MIN_SIZE))
+            }
+        };
+        let (y, height) = match self.corner {
+            Corner::TopLeft | Corner::TopRight => {
+                let height = f64::max(f.size.height - dy, MIN_SIZE);
+                (f.origin.y + f.size.height - height, height)
+            }
+            Corner::BottomLeft | Corner::BottomRight => {
+                (f.origin.y, f64::max(f.size.height + dy, MIN_SIZE))
+            }
+        };
+        CGRect::new(&CGPoint::new(x, y), &CGSize::new(width, height))
+    }
+
+    pub fn resize_to(&self, point: &CGPoint) -> Result<()> {
+        self.system.set_frame(&self.window, self.frame_for(point))
+    }
+
+    pub(crate) fn window(&self) -> &WindowHandle {
+        &self.window
+    }
+}
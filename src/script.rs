@@ -0,0 +1,219 @@
+use std::{cell::RefCell, collections::HashMap, fs, path::Path, rc::Rc};
+
+use anyhow::{anyhow, Context, Result};
+use core_graphics::geometry::{CGPoint, CGRect, CGSize};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+use crate::{
+    action::Action,
+    hooks::HookEvent,
+    layout::{register_layout, LayoutAlgorithm},
+};
+
+/// Limits so that a runaway script can't hang the event tap.
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MAX_CALL_LEVELS: usize = 32;
+
+/// A user's Rhai script. Scripts can't touch the window manager directly:
+/// they queue actions with `action("show-group 3")`, which are performed
+/// once the script returns, and bind keys with `bind("alt+return", "spawn
+/// open -na Terminal")` when loaded.
+///
+/// A script reacts to events by defining functions named after them, e.g.
+/// `fn window_created(e)`, where `e` is the event as a map like the JSON
+/// given to hooks. Actions queued while handling `window_created` act on the
+/// new window. Other functions can be run with the `run-script` action.
+///
+/// `layout("spiral", "spiral")` registers the function `spiral(bounds, n)`
+/// as a custom layout. It is given the display as a map with `x`, `y`,
+/// `width` and `height`, and returns an array of such maps.
+pub struct Script {
+    engine: Rc<Engine>,
+    ast: Rc<AST>,
+    queue: Rc<RefCell<Vec<Action>>>,
+    key_bindings: HashMap<String, String>,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Could not read script {}", path.display()))?;
+
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.set_max_call_levels(MAX_CALL_LEVELS);
+
+        let queue: Rc<RefCell<Vec<Action>>> = Rc::new(RefCell::new(vec![]));
+        {
+            let queue = queue.clone();
+            engine.register_fn(
+                "action",
+                move |command: &str| -> Result<(), Box<EvalAltResult>> {
+                    let action = parse_command(command).map_err(|e| e.to_string())?;
+                    queue.borrow_mut().push(action);
+                    Ok(())
+                },
+            );
+        }
+        let bindings: Rc<RefCell<HashMap<String, String>>> = Rc::new(RefCell::new(HashMap::new()));
+        {
+            let bindings = bindings.clone();
+            engine.register_fn("bind", move |key: &str, command: &str| {
+                bindings
+                    .borrow_mut()
+                    .insert(key.to_string(), command.to_string());
+            });
+        }
+
+        let layouts: Rc<RefCell<Vec<(String, String)>>> = Rc::new(RefCell::new(vec![]));
+        {
+            let layouts = layouts.clone();
+            engine.register_fn("layout", move |name: &str, function: &str| {
+                layouts
+                    .borrow_mut()
+                    .push((name.to_string(), function.to_string()));
+            });
+        }
+
+        let ast = engine
+            .compile(&source)
+            .map_err(|e| anyhow!("Could not compile script {}: {}", path.display(), e))?;
+        engine
+            .run_ast(&ast)
+            .map_err(|e| anyhow!("While running script {}: {}", path.display(), e))?;
+        // Actions queued while loading aren't performed.
+        queue.borrow_mut().clear();
+        let key_bindings = bindings.take();
+        let engine = Rc::new(engine);
+        let ast = Rc::new(ast);
+        for (name, function) in layouts.take() {
+            register_layout(
+                &name,
+                ScriptLayout {
+                    engine: engine.clone(),
+                    ast: ast.clone(),
+                    function,
+                },
+            );
+        }
+        Ok(Self {
+            engine,
+            ast,
+            queue,
+            key_bindings,
+        })
+    }
+
+    /// Keys bound with `bind`, like `bindings.keys` in the config.
+    pub fn key_bindings(&self) -> &HashMap<String, String> {
+        &self.key_bindings
+    }
+
+    /// Run the script's function for `event`, if it defines one, returning
+    /// the actions it queued.
+    pub fn handle(&mut self, event: &HookEvent) -> Result<Vec<Action>> {
+        let name = event.name().replace('-', "_");
+        if !self.defines(&name, 1) {
+            return Ok(vec![]);
+        }
+        let event = rhai::serde::to_dynamic(event).map_err(|e| anyhow!("{}", e))?;
+        self.call_with(&name, (event,))
+    }
+
+    /// Run a function of the script that takes no arguments, returning the
+    /// actions it queued.
+    pub fn call(&mut self, name: &str) -> Result<Vec<Action>> {
+        if !self.defines(name, 0) {
+            return Err(anyhow!("The script has no function {}()", name));
+        }
+        self.call_with(name, ())
+    }
+
+    fn defines(&self, name: &str, num_params: usize) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|f| f.name == name && f.params.len() == num_params)
+    }
+
+    fn call_with(&mut self, name: &str, args: impl rhai::FuncArgs) -> Result<Vec<Action>> {
+        self.queue.borrow_mut().clear();
+        self.engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, name, args)
+            .map_err(|e| anyhow!("In script function {}: {}", name, e))?;
+        Ok(self.queue.take())
+    }
+}
+
+/// A layout computed by a function of the script.
+struct ScriptLayout {
+    engine: Rc<Engine>,
+    ast: Rc<AST>,
+    function: String,
+}
+
+impl ScriptLayout {
+    fn call(&self, bounds: &CGRect, num_windows: usize) -> Result<Vec<CGRect>> {
+        let mut d = Map::new();
+        d.insert("x".into(), bounds.origin.x.into());
+        d.insert("y".into(), bounds.origin.y.into());
+        d.insert("width".into(), bounds.size.width.into());
+        d.insert("height".into(), bounds.size.height.into());
+        let rects = self
+            .engine
+            .call_fn::<Array>(
+                &mut Scope::new(),
+                &self.ast,
+                &self.function,
+                (d, num_windows as rhai::INT),
+            )
+            .map_err(|e| anyhow!("In layout function {}: {}", self.function, e))?;
+        rects
+            .into_iter()
+            .map(|r| {
+                let r = r
+                    .try_cast::<Map>()
+                    .ok_or_else(|| anyhow!("Expected a map from {}", self.function))?;
+                let number = |key: &str| -> Result<f64> {
+                    let value = r
+                        .get(key)
+                        .ok_or_else(|| anyhow!("Missing {} in {}", key, self.function))?;
+                    value
+                        .as_float()
+                        .or_else(|_| value.as_int().map(|i| i as f64))
+                        .map_err(|_| anyhow!("Expected a number for {}", key))
+                };
+                Ok(CGRect::new(
+                    &CGPoint::new(number("x")?, number("y")?),
+                    &CGSize::new(number("width")?, number("height")?),
+                ))
+            })
+            .collect()
+    }
+}
+
+impl LayoutAlgorithm for ScriptLayout {
+    fn compute(&self, bounds: &CGRect, num_windows: usize) -> Vec<CGRect> {
+        self.call(bounds, num_windows).unwrap_or_else(|e| {
+            eprintln!("While computing script layout: {:?}", e);
+            vec![]
+        })
+    }
+}
+
+fn parse_command(command: &str) -> Result<Action> {
+    let mut words = command.split_whitespace();
+    let id = words.next().unwrap_or("");
+    let args: Vec<String> = words.map(|w| w.to_string()).collect();
+    let action = Action::parse(id, &args)?;
+    if matches!(action, Action::RunScript(_)) {
+        // It could run itself forever.
+        return Err(anyhow!("Scripts can't queue run-script"));
+    }
+    Ok(action)
+}
+
+impl std::fmt::Debug for Script {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Script")
+    }
+}
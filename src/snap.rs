@@ -0,0 +1,149 @@
+use core_graphics::geometry::{CGPoint, CGRect, CGSize};
+
+use crate::direction::Direction;
+
+/// Part of a display that a window can be put in from the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapRegion {
+    LeftHalf,
+    RightHalf,
+    TopHalf,
+    BottomHalf,
+    TopLeftQuarter,
+    TopRightQuarter,
+    BottomLeftQuarter,
+    BottomRightQuarter,
+    /// The middle of the display, keeping the window's size.
+    Center,
+}
+
+impl SnapRegion {
+    /// The frame a window with frame `w` gets in this region of a display
+    /// with bounds `d`, taking up `size` of the display across the edge it is
+    /// against. Quarters are always half the display high.
+    pub fn frame(self, d: &CGRect, w: &CGRect, size: f64) -> CGRect {
+        use SnapRegion::*;
+        let (width, height) = (d.size.width * size, d.size.height * size);
+        let half_h = d.size.height / 2.;
+        let (left, top) = (d.origin.x, d.origin.y);
+        let (right, bottom) = (left + d.size.width, top + d.size.height);
+        let (x, y, width, height) = match self {
+            LeftHalf => (left, top, width, d.size.height),
+            RightHalf => (right - width, top, width, d.size.height),
+            TopHalf => (left, top, d.size.width, height),
+            BottomHalf => (left, bottom - height, d.size.width, height),
+            TopLeftQuarter => (left, top, width, half_h),
+            TopRightQuarter => (right - width, top, width, half_h),
+            BottomLeftQuarter => (left, top + half_h, width, half_h),
+            BottomRightQuarter => (right - width, top + half_h, width, half_h),
+            Center => (
+                left + (d.size.width - w.size.width) / 2.,
+                top + (d.size.height - w.size.height) / 2.,
+                w.size.width,
+                w.size.height,
+            ),
+        };
+        CGRect::new(&CGPoint::new(x, y), &CGSize::new(width, height))
+    }
+
+    /// The display edge this region is against, which a window already in
+    /// the region is pushed past onto the next display.
+    pub fn edge(self) -> Option<Direction> {
+        use SnapRegion::*;
+        match self {
+            LeftHalf | TopLeftQuarter | BottomLeftQuarter => Some(Direction::Left),
+            RightHalf | TopRightQuarter | BottomRightQuarter => Some(Direction::Right),
+            TopHalf => Some(Direction::Up),
+            BottomHalf => Some(Direction::Down),
+            Center => None,
+        }
+    }
+
+    /// The region on the far side of `edge()`, where a window pushed past it
+    /// lands on the next display.
+    pub fn opposite(self) -> Self {
+        use SnapRegion::*;
+        match self {
+            LeftHalf => RightHalf,
+            RightHalf => LeftHalf,
+            TopHalf => BottomHalf,
+            BottomHalf => TopHalf,
+            TopLeftQuarter => TopRightQuarter,
+            TopRightQuarter => TopLeftQuarter,
+            BottomLeftQuarter => BottomRightQuarter,
+            BottomRightQuarter => BottomLeftQuarter,
+            Center => Center,
+        }
+    }
+}
+
+/// The offset that moves one of `edges` onto the closest of `targets`, if any
+/// is within `threshold`.
+fn nearest_offset(edges: &[f64], targets: &[f64], threshold: f64) -> f64 {
+    let mut best: Option<f64> = None;
+    for edge in edges {
+        for target in targets {
+            let offset = target - edge;
+            if offset.abs() <= threshold && best.map_or(true, |b| offset.abs() < b.abs()) {
+                best = Some(offset);
+            }
+        }
+    }
+    best.unwrap_or(0.)
+}
+
+/// Snap the frame of a window dropped with the mouse at `mouse`.
+///
+/// Dropping with the mouse within `threshold` of a display edge fills that
+/// half of the display (or quarter, in a corner). Otherwise the window is
+/// moved so that its edges line up with nearby display or window edges.
+pub fn snap_frame(
+    frame: &CGRect,
+    mouse: &CGPoint,
+    display: &CGRect,
+    others: &[CGRect],
+    threshold: f64,
+) -> CGRect {
+    let d = display;
+    let near_left = mouse.x - d.origin.x <= threshold;
+    let near_right = d.origin.x + d.size.width - mouse.x <= threshold;
+    let near_top = mouse.y - d.origin.y <= threshold;
+    let near_bottom = d.origin.y + d.size.height - mouse.y <= threshold;
+
+    if near_left || near_right || near_top || near_bottom {
+        let (x, width) = if near_left {
+            (d.origin.x, d.size.width / 2.)
+        } else if near_right {
+            (d.origin.x + d.size.width / 2., d.size.width / 2.)
+        } else {
+            (d.origin.x, d.size.width)
+        };
+        let (y, height) = if near_top {
+            (d.origin.y, d.size.height / 2.)
+        } else if near_bottom {
+            (d.origin.y + d.size.height / 2., d.size.height / 2.)
+        } else {
+            (d.origin.y, d.size.height)
+        };
+        return CGRect::new(&CGPoint::new(x, y), &CGSize::new(width, height));
+    }
+
+    let mut x_targets = vec![d.origin.x, d.origin.x + d.size.width];
+    let mut y_targets = vec![d.origin.y, d.origin.y + d.size.height];
+    for o in others {
+        x_targets.extend([o.origin.x, o.origin.x + o.size.width]);
+        y_targets.extend([o.origin.y, o.origin.y + o.size.height]);
+    }
+    let f = frame;
+    let dx = nearest_offset(
+        &[f.origin.x, f.origin.x + f.size.width],
+        &x_targets,
+        threshold,
+    );
+    let dy = nearest_offset(
+        &[f.origin.y, f.origin.y + f.size.height],
+        &y_targets,
+        threshold,
+    );
+    CGRect::new(&CGPoint::new(f.origin.x + dx, f.origin.y + dy), &f.size)
+}
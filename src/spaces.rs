@@ -0,0 +1,167 @@
+//! Mission Control Spaces, through the window server's private API. There is
+//! no public API to list Spaces, switch between them or move windows to
+//! them, so this is behind the `spaces` feature, and may stop working with
+//! any macOS update.
+//!
+//! Switching Space this way doesn't animate, and moving other apps' windows
+//! between Spaces isn't allowed on some macOS versions, in which case the
+//! window stays where it is and an error is logged.
+
+use anyhow::{anyhow, Result};
+use core_foundation::{
+    array::{CFArray, CFArrayRef},
+    base::{CFType, TCFType, TCFTypeRef},
+    dictionary::CFDictionary,
+    number::CFNumber,
+    string::{CFString, CFStringRef},
+};
+use core_graphics::window::CGWindowID;
+
+type CGSConnectionID = i32;
+pub type SpaceID = u64;
+
+/// Spaces of every kind, for `CGSCopySpacesForWindows`.
+const K_CGS_ALL_SPACES_MASK: i32 = 0x7;
+/// A Space made in Mission Control, as opposed to a full-screen app's.
+const USER_SPACE_TYPE: i64 = 0;
+/// The identifier of the only entry in `CGSCopyManagedDisplaySpaces` when
+/// displays don't have separate Spaces.
+const MAIN_DISPLAY: &str = "Main";
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGSMainConnectionID() -> CGSConnectionID;
+    fn CGSCopyManagedDisplaySpaces(cid: CGSConnectionID) -> CFArrayRef;
+    fn CGSCopySpacesForWindows(cid: CGSConnectionID, mask: i32, wids: CFArrayRef) -> CFArrayRef;
+    fn CGSMoveWindowsToManagedSpace(cid: CGSConnectionID, wids: CFArrayRef, sid: SpaceID);
+    fn CGSManagedDisplaySetCurrentSpace(
+        cid: CGSConnectionID,
+        display: CFStringRef,
+        sid: SpaceID,
+    ) -> i32;
+}
+
+/// A display's Spaces, in Mission Control's order.
+#[derive(Debug, Clone)]
+pub struct DisplaySpaces {
+    /// The display's UUID, or "Main" when displays share their Spaces.
+    display: String,
+    /// Only the Spaces made in Mission Control: full-screen apps' Spaces come
+    /// and go.
+    pub spaces: Vec<SpaceID>,
+    /// The Space being shown, which may be a full-screen app's.
+    pub current: SpaceID,
+}
+
+impl DisplaySpaces {
+    /// Where the Space being shown is among `spaces`.
+    pub fn current_index(&self) -> Option<usize> {
+        self.spaces.iter().position(|sid| *sid == self.current)
+    }
+}
+
+type Dictionary = CFDictionary<CFString, CFType>;
+
+/// `dict[key]`, if it is a `T`.
+fn get<T: TCFType>(dict: &Dictionary, key: &str) -> Option<T> {
+    let value = dict.find(&CFString::new(key))?;
+    if value.type_of() != T::type_id() {
+        return None;
+    }
+    Some(unsafe { T::wrap_under_get_rule(T::Ref::from_void_ptr(value.as_CFTypeRef())) })
+}
+
+fn space_id(dict: &Dictionary) -> Option<SpaceID> {
+    get::<CFNumber>(dict, "ManagedSpaceID")
+        .and_then(|n| n.to_i64())
+        .map(|n| n as SpaceID)
+}
+
+/// Every display's Spaces.
+pub fn display_spaces() -> Result<Vec<DisplaySpaces>> {
+    let displays = unsafe {
+        let displays = CGSCopyManagedDisplaySpaces(CGSMainConnectionID());
+        if displays.is_null() {
+            return Err(anyhow!("CGSCopyManagedDisplaySpaces failed"));
+        }
+        CFArray::<Dictionary>::wrap_under_create_rule(displays)
+    };
+    Ok(displays
+        .iter()
+        .filter_map(|d| {
+            let display = get::<CFString>(&d, "Display Identifier")?.to_string();
+            let current = space_id(&get::<Dictionary>(&d, "Current Space")?)?;
+            let spaces = get::<CFArray<Dictionary>>(&d, "Spaces")?
+                .iter()
+                .filter(|s| {
+                    get::<CFNumber>(s, "type").and_then(|t| t.to_i64()) == Some(USER_SPACE_TYPE)
+                })
+                .filter_map(|s| space_id(&s))
+                .collect();
+            Some(DisplaySpaces {
+                display,
+                spaces,
+                current,
+            })
+        })
+        .collect())
+}
+
+/// The Spaces of the display with `uuid`, among `all`.
+pub fn spaces_of_display<'a>(
+    all: &'a [DisplaySpaces],
+    uuid: Option<&str>,
+) -> Option<&'a DisplaySpaces> {
+    all.iter()
+        .find(|ds| Some(ds.display.as_str()) == uuid)
+        .or_else(|| all.iter().find(|ds| ds.display == MAIN_DISPLAY))
+}
+
+fn window_ids(wid: CGWindowID) -> CFArray<CFNumber> {
+    CFArray::from_CFTypes(&[CFNumber::from(wid as i64)])
+}
+
+/// The Spaces the window `wid` is on: none while it is minimized, and
+/// several if it is on every Space.
+pub fn window_spaces(wid: CGWindowID) -> Result<Vec<SpaceID>> {
+    let wids = window_ids(wid);
+    let spaces = unsafe {
+        let spaces = CGSCopySpacesForWindows(
+            CGSMainConnectionID(),
+            K_CGS_ALL_SPACES_MASK,
+            wids.as_concrete_TypeRef(),
+        );
+        if spaces.is_null() {
+            return Err(anyhow!("CGSCopySpacesForWindows failed"));
+        }
+        CFArray::<CFNumber>::wrap_under_create_rule(spaces)
+    };
+    Ok(spaces
+        .iter()
+        .filter_map(|n| n.to_i64())
+        .map(|n| n as SpaceID)
+        .collect())
+}
+
+pub fn move_window_to_space(wid: CGWindowID, sid: SpaceID) -> Result<()> {
+    let wids = window_ids(wid);
+    unsafe {
+        CGSMoveWindowsToManagedSpace(CGSMainConnectionID(), wids.as_concrete_TypeRef(), sid);
+    }
+    if !window_spaces(wid)?.contains(&sid) {
+        return Err(anyhow!("The window server didn't move the window"));
+    }
+    Ok(())
+}
+
+/// Show Space `sid` on the display it belongs to.
+pub fn switch_to_space(display: &DisplaySpaces, sid: SpaceID) -> Result<()> {
+    let name = CFString::new(&display.display);
+    let err = unsafe {
+        CGSManagedDisplaySetCurrentSpace(CGSMainConnectionID(), name.as_concrete_TypeRef(), sid)
+    };
+    if err != 0 {
+        return Err(anyhow!("CGSManagedDisplaySetCurrentSpace failed: {}", err));
+    }
+    Ok(())
+}
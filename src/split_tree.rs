@@ -0,0 +1,693 @@
+//! i3-style manual tiling: a group's tiled windows are the leaves of a tree
+//! of containers, each of which splits its frame between its children, side
+//! by side or one above the other.
+
+use core_graphics::display::{CGPoint, CGRect, CGSize};
+
+use crate::direction::Direction;
+
+/// The smallest and largest share of its container that resizing gives a
+/// child.
+const MIN_SHARE: f64 = 0.1;
+const MAX_SHARE: f64 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitDirection {
+    /// Children side by side.
+    Horizontal,
+    /// Children one above the other.
+    Vertical,
+}
+
+impl SplitDirection {
+    /// The direction of a container whose children are in `direction` of
+    /// each other.
+    fn along(direction: Direction) -> Self {
+        match direction {
+            Direction::Left | Direction::Right => SplitDirection::Horizontal,
+            Direction::Up | Direction::Down => SplitDirection::Vertical,
+        }
+    }
+}
+
+impl std::fmt::Display for SplitDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SplitDirection::Horizontal => write!(f, "horizontal"),
+            SplitDirection::Vertical => write!(f, "vertical"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Window(uuid::Uuid),
+    Container {
+        direction: SplitDirection,
+        children: Vec<Node>,
+        /// Each child's share of the container, relative to the others'.
+        ratios: Vec<f64>,
+    },
+}
+
+impl Node {
+    /// A container sharing its frame equally between `children`.
+    fn container(direction: SplitDirection, children: Vec<Node>) -> Self {
+        Node::Container {
+            direction,
+            ratios: vec![1.; children.len()],
+            children,
+        }
+    }
+
+    fn contains(&self, w_id: &uuid::Uuid) -> bool {
+        match self {
+            Node::Window(id) => id == w_id,
+            Node::Container { children, .. } => children.iter().any(|c| c.contains(w_id)),
+        }
+    }
+
+    fn windows(&self, ids: &mut Vec<uuid::Uuid>) {
+        match self {
+            Node::Window(id) => ids.push(*id),
+            Node::Container { children, .. } => {
+                for child in children {
+                    child.windows(ids);
+                }
+            }
+        }
+    }
+
+    /// The indices of the children leading from this node to the window.
+    fn path_to(&self, w_id: &uuid::Uuid) -> Option<Vec<usize>> {
+        match self {
+            Node::Window(id) => (id == w_id).then(Vec::new),
+            Node::Container { children, .. } => children.iter().enumerate().find_map(|(i, c)| {
+                let mut path = c.path_to(w_id)?;
+                path.insert(0, i);
+                Some(path)
+            }),
+        }
+    }
+
+    fn at_mut(&mut self, path: &[usize]) -> &mut Node {
+        match (self, path.split_first()) {
+            (Node::Container { children, .. }, Some((i, rest))) => children[*i].at_mut(rest),
+            (node, _) => node,
+        }
+    }
+
+    fn at(&self, path: &[usize]) -> &Node {
+        match (self, path.split_first()) {
+            (Node::Container { children, .. }, Some((i, rest))) => children[*i].at(rest),
+            (node, _) => node,
+        }
+    }
+
+    /// Insert `node` into this container at `idx`, or at the end if `idx` is
+    /// out of range, with the average share of the other children.
+    fn insert_child(&mut self, idx: usize, node: Node) {
+        if let Node::Container {
+            children, ratios, ..
+        } = self
+        {
+            let ratio = if ratios.is_empty() {
+                1.
+            } else {
+                ratios.iter().sum::<f64>() / ratios.len() as f64
+            };
+            let idx = usize::min(idx, children.len());
+            children.insert(idx, node);
+            ratios.insert(idx, ratio);
+        }
+    }
+
+    /// Drop the windows `keep` rejects, and containers left empty, and put
+    /// the only child of a container in its place.
+    fn retain(&mut self, keep: &impl Fn(&uuid::Uuid) -> bool) {
+        if let Node::Container {
+            children, ratios, ..
+        } = self
+        {
+            for child in children.iter_mut() {
+                child.retain(keep);
+            }
+            let (kept, kept_ratios): (Vec<_>, Vec<_>) = children
+                .drain(..)
+                .zip(ratios.drain(..))
+                .filter(|(c, _)| match c {
+                    Node::Window(id) => keep(id),
+                    Node::Container { children, .. } => !children.is_empty(),
+                })
+                .unzip();
+            *children = kept;
+            *ratios = kept_ratios;
+            for child in children.iter_mut() {
+                if let Node::Container {
+                    children: inner, ..
+                } = child
+                {
+                    if inner.len() == 1 {
+                        let only = inner.remove(0);
+                        *child = only;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The frames of this container's children within `bounds`.
+    fn child_bounds(&self, bounds: &CGRect) -> Vec<CGRect> {
+        let (direction, ratios) = match self {
+            Node::Container {
+                direction, ratios, ..
+            } => (direction, ratios),
+            Node::Window(_) => return vec![],
+        };
+        let total: f64 = ratios.iter().sum();
+        let mut before = 0.;
+        ratios
+            .iter()
+            .map(|ratio| {
+                let rect = match direction {
+                    SplitDirection::Horizontal => CGRect::new(
+                        &CGPoint::new(
+                            bounds.origin.x + bounds.size.width * before / total,
+                            bounds.origin.y,
+                        ),
+                        &CGSize::new(bounds.size.width * ratio / total, bounds.size.height),
+                    ),
+                    SplitDirection::Vertical => CGRect::new(
+                        &CGPoint::new(
+                            bounds.origin.x,
+                            bounds.origin.y + bounds.size.height * before / total,
+                        ),
+                        &CGSize::new(bounds.size.width, bounds.size.height * ratio / total),
+                    ),
+                };
+                before += ratio;
+                rect
+            })
+            .collect()
+    }
+
+    /// The frame of the node at `path` when this one is in `bounds`.
+    fn bounds_at(&self, path: &[usize], bounds: &CGRect) -> CGRect {
+        match (self, path.split_first()) {
+            (Node::Container { children, .. }, Some((i, rest))) => {
+                children[*i].bounds_at(rest, &self.child_bounds(bounds)[*i])
+            }
+            _ => *bounds,
+        }
+    }
+
+    fn frames(&self, bounds: &CGRect, frames: &mut Vec<(uuid::Uuid, CGRect)>) {
+        match self {
+            Node::Window(id) => frames.push((*id, *bounds)),
+            Node::Container { children, .. } => {
+                for (child, rect) in children.iter().zip(self.child_bounds(bounds)) {
+                    child.frames(&rect, frames);
+                }
+            }
+        }
+    }
+
+    /// Give every child of this container and those in it an equal share.
+    fn balance(&mut self) {
+        if let Node::Container {
+            children, ratios, ..
+        } = self
+        {
+            ratios.iter_mut().for_each(|r| *r = 1.);
+            children.iter_mut().for_each(Node::balance);
+        }
+    }
+}
+
+/// Make child `idx` `share` of its container, within `MIN_SHARE` and
+/// `MAX_SHARE`, keeping the others' shares in proportion. An only child
+/// fills its container whatever its ratio.
+fn set_share(ratios: &mut [f64], idx: usize, share: f64) {
+    let others: f64 = ratios
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != idx)
+        .map(|(_, r)| r)
+        .sum();
+    if others <= 0. {
+        return;
+    }
+    let share = share.clamp(MIN_SHARE, MAX_SHARE);
+    ratios[idx] = share * others / (1. - share);
+}
+
+/// Where a group's tiled windows are, once it has been split by hand.
+#[derive(Debug, Clone)]
+pub struct SplitTree {
+    /// Always a container.
+    root: Node,
+    /// The window new windows go next to: the active window, when last told.
+    focused: Option<uuid::Uuid>,
+    /// Set by `split`: the next window shares this window's space, in this
+    /// direction.
+    pending_split: Option<(uuid::Uuid, SplitDirection)>,
+}
+
+impl SplitTree {
+    /// A tree with `ids` side by side.
+    pub fn new(ids: &[uuid::Uuid]) -> Self {
+        Self {
+            root: Node::container(
+                SplitDirection::Horizontal,
+                ids.iter().map(|id| Node::Window(*id)).collect(),
+            ),
+            focused: None,
+            pending_split: None,
+        }
+    }
+
+    /// The windows in the tree, from the top left.
+    pub fn windows(&self) -> Vec<uuid::Uuid> {
+        let mut ids = vec![];
+        self.root.windows(&mut ids);
+        ids
+    }
+
+    pub fn pending_split(&self) -> Option<(uuid::Uuid, SplitDirection)> {
+        self.pending_split
+    }
+
+    /// Note that `w_id` is the active window, if it is in the tree.
+    pub fn focus(&mut self, w_id: uuid::Uuid) {
+        if self.root.contains(&w_id) {
+            self.focused = Some(w_id);
+        }
+    }
+
+    /// Make the next window share `w_id`'s space, split in `direction`.
+    pub fn split(&mut self, w_id: uuid::Uuid, direction: SplitDirection) {
+        self.focus(w_id);
+        self.pending_split = Some((w_id, direction));
+    }
+
+    /// Drop the windows that aren't in `ids`, and add those of `ids` that
+    /// aren't in the tree yet after the focused window.
+    pub fn sync(&mut self, ids: &[uuid::Uuid]) {
+        self.root.retain(&|id| ids.contains(id));
+        if self.focused.map_or(false, |id| !ids.contains(&id)) {
+            self.focused = None;
+        }
+        if self
+            .pending_split
+            .map_or(false, |(id, _)| !ids.contains(&id))
+        {
+            self.pending_split = None;
+        }
+        for id in ids {
+            if !self.root.contains(id) {
+                self.insert(*id);
+            }
+        }
+    }
+
+    fn insert(&mut self, w_id: uuid::Uuid) {
+        let target = self
+            .focused
+            .and_then(|id| Some((id, self.root.path_to(&id)?)));
+        let (target_id, path) = match target {
+            Some(target) => target,
+            None => {
+                self.root.insert_child(usize::MAX, Node::Window(w_id));
+                self.focused = Some(w_id);
+                return;
+            }
+        };
+        match self.pending_split.take() {
+            Some((id, direction)) if id == target_id => {
+                *self.root.at_mut(&path) =
+                    Node::container(direction, vec![Node::Window(target_id), Node::Window(w_id)]);
+            }
+            pending => {
+                self.pending_split = pending;
+                let (idx, parent) = path.split_last().expect("windows are in a container");
+                self.root
+                    .at_mut(parent)
+                    .insert_child(idx + 1, Node::Window(w_id));
+            }
+        }
+        self.focused = Some(w_id);
+    }
+
+    /// Swap the places of two windows in the tree.
+    pub fn swap(&mut self, a: uuid::Uuid, b: uuid::Uuid) {
+        if let (Some(path_a), Some(path_b)) = (self.root.path_to(&a), self.root.path_to(&b)) {
+            *self.root.at_mut(&path_a) = Node::Window(b);
+            *self.root.at_mut(&path_b) = Node::Window(a);
+        }
+    }
+
+    /// Move the window past its neighbor in `direction`, as i3 does: within
+    /// its container if it can, or else out into the nearest enclosing
+    /// container split that way, making a new one around the whole tree if
+    /// there is none. Returns false if the window isn't in the tree or is
+    /// already at its edge.
+    pub fn move_window(&mut self, w_id: uuid::Uuid, direction: Direction) -> bool {
+        let path = match self.root.path_to(&w_id) {
+            Some(path) => path,
+            None => return false,
+        };
+        let along = SplitDirection::along(direction);
+        let forward = matches!(direction, Direction::Right | Direction::Down);
+        // The deepest container split `along` in which the window (or the
+        // container it is in) can move that way.
+        let level = (0..path.len())
+            .rev()
+            .find(|level| match self.root.at(&path[..*level]) {
+                Node::Container {
+                    direction: d,
+                    children,
+                } if *d == along => {
+                    let idx = path[*level];
+                    if forward {
+                        idx + 1 < children.len()
+                    } else {
+                        idx > 0
+                    }
+                }
+                _ => false,
+            });
+        match level {
+            Some(level) if level + 1 == path.len() => {
+                // A sibling: swap with it, taking its share along.
+                if let Node::Container {
+                    children, ratios, ..
+                } = self.root.at_mut(&path[..level])
+                {
+                    let idx = path[level];
+                    let other = if forward { idx + 1 } else { idx - 1 };
+                    children.swap(idx, other);
+                    ratios.swap(idx, other);
+                }
+            }
+            Some(level) => {
+                self.root.retain(&|id| *id != w_id);
+                let idx = path[level];
+                let idx = if forward { idx + 1 } else { idx };
+                self.root
+                    .at_mut(&path[..level])
+                    .insert_child(idx, Node::Window(w_id));
+            }
+            None if matches!(self.root, Node::Container { direction, .. } if direction == along) => {
+                return false;
+            }
+            None => {
+                self.root.retain(&|id| *id != w_id);
+                let old = std::mem::replace(&mut self.root, Node::container(along, vec![]));
+                let children = if forward {
+                    vec![old, Node::Window(w_id)]
+                } else {
+                    vec![Node::Window(w_id), old]
+                };
+                self.root = Node::container(along, children);
+                // The old root may now hold a single window.
+                self.root.retain(&|_| true);
+            }
+        }
+        true
+    }
+
+    /// Scale the window's share of its container by `factor`. Returns false
+    /// if the window isn't in the tree.
+    pub fn scale(&mut self, w_id: uuid::Uuid, factor: f64) -> bool {
+        let path = match self.root.path_to(&w_id) {
+            Some(path) => path,
+            None => return false,
+        };
+        let (idx, parent) = path.split_last().expect("windows are in a container");
+        if let Node::Container { ratios, .. } = self.root.at_mut(parent) {
+            let total: f64 = ratios.iter().sum();
+            let ratio = ratios[*idx] * factor;
+            let share = ratio / (total - ratios[*idx] + ratio);
+            set_share(ratios, *idx, share);
+        }
+        true
+    }
+
+    /// Make the window `size`, or as near as it can be, when the tree is in
+    /// `bounds`: its width is taken from the nearest enclosing container
+    /// split horizontally, and its height from the nearest split
+    /// vertically. Returns false if the window isn't in the tree.
+    pub fn resize(&mut self, w_id: uuid::Uuid, bounds: &CGRect, size: &CGSize) -> bool {
+        let path = match self.root.path_to(&w_id) {
+            Some(path) => path,
+            None => return false,
+        };
+        for direction in [SplitDirection::Horizontal, SplitDirection::Vertical] {
+            let level = (0..path.len()).rev().find(|level| {
+                matches!(
+                    self.root.at(&path[..*level]),
+                    Node::Container { direction: d, .. } if *d == direction
+                )
+            });
+            let level = match level {
+                Some(level) => level,
+                None => continue,
+            };
+            let container = self.root.bounds_at(&path[..level], bounds);
+            let share = match direction {
+                SplitDirection::Horizontal => size.width / container.size.width,
+                SplitDirection::Vertical => size.height / container.size.height,
+            };
+            if let Node::Container { ratios, .. } = self.root.at_mut(&path[..level]) {
+                set_share(ratios, path[level], share);
+            }
+        }
+        true
+    }
+
+    /// Give every window an equal share of its container.
+    pub fn balance(&mut self) {
+        self.root.balance();
+    }
+
+    /// Where each window goes within `bounds`.
+    pub fn frames(&self, bounds: &CGRect) -> Vec<(uuid::Uuid, CGRect)> {
+        let mut frames = vec![];
+        self.root.frames(bounds, &mut frames);
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::same_frame;
+
+    fn id(n: u128) -> uuid::Uuid {
+        uuid::Uuid::from_u128(n)
+    }
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> CGRect {
+        CGRect::new(&CGPoint::new(x, y), &CGSize::new(width, height))
+    }
+
+    /// Check each window's frame within a 1000x800 display, in the tree's
+    /// order.
+    fn assert_frames(tree: &SplitTree, expected: &[(uuid::Uuid, CGRect)]) {
+        let frames = tree.frames(&rect(0., 0., 1000., 800.));
+        let matches = frames.len() == expected.len()
+            && frames
+                .iter()
+                .zip(expected)
+                .all(|((a, fa), (b, fb))| a == b && same_frame(fa, fb));
+        assert!(matches, "Expected frames {:?}, got {:?}", expected, frames);
+    }
+
+    #[test]
+    fn sync_adds_new_windows_after_the_focused_one() {
+        let mut tree = SplitTree::new(&[id(1), id(2), id(3)]);
+        tree.focus(id(1));
+        tree.sync(&[id(1), id(2), id(3), id(4)]);
+
+        assert_eq!(tree.windows(), vec![id(1), id(4), id(2), id(3)]);
+    }
+
+    #[test]
+    fn sync_appends_new_windows_without_a_focused_one() {
+        let mut tree = SplitTree::new(&[id(1), id(2)]);
+        tree.sync(&[id(1), id(2), id(3)]);
+
+        assert_eq!(tree.windows(), vec![id(1), id(2), id(3)]);
+    }
+
+    #[test]
+    fn sync_drops_closed_windows_and_forgets_their_focus() {
+        let mut tree = SplitTree::new(&[id(1), id(2), id(3)]);
+        tree.split(id(2), SplitDirection::Vertical);
+        tree.sync(&[id(1), id(3)]);
+
+        assert_eq!(tree.windows(), vec![id(1), id(3)]);
+        assert_eq!(tree.pending_split(), None);
+        tree.sync(&[id(1), id(3), id(4)]);
+        assert_eq!(tree.windows(), vec![id(1), id(3), id(4)]);
+    }
+
+    #[test]
+    fn split_shares_the_window_space_with_the_next_window() {
+        let mut tree = SplitTree::new(&[id(1), id(2)]);
+        tree.split(id(1), SplitDirection::Vertical);
+        tree.sync(&[id(1), id(2), id(3)]);
+
+        assert_eq!(tree.pending_split(), None);
+        assert_frames(
+            &tree,
+            &[
+                (id(1), rect(0., 0., 500., 400.)),
+                (id(3), rect(0., 400., 500., 400.)),
+                (id(2), rect(500., 0., 500., 800.)),
+            ],
+        );
+    }
+
+    #[test]
+    fn removing_a_window_collapses_its_single_child_container() {
+        let mut tree = SplitTree::new(&[id(1), id(2)]);
+        tree.split(id(1), SplitDirection::Vertical);
+        tree.sync(&[id(1), id(2), id(3)]);
+        tree.sync(&[id(1), id(2)]);
+
+        assert_frames(
+            &tree,
+            &[
+                (id(1), rect(0., 0., 500., 800.)),
+                (id(2), rect(500., 0., 500., 800.)),
+            ],
+        );
+        // Window 1 is a sibling of window 2 again, rather than alone in a
+        // container of its own.
+        assert!(tree.move_window(id(1), Direction::Right));
+        assert_eq!(tree.windows(), vec![id(2), id(1)]);
+    }
+
+    #[test]
+    fn move_window_swaps_siblings() {
+        let mut tree = SplitTree::new(&[id(1), id(2), id(3)]);
+
+        assert!(tree.move_window(id(2), Direction::Left));
+        assert_eq!(tree.windows(), vec![id(2), id(1), id(3)]);
+    }
+
+    #[test]
+    fn move_window_leaves_its_container() {
+        let mut tree = SplitTree::new(&[id(1), id(2)]);
+        tree.split(id(2), SplitDirection::Vertical);
+        tree.sync(&[id(1), id(2), id(3)]);
+
+        assert!(tree.move_window(id(3), Direction::Left));
+        assert_frames(
+            &tree,
+            &[
+                (id(1), rect(0., 0., 1000. / 3., 800.)),
+                (id(3), rect(1000. / 3., 0., 1000. / 3., 800.)),
+                (id(2), rect(2000. / 3., 0., 1000. / 3., 800.)),
+            ],
+        );
+    }
+
+    #[test]
+    fn move_window_across_the_split_wraps_the_tree() {
+        let mut tree = SplitTree::new(&[id(1), id(2)]);
+
+        assert!(tree.move_window(id(1), Direction::Up));
+        assert_frames(
+            &tree,
+            &[
+                (id(1), rect(0., 0., 1000., 400.)),
+                (id(2), rect(0., 400., 1000., 400.)),
+            ],
+        );
+    }
+
+    #[test]
+    fn scale_changes_the_window_share_of_its_container() {
+        let mut tree = SplitTree::new(&[id(1), id(2)]);
+
+        assert!(tree.scale(id(1), 3.));
+        assert!(!tree.scale(id(3), 3.));
+        assert_frames(
+            &tree,
+            &[
+                (id(1), rect(0., 0., 750., 800.)),
+                (id(2), rect(750., 0., 250., 800.)),
+            ],
+        );
+    }
+
+    #[test]
+    fn moved_windows_keep_their_share() {
+        let mut tree = SplitTree::new(&[id(1), id(2)]);
+        tree.scale(id(1), 3.);
+
+        assert!(tree.move_window(id(1), Direction::Right));
+        assert_frames(
+            &tree,
+            &[
+                (id(2), rect(0., 0., 250., 800.)),
+                (id(1), rect(250., 0., 750., 800.)),
+            ],
+        );
+    }
+
+    #[test]
+    fn resize_sets_the_width_and_height_from_the_enclosing_containers() {
+        let mut tree = SplitTree::new(&[id(1), id(2)]);
+        tree.split(id(1), SplitDirection::Vertical);
+        tree.sync(&[id(1), id(2), id(3)]);
+
+        assert!(tree.resize(id(1), &rect(0., 0., 1000., 800.), &CGSize::new(750., 600.)));
+        assert_frames(
+            &tree,
+            &[
+                (id(1), rect(0., 0., 750., 600.)),
+                (id(3), rect(0., 600., 750., 200.)),
+                (id(2), rect(750., 0., 250., 800.)),
+            ],
+        );
+    }
+
+    #[test]
+    fn resize_leaves_the_others_some_room() {
+        let mut tree = SplitTree::new(&[id(1), id(2)]);
+        tree.resize(id(1), &rect(0., 0., 1000., 800.), &CGSize::new(1000., 800.));
+
+        let frames = tree.frames(&rect(0., 0., 1000., 800.));
+        assert!((frames[1].1.size.width - 100.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn balance_shares_containers_equally() {
+        let mut tree = SplitTree::new(&[id(1), id(2)]);
+        tree.split(id(1), SplitDirection::Vertical);
+        tree.sync(&[id(1), id(2), id(3)]);
+        tree.resize(id(1), &rect(0., 0., 1000., 800.), &CGSize::new(750., 600.));
+        tree.balance();
+
+        assert_frames(
+            &tree,
+            &[
+                (id(1), rect(0., 0., 500., 400.)),
+                (id(3), rect(0., 400., 500., 400.)),
+                (id(2), rect(500., 0., 500., 800.)),
+            ],
+        );
+    }
+
+    #[test]
+    fn move_window_stops_at_the_edge() {
+        let mut tree = SplitTree::new(&[id(1), id(2)]);
+
+        assert!(!tree.move_window(id(1), Direction::Left));
+        assert!(!tree.move_window(id(4), Direction::Right));
+        assert_eq!(tree.windows(), vec![id(1), id(2)]);
+    }
+}
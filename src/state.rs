@@ -0,0 +1,117 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{Config, Layout};
+
+/// A window as it can be recognised again by another process: windows are
+/// matched by pid and title, preferring the one with the same frame.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedWindow {
+    pub id: uuid::Uuid,
+    pub pid: i32,
+    pub title: String,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    /// Floating windows are skipped by the group's layout.
+    #[serde(default)]
+    pub floating: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedGroup {
+    pub id: u8,
+    pub layout: Layout,
+    pub primary_column_max_windows: i32,
+    pub primary_column_pct: u8,
+    #[serde(default)]
+    pub gap: f64,
+    pub active_window_idx: Option<usize>,
+    pub windows: Vec<SavedWindow>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedDisplay {
+    pub display_id: u32,
+    /// Identifies the display if its id has changed since.
+    #[serde(default)]
+    pub uuid: Option<String>,
+    pub active_group: Option<u8>,
+    pub groups: Vec<SavedGroup>,
+    /// The primary column's max windows and width of groups that were
+    /// dropped, which they start with when they're used again.
+    #[serde(default)]
+    pub primary_columns: HashMap<u8, (i32, u8)>,
+}
+
+/// A serializable snapshot of the window manager's model, used to hand over
+/// to a new instance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedState {
+    pub active_display_id: Option<u32>,
+    pub displays: Vec<SavedDisplay>,
+    #[serde(default)]
+    pub group_names: HashMap<u8, String>,
+}
+
+/// A window in a layout snapshot. Its app may have been relaunched since, so
+/// it is recognised by its app and title rather than by pid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotWindow {
+    pub bundle_id: Option<String>,
+    pub app_name: Option<String>,
+    pub title: String,
+    /// The frame relative to the display's top left.
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    #[serde(default)]
+    pub floating: bool,
+}
+
+/// A group's arrangement, saved under a name with `save-layout` and put back
+/// with `restore-layout`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayoutSnapshot {
+    pub layout: Layout,
+    pub primary_column_max_windows: i32,
+    pub primary_column_pct: u8,
+    #[serde(default)]
+    pub gap: f64,
+    pub windows: Vec<SnapshotWindow>,
+}
+
+impl LayoutSnapshot {
+    /// `layouts.json`, next to the config file.
+    pub fn default_path() -> Option<PathBuf> {
+        Config::default_path().map(|path| path.with_file_name("layouts.json"))
+    }
+
+    /// Every snapshot saved at `path`, by name. There are none until the
+    /// first is saved.
+    pub fn load_all(path: &Path) -> Result<HashMap<String, LayoutSnapshot>> {
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Could not read layouts file {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Could not parse layouts file {}", path.display()))
+    }
+
+    pub fn save_all(path: &Path, snapshots: &HashMap<String, LayoutSnapshot>) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(snapshots)?)
+            .with_context(|| format!("Could not write layouts file {}", path.display()))
+    }
+}
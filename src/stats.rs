@@ -0,0 +1,135 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Where keyboard focus is: the active group and the focused window's app.
+#[derive(Debug, Clone, PartialEq)]
+struct FocusTarget {
+    group: Option<u8>,
+    app: Option<String>,
+}
+
+/// Time spent focused on each group and app since the window manager started.
+#[derive(Debug)]
+pub struct FocusStats {
+    started: Instant,
+    current: Option<(FocusTarget, Instant)>,
+    by_group: HashMap<u8, Duration>,
+    by_app: HashMap<String, Duration>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupFocusTime {
+    pub group: u8,
+    pub secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppFocusTime {
+    pub app: String,
+    pub secs: u64,
+}
+
+/// A breakdown of focus time, most focused first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FocusReport {
+    pub session_secs: u64,
+    pub groups: Vec<GroupFocusTime>,
+    pub apps: Vec<AppFocusTime>,
+}
+
+impl Default for FocusStats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            current: None,
+            by_group: HashMap::new(),
+            by_app: HashMap::new(),
+        }
+    }
+}
+
+impl FocusStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that focus moved to `app` in `group`. Does nothing if focus
+    /// hasn't changed.
+    pub fn focus(&mut self, group: Option<u8>, app: Option<String>) {
+        let target = FocusTarget { group, app };
+        if self.current.as_ref().map_or(false, |(t, _)| *t == target) {
+            return;
+        }
+        self.close_current();
+        self.current = Some((target, Instant::now()));
+    }
+
+    fn close_current(&mut self) {
+        if let Some((target, since)) = self.current.take() {
+            let elapsed = since.elapsed();
+            if let Some(group) = target.group {
+                *self.by_group.entry(group).or_default() += elapsed;
+            }
+            if let Some(app) = target.app {
+                *self.by_app.entry(app).or_default() += elapsed;
+            }
+        }
+    }
+
+    pub fn report(&self) -> FocusReport {
+        let mut by_group = self.by_group.clone();
+        let mut by_app = self.by_app.clone();
+        if let Some((target, since)) = &self.current {
+            if let Some(group) = target.group {
+                *by_group.entry(group).or_default() += since.elapsed();
+            }
+            if let Some(app) = &target.app {
+                *by_app.entry(app.clone()).or_default() += since.elapsed();
+            }
+        }
+
+        let mut groups: Vec<_> = by_group
+            .into_iter()
+            .map(|(group, d)| GroupFocusTime {
+                group,
+                secs: d.as_secs(),
+            })
+            .collect();
+        groups.sort_by(|a, b| b.secs.cmp(&a.secs));
+        let mut apps: Vec<_> = by_app
+            .into_iter()
+            .map(|(app, d)| AppFocusTime {
+                app,
+                secs: d.as_secs(),
+            })
+            .collect();
+        apps.sort_by(|a, b| b.secs.cmp(&a.secs));
+
+        FocusReport {
+            session_secs: self.started.elapsed().as_secs(),
+            groups,
+            apps,
+        }
+    }
+}
+
+fn format_secs(secs: u64) -> String {
+    format!("{}h {:02}m", secs / 3600, (secs % 3600) / 60)
+}
+
+impl std::fmt::Display for FocusReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Session: {}", format_secs(self.session_secs))?;
+        for g in self.groups.iter() {
+            writeln!(f, "  Group {}: {}", g.group, format_secs(g.secs))?;
+        }
+        for a in self.apps.iter() {
+            writeln!(f, "  {}: {}", a.app, format_secs(a.secs))?;
+        }
+        Ok(())
+    }
+}
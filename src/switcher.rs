@@ -0,0 +1,200 @@
+use cocoa::{
+    appkit::{
+        NSBackingStoreType::NSBackingStoreBuffered, NSColor, NSView, NSWindow, NSWindowStyleMask,
+    },
+    base::{id, nil, NO, YES},
+    foundation::{NSInteger, NSPoint, NSRect, NSSize, NSString},
+};
+use core_graphics::{display::CGRect, image::CGImage};
+use objc::{class, msg_send, sel, sel_impl};
+
+use crate::{
+    capture::ns_image,
+    overlay::{center_on, overlay_scale, POPUP_WINDOW_LEVEL},
+    window_manager::DisplayID,
+};
+
+const THUMBNAIL_WIDTH: f64 = 200.;
+const THUMBNAIL_HEIGHT: f64 = 140.;
+const LABEL_HEIGHT: f64 = 20.;
+const PADDING: f64 = 12.;
+const FONT_SIZE: f64 = 13.;
+const MAX_COLUMNS: usize = 6;
+/// NSImageScaleProportionallyUpOrDown
+const IMAGE_SCALE_PROPORTIONALLY: NSInteger = 3;
+
+/// A window listed in the switcher.
+pub struct SwitcherEntry {
+    pub display_id: DisplayID,
+    pub group: u8,
+    pub window_id: uuid::Uuid,
+    pub title: String,
+    pub image: Option<CGImage>,
+}
+
+/// An alt-tab style overlay listing windows with their thumbnails. The panel
+/// is closed when the switcher is dropped.
+pub struct Switcher {
+    panel: id,
+    tiles: Vec<id>,
+    entries: Vec<SwitcherEntry>,
+    selected: usize,
+}
+
+impl Switcher {
+    /// Open the switcher in the middle of the display with these bounds.
+    pub fn open(entries: Vec<SwitcherEntry>, selected: usize, bounds: &CGRect) -> Self {
+        let scale = overlay_scale(bounds);
+        let padding = PADDING * scale;
+        let columns = usize::min(entries.len(), MAX_COLUMNS).max(1);
+        let rows = (entries.len() + columns - 1) / columns;
+        let tile_width = (THUMBNAIL_WIDTH + PADDING) * scale;
+        let tile_height = (THUMBNAIL_HEIGHT + LABEL_HEIGHT + PADDING) * scale;
+        let width = columns as f64 * tile_width + padding;
+        let height = rows.max(1) as f64 * tile_height + padding;
+
+        unsafe {
+            let panel: id = msg_send![class!(NSPanel), alloc];
+            panel.initWithContentRect_styleMask_backing_defer_(
+                NSRect::new(NSPoint::new(0., 0.), NSSize::new(width, height)),
+                NSWindowStyleMask::NSBorderlessWindowMask
+                    | NSWindowStyleMask::NSNonactivatingPanelMask,
+                NSBackingStoreBuffered,
+                NO,
+            );
+            panel.setOpaque_(NO);
+            panel.setBackgroundColor_(NSColor::colorWithSRGBRed_green_blue_alpha_(
+                nil, 0.1, 0.1, 0.1, 0.85,
+            ));
+            panel.setLevel_(POPUP_WINDOW_LEVEL);
+            center_on(panel, bounds);
+
+            let content = panel.contentView();
+            let mut tiles = vec![];
+            for (i, entry) in entries.iter().enumerate() {
+                let column = i % columns;
+                // Cocoa coordinates: the first row is at the top.
+                let row = rows - 1 - i / columns;
+                let origin = NSPoint::new(
+                    padding / 2. + column as f64 * tile_width,
+                    padding / 2. + row as f64 * tile_height,
+                );
+                let tile = make_tile(origin, entry, scale);
+                content.addSubview_(tile);
+                tiles.push(tile);
+            }
+
+            let switcher = Self {
+                panel,
+                tiles,
+                entries,
+                selected: 0,
+            };
+            switcher.select(selected);
+            panel.orderFrontRegardless();
+            switcher
+        }
+    }
+
+    fn select(&self, idx: usize) {
+        for (i, tile) in self.tiles.iter().enumerate() {
+            unsafe {
+                let layer: id = msg_send![*tile, layer];
+                let color = if i == idx {
+                    NSColor::colorWithSRGBRed_green_blue_alpha_(nil, 0.3, 0.5, 1., 0.8)
+                } else {
+                    NSColor::clearColor(nil)
+                };
+                let cg_color: id = msg_send![color, CGColor];
+                let () = msg_send![layer, setBackgroundColor: cg_color];
+            }
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.entries.is_empty() {
+            self.selected = (self.selected + 1) % self.entries.len();
+            self.select(self.selected);
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if !self.entries.is_empty() {
+            self.selected = (self.selected + self.entries.len() - 1) % self.entries.len();
+            self.select(self.selected);
+        }
+    }
+
+    pub fn selected(&self) -> Option<&SwitcherEntry> {
+        self.entries.get(self.selected)
+    }
+}
+
+/// A thumbnail with the window's title below it.
+unsafe fn make_tile(origin: NSPoint, entry: &SwitcherEntry, scale: f64) -> id {
+    let thumbnail_width = THUMBNAIL_WIDTH * scale;
+    let thumbnail_height = THUMBNAIL_HEIGHT * scale;
+    let label_height = LABEL_HEIGHT * scale;
+    let tile_size = NSSize::new(thumbnail_width, thumbnail_height + label_height);
+    let tile = NSView::alloc(nil).initWithFrame_(NSRect::new(origin, tile_size));
+    tile.setWantsLayer(YES);
+    let layer: id = msg_send![tile, layer];
+    let () = msg_send![layer, setCornerRadius: 8. * scale];
+
+    let image_view: id = msg_send![class!(NSImageView), alloc];
+    let image_view: id = msg_send![
+        image_view,
+        initWithFrame: NSRect::new(
+            NSPoint::new(0., label_height),
+            NSSize::new(thumbnail_width, thumbnail_height),
+        )
+    ];
+    let () = msg_send![image_view, setImageScaling: IMAGE_SCALE_PROPORTIONALLY];
+    if let Some(image) = &entry.image {
+        let image = ns_image(image);
+        let () = msg_send![image_view, setImage: image];
+        let () = msg_send![image, release];
+    }
+    tile.addSubview_(image_view);
+    let () = msg_send![image_view, release];
+
+    let label: id = msg_send![class!(NSTextField), alloc];
+    let label: id = msg_send![
+        label,
+        initWithFrame: NSRect::new(NSPoint::new(0., 0.), NSSize::new(thumbnail_width, label_height))
+    ];
+    let () = msg_send![label, setEditable: NO];
+    let () = msg_send![label, setBezeled: NO];
+    let () = msg_send![label, setDrawsBackground: NO];
+    let white: id = msg_send![class!(NSColor), whiteColor];
+    let () = msg_send![label, setTextColor: white];
+    let font: id = msg_send![class!(NSFont), systemFontOfSize: FONT_SIZE * scale];
+    let () = msg_send![label, setFont: font];
+    let title = format!("{}: {}", entry.group, entry.title);
+    let () = msg_send![label, setStringValue: NSString::alloc(nil).init_str(&title)];
+    tile.addSubview_(label);
+    let () = msg_send![label, release];
+
+    tile
+}
+
+impl Drop for Switcher {
+    fn drop(&mut self) {
+        unsafe {
+            for tile in self.tiles.iter() {
+                let () = msg_send![*tile, release];
+            }
+            self.panel.close();
+        }
+    }
+}
+
+impl std::fmt::Debug for Switcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let titles: Vec<_> = self.entries.iter().map(|e| &e.title).collect();
+        f.debug_struct("Switcher")
+            .field("entries", &titles)
+            .field("selected", &self.selected)
+            .finish()
+    }
+}
@@ -0,0 +1,235 @@
+use std::{cell::RefCell, rc::Rc};
+
+use cocoa::{
+    appkit::{NSColor, NSView},
+    base::{id, nil, NO, YES},
+    foundation::{NSArray, NSInteger, NSPoint, NSRect, NSSize, NSString},
+};
+use core_graphics::image::CGImage;
+use objc::{class, msg_send, sel, sel_impl};
+
+use crate::{
+    capture::ns_image,
+    menu_bar::{MenuTarget, TagTarget},
+    timer::Timer,
+};
+
+const TILE_WIDTH: f64 = 96.;
+const PADDING: f64 = 6.;
+/// NSImageScaleProportionallyUpOrDown
+const IMAGE_SCALE_PROPORTIONALLY: NSInteger = 3;
+
+/// What to do when a thumbnail is clicked, shared by every tasklist opened.
+#[derive(Clone)]
+pub struct TasklistHandler(Rc<dyn Fn(MenuTarget)>);
+
+impl TasklistHandler {
+    /// `on_select` is called on the main thread with the clicked window.
+    pub fn new(on_select: impl Fn(MenuTarget) + 'static) -> Self {
+        Self(Rc::new(on_select))
+    }
+}
+
+impl std::fmt::Debug for TasklistHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TasklistHandler")
+    }
+}
+
+/// A window shown in the tasklist.
+pub struct TasklistEntry {
+    /// A `MenuTarget::Window`.
+    pub target: MenuTarget,
+    pub title: String,
+    pub image: Option<CGImage>,
+    pub is_active: bool,
+}
+
+/// A row of window thumbnails, like awesome's tasklist, which focus their
+/// window when clicked. The view is removed from its window when the
+/// tasklist is dropped.
+pub struct Tasklist {
+    view: id,
+    target: TagTarget,
+    /// The window of each thumbnail, indexed by the button's tag.
+    targets: Rc<RefCell<Vec<MenuTarget>>>,
+    /// The thumbnails' buttons, which the view retains.
+    buttons: Rc<RefCell<Vec<id>>>,
+    /// Entries waiting for `rebuild` to replace the buttons with.
+    pending: Rc<RefCell<Option<Vec<TasklistEntry>>>>,
+    rebuild: RefCell<Option<Timer>>,
+    scale: f64,
+}
+
+impl Tasklist {
+    pub fn new(frame: NSRect, scale: f64, handler: &TasklistHandler) -> Self {
+        let targets: Rc<RefCell<Vec<MenuTarget>>> = Rc::new(RefCell::new(vec![]));
+        let target = {
+            let targets = targets.clone();
+            let handler = handler.clone();
+            TagTarget::new(move |tag| {
+                let target = targets.borrow().get(tag as usize).cloned();
+                if let Some(target) = target {
+                    (handler.0)(target);
+                }
+            })
+        };
+        let view = unsafe { NSView::alloc(nil).initWithFrame_(frame) };
+        Self {
+            view,
+            target,
+            targets,
+            buttons: Rc::new(RefCell::new(vec![])),
+            pending: Rc::new(RefCell::new(None)),
+            rebuild: RefCell::new(None),
+            scale,
+        }
+    }
+
+    pub fn view(&self) -> id {
+        self.view
+    }
+
+    /// Show `entries`. If they are the windows already shown, their buttons
+    /// are updated in place. Otherwise the buttons are replaced on the next
+    /// turn of the run loop: a click on a thumbnail may have led here, and
+    /// the clicked button must outlive its action.
+    pub fn update(&self, entries: Vec<TasklistEntry>) {
+        let unchanged = {
+            let targets = self.targets.borrow();
+            self.pending.borrow().is_none()
+                && targets.len() == entries.len()
+                && targets
+                    .iter()
+                    .zip(entries.iter())
+                    .all(|(t, e)| *t == e.target)
+        };
+        if unchanged {
+            for (button, entry) in self.buttons.borrow().iter().zip(entries.iter()) {
+                unsafe { show_entry(*button, entry, self.scale) };
+            }
+            return;
+        }
+        if self.pending.replace(Some(entries)).is_some() {
+            // Already scheduled.
+            return;
+        }
+        let (view, target) = (self.view, self.target.id());
+        let (targets, buttons, pending) = (
+            self.targets.clone(),
+            self.buttons.clone(),
+            self.pending.clone(),
+        );
+        let scale = self.scale;
+        *self.rebuild.borrow_mut() = Some(Timer::once(0., move || {
+            if let Some(entries) = pending.borrow_mut().take() {
+                unsafe { rebuild(view, target, scale, &targets, &buttons, entries) };
+            }
+        }));
+    }
+}
+
+/// Replace the view's buttons with `entries`'.
+unsafe fn rebuild(
+    view: id,
+    target: id,
+    scale: f64,
+    targets: &RefCell<Vec<MenuTarget>>,
+    buttons: &RefCell<Vec<id>>,
+    entries: Vec<TasklistEntry>,
+) {
+    let mut targets = targets.borrow_mut();
+    let mut buttons = buttons.borrow_mut();
+    targets.clear();
+    buttons.clear();
+    let () = msg_send![view, setSubviews: NSArray::array(nil)];
+    if entries.is_empty() {
+        return;
+    }
+    let frame = NSView::frame(view);
+    let padding = PADDING * scale;
+    let available = (frame.size.width - padding) / entries.len() as f64 - padding;
+    let width = f64::min(TILE_WIDTH * scale, available);
+    let height = frame.size.height - 2. * padding;
+    for (i, entry) in entries.into_iter().enumerate() {
+        let origin = NSPoint::new(padding + i as f64 * (width + padding), padding);
+        let button = make_button(
+            NSRect::new(origin, NSSize::new(width, height)),
+            target,
+            targets.len(),
+            scale,
+        );
+        show_entry(button, &entry, scale);
+        view.addSubview_(button);
+        let () = msg_send![button, release];
+        buttons.push(button);
+        targets.push(entry.target);
+    }
+}
+
+/// Returns a retained, empty button that sends `target` its tag when
+/// clicked.
+unsafe fn make_button(frame: NSRect, target: id, tag: usize, scale: f64) -> id {
+    let button: id = msg_send![class!(NSButton), alloc];
+    let button: id = msg_send![button, initWithFrame: frame];
+    let () = msg_send![button, setBordered: NO];
+    let () = msg_send![button, setTarget: target];
+    let () = msg_send![button, setAction: sel!(itemSelected:)];
+    let () = msg_send![button, setTag: tag as NSInteger];
+    button.setWantsLayer(YES);
+    let layer: id = msg_send![button, layer];
+    let () = msg_send![layer, setCornerRadius: 4. * scale];
+    button
+}
+
+/// Show the window's thumbnail on the button, or its title if there is
+/// none, and a border if it is the active window.
+unsafe fn show_entry(button: id, entry: &TasklistEntry, scale: f64) {
+    let title = NSString::alloc(nil).init_str(&entry.title);
+    match &entry.image {
+        Some(image) => {
+            let image = ns_image(image);
+            let () = msg_send![button, setImage: image];
+            let () = msg_send![image, release];
+            let () = msg_send![button, setImageScaling: IMAGE_SCALE_PROPORTIONALLY];
+            let () = msg_send![button, setTitle: NSString::alloc(nil).init_str("")];
+        }
+        None => {
+            let () = msg_send![button, setImage: nil];
+            let () = msg_send![button, setTitle: title];
+        }
+    }
+    let () = msg_send![button, setToolTip: title];
+
+    let layer: id = msg_send![button, layer];
+    if entry.is_active {
+        let color = NSColor::colorWithSRGBRed_green_blue_alpha_(nil, 0.3, 0.5, 1., 0.8);
+        let cg_color: id = msg_send![color, CGColor];
+        let () = msg_send![layer, setBorderColor: cg_color];
+        let () = msg_send![layer, setBorderWidth: 2. * scale];
+    } else {
+        let () = msg_send![layer, setBorderWidth: 0.];
+    }
+}
+
+impl Drop for Tasklist {
+    fn drop(&mut self) {
+        unsafe {
+            // Before the view goes: a rebuild still to come would use it.
+            if let Some(timer) = self.rebuild.get_mut() {
+                timer.invalidate();
+            }
+            // Before the target goes: the buttons don't retain it.
+            let () = msg_send![self.view, removeFromSuperview];
+            let () = msg_send![self.view, release];
+        }
+    }
+}
+
+impl std::fmt::Debug for Tasklist {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tasklist")
+            .field("targets", &self.targets.borrow())
+            .finish()
+    }
+}
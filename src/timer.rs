@@ -0,0 +1,70 @@
+use std::ffi::c_void;
+
+use core_foundation::{
+    base::TCFType,
+    date::CFAbsoluteTimeGetCurrent,
+    runloop::{
+        kCFRunLoopCommonModes, CFRunLoop, CFRunLoopTimer, CFRunLoopTimerContext,
+        CFRunLoopTimerInvalidate, CFRunLoopTimerRef,
+    },
+};
+
+type Callback = Box<dyn FnMut()>;
+
+extern "C" fn timer_callback(_timer: CFRunLoopTimerRef, info: *mut c_void) {
+    let callback = unsafe { &mut *(info as *mut Callback) };
+    callback();
+}
+
+extern "C" fn release_callback(info: *const c_void) {
+    unsafe { drop(Box::from_raw(info as *mut Callback)) };
+}
+
+/// A timer on the current thread's run loop. Callbacks run on that thread, so
+/// they can safely touch AppKit and the window manager state.
+pub struct Timer(CFRunLoopTimer);
+
+impl Timer {
+    fn new(delay: f64, interval: f64, f: impl FnMut() + 'static) -> Self {
+        let callback: Box<Callback> = Box::new(Box::new(f));
+        let mut context = CFRunLoopTimerContext {
+            version: 0,
+            info: Box::into_raw(callback) as *mut c_void,
+            retain: None,
+            release: Some(release_callback),
+            copyDescription: None,
+        };
+        let timer = CFRunLoopTimer::new(
+            unsafe { CFAbsoluteTimeGetCurrent() } + delay,
+            interval,
+            0,
+            0,
+            timer_callback,
+            &mut context,
+        );
+        unsafe {
+            CFRunLoop::get_current().add_timer(&timer, kCFRunLoopCommonModes);
+        }
+        Self(timer)
+    }
+
+    /// Call `f` every `interval` seconds until the timer is invalidated.
+    pub fn repeating(interval: f64, f: impl FnMut() + 'static) -> Self {
+        Self::new(interval, interval, f)
+    }
+
+    /// Call `f` once after `delay` seconds.
+    pub fn once(delay: f64, f: impl FnMut() + 'static) -> Self {
+        Self::new(delay, 0., f)
+    }
+
+    pub fn invalidate(&self) {
+        unsafe { CFRunLoopTimerInvalidate(self.0.as_concrete_TypeRef()) }
+    }
+}
+
+impl std::fmt::Debug for Timer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Timer")
+    }
+}
@@ -0,0 +1,201 @@
+//! `awesome-rs://` URLs, so Shortcuts, Alfred, Raycast and the like can
+//! perform actions: `awesome-rs://action/show-group/3` does what
+//! `awesomectl action show-group 3` does.
+//!
+//! Any web page or app can open a URL, so only the actions in
+//! `URL_ACTIONS`, which move focus and change layouts, are allowed. The rest
+//! need `awesomectl`, whose socket other users can't reach.
+//!
+//! macOS only sends URLs to apps whose bundle declares the scheme, so the
+//! binary must be run from an app bundle whose `Info.plist` has a
+//! `CFBundleURLTypes` entry with `awesome-rs` in its `CFBundleURLSchemes`:
+//! `make bundle` builds one with `res/Info.plist`.
+
+use std::{ffi::c_void, sync::Once};
+
+use anyhow::{anyhow, Result};
+use cocoa::base::id;
+use objc::{
+    class,
+    declare::ClassDecl,
+    msg_send,
+    runtime::{Class, Object, Sel},
+    sel, sel_impl,
+};
+
+use crate::{window::nsstring_to_string, Action};
+
+pub const URL_SCHEME: &str = "awesome-rs";
+
+/// The actions a URL may perform: none of them close or quit anything or
+/// run commands.
+const URL_ACTIONS: &[&str] = &[
+    "mode-normal",
+    "mode-insert",
+    "relayout-all",
+    "layout-floating",
+    "layout-cascade",
+    "layout-tiling",
+    "layout-custom",
+    "restore-layout",
+    "balance",
+    "next-window",
+    "prev-window",
+    "focus-left",
+    "focus-right",
+    "focus-up",
+    "focus-down",
+    "focus-last",
+    "focus-older",
+    "focus-newer",
+    "focus-urgent",
+    "next-app-window",
+    "prev-app-window",
+    "next-display",
+    "prev-display",
+    "identify-displays",
+    "show-group",
+    "next-group",
+    "prev-group",
+];
+
+/// `kInternetEventClass` and `kAEGetURL`: 'GURL'.
+const GET_URL: u32 = 0x4755_524c;
+/// `keyDirectObject`: '----'.
+const DIRECT_OBJECT: u32 = 0x2d2d_2d2d;
+
+type Callback = Box<dyn Fn(&str)>;
+
+const CALLBACK_IVAR: &str = "callback";
+
+extern "C" fn url_event_received(this: &Object, _cmd: Sel, event: id, _reply: id) {
+    unsafe {
+        let callback = *this.get_ivar::<*mut c_void>(CALLBACK_IVAR) as *const Callback;
+        let descriptor: id = msg_send![event, paramDescriptorForKeyword: DIRECT_OBJECT];
+        let url: id = msg_send![descriptor, stringValue];
+        if let Some(url) = nsstring_to_string(url) {
+            (*callback)(&url);
+        }
+    }
+}
+
+/// An Objective-C class whose instances forward
+/// `handleURLEvent:withReplyEvent:` to a Rust callback.
+fn handler_class() -> &'static Class {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let mut decl = ClassDecl::new("AwesomeUrlHandler", class!(NSObject)).unwrap();
+        decl.add_ivar::<*mut c_void>(CALLBACK_IVAR);
+        unsafe {
+            decl.add_method(
+                sel!(handleURLEvent:withReplyEvent:),
+                url_event_received as extern "C" fn(&Object, Sel, id, id),
+            );
+        }
+        decl.register();
+    });
+    Class::get("AwesomeUrlHandler").unwrap()
+}
+
+unsafe fn event_manager() -> id {
+    msg_send![class!(NSAppleEventManager), sharedAppleEventManager]
+}
+
+/// Calls back on the main thread with each URL the app is asked to open,
+/// until dropped.
+pub struct UrlHandler {
+    handler: id,
+    callback: *mut Callback,
+}
+
+impl UrlHandler {
+    pub fn new(f: impl Fn(&str) + 'static) -> Self {
+        let callback: *mut Callback = Box::into_raw(Box::new(Box::new(f)));
+        unsafe {
+            let handler: id = msg_send![handler_class(), new];
+            (*handler).set_ivar::<*mut c_void>(CALLBACK_IVAR, callback as *mut c_void);
+            let () = msg_send![
+                event_manager(),
+                setEventHandler: handler
+                andSelector: sel!(handleURLEvent:withReplyEvent:)
+                forEventClass: GET_URL
+                andEventID: GET_URL
+            ];
+            Self { handler, callback }
+        }
+    }
+}
+
+impl Drop for UrlHandler {
+    fn drop(&mut self) {
+        unsafe {
+            let () = msg_send![
+                event_manager(),
+                removeEventHandlerForEventClass: GET_URL
+                andEventID: GET_URL
+            ];
+            let () = msg_send![self.handler, release];
+            drop(Box::from_raw(self.callback));
+        }
+    }
+}
+
+impl std::fmt::Debug for UrlHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "UrlHandler")
+    }
+}
+
+/// Decode `%XX` escapes, e.g. `Web%20Browsing`.
+fn percent_decode(s: &str) -> Result<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| anyhow!("Invalid escape in {:?}", s))?;
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| anyhow!("Invalid UTF-8 in {:?}", s))
+}
+
+/// The action an `awesome-rs://action/<id>/<args>...` URL asks for.
+pub fn action_of_url(url: &str) -> Result<Action> {
+    let path = url
+        .strip_prefix(URL_SCHEME)
+        .and_then(|rest| rest.strip_prefix("://"))
+        .ok_or_else(|| anyhow!("Not an {}:// URL: {:?}", URL_SCHEME, url))?;
+    // Shortcuts may add a trailing slash or a query.
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let mut segments = path.split('/').filter(|s| !s.is_empty());
+    match segments.next() {
+        Some("action") => {}
+        _ => {
+            return Err(anyhow!(
+                "Expected {}://action/<id>, got {:?}",
+                URL_SCHEME,
+                url
+            ))
+        }
+    }
+    let id = segments
+        .next()
+        .ok_or_else(|| anyhow!("Missing action in {:?}", url))
+        .and_then(percent_decode)?;
+    if !URL_ACTIONS.contains(&id.as_str()) {
+        return Err(anyhow!(
+            "Action {:?} can't be performed from a URL, use awesomectl",
+            id
+        ));
+    }
+    let args = segments.map(percent_decode).collect::<Result<Vec<_>>>()?;
+    Action::parse(&id, &args)
+}
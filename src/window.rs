@@ -1,9 +1,24 @@
-use std::{error::Error, fmt::Display, ops::Deref};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    error::Error,
+    ffi::CStr,
+    fmt::Display,
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
 
 use accessibility::{AXAttribute, AXUIElement, AXUIElementAttributes, AXValue};
-use accessibility_sys::{kAXApplicationRole, kAXCloseButtonAttribute, kAXPressAction};
-use anyhow::Result;
-use cocoa::appkit::{NSApp, NSApplicationActivationOptions, NSRunningApplication};
+use accessibility_sys::{
+    kAXApplicationRole, kAXCloseButtonAttribute, kAXFrontmostAttribute, kAXMinimizeButtonAttribute,
+    kAXPressAction, kAXRaiseAction, kAXZoomButtonAttribute,
+};
+use anyhow::{anyhow, Result};
+use cocoa::{
+    appkit::{NSApp, NSApplicationActivationOptions, NSRunningApplication},
+    base::{id, nil, BOOL, NO, YES},
+    foundation::NSString,
+};
 use core_foundation::{
     base::{CFType, ItemRef, TCFType},
     boolean::CFBoolean,
@@ -13,6 +28,10 @@ use core_graphics::{
     base::CGError,
     display::{CGDisplay, CGPoint, CGRect, CGSize},
 };
+use objc::{class, msg_send, sel, sel_impl};
+use serde::Deserialize;
+
+use crate::layout::same_frame;
 
 #[derive(Debug)]
 pub struct CGErrorWrapper(pub CGError);
@@ -25,8 +44,114 @@ impl Display for CGErrorWrapper {
 
 impl Error for CGErrorWrapper {}
 
+const SIGKILL: i32 = 9;
+
+extern "C" {
+    fn kill(pid: i32, sig: i32) -> i32;
+}
+
+/// Kill the application with `pid` with SIGKILL, for when it has hung and
+/// won't answer `Window::quit_app`. Unsaved documents are lost.
+pub fn force_quit_app(pid: i32) -> Result<()> {
+    if unsafe { kill(pid, SIGKILL) } != 0 {
+        return Err(anyhow!(
+            "Killing {}: {}",
+            pid,
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(())
+}
+
+thread_local! {
+    // Windows are only moved on the main thread. Keyed by pid, so an app's
+    // windows can be forgotten together.
+    static FRAME_CACHE: RefCell<HashMap<i32, Vec<(AXUIElement, CGRect)>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Forget the frames of the app's windows, e.g. because it says one of them
+/// moved or resized.
+pub fn forget_frames(pid: i32) {
+    FRAME_CACHE.with(|cache| cache.borrow_mut().remove(&pid));
+}
+
+/// Forget every cached frame, e.g. because an app we don't observe may have
+/// moved its windows.
+pub fn forget_all_frames() {
+    FRAME_CACHE.with(|cache| cache.borrow_mut().clear());
+}
+
+fn cached_frame(element: &AXUIElement) -> Option<CGRect> {
+    let pid = element.pid().ok()?;
+    FRAME_CACHE.with(|cache| {
+        cache
+            .borrow()
+            .get(&pid)?
+            .iter()
+            .find(|(e, _)| e == element)
+            .map(|(_, frame)| *frame)
+    })
+}
+
+fn cache_frame(element: &AXUIElement, frame: CGRect) {
+    let pid = match element.pid() {
+        Ok(pid) => pid,
+        Err(_) => return,
+    };
+    FRAME_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let frames = cache.entry(pid).or_default();
+        match frames.iter_mut().find(|(e, _)| e == element) {
+            Some((_, f)) => *f = frame,
+            None => frames.push((element.clone(), frame)),
+        }
+    });
+}
+
+fn forget_frame(element: &AXUIElement) {
+    if let Ok(pid) = element.pid() {
+        FRAME_CACHE.with(|cache| {
+            if let Some(frames) = cache.borrow_mut().get_mut(&pid) {
+                frames.retain(|(e, _)| e != element);
+            }
+        });
+    }
+}
+
+/// Copy an `NSString` into a Rust string.
+pub(crate) unsafe fn nsstring_to_string(s: id) -> Option<String> {
+    if s == nil {
+        return None;
+    }
+    let bytes = s.UTF8String();
+    Some(CStr::from_ptr(bytes).to_string_lossy().into_owned())
+}
+
+/// One way of bringing a window to the front. Apps differ in which of these
+/// they respond to.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ActivationStep {
+    /// Set the window as its application's main window.
+    SetMain,
+    /// Activate the application with `NSRunningApplication`.
+    ActivateApp,
+    /// Perform the window's AXRaise action.
+    Raise,
+    /// Set the application's AXFrontmost attribute.
+    SetFrontmost,
+}
+
+pub const DEFAULT_ACTIVATION: &[ActivationStep] =
+    &[ActivationStep::SetMain, ActivationStep::ActivateApp];
+
 #[allow(non_upper_case_globals)]
 const kAXEnhancedUserInterfaceAttribute: &str = "AXEnhancedUserInterface";
+#[allow(non_upper_case_globals)]
+const kAXFullScreenButtonAttribute: &str = "AXFullScreenButton";
+#[allow(non_upper_case_globals)]
+const kAXModalAttribute: &str = "AXModal";
 
 pub trait Window {
     fn element(&self) -> &AXUIElement;
@@ -42,6 +167,49 @@ pub trait Window {
         }
     }
 
+    /// The bundle identifier of this window's application, if it has one.
+    fn bundle_identifier(&self) -> Result<Option<String>> {
+        let pid = self.element().pid()?;
+        unsafe {
+            let app = NSRunningApplication::runningApplicationWithProcessIdentifier(nil, pid);
+            if app == nil {
+                return Ok(None);
+            }
+            let bundle_id: id = msg_send![app, bundleIdentifier];
+            Ok(nsstring_to_string(bundle_id))
+        }
+    }
+
+    /// The localized name of this window's application.
+    fn app_name(&self) -> Result<Option<String>> {
+        let pid = self.element().pid()?;
+        unsafe {
+            let app = NSRunningApplication::runningApplicationWithProcessIdentifier(nil, pid);
+            if app == nil {
+                return Ok(None);
+            }
+            let name: id = msg_send![app, localizedName];
+            Ok(nsstring_to_string(name))
+        }
+    }
+
+    /// Ask this window's application to quit, as its Quit menu item would,
+    /// so that it can offer to save its documents first.
+    fn quit_app(&self) -> Result<()> {
+        let pid = self.element().pid()?;
+        unsafe {
+            let app = NSRunningApplication::runningApplicationWithProcessIdentifier(nil, pid);
+            if app == nil {
+                return Err(anyhow!("No running application with pid {}", pid));
+            }
+            let requested: BOOL = msg_send![app, terminate];
+            if requested == NO {
+                return Err(anyhow!("Application {} can't be asked to quit", pid));
+            }
+        }
+        Ok(())
+    }
+
     fn debug_attributes(&self) -> Result<()> {
         let w = self.element();
         eprintln!("{:?}", w);
@@ -89,8 +257,16 @@ pub trait Window {
 
     fn set_position(&self, pos: CGPoint) -> Result<()> {
         let value = AXValue::from_CGPoint(pos)?;
-        self.element()
-            .set_attribute(&AXAttribute::position(), value)?;
+        let result = self
+            .element()
+            .set_attribute(&AXAttribute::position(), value);
+        #[cfg(feature = "cgs-move")]
+        if let Err(e) = result {
+            eprintln!("While setting position, falling back to CGS: {:?}", e);
+            return crate::cgs::move_window(self.element(), pos);
+        }
+        result?;
+        forget_frame(self.element());
         println!(
             "set_position desired:{:?} result:{:?}",
             pos,
@@ -107,6 +283,7 @@ pub trait Window {
     fn set_size(&self, size: CGSize) -> Result<()> {
         let value = AXValue::from_CGSize(size)?;
         self.element().set_attribute(&AXAttribute::size(), value)?;
+        forget_frame(self.element());
         println!("set_size desired:{:?} result:{:?}", size, self.size());
         Ok(())
     }
@@ -117,10 +294,17 @@ pub trait Window {
         Ok(app_is_frontmost && window_is_main)
     }
 
+    /// The window's frame, from the cache if we have read it since it last
+    /// moved or resized.
     fn frame(&self) -> Result<CGRect> {
+        if let Some(frame) = cached_frame(self.element()) {
+            return Ok(frame);
+        }
         let position = self.position()?;
         let size = self.size()?;
-        Ok(CGRect::new(&position, &size))
+        let frame = CGRect::new(&position, &size);
+        cache_frame(self.element(), frame);
+        Ok(frame)
     }
 
     fn set_frame(&self, frame: CGRect) -> Result<()> {
@@ -147,21 +331,91 @@ pub trait Window {
             }
         }
 
-        self.set_size(frame.size)?;
-        self.set_position(frame.origin)?;
-        self.set_size(frame.size)
+        let current = self.frame().ok();
+        if matches!(&current, Some(current) if same_frame(current, &frame)) {
+            return Ok(());
+        }
+        let resize = current.map_or(true, |c| {
+            c.size.width != frame.size.width || c.size.height != frame.size.height
+        });
+        let moves = current.map_or(true, |c| {
+            c.origin.x != frame.origin.x || c.origin.y != frame.origin.y
+        });
+
+        if resize {
+            if let Err(e) = self.set_size(frame.size) {
+                if !cfg!(feature = "cgs-move") {
+                    return Err(e);
+                }
+                // The window may still be movable.
+                eprintln!("While setting size, only moving the window: {:?}", e);
+                return self.set_position(frame.origin);
+            }
+        }
+        if moves {
+            self.set_position(frame.origin)?;
+        }
+        // Resize again in case the window was too big to fit where it was.
+        if resize && moves {
+            self.set_size(frame.size)?;
+        }
+        Ok(())
     }
 
     /// Bring this window's application to front, and set this window as main.
     fn activate(&self) -> Result<()> {
-        self.element().set_main(true)?;
-        let pid = self.element().pid()?;
-        unsafe {
-            let app = NSRunningApplication::runningApplicationWithProcessIdentifier(NSApp(), pid);
-            app.activateWithOptions_(
-                NSApplicationActivationOptions::NSApplicationActivateIgnoringOtherApps,
-            );
-        };
+        self.activate_with(DEFAULT_ACTIVATION)
+    }
+
+    /// Perform each of `steps` in order. A step that fails is logged and we
+    /// fall back to the remaining steps; this only fails if every step does.
+    fn activate_with(&self, steps: &[ActivationStep]) -> Result<()> {
+        let mut last_error = None;
+        let mut any_succeeded = false;
+        for step in steps {
+            match self.activation_step(*step) {
+                Ok(()) => any_succeeded = true,
+                Err(e) => {
+                    eprintln!("While activating window with {:?}: {:?}", step, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        match last_error {
+            Some(e) if !any_succeeded => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn activation_step(&self, step: ActivationStep) -> Result<()> {
+        match step {
+            ActivationStep::SetMain => self.element().set_main(true)?,
+            ActivationStep::ActivateApp => {
+                let pid = self.element().pid()?;
+                let activated = unsafe {
+                    let app =
+                        NSRunningApplication::runningApplicationWithProcessIdentifier(NSApp(), pid);
+                    if app == nil {
+                        return Err(anyhow!("No running application with pid {}", pid));
+                    }
+                    app.activateWithOptions_(
+                        NSApplicationActivationOptions::NSApplicationActivateIgnoringOtherApps,
+                    ) == YES
+                };
+                if !activated {
+                    return Err(anyhow!("activateWithOptions failed"));
+                }
+            }
+            ActivationStep::Raise => self
+                .element()
+                .perform_action(&CFString::from_static_string(kAXRaiseAction))?,
+            ActivationStep::SetFrontmost => {
+                let frontmost =
+                    AXAttribute::new(&CFString::from_static_string(kAXFrontmostAttribute));
+                self.application()?
+                    .set_attribute(&frontmost, CFBoolean::true_value().as_CFType())?;
+            }
+        }
         Ok(())
     }
 
@@ -173,6 +427,22 @@ pub trait Window {
         Ok(display)
     }
 
+    /// The window's AXSubrole, e.g. "AXStandardWindow" or "AXDialog".
+    fn subrole(&self) -> Option<String> {
+        self.element().subrole().ok().map(|s| s.to_string())
+    }
+
+    /// Whether the window is modal, i.e. blocks the rest of its app.
+    fn is_modal(&self) -> bool {
+        let modal: AXAttribute<CFType> =
+            AXAttribute::new(&CFString::from_static_string(kAXModalAttribute));
+        self.element()
+            .attribute(&modal)
+            .ok()
+            .and_then(|v| v.downcast_into::<CFBoolean>())
+            .map_or(false, bool::from)
+    }
+
     fn minimized(&self) -> Result<bool> {
         let b = self.element().minimized()?.into();
         Ok(b)
@@ -184,39 +454,110 @@ pub trait Window {
         Ok(())
     }
 
+    /// The button in the window's `attr` attribute, e.g.
+    /// `kAXCloseButtonAttribute`, if it has one.
+    fn button(&self, attr: &'static str) -> Result<Option<AXUIElement>> {
+        find_button(self.element(), attr)
+    }
+
+    /// Forget a button returned by `button`, e.g. because it is no longer
+    /// valid.
+    fn forget_button(&self, _attr: &'static str) {}
+
+    /// Press the button in the window's `attr` attribute. Does nothing if the
+    /// window has no such button.
+    fn press_button(&self, attr: &'static str) -> Result<()> {
+        let press = CFString::from_static_string(kAXPressAction);
+        if let Some(btn) = self.button(attr)? {
+            match btn.perform_action(&press) {
+                Err(accessibility::Error::Ax(accessibility_sys::kAXErrorInvalidUIElement)) => {
+                    // The cached button went away: look it up again.
+                    self.forget_button(attr);
+                    if let Some(btn) = self.button(attr)? {
+                        btn.perform_action(&press)?;
+                    }
+                }
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
     fn close(&self) -> Result<()> {
-        let close_button_attr: AXAttribute<CFType> =
-            AXAttribute::new(&CFString::from_static_string(kAXCloseButtonAttribute));
-        let btn = self
-            .element()
-            .attribute(&close_button_attr)?
-            .downcast_into::<AXUIElement>();
-        if let Some(btn) = btn {
-            btn.perform_action(&CFString::from_static_string(kAXPressAction))?;
-            Ok(())
-        } else {
-            Ok(())
+        self.press_button(kAXCloseButtonAttribute)
+    }
+
+    /// Press the window's zoom (green) button.
+    fn zoom(&self) -> Result<()> {
+        self.press_button(kAXZoomButtonAttribute)
+    }
+
+    /// Press the window's minimize (yellow) button. Unlike `set_minimized`,
+    /// this goes through the app, which may e.g. save state first.
+    fn press_minimize_button(&self) -> Result<()> {
+        self.press_button(kAXMinimizeButtonAttribute)
+    }
+
+    /// Move the window into, or out of, its own full-screen space.
+    fn toggle_full_screen(&self) -> Result<()> {
+        self.press_button(kAXFullScreenButtonAttribute)
+    }
+}
+
+fn find_button(element: &AXUIElement, attr: &'static str) -> Result<Option<AXUIElement>> {
+    let attr: AXAttribute<CFType> = AXAttribute::new(&CFString::from_static_string(attr));
+    match element.attribute(&attr) {
+        Ok(value) => Ok(value.downcast_into::<AXUIElement>()),
+        Err(accessibility::Error::Ax(accessibility_sys::kAXErrorNoValue))
+        | Err(accessibility::Error::Ax(accessibility_sys::kAXErrorAttributeUnsupported)) => {
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// What double-clicking a window's title bar does, from the "Double-click a
+/// window's title bar to" system setting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TitleBarDoubleClickAction {
+    Zoom,
+    Minimize,
+    None,
+}
+
+impl TitleBarDoubleClickAction {
+    pub fn from_user_defaults() -> Self {
+        let value = unsafe {
+            let defaults: id = msg_send![class!(NSUserDefaults), standardUserDefaults];
+            let key = NSString::alloc(nil).init_str("AppleActionOnDoubleClick");
+            let value: id = msg_send![defaults, stringForKey: key];
+            nsstring_to_string(value)
+        };
+        match value.as_deref() {
+            Some("Minimize") => Self::Minimize,
+            Some("None") => Self::None,
+            // "Maximize", "Fill" and unset all zoom the window.
+            _ => Self::Zoom,
         }
     }
 }
 
+/// An Accessibility window, as `AXWindowSystem` hands it out in a
+/// `WindowHandle`.
 #[derive(Debug, Clone)]
 pub struct WindowWrapper<T> {
-    id: uuid::Uuid,
     element: T,
+    /// Buttons already looked up by `Window::button`, shared between clones.
+    buttons: Arc<Mutex<HashMap<&'static str, AXUIElement>>>,
 }
 
 impl<T> WindowWrapper<T> {
     pub fn new(element: T) -> Self {
         Self {
-            id: uuid::Uuid::new_v4(),
             element,
+            buttons: Arc::new(Mutex::new(HashMap::new())),
         }
     }
-
-    pub fn id(&self) -> &uuid::Uuid {
-        &self.id
-    }
 }
 
 impl WindowWrapper<AXUIElement> {
@@ -253,7 +594,8 @@ impl WindowWrapper<AXUIElement> {
         }
     }
 
-    fn _active() -> Result<Self> {
+    /// The window with keyboard focus.
+    pub fn focused() -> Result<Self> {
         let element = AXUIElement::system_wide().focused_uielement()?;
         Self::from_ui_element(element)
     }
@@ -263,6 +605,21 @@ impl Window for WindowWrapper<AXUIElement> {
     fn element(&self) -> &AXUIElement {
         &self.element
     }
+
+    fn button(&self, attr: &'static str) -> Result<Option<AXUIElement>> {
+        if let Some(btn) = self.buttons.lock().unwrap().get(attr) {
+            return Ok(Some(btn.clone()));
+        }
+        let btn = find_button(&self.element, attr)?;
+        if let Some(btn) = &btn {
+            self.buttons.lock().unwrap().insert(attr, btn.clone());
+        }
+        Ok(btn)
+    }
+
+    fn forget_button(&self, attr: &'static str) {
+        self.buttons.lock().unwrap().remove(attr);
+    }
 }
 
 impl Window for WindowWrapper<&AXUIElement> {
@@ -1,175 +1,597 @@
-use std::{collections::HashMap, ffi::c_void, mem};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    mem,
+    process::Command,
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
 
-use accessibility::{AXUIElement, AXUIElementAttributes};
-use accessibility_sys::kAXWindowRole;
 use anyhow::{anyhow, Result};
 use cocoa::{
     appkit::{
-        NSBackingStoreType::NSBackingStoreBuffered, NSColor, NSRunningApplication, NSTextField,
-        NSView, NSWindow, NSWindowStyleMask,
+        NSBackingStoreType::NSBackingStoreBuffered, NSColor, NSTextField, NSView, NSWindow,
+        NSWindowStyleMask,
     },
-    base::{id, nil},
+    base::{id, nil, NO, YES},
     foundation::{NSPoint, NSRect, NSSize, NSString},
 };
-use core_foundation::{
-    array::CFArray,
-    base::{FromVoid, ItemRef, TCFType, ToVoid},
-    dictionary::CFDictionary,
-    number::CFNumber,
-    string::CFString,
-};
 use core_graphics::{
-    display::{kCGWindowListExcludeDesktopElements, kCGWindowListOptionOnScreenOnly, CGDisplay},
+    event::{CGEvent, EventField},
     geometry::{CGPoint, CGRect, CGSize},
-    window::{kCGWindowLayer, kCGWindowOwnerPID},
 };
+use objc::{class, msg_send, sel, sel_impl};
 
 use crate::{
-    action::Action,
-    drag_window::DragWindow,
-    layout::Layout,
+    action::{holds_insert_normal_chord, Action},
+    alert::alert,
+    capture::ThumbnailCache,
+    chord::{ChordKey, Chords, KeyBindings, KeyPress},
+    config::{modifier_flags, Color, Config, GroupDefaults, LayoutName},
+    direction::{nearest_in_direction, Direction},
+    display_labels::{display_name, DisplayLabels},
+    double_tap::DoubleTaps,
+    drag_window::{get_modifier_flags, get_mouse_location, DragWindow},
+    hooks::{HookState, Hooks},
+    ipc::{Request, Response},
+    layout::{inset, is_registered_layout, same_frame, FloatingFrames, Layout},
+    menu_bar::{MenuBar, MenuGroup, MenuTarget},
     mode::Mode,
-    window::{Window, WindowWrapper},
-    CGErrorWrapper,
+    overlay::{center_on, overlay_scale, OverlayWindow},
+    palette::Palette,
+    resize_window::ResizeWindow,
+    snap::{snap_frame, SnapRegion},
+    split_tree::{SplitDirection, SplitTree},
+    state::{LayoutSnapshot, SavedDisplay, SavedGroup, SavedState, SavedWindow, SnapshotWindow},
+    stats::FocusStats,
+    switcher::{Switcher, SwitcherEntry},
+    tasklist::{Tasklist, TasklistEntry, TasklistHandler},
+    timer::Timer,
+    window::{nsstring_to_string, TitleBarDoubleClickAction},
+    window_system::{AXWindowSystem, DynWindowSystem, PendingWindows, WindowHandle},
+};
+#[cfg(feature = "spaces")]
+use crate::{
+    config::SpacesConfig,
+    spaces::{
+        display_spaces, move_window_to_space, spaces_of_display, switch_to_space, window_spaces,
+        SpaceID,
+    },
 };
+#[cfg(feature = "scripting")]
+use crate::{hooks::HookEvent, script::Script};
 
-fn get_window_pids(on_screen_only: bool) -> Result<Vec<i64>> {
-    let opts = kCGWindowListExcludeDesktopElements;
-    let opts = if on_screen_only {
-        opts | kCGWindowListOptionOnScreenOnly
-    } else {
-        opts
-    };
-    let window_list: CFArray<*const c_void> =
-        CGDisplay::window_list_info(opts, None).ok_or(anyhow!("no window_list_info"))?;
-
-    let iter = window_list
-        .iter()
-        .map(|w| unsafe { CFDictionary::from_void(*w) })
-        .filter(|d: &ItemRef<CFDictionary>| {
-            // Keep only windows at layer 0
-            let l: CFString = unsafe { CFString::wrap_under_create_rule(kCGWindowLayer) };
-            let layer_void: ItemRef<'_, *const c_void> = d.get(l.to_void());
-            let layer = unsafe { CFNumber::from_void(*layer_void) };
-            layer.to_i32() == Some(0)
-        })
-        .filter_map(|d| {
-            // eprintln!("{:?}", d);
-            let k: CFString = unsafe { CFString::wrap_under_create_rule(kCGWindowOwnerPID) };
-            let pid = d.get(k.to_void());
-            let pid = unsafe { CFNumber::from_void(*pid) };
-            pid.to_i64()
-        })
-        .collect::<Vec<i64>>();
-    Ok(iter)
+/// Return the position of the bottom-left of the window in Cocoa coordinates:
+/// (0,0) is bottom-left of main display, y increases in the up direction.
+fn position_to_origin(system: &DynWindowSystem, w: &WindowHandle) -> Result<NSPoint> {
+    // (0,0) is top-left of main display, y increases down the screen
+    let f = system.frame(w)?;
+    let m = system.display_bounds(system.main_display_id());
+
+    // (0,0) is bottom-left of main display, y increases up the screen
+    let x = f.origin.x;
+    let y = m.size.height - f.origin.y - f.size.height;
+    Ok(NSPoint::new(x, y))
 }
 
-fn get_all_windows() -> Result<(
-    Vec<WindowWrapper<AXUIElement>>,
-    Vec<WindowWrapper<AXUIElement>>,
-)> {
-    let mut window_pids_deduped = vec![];
-    // First use onScreenOnly to get apps with recent windows first
-    for &pid in get_window_pids(true)?.iter() {
-        if !window_pids_deduped.contains(&pid) {
-            window_pids_deduped.push(pid);
+/// NSFloatingWindowLevel
+const FLOATING_WINDOW_LEVEL: i64 = 3;
+
+/// The group a display starts on.
+const DEFAULT_GROUP: u8 = 1;
+
+/// How much `GrowTile` and `ShrinkTile` change a tile's height (or in a
+/// split tree, its share of its container) by, and how far they can go
+/// relative to the other tiles in its column.
+const TILE_WEIGHT_STEP: f64 = 1.25;
+const MIN_TILE_WEIGHT: f64 = 0.2;
+const MAX_TILE_WEIGHT: f64 = 5.;
+
+/// Size of the status window before scaling for the display it's shown on.
+const STATUS_WINDOW_WIDTH: f64 = 360.;
+const STATUS_WINDOW_HEIGHT: f64 = 420.;
+const STATUS_FONT_SIZE: f64 = 13.;
+/// Height of the row of thumbnails at the bottom of the status window.
+const STATUS_TASKLIST_HEIGHT: f64 = 72.;
+
+/// How long after the last repeat of a held motion key to assume its
+/// release was missed.
+const KEY_REPEAT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Shown in the status window while windows are listed in the background.
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+const SPINNER_FRAME_MILLIS: u128 = 100;
+
+/// Updates skipped while a batch of actions runs, to be done once at the end.
+#[derive(Debug, Default)]
+struct DeferredUpdates {
+    /// Relayout every display, since the batch may have changed several.
+    relayout: Cell<bool>,
+    activate: Cell<bool>,
+    highlight: Cell<bool>,
+}
+
+/// Arranges for `WindowManager::wake_up` to be called after some seconds,
+/// returning a timer that cancels the call when invalidated.
+#[derive(Clone)]
+pub struct WakeHandler(Rc<dyn Fn(f64) -> Timer>);
+
+impl WakeHandler {
+    pub fn new(schedule: impl Fn(f64) -> Timer + 'static) -> Self {
+        Self(Rc::new(schedule))
+    }
+}
+
+impl std::fmt::Debug for WakeHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WakeHandler")
+    }
+}
+
+/// How long to wait for more relayout requests before laying displays out.
+const RELAYOUT_DELAY: Duration = Duration::from_millis(30);
+
+/// Displays waiting to be laid out. Actions often ask for a relayout several
+/// times in a row, e.g. moving a window relayouts both displays and then all
+/// of them: each display is laid out once, when the requests stop.
+#[derive(Debug, Default)]
+struct RelayoutScheduler {
+    display_ids: RefCell<HashSet<DisplayID>>,
+    due: Cell<Option<Instant>>,
+    /// Whether to redraw the highlight once the displays are laid out.
+    highlight: Cell<bool>,
+}
+
+impl RelayoutScheduler {
+    fn request(&self, display_ids: impl IntoIterator<Item = DisplayID>) {
+        self.display_ids.borrow_mut().extend(display_ids);
+        self.due.set(Some(Instant::now() + RELAYOUT_DELAY));
+    }
+
+    fn is_pending(&self) -> bool {
+        self.due.get().is_some()
+    }
+
+    /// Lay out displays whose ids changed under their new ids.
+    fn rekey(&self, moved: &HashMap<DisplayID, DisplayID>) {
+        let mut display_ids = self.display_ids.borrow_mut();
+        *display_ids = display_ids
+            .iter()
+            .map(|d_id| moved.get(d_id).copied().unwrap_or(*d_id))
+            .collect();
+    }
+
+    /// The displays to lay out now, if the requests have stopped.
+    fn take_due(&self) -> Option<HashSet<DisplayID>> {
+        match self.due.get() {
+            Some(due) if Instant::now() >= due => {
+                self.due.set(None);
+                Some(self.display_ids.take())
+            }
+            _ => None,
         }
     }
-    // Then get everything else to get apps with minimized
-    for &pid in get_window_pids(false)?.iter() {
-        if !window_pids_deduped.contains(&pid) {
-            window_pids_deduped.push(pid);
+}
+
+/// How long `IdentifyDisplays` shows its labels for, in seconds.
+const DISPLAY_LABEL_DURATION: f64 = 2.5;
+
+/// How long a destructive action waits to be done again to confirm it.
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// What a destructive action waiting to be confirmed will act on, so that
+/// doing it again only confirms it if it would still act on the same thing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Confirmation {
+    /// Kill the app with this pid.
+    ForceQuitApp(i32),
+    /// Close the windows of this display's group.
+    CloseGroupWindows(DisplayID, u8),
+}
+
+impl Confirmation {
+    fn action_id(&self) -> &'static str {
+        match self {
+            Confirmation::ForceQuitApp(_) => Action::ForceQuitApp.id(),
+            Confirmation::CloseGroupWindows(_, _) => Action::CloseGroupWindows.id(),
         }
     }
+}
 
-    let apps = window_pids_deduped
-        .iter()
-        .map(|pid| AXUIElement::application(*pid as i32))
-        .collect::<Vec<_>>();
-
-    let mut open_windows = vec![];
-    let mut minimized_windows = vec![];
-    for app in apps {
-        match app.windows() {
-            Ok(windows) => {
-                for w in windows.iter() {
-                    if w.role()? == kAXWindowRole {
-                        let w = WindowWrapper::new(w.clone());
-                        // w.debug_attributes()?;
-                        if w.minimized()? {
-                            minimized_windows.push(w);
-                        } else {
-                            open_windows.push(w);
-                        }
-                    }
-                }
-            }
-            Err(accessibility::Error::Ax(accessibility_sys::kAXErrorCannotComplete)) => {
-                // e.g. kCGWindowOwnerName="Window Server" kCGWindowName=StatusIndicator
-                ()
+/// How many windows `FocusOlder` can go back through.
+const FOCUS_HISTORY_LEN: usize = 50;
+
+/// Windows in the order they were focused, most recent last.
+#[derive(Debug, Default)]
+struct FocusHistory {
+    windows: Vec<uuid::Uuid>,
+    /// Where `FocusOlder` and `FocusNewer` have stepped to. The history
+    /// isn't reordered while stepping through it, only once another window
+    /// is focused some other way.
+    cursor: Option<usize>,
+}
+
+impl FocusHistory {
+    fn focus(&mut self, w_id: uuid::Uuid) {
+        if let Some(cursor) = self.cursor {
+            if self.windows.get(cursor) == Some(&w_id) {
+                return;
             }
-            Err(e) => return Err(e.into()),
+            self.cursor = None;
+        }
+        if self.windows.last() == Some(&w_id) {
+            return;
+        }
+        self.windows.retain(|id| *id != w_id);
+        self.windows.push(w_id);
+        if self.windows.len() > FOCUS_HISTORY_LEN {
+            self.windows.remove(0);
         }
     }
 
-    // eprintln!("open windows: {:?}", open_windows);
-    // eprintln!("minimized windows: {:?}", minimized_windows);
+    /// The window focused before the current one.
+    fn last(&mut self) -> Option<uuid::Uuid> {
+        self.cursor = None;
+        self.windows.iter().rev().nth(1).copied()
+    }
+
+    fn older(&mut self) -> Option<uuid::Uuid> {
+        let cursor = self.cursor.unwrap_or(self.windows.len().checked_sub(1)?);
+        self.cursor = Some(cursor.checked_sub(1)?);
+        self.cursor.map(|cursor| self.windows[cursor])
+    }
+
+    fn newer(&mut self) -> Option<uuid::Uuid> {
+        let cursor = self.cursor? + 1;
+        if cursor >= self.windows.len() {
+            return None;
+        }
+        self.cursor = Some(cursor);
+        Some(self.windows[cursor])
+    }
 
-    Ok((open_windows, minimized_windows))
+    fn retain(&mut self, f: impl Fn(&uuid::Uuid) -> bool) {
+        self.windows.retain(f);
+        self.cursor = None;
+    }
 }
 
-/// Return the position of the bottom-left of the window in Cocoa coordinates:
-/// (0,0) is bottom-left of main display, y increases in the up direction.
-fn position_to_origin(w: &WindowWrapper<AXUIElement>) -> Result<NSPoint> {
-    // (0,0) is top-left of main display, y increases down the screen
-    let f = w.frame()?;
-    let m = CGDisplay::main().bounds();
+fn border_rect(system: &DynWindowSystem, w: &WindowHandle, outset: f64) -> Result<NSRect> {
+    let f = system.frame(w)?;
+    let pos = position_to_origin(system, w)?;
+    let size = unsafe { mem::transmute::<CGSize, NSSize>(f.size) };
+    Ok(NSRect::new(pos, size).inset(-outset, -outset))
+}
 
-    // (0,0) is bottom-left of main display, y increases up the screen
-    let x = f.origin.x;
-    let y = m.size.height - f.origin.y - f.size.height;
-    Ok(NSPoint::new(x, y))
+/// Create a transparent panel that draws only a border, doesn't take key
+/// status and lets clicks through to the windows below.
+fn create_border_overlay(
+    rect: NSRect,
+    width: f64,
+    corner_radius: f64,
+    color: &Color,
+) -> OverlayWindow {
+    unsafe {
+        let panel: id = msg_send![class!(NSPanel), alloc];
+        panel.initWithContentRect_styleMask_backing_defer_(
+            rect,
+            NSWindowStyleMask::NSBorderlessWindowMask | NSWindowStyleMask::NSNonactivatingPanelMask,
+            NSBackingStoreBuffered,
+            NO,
+        );
+        panel.setOpaque_(NO);
+        panel.setHasShadow_(NO);
+        panel.setBackgroundColor_(NSColor::clearColor(nil));
+        panel.setIgnoresMouseEvents_(YES);
+        panel.setLevel_(FLOATING_WINDOW_LEVEL);
+
+        let overlay = OverlayWindow::new(panel);
+        let view = overlay.content_view();
+        view.setWantsLayer(YES);
+        let layer: id = msg_send![view, layer];
+        let () = msg_send![layer, setBorderWidth: width];
+        let () = msg_send![layer, setCornerRadius: corner_radius];
+        set_border_color(&overlay, color);
+
+        overlay.order_front();
+        overlay
+    }
+}
+
+fn set_border_color(overlay: &OverlayWindow, c: &Color) {
+    unsafe {
+        let color =
+            NSColor::colorWithSRGBRed_green_blue_alpha_(nil, c.red, c.green, c.blue, c.alpha);
+        let layer: id = msg_send![overlay.content_view(), layer];
+        let cg_color: id = msg_send![color, CGColor];
+        let () = msg_send![layer, setBorderColor: cg_color];
+    }
+}
+
+/// Run `command` with `sh -c`, without waiting for it to finish.
+fn spawn(command: &str) -> Result<()> {
+    let mut child = Command::new("/bin/sh")
+        .arg("-c")
+        .arg(command)
+        .spawn()
+        .map_err(|e| anyhow!("Could not run {:?}: {}", command, e))?;
+    // Reap it when it exits.
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Activate a window using the strategy configured for its application.
+fn activate_window(system: &DynWindowSystem, w: &WindowHandle, config: &Config) -> Result<()> {
+    let bundle_id = system.bundle_identifier(w).unwrap_or(None);
+    let app_name = system.app_name(w).unwrap_or(None);
+    system.activate(
+        w,
+        config.activation_for(bundle_id.as_deref(), app_name.as_deref()),
+    )
+}
+
+fn save_window(system: &DynWindowSystem, w: &WindowHandle, floating: bool) -> Result<SavedWindow> {
+    let frame = system.frame(w)?;
+    Ok(SavedWindow {
+        id: *w.id(),
+        pid: system.pid(w)?,
+        title: system.title(w)?,
+        x: frame.origin.x,
+        y: frame.origin.y,
+        width: frame.size.width,
+        height: frame.size.height,
+        floating,
+    })
+}
+
+/// Find the open window that best matches a saved window, skipping windows
+/// already claimed by another saved window.
+fn find_saved_window<'a>(
+    system: &DynWindowSystem,
+    saved: &SavedWindow,
+    open_windows: &'a [WindowHandle],
+    claimed: &[uuid::Uuid],
+) -> Option<&'a WindowHandle> {
+    let candidates: Vec<_> = open_windows
+        .iter()
+        .filter(|w| !claimed.contains(w.id()))
+        .filter(|w| {
+            system.pid(w).map_or(false, |pid| pid == saved.pid)
+                && system.title(w).map_or(false, |title| title == saved.title)
+        })
+        .collect();
+    candidates
+        .iter()
+        .find(|w| {
+            system.frame(w).map_or(false, |f| {
+                f.origin.x == saved.x
+                    && f.origin.y == saved.y
+                    && f.size.width == saved.width
+                    && f.size.height == saved.height
+            })
+        })
+        .or(candidates.first())
+        .copied()
 }
 
-type DisplayID = u32;
+pub(crate) type DisplayID = u32;
 
 #[derive(Debug)]
 pub struct WindowGroup {
     layout: Layout,
     primary_column_max_windows: i32,
     primary_column_pct: u8,
+    /// Points around and between tiled windows.
+    gap: f64,
+    /// Relative heights of tiles within their column, for windows resized
+    /// with `GrowTile`/`ShrinkTile`. Other windows have weight 1.
+    tile_weights: HashMap<uuid::Uuid, f64>,
+    /// Where the windows were when the group last left the floating layout,
+    /// to put them back when it returns to it.
+    floating_frames: HashMap<uuid::Uuid, CGRect>,
+    /// Window ids from the bottom of the group's stacking order to the top,
+    /// as last set by `WindowRaise`/`WindowLower`. Windows not in it are
+    /// above those that are, in window order, as new windows open on top.
+    stacking: Vec<uuid::Uuid>,
+    /// Windows sharing a tile, like i3's tabbed containers, in tab order.
+    /// The tile is where the layout puts the stack's first tiled window, and
+    /// the others are moved behind it.
+    stacks: Vec<Vec<uuid::Uuid>>,
+    /// Where the tiles are once the group has been split by hand, instead of
+    /// where the layout would put them. Choosing a layout drops it.
+    split_tree: Option<SplitTree>,
     active_window_idx: Option<usize>,
-    windows: Vec<WindowWrapper<AXUIElement>>,
+    windows: Vec<WindowHandle>,
+    /// Shared with the window manager, which the windows belong to.
+    system: Rc<DynWindowSystem>,
 }
 
 #[derive(Debug)]
 pub struct DisplayState {
+    /// The display's current id, which macOS may change.
     display_id: DisplayID,
+    /// What identifies the display from one connection to the next.
+    uuid: Option<String>,
     active_group: Option<u8>,
     groups: HashMap<u8, WindowGroup>,
+    /// What new groups start with, from the config.
+    group_defaults: Rc<HashMap<u8, GroupDefaults>>,
+    /// The primary column's max windows and width of groups dropped while
+    /// empty, which they start with again instead of the defaults.
+    primary_columns: HashMap<u8, (i32, u8)>,
+    /// Shared with the window manager, and with each group.
+    system: Rc<DynWindowSystem>,
 }
 
 #[derive(Debug)]
 pub struct WindowManager {
+    /// Where the windows and displays come from, shared with each display.
+    system: Rc<DynWindowSystem>,
+    config: Config,
     drag_window: Option<DragWindow>,
+    resize_window: Option<ResizeWindow>,
     mode: Mode,
     active_display_idx: Option<usize>,
     /// Index into self.display_ids
     display_ids: Vec<DisplayID>,
     displays: HashMap<DisplayID, DisplayState>,
-    minimized_windows: Vec<WindowWrapper<AXUIElement>>,
-    highlight_overlay_window: Option<id>,
-    status_window: Option<(id, id)>,
+    minimized_windows: Vec<WindowHandle>,
+    /// The display and group each window was minimized from.
+    minimized_from: HashMap<uuid::Uuid, (DisplayID, u8)>,
+    /// Minimized windows parked off-screen by `minimize.suppress_animation`
+    /// rather than in the Dock, and the frames to put them back at.
+    parked_windows: HashMap<uuid::Uuid, CGRect>,
+    /// Windows skipped by their group's layout.
+    floating_windows: HashSet<uuid::Uuid>,
+    /// The tiled window expanded by `ToggleZoom`. It goes back to its tile
+    /// when toggled again or when it loses focus.
+    zoomed_window: Option<uuid::Uuid>,
+    /// The region each window was last snapped to, and which of
+    /// `snap.cycle`'s sizes it was given.
+    snapped_windows: HashMap<uuid::Uuid, (SnapRegion, usize)>,
+    floating_frames: FloatingFrames,
+    highlight_overlay_window: Option<OverlayWindow>,
+    /// Borders around the active group's other windows.
+    group_overlay_windows: Vec<OverlayWindow>,
+    stats: FocusStats,
+    focus_history: FocusHistory,
+    /// Windows that opened without taking focus, because of
+    /// `activation.prevent_focus_stealing`, most recent last.
+    urgent_windows: Vec<uuid::Uuid>,
+    menu_bar: Option<MenuBar>,
+    switcher: Option<Switcher>,
+    /// The mode to go back to when the switcher closes.
+    mode_before_switcher: Mode,
+    /// Whether the switcher lists minimized windows, to restore rather than
+    /// focus.
+    switcher_restores: bool,
+    palette: Option<Palette>,
+    display_labels: Option<DisplayLabels>,
+    chords: Chords,
+    key_bindings: KeyBindings,
+    double_taps: DoubleTaps,
+    hooks: Hooks,
+    /// Windows that appeared since hooks last ran, for `window-created`.
+    created_windows: Vec<(uuid::Uuid, Option<String>, Option<String>)>,
+    #[cfg(feature = "scripting")]
+    script: Option<Script>,
+    /// The Space each window was last put on, so it is only moved when its
+    /// group changes.
+    #[cfg(feature = "spaces")]
+    space_of_window: HashMap<uuid::Uuid, SpaceID>,
+    /// The last frontmost app's pid, and whether a rule passes the keyboard
+    /// through to it.
+    passthrough_app: Cell<Option<(i32, bool)>>,
+    /// When to activate and highlight the active window, put off while the
+    /// user moves quickly between windows.
+    pending_activation: Cell<Option<Instant>>,
+    /// The group to switch to when transient mode ends, and the preview of
+    /// its windows (if it has any).
+    group_preview: Option<(u8, Option<Switcher>)>,
+    thumbnails: ThumbnailCache,
+    /// Names shown next to group numbers.
+    group_names: HashMap<u8, String>,
+    /// Shared with each display, which creates the groups.
+    group_defaults: Rc<HashMap<u8, GroupDefaults>>,
+    /// Set by `TargetMouseWindow`: the next action acts on the window under
+    /// the mouse.
+    target_mouse_window: bool,
+    /// The last repeatable action done by `do_action`, for `RepeatLast`.
+    last_action: Option<Action>,
+    /// When an action was last done or the mode changed, for
+    /// `transient_mode.timeout`.
+    last_activity: Instant,
+    /// When a motion repeated by holding its key was last performed, until
+    /// the key is released.
+    last_repeat: Option<Instant>,
+    relayouts: RelayoutScheduler,
+    /// The groups of disconnected displays, by display UUID, to put back if
+    /// they are reconnected.
+    detached_displays: HashMap<String, DisplayState>,
+    /// Windows being listed in the background, and when that started.
+    pending_refresh: Option<(PendingWindows<WindowHandle>, Instant)>,
+    /// Why keyboard shortcuts may not be working, e.g. macOS disabled the
+    /// event tap. Cleared when the next event arrives.
+    event_tap_warning: Option<String>,
+    /// Log each action performed, for `--verbose`.
+    verbose: bool,
+    /// An action waiting to be confirmed by doing it again, until when, and
+    /// what it will do.
+    pending_confirmation: Option<(Confirmation, Instant, String)>,
+    /// Set while `do_actions` runs a batch.
+    deferred_updates: Option<DeferredUpdates>,
+    /// The mode to go back to when the command palette closes.
+    mode_before_palette: Mode,
+    /// Whether the status window shows focus statistics.
+    show_stats: bool,
+    /// Whether to keep the border around the focused window in insert mode.
+    border_enabled: bool,
+    /// The window and its text field, which the window's content view owns.
+    status_window: Option<(OverlayWindow, id)>,
+    /// Thumbnails of the active group's windows in the status window.
+    status_tasklist: Option<Tasklist>,
+    /// What clicking a thumbnail in the status window does.
+    tasklist_handler: Option<TasklistHandler>,
+    wake_handler: Option<WakeHandler>,
+    /// The call to `wake_up` arranged by `schedule_wake_up`, and when it is
+    /// due.
+    wake_timer: RefCell<Option<(Instant, Timer)>>,
 }
 
 impl WindowGroup {
-    fn new(window: WindowWrapper<AXUIElement>) -> Self {
+    fn new(
+        window: WindowHandle,
+        defaults: Option<&GroupDefaults>,
+        system: Rc<DynWindowSystem>,
+    ) -> Self {
         Self {
-            layout: Layout::tile_horizontal(1, 50),
             active_window_idx: Some(0),
             windows: vec![window],
-            primary_column_max_windows: 1,
-            primary_column_pct: 50,
+            ..Self::empty(defaults, system)
+        }
+    }
+
+    fn empty(defaults: Option<&GroupDefaults>, system: Rc<DynWindowSystem>) -> Self {
+        let defaults = defaults.cloned().unwrap_or_default();
+        let primary_column_max_windows = defaults.primary_column_max_windows.unwrap_or(1);
+        let primary_column_pct = defaults.primary_column_pct.unwrap_or(50);
+        let layout = match defaults.layout {
+            Some(LayoutName::Cascade) => Layout::cascade(),
+            Some(LayoutName::Floating) => Layout::floating(),
+            Some(LayoutName::Tiling) | None => {
+                Layout::tile_horizontal(primary_column_max_windows, primary_column_pct)
+            }
+        };
+        Self {
+            layout,
+            active_window_idx: None,
+            windows: vec![],
+            primary_column_max_windows,
+            primary_column_pct,
+            gap: defaults.gap.unwrap_or(0.),
+            tile_weights: HashMap::new(),
+            floating_frames: HashMap::new(),
+            stacking: vec![],
+            stacks: vec![],
+            split_tree: None,
+            system,
+        }
+    }
+
+    fn save(&self, id: u8, floating: &HashSet<uuid::Uuid>) -> SavedGroup {
+        SavedGroup {
+            id,
+            layout: self.layout.clone(),
+            primary_column_max_windows: self.primary_column_max_windows,
+            primary_column_pct: self.primary_column_pct,
+            gap: self.gap,
+            active_window_idx: self.active_window_idx,
+            windows: self
+                .windows
+                .iter()
+                .filter_map(|w| save_window(&*self.system, w, floating.contains(w.id())).ok())
+                .collect(),
         }
     }
 
@@ -225,14 +647,14 @@ impl WindowGroup {
         }
     }
 
-    fn get_active_window(&self) -> Option<&WindowWrapper<AXUIElement>> {
+    fn get_active_window(&self) -> Option<&WindowHandle> {
         self.active_window_idx.and_then(|idx| self.windows.get(idx))
     }
 
     fn swap_window_prev(&mut self) {
         match (self.active_window_idx, self.prev_window_idx()) {
             (Some(idx), Some(prev_idx)) => {
-                self.windows.swap(idx, prev_idx);
+                self.swap_windows(idx, prev_idx);
                 self.active_window_idx = Some(prev_idx);
             }
             _ => (),
@@ -242,17 +664,34 @@ impl WindowGroup {
     fn swap_window_next(&mut self) {
         match (self.active_window_idx, self.next_window_idx()) {
             (Some(idx), Some(next_idx)) => {
-                self.windows.swap(idx, next_idx);
+                self.swap_windows(idx, next_idx);
                 self.active_window_idx = Some(next_idx);
             }
             _ => (),
         }
     }
 
-    fn pop_active_window(&mut self) -> Option<WindowWrapper<AXUIElement>> {
+    /// Swap the windows at `a` and `b`, and their places in the split tree.
+    fn swap_windows(&mut self, a: usize, b: usize) {
+        if let Some(tree) = &mut self.split_tree {
+            tree.swap(*self.windows[a].id(), *self.windows[b].id());
+        }
+        self.windows.swap(a, b);
+    }
+
+    /// Insert `window` at `idx` (or at the end if `idx` is out of range) and
+    /// make it active.
+    fn insert_window(&mut self, idx: usize, window: WindowHandle) {
+        let idx = usize::min(idx, self.windows.len());
+        self.windows.insert(idx, window);
+        self.active_window_idx = Some(idx);
+    }
+
+    fn pop_active_window(&mut self) -> Option<WindowHandle> {
         match self.active_window_idx {
             Some(idx) => {
                 let w = self.windows.remove(idx);
+                self.tile_weights.remove(w.id());
                 self.active_window_idx = if self.windows.len() == 0 {
                     None
                 } else {
@@ -264,8 +703,28 @@ impl WindowGroup {
         }
     }
 
+    /// Switch to `layout`. Leaving the floating layout saves the windows'
+    /// frames, and returning to it puts them back.
     fn set_layout(&mut self, layout: Layout) {
+        let was_floating = matches!(self.layout, Layout::Floating);
+        let is_floating = matches!(layout, Layout::Floating);
+        if was_floating && !is_floating {
+            self.floating_frames = self
+                .windows
+                .iter()
+                .filter_map(|w| self.system.frame(w).ok().map(|f| (*w.id(), f)))
+                .collect();
+        }
         self.layout = layout;
+        if is_floating && !was_floating {
+            for w in self.windows.iter() {
+                if let Some(frame) = self.floating_frames.get(w.id()) {
+                    self.system
+                        .set_frame(w, *frame)
+                        .unwrap_or_else(|e| eprintln!("While restoring floating frame: {:?}", e));
+                }
+            }
+        }
     }
 
     fn set_layout_floating(&mut self) {
@@ -283,17 +742,419 @@ impl WindowGroup {
         ))
     }
 
-    fn relayout(&self, display_id: DisplayID) -> Result<()> {
-        self.layout.apply(display_id, &self.windows)
+    /// Apply the layout to the windows that aren't floating, and keep the
+    /// floating ones on the display.
+    fn relayout(
+        &self,
+        display_id: DisplayID,
+        floating: &HashSet<uuid::Uuid>,
+        frames: Option<&FloatingFrames>,
+    ) -> Result<()> {
+        let (floating, tiled): (Vec<_>, Vec<_>) = self
+            .windows
+            .iter()
+            .cloned()
+            .partition(|w| floating.contains(w.id()));
+        let tiled_ids: Vec<uuid::Uuid> = tiled.iter().map(|w| *w.id()).collect();
+        let (tiles, stacked): (Vec<_>, Vec<_>) = tiled
+            .into_iter()
+            .partition(|w| self.tile_of(w.id(), &tiled_ids) == *w.id());
+        let weights: Vec<f64> = tiles
+            .iter()
+            .map(|w| self.tile_weights.get(w.id()).copied().unwrap_or(1.))
+            .collect();
+        match &self.split_tree {
+            Some(tree) => self.apply_split_tree(tree, display_id, &tiles),
+            None => self.layout.apply(
+                &*self.system,
+                display_id,
+                &tiles,
+                &weights,
+                frames,
+                self.gap,
+            )?,
+        }
+        for w in stacked.iter() {
+            let tile_id = self.tile_of(w.id(), &tiled_ids);
+            if let Some(frame) = tiles
+                .iter()
+                .find(|t| *t.id() == tile_id)
+                .and_then(|t| self.system.frame(t).ok())
+            {
+                self.system.set_frame(w, frame).unwrap_or_else(|e| {
+                    eprintln!("Could not set_frame on stacked window {:?}: {:?}", w, e)
+                });
+            }
+        }
+        Layout::Floating.apply(&*self.system, display_id, &floating, &[], frames, self.gap)
+    }
+
+    /// Put the tiles where the split tree has them.
+    fn apply_split_tree(&self, tree: &SplitTree, display_id: DisplayID, tiles: &[WindowHandle]) {
+        // Normally in sync already, see `sync_split_tree`.
+        let mut tree = tree.clone();
+        tree.sync(&tiles.iter().map(|w| *w.id()).collect::<Vec<_>>());
+        for (w_id, rect) in tree.frames(&self.split_tree_bounds(display_id)) {
+            let rect = inset(&rect, self.gap / 2.);
+            if let Some(w) = tiles.iter().find(|w| *w.id() == w_id) {
+                if self
+                    .system
+                    .frame(w)
+                    .map_or(true, |f| !same_frame(&f, &rect))
+                {
+                    self.system.set_frame(w, rect).unwrap_or_else(|e| {
+                        eprintln!("Could not set_frame on window {:?}: {:?}", w, e)
+                    });
+                }
+            }
+        }
+    }
+
+    /// Where the split tree's tiles go on the display: below the menu bar,
+    /// before each tile is inset by the gap.
+    fn split_tree_bounds(&self, display_id: DisplayID) -> CGRect {
+        let d = inset(&self.system.display_bounds(display_id), self.gap / 2.);
+        CGRect::new(
+            &CGPoint::new(d.origin.x, d.origin.y + 38.),
+            &CGSize::new(d.size.width, d.size.height - 38.),
+        )
+    }
+
+    fn tiled_ids(&self, floating: &HashSet<uuid::Uuid>) -> Vec<uuid::Uuid> {
+        self.windows
+            .iter()
+            .map(|w| *w.id())
+            .filter(|id| !floating.contains(id))
+            .collect()
+    }
+
+    /// The windows with a tile of their own, each stack's first window
+    /// standing for the stack.
+    fn tile_ids(&self, floating: &HashSet<uuid::Uuid>) -> Vec<uuid::Uuid> {
+        let tiled_ids = self.tiled_ids(floating);
+        tiled_ids
+            .iter()
+            .copied()
+            .filter(|id| self.tile_of(id, &tiled_ids) == *id)
+            .collect()
+    }
+
+    /// Bring the split tree up to date with the group's tiles, and put the
+    /// windows in its order, so that window motions follow the tree.
+    fn sync_split_tree(&mut self, floating: &HashSet<uuid::Uuid>) {
+        let tile_ids = self.tile_ids(floating);
+        let active = self.get_active_window().map(|w| *w.id());
+        let order = match &mut self.split_tree {
+            Some(tree) => {
+                tree.sync(&tile_ids);
+                tree.windows()
+            }
+            None => return,
+        };
+        self.windows.sort_by_key(|w| {
+            order
+                .iter()
+                .position(|id| id == w.id())
+                .unwrap_or(usize::MAX)
+        });
+        self.active_window_idx =
+            active.and_then(|w_id| self.windows.iter().position(|w| *w.id() == w_id));
+    }
+
+    /// Note the active window as the one new windows go next to.
+    fn focus_split_tree(&mut self) {
+        let active = self.get_active_window().map(|w| *w.id());
+        if let (Some(tree), Some(w_id)) = (&mut self.split_tree, active) {
+            tree.focus(w_id);
+        }
+    }
+
+    /// Make the next window share the active window's tile, split in
+    /// `direction`, starting a split tree from the current tiles if there is
+    /// none.
+    fn split_active_window(&mut self, floating: &HashSet<uuid::Uuid>, direction: SplitDirection) {
+        let active = match self.get_active_window() {
+            Some(w) if !floating.contains(w.id()) => *w.id(),
+            _ => return,
+        };
+        let tile = self.tile_of(&active, &self.tiled_ids(floating));
+        if self.split_tree.is_none() {
+            self.split_tree = Some(SplitTree::new(&self.tile_ids(floating)));
+        }
+        if let Some(tree) = &mut self.split_tree {
+            tree.split(tile, direction);
+        }
+    }
+
+    /// Move the active window's tile in `direction` in the split tree.
+    /// Returns false if there is no tree or the tile is at its edge.
+    fn move_active_window_in_split_tree(
+        &mut self,
+        floating: &HashSet<uuid::Uuid>,
+        direction: Direction,
+    ) -> bool {
+        let active = match self.get_active_window() {
+            Some(w) if self.split_tree.is_some() && !floating.contains(w.id()) => *w.id(),
+            _ => return false,
+        };
+        self.sync_split_tree(floating);
+        let tile = self.tile_of(&active, &self.tiled_ids(floating));
+        let moved = match &mut self.split_tree {
+            Some(tree) => tree.move_window(tile, direction),
+            None => false,
+        };
+        self.sync_split_tree(floating);
+        moved
+    }
+
+    /// The window whose tile `w_id` is in: the first of its stack's windows
+    /// among `tiled_ids`, or itself if it isn't stacked.
+    fn tile_of(&self, w_id: &uuid::Uuid, tiled_ids: &[uuid::Uuid]) -> uuid::Uuid {
+        self.stacks
+            .iter()
+            .find(|stack| stack.contains(w_id))
+            .and_then(|stack| tiled_ids.iter().find(|id| stack.contains(id)))
+            .copied()
+            .unwrap_or(*w_id)
+    }
+
+    /// Forget windows that have left the group, and stacks left with a
+    /// single window.
+    fn prune_stacks(&mut self) {
+        let ids: HashSet<uuid::Uuid> = self.windows.iter().map(|w| *w.id()).collect();
+        for stack in self.stacks.iter_mut() {
+            stack.retain(|id| ids.contains(id));
+        }
+        self.stacks.retain(|stack| stack.len() > 1);
+    }
+
+    /// Take the stack `w_id` is in out of `stacks`, or a stack of just it.
+    fn take_stack(&mut self, w_id: uuid::Uuid) -> Vec<uuid::Uuid> {
+        match self.stacks.iter().position(|stack| stack.contains(&w_id)) {
+            Some(idx) => self.stacks.remove(idx),
+            None => vec![w_id],
+        }
+    }
+
+    /// Put the active window's stack, or the window itself, in the same
+    /// stack as the next tile's windows, or the previous tile's if it is the
+    /// last tile.
+    fn stack_active_window(&mut self, floating: &HashSet<uuid::Uuid>) {
+        self.prune_stacks();
+        let active = match self.get_active_window() {
+            Some(w) if !floating.contains(w.id()) => *w.id(),
+            _ => return,
+        };
+        let tiled_ids = self.tiled_ids(floating);
+        let tiles = self.tile_ids(floating);
+        let idx = match tiles
+            .iter()
+            .position(|id| *id == self.tile_of(&active, &tiled_ids))
+        {
+            Some(idx) => idx,
+            None => return,
+        };
+        let neighbor = match tiles
+            .get(idx + 1)
+            .or_else(|| tiles.get(idx.checked_sub(1)?))
+        {
+            Some(neighbor) => *neighbor,
+            None => return,
+        };
+        let mut stack = self.take_stack(active);
+        stack.extend(self.take_stack(neighbor));
+        self.stacks.push(stack);
+    }
+
+    /// Take the active window out of its stack.
+    fn unstack_active_window(&mut self) {
+        if let Some(w_id) = self.get_active_window().map(|w| *w.id()) {
+            for stack in self.stacks.iter_mut() {
+                stack.retain(|id| *id != w_id);
+            }
+            self.prune_stacks();
+        }
+    }
+
+    /// Make the next (or previous) window in the active window's stack
+    /// active.
+    fn set_next_stacked_window_active(&mut self, forward: bool) {
+        self.prune_stacks();
+        let w_id = match self.get_active_window() {
+            Some(w) => *w.id(),
+            None => return,
+        };
+        let stack = match self.stacks.iter().find(|stack| stack.contains(&w_id)) {
+            Some(stack) => stack,
+            None => return,
+        };
+        let idx = stack.iter().position(|id| *id == w_id).unwrap_or(0);
+        let next = if forward {
+            stack[(idx + 1) % stack.len()]
+        } else {
+            stack[(idx + stack.len() - 1) % stack.len()]
+        };
+        self.active_window_idx = self.windows.iter().position(|w| *w.id() == next);
+    }
+
+    /// Where the active window is in its stack, and how many windows share
+    /// its tile.
+    fn active_stack_position(&self) -> Option<(usize, usize)> {
+        let w_id = self.get_active_window()?.id();
+        let stack = self.stacks.iter().find(|stack| stack.contains(w_id))?;
+        let present: Vec<_> = stack
+            .iter()
+            .filter(|id| self.windows.iter().any(|w| w.id() == *id))
+            .collect();
+        let idx = present.iter().position(|id| *id == w_id)?;
+        Some((idx, present.len())).filter(|(_, len)| *len > 1)
     }
 
-    fn bring_all_to_front(&self) -> Result<()> {
-        for window in self.windows.iter() {
-            window.activate()?;
+    fn bring_all_to_front(&self, config: &Config) -> Result<()> {
+        for window in self.stacked_windows() {
+            activate_window(&*self.system, window, config)?;
         }
         Ok(())
     }
 
+    /// The windows from the bottom of the stacking order to the top.
+    fn stacked_windows(&self) -> Vec<&WindowHandle> {
+        let mut windows: Vec<_> = self.windows.iter().collect();
+        windows.sort_by_key(|w| {
+            self.stacking
+                .iter()
+                .position(|w_id| w_id == w.id())
+                .unwrap_or(usize::MAX)
+        });
+        windows
+    }
+
+    /// Put the active window at the top of the stacking order.
+    fn raise_active_window(&mut self) {
+        if let Some(w_id) = self.get_active_window().map(|w| *w.id()) {
+            self.stacking = self.stacked_windows().iter().map(|w| *w.id()).collect();
+            self.stacking.retain(|id| *id != w_id);
+            self.stacking.push(w_id);
+        }
+    }
+
+    /// Put the active window at the bottom of the stacking order, and make
+    /// the window now at the top active.
+    fn lower_active_window(&mut self) {
+        if let Some(w_id) = self.get_active_window().map(|w| *w.id()) {
+            self.stacking = self.stacked_windows().iter().map(|w| *w.id()).collect();
+            self.stacking.retain(|id| *id != w_id);
+            self.stacking.insert(0, w_id);
+            let top = self.stacking.last().copied();
+            self.active_window_idx = self.windows.iter().position(|w| Some(*w.id()) == top);
+        }
+    }
+
+    /// Resize the primary column so that window `w_id`'s tile is `width_pct`
+    /// of the display width. Floating windows have no tile, so resizing them
+    /// leaves the columns as they are.
+    fn set_tile_width_pct(
+        &mut self,
+        w_id: &uuid::Uuid,
+        width_pct: f64,
+        floating: &HashSet<uuid::Uuid>,
+    ) {
+        if let Layout::TileHorizontal(_) = self.layout {
+            let tiled_ids = self.tiled_ids(floating);
+            if !tiled_ids.contains(w_id) {
+                return;
+            }
+            // Stacked windows share their stack's tile.
+            let tile_id = self.tile_of(w_id, &tiled_ids);
+            let tiles = self.tile_ids(floating);
+            let tile_idx = match tiles.iter().position(|id| *id == tile_id) {
+                Some(tile_idx) => tile_idx,
+                None => return,
+            };
+            let num_primary = usize::min(self.primary_column_max_windows as usize, tiles.len());
+            if num_primary >= tiles.len() {
+                // Only one column: its width is fixed.
+                return;
+            }
+            let primary_pct = if tile_idx < num_primary {
+                width_pct
+            } else {
+                100. - width_pct
+            };
+            self.primary_column_pct = primary_pct.round().clamp(10., 90.) as u8;
+            self.set_layout_tile_horizontal();
+        }
+    }
+
+    /// Give window `w_id`'s tile in the split tree the size of `frame`, as
+    /// far as the containers it is in allow.
+    fn resize_split_tile(
+        &mut self,
+        w_id: &uuid::Uuid,
+        frame: &CGRect,
+        display_id: DisplayID,
+        floating: &HashSet<uuid::Uuid>,
+    ) {
+        let tiled_ids = self.tiled_ids(floating);
+        if self.split_tree.is_none() || !tiled_ids.contains(w_id) {
+            return;
+        }
+        let tile_id = self.tile_of(w_id, &tiled_ids);
+        let tile_ids = self.tile_ids(floating);
+        let bounds = self.split_tree_bounds(display_id);
+        // Tiles are inset by the gap.
+        let size = CGSize::new(frame.size.width + self.gap, frame.size.height + self.gap);
+        if let Some(tree) = &mut self.split_tree {
+            // Leaves the windows' order alone: the caller holds an index.
+            tree.sync(&tile_ids);
+            tree.resize(tile_id, &bounds, &size);
+        }
+    }
+
+    /// Size the columns so that every tiled window gets the same area, or
+    /// give every tile an equal share of its container in the split tree.
+    fn balance(&mut self, floating: &HashSet<uuid::Uuid>) {
+        self.tile_weights.clear();
+        if let Some(tree) = &mut self.split_tree {
+            tree.balance();
+        }
+        if let Layout::TileHorizontal(_) = self.layout {
+            let num_tiled = self
+                .windows
+                .iter()
+                .filter(|w| !floating.contains(w.id()))
+                .count();
+            let num_primary = usize::min(self.primary_column_max_windows as usize, num_tiled);
+            if num_primary == 0 || num_primary >= num_tiled {
+                return;
+            }
+            let primary_pct = num_primary as f64 / num_tiled as f64 * 100.;
+            self.primary_column_pct = primary_pct.round().clamp(10., 90.) as u8;
+            self.set_layout_tile_horizontal();
+        }
+    }
+
+    /// Make the active window's tile taller (`factor` > 1) or shorter than
+    /// the others in its column, or in the split tree larger or smaller
+    /// than the others in its container.
+    fn scale_active_tile(&mut self, factor: f64, floating: &HashSet<uuid::Uuid>) {
+        if let Some(w_id) = self.get_active_window().map(|w| *w.id()) {
+            if self.split_tree.is_some() {
+                if floating.contains(&w_id) {
+                    return;
+                }
+                self.sync_split_tree(floating);
+                let tile_id = self.tile_of(&w_id, &self.tiled_ids(floating));
+                if let Some(tree) = &mut self.split_tree {
+                    tree.scale(tile_id, factor);
+                }
+                return;
+            }
+            let weight = self.tile_weights.entry(w_id).or_insert(1.);
+            *weight = (*weight * factor).clamp(MIN_TILE_WEIGHT, MAX_TILE_WEIGHT);
+        }
+    }
+
     fn incr_primary_column_max_windows(&mut self) {
         self.primary_column_max_windows = i32::min(
             self.primary_column_max_windows + 1,
@@ -307,6 +1168,36 @@ impl WindowGroup {
         self.set_layout_tile_horizontal();
     }
 
+    /// Set how many windows the primary column holds. With `tile` the group
+    /// is tiled; otherwise it keeps its layout and uses the setting once it is.
+    fn set_primary_column_max_windows(&mut self, n: i32, tile: bool) {
+        self.primary_column_max_windows = n;
+        self.retile_primary_column(tile);
+    }
+
+    /// Remember the primary column's settings from before the group was
+    /// dropped, if any.
+    fn with_primary_column(mut self, remembered: Option<&(i32, u8)>) -> Self {
+        if let Some((max_windows, pct)) = remembered {
+            self.set_primary_column_max_windows(*max_windows, false);
+            self.set_primary_column_width(*pct, false);
+        }
+        self
+    }
+
+    /// Set the primary column's width. With `tile` the group is tiled;
+    /// otherwise it keeps its layout and uses the setting once it is.
+    fn set_primary_column_width(&mut self, pct: u8, tile: bool) {
+        self.primary_column_pct = pct;
+        self.retile_primary_column(tile);
+    }
+
+    fn retile_primary_column(&mut self, tile: bool) {
+        if tile || matches!(self.layout, Layout::TileHorizontal(_)) {
+            self.set_layout_tile_horizontal();
+        }
+    }
+
     fn incr_primary_column_width(&mut self) {
         if self.primary_column_pct <= 80 {
             self.primary_column_pct += 10;
@@ -323,13 +1214,72 @@ impl WindowGroup {
 }
 
 impl DisplayState {
-    fn new(display_id: DisplayID, window: WindowWrapper<AXUIElement>) -> Self {
-        let mut groups = HashMap::new();
-        groups.insert(1, WindowGroup::new(window));
+    fn new(
+        display_id: DisplayID,
+        system: Rc<DynWindowSystem>,
+        group_defaults: Rc<HashMap<u8, GroupDefaults>>,
+    ) -> Self {
         Self {
             display_id,
-            active_group: Some(1),
-            groups,
+            uuid: system.display_uuid(display_id),
+            active_group: None,
+            groups: HashMap::new(),
+            group_defaults,
+            primary_columns: HashMap::new(),
+            system,
+        }
+    }
+
+    fn new_group(&self, g_id: u8, window: WindowHandle) -> WindowGroup {
+        WindowGroup::new(window, self.group_defaults.get(&g_id), self.system.clone())
+            .with_primary_column(self.primary_columns.get(&g_id))
+    }
+
+    fn empty_group(&self, g_id: u8) -> WindowGroup {
+        WindowGroup::empty(self.group_defaults.get(&g_id), self.system.clone())
+            .with_primary_column(self.primary_columns.get(&g_id))
+    }
+
+    /// Drop groups that have no windows, except the active one: a group
+    /// exists while it is shown or has windows. If the active group doesn't
+    /// exist, the lowest numbered one that does is shown instead.
+    fn gc_groups(&mut self) {
+        let active_group = self.active_group;
+        let primary_columns = &mut self.primary_columns;
+        self.groups.retain(|g_id, g| {
+            let keep = !g.windows.is_empty() || Some(*g_id) == active_group;
+            if !keep {
+                primary_columns.insert(*g_id, (g.primary_column_max_windows, g.primary_column_pct));
+            }
+            keep
+        });
+        if active_group.map_or(false, |g_id| !self.groups.contains_key(&g_id)) {
+            self.active_group = self.groups.keys().min().copied();
+        }
+    }
+
+    /// Set group `g_id`'s primary column, or the one it will start with if
+    /// it doesn't exist. Settings that are None are left as they are.
+    fn set_group_primary_column(&mut self, g_id: u8, pct: Option<u8>, max_windows: Option<i32>) {
+        match self.groups.get_mut(&g_id) {
+            Some(g) => {
+                if let Some(pct) = pct {
+                    g.set_primary_column_width(pct, false);
+                }
+                if let Some(n) = max_windows {
+                    g.set_primary_column_max_windows(n, false);
+                }
+            }
+            None => {
+                let g = self.empty_group(g_id);
+                self.primary_columns.insert(
+                    g_id,
+                    (
+                        max_windows.unwrap_or(g.primary_column_max_windows),
+                        pct.unwrap_or(g.primary_column_pct),
+                    ),
+                );
+            }
         }
     }
 
@@ -341,14 +1291,14 @@ impl DisplayState {
         self.active_group.and_then(|idx| self.groups.get_mut(&idx))
     }
 
-    fn bring_active_group_to_front(&self) -> Result<()> {
+    fn bring_active_group_to_front(&self, config: &Config) -> Result<()> {
         if let Some(g) = self.get_active_group() {
-            g.bring_all_to_front()?;
+            g.bring_all_to_front(config)?;
         }
         Ok(())
     }
 
-    fn get_active_window(&self) -> Option<&WindowWrapper<AXUIElement>> {
+    fn get_active_window(&self) -> Option<&WindowHandle> {
         self.get_active_group().and_then(|g| g.get_active_window())
     }
 
@@ -364,14 +1314,90 @@ impl DisplayState {
         }
     }
 
-    fn pop_active_window(&mut self) -> Option<WindowWrapper<AXUIElement>> {
+    fn pop_active_window(&mut self) -> Option<WindowHandle> {
         let window = self
             .get_active_group_mut()
             .and_then(|g| g.pop_active_window());
-        self.groups.retain(|_g_id, g| !g.windows.is_empty());
+        self.gc_groups();
         window
     }
 
+    /// Remove the window from every group on this display.
+    fn remove_window(&mut self, w_id: &uuid::Uuid) -> Option<WindowHandle> {
+        let mut removed = None;
+        for g in self.groups.values_mut() {
+            if let Some(idx) = g.windows.iter().position(|w| w.id() == w_id) {
+                g.tile_weights.remove(w_id);
+                removed = Some(g.windows.remove(idx));
+                g.active_window_idx = if g.windows.is_empty() {
+                    None
+                } else {
+                    Some(usize::min(idx, g.windows.len() - 1))
+                };
+            }
+        }
+        self.gc_groups();
+        removed
+    }
+
+    /// Put `windows` at the front of group `g_id`, in order, creating the
+    /// group if needed. The first becomes the group's active window.
+    fn insert_windows_in_group(&mut self, g_id: u8, windows: Vec<WindowHandle>) {
+        if windows.is_empty() {
+            return;
+        }
+        if !self.groups.contains_key(&g_id) {
+            let g = self.empty_group(g_id);
+            self.groups.insert(g_id, g);
+        }
+        if let Some(g) = self.groups.get_mut(&g_id) {
+            for window in windows.into_iter().rev() {
+                g.insert_window(0, window);
+            }
+        }
+    }
+
+    /// Remove the active group, leaving an empty group with the same number
+    /// in its place. Its windows are removed from this display's other groups
+    /// too, since they are going elsewhere.
+    fn take_active_group(&mut self) -> Option<WindowGroup> {
+        let g_id = self.active_group?;
+        let g = self.groups.remove(&g_id)?;
+        for w in g.windows.iter() {
+            self.remove_window(w.id());
+        }
+        self.set_active_group(g_id);
+        Some(g)
+    }
+
+    /// Show `g` on this display as group `g_id`, replacing any group there.
+    fn put_group(&mut self, g_id: u8, g: WindowGroup) {
+        self.groups.insert(g_id, g);
+        self.set_active_group(g_id);
+    }
+
+    /// `g_id` if this display has no windows in that group, otherwise the
+    /// lowest group number without windows.
+    fn free_group_id(&self, g_id: u8) -> Option<u8> {
+        let is_free = |id: &u8| self.groups.get(id).map_or(true, |g| g.windows.is_empty());
+        Some(g_id).filter(is_free).or_else(|| (0..=9).find(is_free))
+    }
+
+    fn get_active_group_window(&self, w_idx: usize) -> Option<&WindowHandle> {
+        self.get_active_group().and_then(|g| g.windows.get(w_idx))
+    }
+
+    /// Put `window` at `w_idx` in the active group, returning the window that
+    /// was there.
+    fn replace_active_group_window(
+        &mut self,
+        w_idx: usize,
+        window: WindowHandle,
+    ) -> Option<WindowHandle> {
+        self.get_active_group_mut()
+            .and_then(|g| g.windows.get_mut(w_idx).map(|w| mem::replace(w, window)))
+    }
+
     fn move_active_window_to_group(&mut self, g_id: u8) {
         if let Some(w) = self.pop_active_window() {
             match self.groups.get_mut(&g_id) {
@@ -382,7 +1408,8 @@ impl DisplayState {
                     }
                 }
                 None => {
-                    self.groups.insert(g_id, WindowGroup::new(w));
+                    let g = self.new_group(g_id, w);
+                    self.groups.insert(g_id, g);
                 }
             }
         }
@@ -401,6 +1428,7 @@ impl DisplayState {
                             // Only remove the window if it is present in another group (prevent
                             // orphan windows).
                             g.windows.remove(w_idx);
+                            g.tile_weights.remove(w.id());
                             g.active_window_idx = if g.windows.len() == 0 {
                                 None
                             } else {
@@ -415,45 +1443,52 @@ impl DisplayState {
                     }
                 }
                 None => {
-                    self.groups.insert(g_id, WindowGroup::new(w));
+                    let g = self.new_group(g_id, w);
+                    self.groups.insert(g_id, g);
                 }
             }
         }
     }
 
-    fn close_active_window(&mut self) -> Result<()> {
-        if let Some(window) = self.pop_active_window() {
-            window.close()
-        } else {
-            Ok(())
-        }
-    }
-
     pub fn layout(&self) -> Option<&Layout> {
         self.get_active_group().map(|g| &g.layout)
     }
 
     fn set_layout_floating(&mut self) {
         if let Some(g) = self.get_active_group_mut() {
+            g.split_tree = None;
             g.set_layout_floating()
         }
     }
 
     fn set_layout_cascade(&mut self) {
         if let Some(g) = self.get_active_group_mut() {
+            g.split_tree = None;
             g.set_layout_cascade()
         }
     }
 
+    fn set_layout(&mut self, layout: Layout) {
+        if let Some(g) = self.get_active_group_mut() {
+            g.split_tree = None;
+            g.set_layout(layout)
+        }
+    }
+
     fn set_layout_tile_horizontal(&mut self) {
         if let Some(g) = self.get_active_group_mut() {
+            g.split_tree = None;
             g.set_layout_tile_horizontal()
         }
     }
 
-    fn relayout(&self) -> Result<()> {
+    fn relayout(
+        &self,
+        floating: &HashSet<uuid::Uuid>,
+        frames: Option<&FloatingFrames>,
+    ) -> Result<()> {
         match self.get_active_group() {
-            Some(g) => g.relayout(self.display_id),
+            Some(g) => g.relayout(self.display_id, floating, frames),
             None => Ok(()),
         }
     }
@@ -494,66 +1529,380 @@ impl DisplayState {
         }
     }
 
+    /// Show group `g_id`, creating it if needed.
     fn set_active_group(&mut self, g_id: u8) {
+        if !self.groups.contains_key(&g_id) {
+            let g = self.empty_group(g_id);
+            self.groups.insert(g_id, g);
+        }
         self.active_group = Some(g_id);
+        self.gc_groups();
     }
 }
 
 impl WindowManager {
-    pub fn new() -> Self {
+    pub fn new(config: Config) -> Self {
+        Self::with_system(config, Rc::new(AXWindowSystem::default()))
+    }
+
+    /// A window manager for the windows and displays of `system`.
+    pub fn with_system(config: Config, system: Rc<DynWindowSystem>) -> Self {
+        let border_enabled = config.highlight.persistent;
+        let group_names = config.groups.names();
+        let group_defaults = Rc::new(config.groups.defaults());
+        let thumbnails = ThumbnailCache::new(&config.thumbnails);
+        let chords = Chords::new(&config.chords).unwrap_or_else(|e| {
+            eprintln!("While setting up chords: {:?}", e);
+            Chords::default()
+        });
+        let double_taps = DoubleTaps::new(&config.double_tap).unwrap_or_else(|e| {
+            eprintln!("While setting up double taps: {:?}", e);
+            DoubleTaps::default()
+        });
+        #[cfg(feature = "scripting")]
+        let script = config.script.as_deref().and_then(|path| {
+            Script::load(path)
+                .map_err(|e| eprintln!("While loading script: {:?}", e))
+                .ok()
+        });
+        #[cfg(not(feature = "scripting"))]
+        if config.script.is_some() {
+            eprintln!("Ignoring script: built without the scripting feature");
+        }
+        #[cfg(not(feature = "spaces"))]
+        if config.spaces.enabled {
+            eprintln!("Ignoring spaces.enabled: built without the spaces feature");
+        }
+        let mut keys = HashMap::new();
+        #[cfg(feature = "scripting")]
+        if let Some(script) = &script {
+            keys.extend(script.key_bindings().clone());
+        }
+        // The config wins over the script.
+        keys.extend(config.bindings.keys.clone());
+        let key_bindings =
+            KeyBindings::new(&keys, config.modes.normal.as_deref()).unwrap_or_else(|e| {
+                eprintln!("While setting up key bindings: {:?}", e);
+                KeyBindings::default()
+            });
         Self {
+            system,
+            config,
             drag_window: None,
+            resize_window: None,
             mode: Mode::Insert,
             active_display_idx: None,
             display_ids: vec![],
             displays: HashMap::new(),
             minimized_windows: vec![],
+            minimized_from: HashMap::new(),
+            parked_windows: HashMap::new(),
+            floating_windows: HashSet::new(),
+            zoomed_window: None,
+            snapped_windows: HashMap::new(),
+            floating_frames: FloatingFrames::default(),
             highlight_overlay_window: None,
+            group_overlay_windows: vec![],
+            stats: FocusStats::new(),
+            focus_history: FocusHistory::default(),
+            urgent_windows: vec![],
+            menu_bar: None,
+            switcher: None,
+            mode_before_switcher: Mode::Insert,
+            switcher_restores: false,
+            palette: None,
+            display_labels: None,
+            chords,
+            key_bindings,
+            double_taps,
+            hooks: Hooks::new(&config.hooks),
+            created_windows: vec![],
+            #[cfg(feature = "scripting")]
+            script,
+            #[cfg(feature = "spaces")]
+            space_of_window: HashMap::new(),
+            passthrough_app: Cell::new(None),
+            target_mouse_window: false,
+            last_action: None,
+            last_activity: Instant::now(),
+            last_repeat: None,
+            event_tap_warning: None,
+            verbose: false,
+            relayouts: RelayoutScheduler::default(),
+            detached_displays: HashMap::new(),
+            pending_refresh: None,
+            group_names,
+            group_defaults,
+            group_preview: None,
+            thumbnails,
+            pending_activation: Cell::new(None),
+            pending_confirmation: None,
+            deferred_updates: None,
+            mode_before_palette: Mode::Insert,
+            show_stats: false,
+            border_enabled,
             status_window: None,
+            status_tasklist: None,
+            tasklist_handler: None,
+            wake_handler: None,
+            wake_timer: RefCell::new(None),
         }
     }
 
     fn refresh_active_window(&mut self) {
+        let system = self.system.clone();
         let active_display_id = self.displays.iter_mut().find_map(|(display_id, ds)| {
-            ds.groups.iter_mut().find_map(|(g_idx, g)| {
+            let found = ds.groups.iter_mut().find_map(|(g_idx, g)| {
                 g.windows
                     .iter()
-                    .position(|w| w.frontmost_and_main().unwrap_or(false))
+                    .position(|w| system.is_frontmost_and_main(w).unwrap_or(false))
                     .map(|w_idx| {
-                        ds.active_group = Some(*g_idx);
                         g.active_window_idx = Some(w_idx);
-                        *display_id
+                        *g_idx
                     })
+            });
+            found.map(|g_idx| {
+                ds.set_active_group(g_idx);
+                *display_id
             })
         });
         self.active_display_idx = active_display_id
             .and_then(|display_id| self.display_ids.iter().position(|d_id| *d_id == display_id));
     }
 
-    fn insert_open_window(&mut self, window: WindowWrapper<AXUIElement>, display_id: DisplayID) {
-        match self.displays.get_mut(&display_id) {
-            Some(ds) => match ds.get_active_group_mut() {
-                Some(g) => {
-                    g.windows.insert(0, window);
-                    g.active_window_idx = Some(0);
+    fn insert_open_window(&mut self, window: WindowHandle, display_id: DisplayID) {
+        self.insert_open_window_at(window, display_id, 0)
+    }
+
+    fn insert_open_window_at(&mut self, window: WindowHandle, display_id: DisplayID, w_idx: usize) {
+        let (system, group_defaults) = (&self.system, &self.group_defaults);
+        let ds = self.displays.entry(display_id).or_insert_with(|| {
+            DisplayState::new(display_id, system.clone(), group_defaults.clone())
+        });
+        // The active group may have been lost, e.g. when restoring a group
+        // whose windows have all closed.
+        ds.set_active_group(ds.active_group.unwrap_or(DEFAULT_GROUP));
+        if let Some(g) = ds.get_active_group_mut() {
+            g.insert_window(w_idx, window);
+        }
+    }
+
+    /// Put the window at the front of the group, creating it if needed, and
+    /// make it the group's active window. The shown group and the active
+    /// display stay as they are, unless the display has no group yet.
+    fn insert_window_in_group(&mut self, window: WindowHandle, display_id: DisplayID, g_id: u8) {
+        let (system, group_defaults) = (&self.system, &self.group_defaults);
+        let ds = self.displays.entry(display_id).or_insert_with(|| {
+            DisplayState::new(display_id, system.clone(), group_defaults.clone())
+        });
+        ds.insert_windows_in_group(g_id, vec![window]);
+        if ds.active_group.is_none() {
+            ds.active_group = Some(g_id);
+        }
+    }
+
+    /// Handle displays being connected or disconnected.
+    pub fn displays_changed(&mut self) -> Result<()> {
+        let display_ids = self.system.display_ids()?;
+        let mut known: Vec<DisplayID> = self.displays.keys().copied().collect();
+        let mut current = display_ids.clone();
+        known.sort();
+        current.sort();
+        if known == current {
+            return Ok(());
+        }
+        self.refresh_window_list()?;
+        self.relayout_all_displays()?;
+        self.update_status_window_content();
+        self.update_menu_bar();
+        self.notify_hooks();
+        Ok(())
+    }
+
+    /// Re-read every window and display and lay them all out again, as
+    /// after waking from sleep or unlocking the screen, when windows may
+    /// have been moved and displays renumbered without telling us.
+    pub fn resync(&mut self) -> Result<()> {
+        self.refresh_window_list()?;
+        self.relayout_all_displays()?;
+        self.update_status_window_content();
+        self.update_menu_bar();
+        self.notify_hooks();
+        Ok(())
+    }
+
+    /// Switch to the connected `display_ids`. Each display's state follows
+    /// its UUID, since macOS may give a display a new id, e.g. when it is
+    /// reconnected or wakes. A disconnected display's groups are merged into
+    /// the main display's, and remembered so they can be put back if it is
+    /// reconnected.
+    fn set_display_ids(&mut self, display_ids: Vec<DisplayID>) {
+        let current: HashMap<String, DisplayID> = display_ids
+            .iter()
+            .filter_map(|d_id| self.system.display_uuid(*d_id).map(|uuid| (uuid, *d_id)))
+            .collect();
+        let active_uuid = self.get_active_display().and_then(|ds| ds.uuid.clone());
+
+        // Put each display's state under its current id.
+        let mut displays = HashMap::new();
+        let mut unmatched = vec![];
+        // Old ids of displays whose windows are now on another id.
+        let mut moved: HashMap<DisplayID, DisplayID> = HashMap::new();
+        for (display_id, mut ds) in self.displays.drain() {
+            match ds.uuid.as_ref().and_then(|uuid| current.get(uuid)) {
+                Some(current_id) => {
+                    if *current_id != display_id {
+                        println!("Display {} is now display {}", display_id, current_id);
+                        moved.insert(display_id, *current_id);
+                    }
+                    ds.display_id = *current_id;
+                    displays.insert(*current_id, ds);
                 }
-                None => {
-                    ds.groups.insert(0, WindowGroup::new(window));
-                    ds.active_group = Some(0);
+                None => unmatched.push(ds),
+            }
+        }
+        let mut gone = vec![];
+        for ds in unmatched {
+            // Without a UUID we can only go by its id, unless that now
+            // belongs to another display.
+            if ds.uuid.is_none()
+                && display_ids.contains(&ds.display_id)
+                && !displays.contains_key(&ds.display_id)
+            {
+                displays.insert(ds.display_id, ds);
+            } else {
+                gone.push(ds);
+            }
+        }
+        self.displays = displays;
+
+        let main_id = self.system.main_display_id();
+        let target_id = if display_ids.contains(&main_id) {
+            Some(main_id)
+        } else {
+            display_ids.first().copied()
+        };
+        for ds in gone {
+            println!("Display {} was disconnected", ds.display_id);
+            if let Some(target_id) = target_id {
+                moved.insert(ds.display_id, target_id);
+                alert(
+                    "Display disconnected",
+                    &format!(
+                        "Its windows were moved to {}.",
+                        display_name(target_id).unwrap_or_else(|| format!("display {}", target_id))
+                    ),
+                );
+                let (system, group_defaults) = (self.system.clone(), self.group_defaults.clone());
+                let target = self
+                    .displays
+                    .entry(target_id)
+                    .or_insert_with(|| DisplayState::new(target_id, system, group_defaults));
+                for (g_id, g) in ds.groups.iter() {
+                    target.insert_windows_in_group(*g_id, g.windows.clone());
                 }
-            },
-            None => {
-                self.displays
-                    .insert(display_id, DisplayState::new(display_id, window));
+                if target.active_group.is_none() {
+                    target.active_group = ds.active_group;
+                }
+            }
+            if let Some(uuid) = ds.uuid.clone() {
+                self.detached_displays.insert(uuid, ds);
+            }
+        }
+
+        for (uuid, display_id) in current.iter() {
+            if self.displays.contains_key(display_id) {
+                continue;
+            }
+            let mut ds = match self.detached_displays.remove(uuid) {
+                Some(ds) => ds,
+                None => continue,
+            };
+            println!(
+                "Display {} was reconnected, restoring its groups",
+                display_id
+            );
+            alert("Display reconnected", "Its groups were restored.");
+            let ids: Vec<uuid::Uuid> = ds
+                .groups
+                .values()
+                .flat_map(|g| g.windows.iter().map(|w| *w.id()))
+                .collect();
+            // Only windows that are still managed come back.
+            let managed: HashSet<uuid::Uuid> =
+                self.take_windows(&ids).iter().map(|w| *w.id()).collect();
+            for g in ds.groups.values_mut() {
+                g.windows.retain(|w| managed.contains(w.id()));
+                g.tile_weights.retain(|id, _| managed.contains(id));
+                g.active_window_idx = match g.active_window_idx {
+                    _ if g.windows.is_empty() => None,
+                    Some(idx) => Some(usize::min(idx, g.windows.len() - 1)),
+                    None => Some(0),
+                };
+            }
+            ds.display_id = *display_id;
+            ds.gc_groups();
+            self.displays.insert(*display_id, ds);
+        }
+
+        // Minimized windows go back to where their group is now.
+        for (display_id, _) in self.minimized_from.values_mut() {
+            if let Some(current_id) = moved.get(display_id) {
+                *display_id = *current_id;
             }
         }
+        self.relayouts.rekey(&moved);
+
+        self.display_ids = display_ids;
+        let active_id = active_uuid
+            .and_then(|uuid| current.get(&uuid).copied())
+            .or(target_id);
+        self.active_display_idx =
+            active_id.and_then(|d_id| self.display_ids.iter().position(|id| *id == d_id));
+    }
+
+    /// The tiled window a dialog belongs to: its app's main window, or else
+    /// another of its app's windows.
+    fn find_parent_window(&self, dialog: &WindowHandle) -> Option<(DisplayID, u8, usize)> {
+        let pid = self.system.pid(dialog).ok()?;
+        let is_candidate = |w: &WindowHandle| {
+            self.system.pid(w).ok() == Some(pid)
+                && !self.system.is_same_window(w, dialog).unwrap_or(false)
+                && !self.floating_windows.contains(w.id())
+        };
+        self.find_window_by(|w| is_candidate(w) && self.system.is_main(w).unwrap_or(false))
+            .or_else(|| self.find_window_by(is_candidate))
+    }
+
+    /// Center a dialog over its parent, the window `parent_idx` of the
+    /// group.
+    fn center_dialog(
+        &self,
+        dialog: &WindowHandle,
+        display_id: DisplayID,
+        g_id: u8,
+        parent_idx: usize,
+    ) -> Result<()> {
+        let parent = self
+            .displays
+            .get(&display_id)
+            .and_then(|ds| ds.groups.get(&g_id))
+            .and_then(|g| g.windows.get(parent_idx))
+            .ok_or_else(|| anyhow!("The dialog's parent window is gone"))?;
+        let parent = self.system.frame(parent)?;
+        let size = self.system.frame(dialog)?.size;
+        let origin = CGPoint::new(
+            parent.origin.x + (parent.size.width - size.width) / 2.,
+            parent.origin.y + (parent.size.height - size.height) / 2.,
+        );
+        self.system.set_frame(dialog, CGRect::new(&origin, &size))
     }
 
-    fn window_exists(&self, window: &WindowWrapper<AXUIElement>) -> Result<bool> {
+    fn window_exists(&self, window: &WindowHandle) -> Result<bool> {
         for (_, d) in self.displays.iter() {
             for (_, g) in d.groups.iter() {
                 for other in g.windows.iter() {
-                    if window.is_same_window(other)? {
+                    if self.system.is_same_window(window, other)? {
                         return Ok(true);
                     }
                 }
@@ -563,46 +1912,488 @@ impl WindowManager {
     }
 
     pub fn refresh_window_list(&mut self) -> Result<()> {
-        self.display_ids = CGDisplay::active_displays()
-            .map_err(|e| anyhow!(format!("CGDisplay::active_displays {:?}", e)))?;
+        let (open_windows, minimized_windows) = self.system.windows()?;
+        self.update_window_list(open_windows, minimized_windows)
+    }
 
-        self.displays
-            .retain(|d_id, _v| self.display_ids.contains(d_id));
+    /// Start listing windows in the background, showing a spinner in the
+    /// status window until `poll_refresh` picks up the result.
+    fn start_refresh(&mut self) -> Result<()> {
+        if self.pending_refresh.is_none() {
+            self.pending_refresh = Some((self.system.start_windows()?, Instant::now()));
+            self.schedule_wake_up();
+        }
+        Ok(())
+    }
+
+    /// When to next check whether the windows listed by `start_refresh` are
+    /// ready, and turn the spinner.
+    fn next_refresh_poll(&self) -> Option<Instant> {
+        let (_, started) = self.pending_refresh.as_ref()?;
+        let frames = started.elapsed().as_millis() / SPINNER_FRAME_MILLIS + 1;
+        Some(*started + Duration::from_millis((frames * SPINNER_FRAME_MILLIS) as u64))
+    }
+
+    /// Update the model if the windows listed by `start_refresh` are ready.
+    /// Must be called on the main thread.
+    fn poll_refresh(&mut self) -> Result<()> {
+        let result = match &self.pending_refresh {
+            Some((pending, _)) => self.system.finish_windows(pending),
+            None => return Ok(()),
+        };
+        let (open_windows, minimized_windows) = match result {
+            Some(result) => {
+                self.pending_refresh = None;
+                result?
+            }
+            None => {
+                // Turn the spinner.
+                self.update_status_window_content();
+                return Ok(());
+            }
+        };
+        // Keep any window chosen while the list was loading, rather than
+        // going back to the frontmost one.
+        let active_id = self.get_active_window().map(|w| *w.id());
+        self.update_window_list(open_windows, minimized_windows)?;
+        if let Some((display_id, g_id, w_idx)) =
+            active_id.and_then(|id| self.find_window_by(|w| *w.id() == id))
+        {
+            self.set_active_window_location(display_id, g_id, w_idx);
+        }
+        if !matches!(self.mode, Mode::Insert | Mode::Passthrough) {
+            self.highlight_active_window()?;
+        }
+        self.update_status_window_content();
+        self.update_menu_bar();
+        self.notify_hooks();
+        Ok(())
+    }
+
+    fn update_window_list(
+        &mut self,
+        open_windows: Vec<WindowHandle>,
+        minimized_windows: Vec<WindowHandle>,
+    ) -> Result<()> {
+        // Before the displays changed, if they did: stranded windows aren't
+        // remembered.
+        self.remember_floating_frames();
+
+        let previous_active = self
+            .get_active_window()
+            .and_then(|w| Some((*w.id(), self.system.pid(w).ok()?)));
 
-        let (open_windows, minimized_windows) = get_all_windows()?;
+        let display_ids = self.system.display_ids()?;
+        self.set_display_ids(display_ids);
 
+        // Keep the windows of hung apps where they are until they respond.
+        let unresponsive_pids = self.system.unresponsive_pids();
+        // Apps only list the windows on the Spaces being shown.
+        let hidden_windows = self.windows_on_hidden_spaces();
+
+        let system = self.system.clone();
         for (_, d) in self.displays.iter_mut() {
             for (_, g) in d.groups.iter_mut() {
                 g.windows = g
                     .windows
                     .drain(..)
                     .filter(|w| {
-                        open_windows.iter().any(|w2| {
-                            w.is_same_window(w2).unwrap_or_else(|e| {
-                                eprintln!("is_same_windows: {:?}", e);
-                                false
+                        system
+                            .pid(w)
+                            .map_or(false, |pid| unresponsive_pids.contains(&pid))
+                            || hidden_windows.contains(w.id())
+                            || open_windows.iter().any(|w2| {
+                                system.is_same_window(w, w2).unwrap_or_else(|e| {
+                                    eprintln!("is_same_windows: {:?}", e);
+                                    false
+                                })
                             })
-                        })
                     })
                     .collect();
             }
         }
 
-        let my_pid = unsafe {
-            let app = NSRunningApplication::currentApplication(nil);
-            app.processIdentifier_()
+        let my_pid = std::process::id() as i32;
+        let managed_ids: HashSet<uuid::Uuid> = self
+            .displays
+            .values()
+            .flat_map(|d| d.groups.values())
+            .flat_map(|g| g.windows.iter().map(|w| *w.id()))
+            .collect();
+        self.floating_windows.retain(|id| managed_ids.contains(id));
+        self.focus_history.retain(|id| managed_ids.contains(id));
+        self.urgent_windows.retain(|id| managed_ids.contains(id));
+        self.floating_frames.retain(|id| managed_ids.contains(id));
+        self.snapped_windows
+            .retain(|id, _| managed_ids.contains(id));
+        self.thumbnails.retain(|id| managed_ids.contains(id));
+
+        // Parked windows are still open, but stay minimized.
+        let parked: Vec<WindowHandle> = self
+            .minimized_windows
+            .iter()
+            .filter(|w| self.parked_windows.contains_key(w.id()))
+            .filter(|w| {
+                open_windows
+                    .iter()
+                    .any(|w2| system.is_same_window(w, w2).unwrap_or(false))
+            })
+            .cloned()
+            .collect();
+        let is_parked = |w: &WindowHandle| {
+            parked
+                .iter()
+                .any(|w2| system.is_same_window(w, w2).unwrap_or(false))
         };
+
+        let mut new_windows = vec![];
         for w in open_windows {
-            if w.element().pid()? != my_pid && !self.window_exists(&w)? {
-                let display_id = w.display()?.id;
-                self.insert_open_window(w, display_id);
+            if self.system.pid(&w)? != my_pid && !is_parked(&w) && !self.window_exists(&w)? {
+                // A window that can't be read yet, e.g. one that is closing,
+                // is tried again on the next refresh.
+                let frame = match self.system.frame(&w) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        eprintln!("While getting frame of new window {:?}: {:?}", w, e);
+                        continue;
+                    }
+                };
+                let bundle_id = self.system.bundle_identifier(&w).unwrap_or(None);
+                let app_name = self.system.app_name(&w).unwrap_or(None);
+                let (bundle_id, app_name) = (bundle_id.as_deref(), app_name.as_deref());
+                let is_dialog = self
+                    .config
+                    .auto_float
+                    .is_dialog(self.system.subrole(&w).as_deref(), self.system.is_modal(&w));
+                let should_float = match self.config.floating_for(bundle_id, app_name) {
+                    Some(floating) => floating,
+                    None => is_dialog || self.config.auto_float.should_float(&frame.size),
+                };
+                if should_float {
+                    println!("Floating window {:?}", self.system.title(&w));
+                    self.floating_windows.insert(*w.id());
+                }
+                new_windows.push(*w.id());
+                self.created_windows.push((
+                    *w.id(),
+                    app_name.map(|n| n.to_string()),
+                    self.system.title(&w).ok(),
+                ));
+                let parent = if is_dialog && should_float {
+                    self.find_parent_window(&w)
+                } else {
+                    None
+                };
+                // Put a dialog in its parent's group, after the group's other
+                // windows so their order is kept. If it can't be placed over
+                // its parent, it is inserted like any other window.
+                if let Some((display_id, g_id, w_idx)) = parent {
+                    match self.center_dialog(&w, display_id, g_id, w_idx) {
+                        Ok(()) => {
+                            if let Some(g) = self
+                                .displays
+                                .get_mut(&display_id)
+                                .and_then(|ds| ds.groups.get_mut(&g_id))
+                            {
+                                g.insert_window(g.windows.len(), w);
+                                continue;
+                            }
+                        }
+                        Err(e) => eprintln!("While placing dialog {:?}: {:?}", w, e),
+                    }
+                }
+                let display_id = self.system.display_of(&w)?;
+                match self.config.group_for(bundle_id, app_name) {
+                    Some(g_id) => self.insert_window_in_group(w, display_id, g_id),
+                    None => self.insert_open_window(w, display_id),
+                }
             }
         }
-        self.minimized_windows = minimized_windows;
+        // Keep the wrappers we already have, so that windows keep the ids
+        // their origin is recorded under.
+        let previously_minimized = mem::take(&mut self.minimized_windows);
+        self.minimized_windows = minimized_windows
+            .into_iter()
+            .map(|w| {
+                previously_minimized
+                    .iter()
+                    .find(|w2| system.is_same_window(&w, w2).unwrap_or(false))
+                    .cloned()
+                    .unwrap_or(w)
+            })
+            .collect();
+        self.minimized_windows.extend(parked);
+        let minimized_ids: HashSet<uuid::Uuid> =
+            self.minimized_windows.iter().map(|w| *w.id()).collect();
+        self.minimized_from
+            .retain(|id, _| minimized_ids.contains(id));
+        self.parked_windows
+            .retain(|id, _| minimized_ids.contains(id));
         self.refresh_active_window();
+        self.prevent_focus_stealing(previous_active, &new_windows)?;
+        if let Some(w_id) = self.get_active_window().map(|w| *w.id()) {
+            self.urgent_windows.retain(|id| *id != w_id);
+        }
+        Ok(())
+    }
+
+    /// With `activation.prevent_focus_stealing`, give focus back to the
+    /// window `previous` (id and pid) had if another app's new window took
+    /// it, and mark that app's new windows urgent.
+    fn prevent_focus_stealing(
+        &mut self,
+        previous: Option<(uuid::Uuid, i32)>,
+        new_windows: &[uuid::Uuid],
+    ) -> Result<()> {
+        if !self.config.activation.prevent_focus_stealing {
+            return Ok(());
+        }
+        let (prev_id, prev_pid) = match previous {
+            Some(previous) => previous,
+            None => return Ok(()),
+        };
+        let mut urgent = false;
+        for w_id in new_windows {
+            let is_background = self.window_by_id(w_id).map_or(false, |w| {
+                self.system.pid(w).map_or(false, |pid| pid != prev_pid)
+            });
+            if is_background {
+                println!("Keeping focus from new window {}", w_id);
+                self.urgent_windows.push(*w_id);
+                urgent = true;
+            }
+        }
+        let stolen = self
+            .get_active_window()
+            .map_or(true, |w| *w.id() != prev_id);
+        if urgent && stolen && self.set_window_active_by_id(prev_id)? {
+            self.activate_active_window()?;
+        }
         Ok(())
     }
 
+    pub fn save_state(&self) -> SavedState {
+        SavedState {
+            active_display_id: self.active_display_idx.map(|idx| self.display_ids[idx]),
+            displays: self
+                .displays
+                .values()
+                .map(|ds| SavedDisplay {
+                    display_id: ds.display_id,
+                    uuid: ds.uuid.clone(),
+                    active_group: ds.active_group,
+                    groups: ds
+                        .groups
+                        .iter()
+                        .map(|(g_id, g)| g.save(*g_id, &self.floating_windows))
+                        .collect(),
+                    primary_columns: ds.primary_columns.clone(),
+                })
+                .collect(),
+            group_names: self.group_names.clone(),
+        }
+    }
+
+    /// Rebuild the model from state saved by another instance, then pick up
+    /// any windows that state didn't know about.
+    pub fn restore_state(&mut self, state: &SavedState) -> Result<()> {
+        self.display_ids = self.system.display_ids()?;
+        let (open_windows, _) = self.system.windows()?;
+
+        // The same window may be saved in several groups: map each saved id to
+        // a single open window.
+        let mut restored: HashMap<uuid::Uuid, WindowHandle> = HashMap::new();
+        let mut claimed = vec![];
+
+        self.displays.clear();
+        self.floating_windows.clear();
+        // Saved ids may be stale: go by UUID where we can.
+        let mut current_ids: HashMap<u32, DisplayID> = HashMap::new();
+        for sd in state.displays.iter() {
+            let display_id = match &sd.uuid {
+                Some(uuid) => self
+                    .display_ids
+                    .iter()
+                    .find(|d_id| self.system.display_uuid(**d_id).as_ref() == Some(uuid))
+                    .copied(),
+                None => self
+                    .display_ids
+                    .contains(&sd.display_id)
+                    .then_some(sd.display_id),
+            };
+            let display_id = match display_id {
+                Some(display_id) => display_id,
+                None => continue,
+            };
+            current_ids.insert(sd.display_id, display_id);
+            let mut groups = HashMap::new();
+            for sg in sd.groups.iter() {
+                let mut windows = vec![];
+                for sw in sg.windows.iter() {
+                    if let Some(w) = restored.get(&sw.id) {
+                        windows.push(w.clone());
+                    } else if let Some(w) =
+                        find_saved_window(&*self.system, sw, &open_windows, &claimed)
+                    {
+                        claimed.push(*w.id());
+                        if sw.floating {
+                            self.floating_windows.insert(*w.id());
+                        }
+                        restored.insert(sw.id, w.clone());
+                        windows.push(w.clone());
+                    }
+                }
+                if windows.is_empty() {
+                    continue;
+                }
+                let active_window_idx = sg
+                    .active_window_idx
+                    .filter(|idx| *idx < windows.len())
+                    .or(Some(0));
+                groups.insert(
+                    sg.id,
+                    WindowGroup {
+                        layout: sg.layout.clone(),
+                        primary_column_max_windows: sg.primary_column_max_windows,
+                        primary_column_pct: sg.primary_column_pct,
+                        gap: sg.gap,
+                        tile_weights: HashMap::new(),
+                        floating_frames: HashMap::new(),
+                        stacking: vec![],
+                        stacks: vec![],
+                        split_tree: None,
+                        active_window_idx,
+                        windows,
+                        system: self.system.clone(),
+                    },
+                );
+            }
+            // The shown group may have had no windows left to restore.
+            let active_group = sd
+                .active_group
+                .filter(|g_id| groups.contains_key(g_id))
+                .or_else(|| groups.keys().min().copied());
+            self.displays.insert(
+                display_id,
+                DisplayState {
+                    display_id,
+                    uuid: self.system.display_uuid(display_id),
+                    active_group,
+                    groups,
+                    group_defaults: self.group_defaults.clone(),
+                    primary_columns: sd.primary_columns.clone(),
+                    system: self.system.clone(),
+                },
+            );
+        }
+        self.active_display_idx = state
+            .active_display_id
+            .and_then(|display_id| current_ids.get(&display_id))
+            .and_then(|display_id| self.display_ids.iter().position(|d_id| d_id == display_id));
+        // Names set over IPC win over the config.
+        self.group_names.extend(state.group_names.clone());
+
+        self.refresh_window_list()
+    }
+
+    /// Handle a request received over IPC. Must be called on the main thread.
+    pub fn handle_request(&mut self, request: &Request) -> Response {
+        match request {
+            // Authentication is handled per connection by the IPC server.
+            Request::Auth { .. } => Response::Ok,
+            Request::Ping => Response::Pong,
+            Request::Action { id, args } => {
+                let result = Action::parse(id, args).and_then(|action| self.do_action(&action));
+                match result {
+                    Ok(()) => Response::Ok,
+                    Err(e) => Response::Error {
+                        message: format!("{:?}", e),
+                    },
+                }
+            }
+            Request::Batch { actions } => {
+                let result = actions
+                    .iter()
+                    .map(|a| Action::parse(&a.id, &a.args))
+                    .collect::<Result<Vec<_>>>()
+                    .and_then(|actions| self.do_actions(&actions));
+                match result {
+                    Ok(()) => Response::Ok,
+                    Err(e) => Response::Error {
+                        message: format!("{:?}", e),
+                    },
+                }
+            }
+            Request::SetGroupName { group, name } => {
+                if *group > 9 {
+                    return Response::Error {
+                        message: format!("Expected a group number 0-9, got {}", group),
+                    };
+                }
+                match name {
+                    Some(name) => self.group_names.insert(*group, name.clone()),
+                    None => self.group_names.remove(group),
+                };
+                self.update_status_window_content();
+                self.update_menu_bar();
+                self.notify_hooks();
+                Response::Ok
+            }
+            Request::SetGroupPrimary {
+                group,
+                pct,
+                max_windows,
+            } => {
+                if *group > 9 {
+                    return Response::Error {
+                        message: format!("Expected a group number 0-9, got {}", group),
+                    };
+                }
+                if matches!(pct, Some(pct) if !(10..=90).contains(pct)) {
+                    return Response::Error {
+                        message: format!("Expected a width of 10-90%, got {:?}", pct),
+                    };
+                }
+                if matches!(max_windows, Some(n) if *n < 1) {
+                    return Response::Error {
+                        message: format!("Expected at least 1 window, got {:?}", max_windows),
+                    };
+                }
+                for ds in self.displays.values_mut() {
+                    ds.set_group_primary_column(*group, *pct, *max_windows);
+                }
+                let result = self.relayout_all_displays();
+                self.update_status_window_content();
+                match result {
+                    Ok(()) => Response::Ok,
+                    Err(e) => Response::Error {
+                        message: format!("{:?}", e),
+                    },
+                }
+            }
+            Request::Stats => Response::Stats(self.stats.report()),
+            Request::State => Response::State(self.save_state()),
+            Request::FrameChanges if !self.system.is_dry_run() => Response::Error {
+                message: "Not running with --dry-run".to_string(),
+            },
+            Request::FrameChanges => Response::FrameChanges(self.system.take_frame_changes()),
+            Request::Shutdown => {
+                self.switcher = None;
+                self.group_preview = None;
+                self.palette = None;
+                self.display_labels = None;
+                self.close_highlight_window();
+                self.close_status_window();
+                self.unpark_windows();
+                Response::State(self.save_state())
+            }
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
     pub fn drag_window(&self) -> Option<&DragWindow> {
         self.drag_window.as_ref()
     }
@@ -611,6 +2402,197 @@ impl WindowManager {
         self.drag_window = dw
     }
 
+    /// Start dragging the window under `point`.
+    pub fn start_drag(&mut self, point: &CGPoint) -> Result<()> {
+        let window = match self.system.window_at_point(point)? {
+            Some(window) => window,
+            None => return Ok(()),
+        };
+        // Drag our own copy of the window if we manage it, so we can find it
+        // again when the drag ends.
+        let window = match self
+            .find_window_by(|w| self.system.is_same_window(&window, w).unwrap_or(false))
+        {
+            Some((display_id, g_id, w_idx)) => {
+                self.displays[&display_id].groups[&g_id].windows[w_idx].clone()
+            }
+            None => window,
+        };
+        let dw = DragWindow::new(
+            self.system.clone(),
+            window,
+            point,
+            self.config.sticky_edges.resistance,
+        )?;
+        activate_window(&*self.system, dw.window(), &self.config)?;
+        self.drag_window = Some(dw);
+        Ok(())
+    }
+
+    pub fn resize_window(&self) -> Option<&ResizeWindow> {
+        self.resize_window.as_ref()
+    }
+
+    /// Start resizing the window under `point`.
+    pub fn start_resize(&mut self, point: &CGPoint) -> Result<()> {
+        let window = match self.system.window_at_point(point)? {
+            Some(window) => window,
+            None => return Ok(()),
+        };
+        let window = match self
+            .find_window_by(|w| self.system.is_same_window(&window, w).unwrap_or(false))
+        {
+            Some((display_id, g_id, w_idx)) => {
+                self.displays[&display_id].groups[&g_id].windows[w_idx].clone()
+            }
+            None => window,
+        };
+        let rw = ResizeWindow::new(self.system.clone(), window, point)?;
+        activate_window(&*self.system, rw.window(), &self.config)?;
+        self.resize_window = Some(rw);
+        Ok(())
+    }
+
+    /// Finish resizing. If the window is tiled, the tile layout is adjusted to
+    /// the new width instead, or in a split tree to the new size.
+    pub fn end_resize(&mut self, point: &CGPoint) -> Result<()> {
+        let rw = match self.resize_window.take() {
+            Some(rw) => rw,
+            None => return Ok(()),
+        };
+        let frame = rw.frame_for(point);
+        let w_id = *rw.window().id();
+        if let Some((display_id, g_id, w_idx)) = self.find_window_by(|w| *w.id() == w_id) {
+            let display_width = self.system.display_bounds(display_id).size.width;
+            if let Some(g) = self
+                .displays
+                .get_mut(&display_id)
+                .and_then(|ds| ds.groups.get_mut(&g_id))
+            {
+                if g.split_tree.is_some() {
+                    g.resize_split_tile(&w_id, &frame, display_id, &self.floating_windows);
+                } else {
+                    g.set_tile_width_pct(
+                        &w_id,
+                        frame.size.width / display_width * 100.,
+                        &self.floating_windows,
+                    );
+                }
+            }
+            self.remember_floating_frame(&w_id);
+            self.set_active_window_location(display_id, g_id, w_idx);
+            self.relayout_all_displays()?;
+            if self.mode != Mode::Insert {
+                self.update_status_window_content();
+                self.highlight_active_window()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop the dragged window at `point`: move it into the active group of the
+    /// display under the mouse, at the position of the tile it was dropped on.
+    /// Floating windows are snapped to nearby edges instead.
+    pub fn end_drag(&mut self, point: &CGPoint) -> Result<()> {
+        let dw = match self.drag_window.take() {
+            Some(dw) => dw,
+            None => return Ok(()),
+        };
+        // Where the window is, if the mouse is just past the edge of its
+        // display.
+        let point = &dw.resisted_point(point);
+        let w_id = *dw.window().id();
+        let location = self.find_window_by(|w| *w.id() == w_id);
+        let dst_display_id = match (self.system.display_at_point(point)?, location) {
+            (Some(display_id), _) => display_id,
+            (None, Some((display_id, _, _))) => display_id,
+            (None, None) => return Ok(()),
+        };
+
+        let dst_group = match location {
+            Some((src_display_id, g_id, _)) if src_display_id == dst_display_id => {
+                self.displays[&src_display_id].groups.get(&g_id)
+            }
+            _ => self
+                .displays
+                .get(&dst_display_id)
+                .and_then(|ds| ds.get_active_group()),
+        };
+        let is_floating = self.floating_windows.contains(&w_id)
+            || dst_group.map_or(true, |g| matches!(g.layout, Layout::Floating));
+
+        if is_floating && self.config.snap.enabled {
+            let others: Vec<CGRect> = dst_group
+                .map(|g| {
+                    g.windows
+                        .iter()
+                        .filter(|w| *w.id() != w_id)
+                        .filter_map(|w| self.system.frame(w).ok())
+                        .collect()
+                })
+                .unwrap_or_default();
+            let frame = snap_frame(
+                &self.system.frame(dw.window())?,
+                point,
+                &self.system.display_bounds(dst_display_id),
+                &others,
+                self.config.snap.threshold,
+            );
+            self.system.set_frame(dw.window(), frame)?;
+        }
+
+        let (src_display_id, g_id, w_idx) = match location {
+            Some(location) => location,
+            // Not a window we manage: leave it where it was dropped.
+            None => return Ok(()),
+        };
+        let dst_idx = if is_floating {
+            None
+        } else {
+            dst_group.and_then(|g| {
+                g.windows.iter().position(|w| {
+                    *w.id() != w_id
+                        && self
+                            .system
+                            .frame(w)
+                            .map_or(false, |frame| frame.contains(point))
+                })
+            })
+        };
+
+        if dst_display_id != src_display_id {
+            let window = self
+                .displays
+                .get_mut(&src_display_id)
+                .and_then(|ds| ds.remove_window(&w_id));
+            if let Some(window) = window {
+                self.insert_open_window_at(window, dst_display_id, dst_idx.unwrap_or(0));
+            }
+        } else if let Some(dst_idx) = dst_idx {
+            if let Some(g) = self
+                .displays
+                .get_mut(&src_display_id)
+                .and_then(|ds| ds.groups.get_mut(&g_id))
+            {
+                let window = g.windows.remove(w_idx);
+                g.insert_window(dst_idx, window);
+            }
+        }
+
+        if is_floating {
+            self.remember_floating_frame(&w_id);
+        }
+        if let Some((display_id, g_id, w_idx)) = self.find_window_by(|w| *w.id() == w_id) {
+            self.set_active_window_location(display_id, g_id, w_idx);
+        }
+        self.relayout_all_displays()?;
+        if self.mode != Mode::Insert {
+            self.update_status_window_content();
+            self.highlight_active_window()?;
+        }
+        Ok(())
+    }
+
     pub fn mode(&self) -> &Mode {
         &self.mode
     }
@@ -621,208 +2603,1466 @@ impl WindowManager {
 
     fn set_mode(&mut self, mode: Mode) {
         self.mode = mode;
+        self.last_activity = Instant::now();
         println!("Entered {:?} mode", self.mode);
     }
 
-    fn maybe_enter_normal_mode(&mut self) -> Result<()> {
-        Ok(if let Mode::Insert = self.mode {
-            self.refresh_window_list()?;
-            self.open_status_window();
-        })
+    /// Called periodically to leave the temporary normal mode if its
+    /// modifiers aren't held, in case we missed their release, or once
+    /// nothing has been done in it for `transient_mode.timeout` seconds.
+    pub fn check_transient_mode(&mut self) -> Result<()> {
+        if self.mode != Mode::InsertNormal {
+            return Ok(());
+        }
+        let timeout = self.config.transient_mode.timeout;
+        if !holds_insert_normal_chord(modifier_flags(get_modifier_flags()?)) {
+            println!("Modifiers were released: leaving {:?} mode", self.mode);
+        } else if timeout > 0. && self.last_activity.elapsed().as_secs_f64() >= timeout {
+            println!(
+                "Nothing done for {}s: leaving {:?} mode",
+                timeout, self.mode
+            );
+        } else {
+            return Ok(());
+        }
+        self.do_action(&Action::ModeInsert)
+    }
+
+    fn maybe_enter_normal_mode(&mut self) -> Result<()> {
+        Ok(if let Mode::Insert = self.mode {
+            // Listing every app's windows can take a while: show the status
+            // window straight away.
+            self.start_refresh()?;
+            self.open_status_window();
+        })
+    }
+
+    fn get_active_display(&self) -> Option<&DisplayState> {
+        self.active_display_idx.and_then(|idx| {
+            let display_id = self.display_ids[idx];
+            self.displays.get(&display_id)
+        })
+    }
+
+    /// Where to show overlays: the active display, or the main display if
+    /// there isn't one.
+    fn active_display_bounds(&self) -> CGRect {
+        match self.active_display_idx {
+            Some(idx) => self.system.display_bounds(self.display_ids[idx]),
+            None => self.system.display_bounds(self.system.main_display_id()),
+        }
+    }
+
+    fn get_active_display_mut(&mut self) -> Option<&mut DisplayState> {
+        self.active_display_idx.and_then(|idx| {
+            let display_id = self.display_ids[idx];
+            self.displays.get_mut(&display_id)
+        })
+    }
+
+    fn get_active_window(&self) -> Option<&WindowHandle> {
+        self.get_active_display()
+            .and_then(|ds| ds.get_active_window())
+    }
+
+    fn window_by_id(&self, w_id: &uuid::Uuid) -> Option<&WindowHandle> {
+        self.displays
+            .values()
+            .flat_map(|ds| ds.groups.values())
+            .flat_map(|g| g.windows.iter())
+            .find(|w| w.id() == w_id)
+    }
+
+    /// Find the display, group and index of the first window matching `pred`,
+    /// preferring each display's active group.
+    fn find_window_by<F>(&self, pred: F) -> Option<(DisplayID, u8, usize)>
+    where
+        F: Fn(&WindowHandle) -> bool,
+    {
+        for (display_id, ds) in self.displays.iter() {
+            let mut g_ids: Vec<_> = ds.groups.keys().copied().collect();
+            g_ids.sort_by_key(|g_id| Some(*g_id) != ds.active_group);
+            let found = g_ids.into_iter().find_map(|g_id| {
+                ds.groups[&g_id]
+                    .windows
+                    .iter()
+                    .position(&pred)
+                    .map(|w_idx| (*display_id, g_id, w_idx))
+            });
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+
+    fn set_active_window_location(&mut self, display_id: DisplayID, g_id: u8, w_idx: usize) {
+        if let Some(ds) = self.displays.get_mut(&display_id) {
+            ds.set_active_group(g_id);
+            if let Some(g) = ds.groups.get_mut(&g_id) {
+                g.active_window_idx = Some(w_idx);
+            }
+        }
+        self.active_display_idx = self.display_ids.iter().position(|d_id| *d_id == display_id);
+    }
+
+    /// Make the nearest window in `direction` active. Any display's active
+    /// group can be moved to, since their windows are all on screen.
+    fn set_window_in_direction_active(&mut self, direction: Direction) -> Result<()> {
+        let (active_id, from) = match self.get_active_window() {
+            Some(w) => (*w.id(), self.system.frame(w)?),
+            None => return Ok(()),
+        };
+        let mut locations = vec![];
+        let mut frames = vec![];
+        for (display_id, ds) in self.displays.iter() {
+            let (g_id, g) = match (ds.active_group, ds.get_active_group()) {
+                (Some(g_id), Some(g)) => (g_id, g),
+                _ => continue,
+            };
+            for (w_idx, w) in g.windows.iter().enumerate() {
+                if *w.id() == active_id {
+                    continue;
+                }
+                match self.system.frame(w) {
+                    Ok(frame) => {
+                        locations.push((*display_id, g_id, w_idx));
+                        frames.push(frame);
+                    }
+                    Err(e) => eprintln!("While getting frame of {:?}: {:?}", w, e),
+                }
+            }
+        }
+        if let Some(idx) = nearest_in_direction(&from, &frames, direction) {
+            let (display_id, g_id, w_idx) = locations[idx];
+            self.set_active_window_location(display_id, g_id, w_idx);
+        }
+        Ok(())
+    }
+
+    /// Swap the active window with its nearest neighbour in `direction` in
+    /// the same group, or move it through the group's split tree if it has
+    /// one. If there is none, move the window to the nearest display in that
+    /// direction and make that display active. Returns whether the window
+    /// moved to another display.
+    fn swap_window_in_direction(&mut self, direction: Direction) -> Result<bool> {
+        let floating = &self.floating_windows;
+        if let Some(g) = self
+            .active_display_idx
+            .and_then(|idx| self.displays.get_mut(&self.display_ids[idx]))
+            .and_then(|ds| ds.get_active_group_mut())
+        {
+            if g.move_active_window_in_split_tree(floating, direction) {
+                return Ok(false);
+            }
+        }
+        let g = match self
+            .get_active_display()
+            .and_then(|ds| ds.get_active_group())
+        {
+            Some(g) => g,
+            None => return Ok(false),
+        };
+        let active_idx = match g.active_window_idx {
+            Some(idx) if idx < g.windows.len() => idx,
+            _ => return Ok(false),
+        };
+        let from = self.system.frame(&g.windows[active_idx])?;
+        let mut others = vec![];
+        for (w_idx, w) in g.windows.iter().enumerate() {
+            if w_idx != active_idx {
+                others.push((w_idx, self.system.frame(w)?));
+            }
+        }
+
+        let frames: Vec<CGRect> = others.iter().map(|(_, frame)| *frame).collect();
+        if let Some(idx) = nearest_in_direction(&from, &frames, direction) {
+            let other_idx = others[idx].0;
+            if let Some(g) = self
+                .get_active_display_mut()
+                .and_then(|ds| ds.get_active_group_mut())
+            {
+                g.windows.swap(active_idx, other_idx);
+                g.active_window_idx = Some(other_idx);
+            }
+            return Ok(false);
+        }
+
+        let active_display_idx = match self.active_display_idx {
+            Some(idx) => idx,
+            None => return Ok(false),
+        };
+        let other_displays: Vec<usize> = (0..self.display_ids.len())
+            .filter(|idx| *idx != active_display_idx)
+            .collect();
+        let bounds: Vec<CGRect> = other_displays
+            .iter()
+            .map(|idx| self.system.display_bounds(self.display_ids[*idx]))
+            .collect();
+        let active_bounds = self
+            .system
+            .display_bounds(self.display_ids[active_display_idx]);
+        match nearest_in_direction(&active_bounds, &bounds, direction) {
+            Some(idx) => {
+                self.move_active_window_to_display_idx(other_displays[idx]);
+                self.active_display_idx = Some(other_displays[idx]);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Make the window under `point` (e.g. one the user clicked) the active
+    /// window, along with its group and display.
+    pub fn set_active_window_at_point(&mut self, point: &CGPoint) -> Result<()> {
+        let window = match self.system.window_at_point(point)? {
+            Some(window) => window,
+            None => return Ok(()),
+        };
+
+        if let Some((display_id, g_id, w_idx)) =
+            self.find_window_by(|w| self.system.is_same_window(&window, w).unwrap_or(false))
+        {
+            self.set_active_window_location(display_id, g_id, w_idx);
+            self.record_focus();
+            self.update_menu_bar();
+            self.notify_hooks();
+            if self.mode != Mode::Insert {
+                self.update_status_window_content();
+                self.highlight_active_window()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw a border around the active window.
+    fn highlight_active_window(&mut self) -> Result<()> {
+        if let Some(deferred) = &self.deferred_updates {
+            deferred.highlight.set(true);
+            return Ok(());
+        }
+        if self.relayouts.is_pending() {
+            // The window is about to move.
+            self.relayouts.highlight.set(true);
+            return Ok(());
+        }
+        self.highlight_active_group();
+        if let Some(w) = self.get_active_window() {
+            let highlight = &self.config.highlight;
+            let color = if self.system.is_frontmost_and_main(w).unwrap_or(false) {
+                highlight.color
+            } else {
+                highlight.unfocused_color
+            };
+            let rect = border_rect(&*self.system, w, highlight.outset())?;
+            match &self.highlight_overlay_window {
+                None => {
+                    let overlay = create_border_overlay(
+                        rect,
+                        highlight.width,
+                        highlight.corner_radius,
+                        &color,
+                    );
+                    self.highlight_overlay_window = Some(overlay);
+                }
+                Some(overlay) => {
+                    overlay.set_frame(rect);
+                    overlay.order_front();
+                    set_border_color(overlay, &color);
+                }
+            }
+        }
+        self.bring_status_window_to_front();
+        Ok(())
+    }
+
+    /// If configured, draw a faint border around the active group's other
+    /// windows.
+    fn highlight_active_group(&mut self) {
+        let highlight = &self.config.highlight;
+        let rects: Vec<NSRect> = match self
+            .get_active_display()
+            .and_then(|ds| ds.get_active_group())
+        {
+            Some(g) if highlight.group => g
+                .windows
+                .iter()
+                .enumerate()
+                .filter(|(w_idx, _)| Some(*w_idx) != g.active_window_idx)
+                .filter_map(|(_, w)| border_rect(&*self.system, w, highlight.outset()).ok())
+                .collect(),
+            _ => vec![],
+        };
+        self.group_overlay_windows.truncate(rects.len());
+        for (i, rect) in rects.into_iter().enumerate() {
+            match self.group_overlay_windows.get(i) {
+                Some(overlay) => {
+                    overlay.set_frame(rect);
+                    overlay.order_front();
+                }
+                None => {
+                    let overlay = create_border_overlay(
+                        rect,
+                        highlight.group_width,
+                        highlight.corner_radius,
+                        &highlight.group_color,
+                    );
+                    self.group_overlay_windows.push(overlay);
+                }
+            }
+        }
+    }
+
+    /// The pids of all apps with managed windows.
+    pub fn window_pids(&self) -> Vec<i32> {
+        let mut pids: Vec<i32> = self
+            .displays
+            .values()
+            .flat_map(|ds| ds.groups.values())
+            .flat_map(|g| g.windows.iter())
+            .filter_map(|w| self.system.pid(w).ok())
+            .collect();
+        pids.sort_unstable();
+        pids.dedup();
+        pids
+    }
+
+    /// Called when an app's focused window changes, or a window moves or
+    /// resizes. In insert mode, make the focused window active so the border
+    /// and focus statistics follow it.
+    pub fn focus_changed(&mut self) -> Result<()> {
+        if self.mode == Mode::Passthrough {
+            return Ok(());
+        }
+        if self.mode == Mode::Insert {
+            if let Ok(window) = self.system.focused_window() {
+                if let Some((display_id, g_id, w_idx)) =
+                    self.find_window_by(|w| self.system.is_same_window(&window, w).unwrap_or(false))
+                {
+                    self.set_active_window_location(display_id, g_id, w_idx);
+                }
+            }
+        }
+        self.record_focus();
+        self.update_menu_bar();
+        self.notify_hooks();
+        if self.mode != Mode::Insert || self.border_enabled {
+            self.highlight_active_window()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Called whenever focus may have changed.
+    fn record_focus(&mut self) {
+        if let Some(w_id) = self.zoomed_window {
+            if self.get_active_window().map(|w| *w.id()) != Some(w_id) {
+                self.zoomed_window = None;
+                self.relayout_all_displays()
+                    .unwrap_or_else(|e| eprintln!("While unzooming window: {:?}", e));
+            }
+        }
+        let group = self.get_active_display().and_then(|ds| ds.active_group);
+        let app = self
+            .get_active_window()
+            .and_then(|w| self.system.app_name(w).ok().flatten());
+        self.stats.focus(group, app);
+        if let Some(w_id) = self.get_active_window().map(|w| *w.id()) {
+            self.focus_history.focus(w_id);
+        }
+        if let Some(g) = self
+            .get_active_display_mut()
+            .and_then(|ds| ds.get_active_group_mut())
+        {
+            g.focus_split_tree();
+        }
+    }
+
+    /// Make the window `w_id` active, showing its group if it is hidden.
+    /// Returns false if it isn't managed any more.
+    fn set_window_active_by_id(&mut self, w_id: uuid::Uuid) -> Result<bool> {
+        let (display_id, g_id, w_idx) = match self.find_window_by(|w| *w.id() == w_id) {
+            Some(location) => location,
+            None => return Ok(false),
+        };
+        let shown = self
+            .displays
+            .get(&display_id)
+            .map_or(false, |ds| ds.active_group == Some(g_id));
+        self.set_active_window_location(display_id, g_id, w_idx);
+        if !shown {
+            self.bring_active_display_group_to_front()?;
+            self.relayout_active_display()?;
+        }
+        Ok(true)
+    }
+
+    fn close_highlight_window(&mut self) {
+        self.highlight_overlay_window = None;
+        self.group_overlay_windows.clear();
+    }
+
+    /// Log each action performed, and whether it failed.
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+    }
+
+    /// Show `warning` in the status window and menu bar, or stop showing the
+    /// last one.
+    pub fn set_event_tap_warning(&mut self, warning: Option<String>) {
+        if self.event_tap_warning == warning {
+            return;
+        }
+        self.event_tap_warning = warning;
+        self.update_status_window_content();
+        self.update_menu_bar();
+    }
+
+    /// A group's number, followed by its name if it has one.
+    fn group_label(&self, g_id: u8) -> String {
+        match self.group_names.get(&g_id) {
+            Some(name) => format!("{} {}", g_id, name),
+            None => g_id.to_string(),
+        }
+    }
+
+    fn describe_displays(&self) -> String {
+        let mut content = String::new();
+
+        if self.system.is_dry_run() {
+            content.push_str("Dry run (windows are not moved)\n");
+        } else if self.system.is_read_only() {
+            content.push_str("Spectator mode (read-only)\n");
+        }
+        if let Some(warning) = &self.event_tap_warning {
+            content.push_str(&format!("\u{26a0} {}\n", warning));
+        }
+
+        let mut is_first = true;
+
+        for (display_id, display) in self.displays.iter() {
+            let display_is_active = self
+                .active_display_idx
+                .map_or(false, |idx| self.display_ids[idx] == *display_id);
+            if is_first {
+                is_first = false;
+            } else {
+                content.push('\n');
+            }
+            if display_is_active {
+                content.push_str("[x] ");
+            } else {
+                content.push_str("[ ] ");
+            }
+            content.push_str(&format!("Display {}", display_id));
+            for group_id in 0..=9 {
+                if let Some(group) = display.groups.get(&group_id) {
+                    content.push_str("\n  ");
+                    let group_is_active = display.active_group.map_or(false, |id| id == group_id);
+                    if display_is_active && group_is_active {
+                        content.push_str("[x] ");
+                    } else {
+                        content.push_str("[ ] ");
+                    }
+                    content.push_str(&format!(
+                        "Group {} ({})",
+                        self.group_label(group_id),
+                        group.layout
+                    ));
+                    let iter = group.windows.iter().enumerate();
+                    let iter: Box<dyn Iterator<Item = _>> = match group.layout {
+                        Layout::TileHorizontal(_) => Box::new(iter),
+                        Layout::Cascade | Layout::Floating | Layout::Custom(_) => {
+                            Box::new(iter.rev())
+                        }
+                    };
+                    for (i, window) in iter {
+                        content.push_str("\n    ");
+                        let window_is_active =
+                            group.active_window_idx.map_or(false, |idx| idx == i);
+                        if display_is_active && group_is_active && window_is_active {
+                            content.push_str("[x] ");
+                        } else {
+                            content.push_str("[ ] ");
+                        }
+                        let title = self.system.title(window).unwrap_or("<Unknown>".to_string());
+                        let title: String = title.chars().take(45).collect();
+                        content.push_str(&format!("{}", title));
+                    }
+                } else if Some(group_id) == display.active_group {
+                    // Group is active, but contains no windows
+                    content.push_str(&format!("\n  [x] Group {}", self.group_label(group_id)));
+                }
+            }
+        }
+        if !self.minimized_windows.is_empty() {
+            content.push_str("\n\nMinimized");
+            for window in self.minimized_windows.iter().rev() {
+                let title = self.system.title(window).unwrap_or("<Unknown>".to_string());
+                let title: String = title.chars().take(45).collect();
+                content.push_str(&format!("\n  {}", title));
+                if let Some((display_id, g_id)) = self.minimized_from.get(window.id()) {
+                    content.push_str(&format!(
+                        " (display {}, group {})",
+                        display_id,
+                        self.group_label(*g_id)
+                    ));
+                }
+            }
+        }
+        content
+    }
+
+    /// A summary of the mode and the active display, group and window.
+    fn describe_active_state(&self) -> String {
+        let mut content = format!("Mode: {:?}\n", self.mode);
+        if let Some((_, _, prompt)) = &self.pending_confirmation {
+            content.push_str(&format!("\u{26a0} {}: do it again to confirm\n", prompt));
+        }
+        if let Some((_, started)) = &self.pending_refresh {
+            let frame = (started.elapsed().as_millis() / SPINNER_FRAME_MILLIS) as usize;
+            content.push_str(&format!(
+                "{} Refreshing windows\n",
+                SPINNER[frame % SPINNER.len()]
+            ));
+        }
+        let display = match (self.active_display_idx, self.get_active_display()) {
+            (Some(idx), Some(display)) => {
+                content.push_str(&format!(
+                    "Display {} of {}\n",
+                    idx + 1,
+                    self.display_ids.len()
+                ));
+                display
+            }
+            _ => return content,
+        };
+
+        let groups: Vec<String> = (0..=9)
+            .filter_map(|g_id| {
+                let is_active = display.active_group == Some(g_id);
+                let count = display.groups.get(&g_id).map_or(0, |g| g.windows.len());
+                let urgent = display.groups.get(&g_id).map_or(false, |g| {
+                    g.windows
+                        .iter()
+                        .any(|w| self.urgent_windows.contains(w.id()))
+                });
+                let mark = if urgent { "!" } else { "" };
+                match (is_active, count) {
+                    (true, _) => Some(format!("[{}:{}{}]", self.group_label(g_id), count, mark)),
+                    (false, 0) => None,
+                    (false, _) => Some(format!("{}:{}{}", self.group_label(g_id), count, mark)),
+                }
+            })
+            .collect();
+        content.push_str(&format!("Groups: {}\n", groups.join(" ")));
+
+        if let Some(group) = display.get_active_group() {
+            match &group.split_tree {
+                Some(tree) => {
+                    content.push_str("Layout: split\n");
+                    if let Some((_, direction)) = tree.pending_split() {
+                        content.push_str(&format!("Next window: split {}\n", direction));
+                    }
+                }
+                None => content.push_str(&format!("Layout: {}\n", group.layout)),
+            }
+            if let Some((idx, len)) = group.active_stack_position() {
+                content.push_str(&format!("Stack: {} of {}\n", idx + 1, len));
+            }
+        }
+        if let Some(window) = display.get_active_window() {
+            let app = self.system.app_name(window).ok().flatten();
+            let title = self.system.title(window).ok();
+            content.push_str(&format!(
+                "Window: {} - {}\n",
+                app.as_deref().unwrap_or("<Unknown>"),
+                title.as_deref().unwrap_or("<Unknown>")
+            ));
+        }
+        if let Some(w_id) = self.urgent_windows.last() {
+            if let Some((display_id, g_id, w_idx)) = self.find_window_by(|w| w.id() == w_id) {
+                let window = &self.displays[&display_id].groups[&g_id].windows[w_idx];
+                let app = self.system.app_name(window).ok().flatten();
+                let title = self.system.title(window).ok();
+                content.push_str(&format!(
+                    "Urgent: {} - {} (group {})\n",
+                    app.as_deref().unwrap_or("<Unknown>"),
+                    title.as_deref().unwrap_or("<Unknown>"),
+                    self.group_label(g_id)
+                ));
+            }
+        }
+        content
+    }
+
+    fn update_status_window_content(&self) {
+        if let Some((_window, text_field)) = &self.status_window {
+            unsafe {
+                let mut content = format!(
+                    "{}\n{}",
+                    self.describe_active_state(),
+                    self.describe_displays()
+                );
+                if self.show_stats {
+                    content.push_str(&format!("\n\n{}", self.stats.report()));
+                }
+                let text = NSString::alloc(nil).init_str(&content);
+                text_field.setStringValue_(text);
+            }
+        }
+    }
+
+    /// Run the hooks, and the script's event handlers, for whatever changed
+    /// since they last ran.
+    fn notify_hooks(&mut self) {
+        let created = mem::take(&mut self.created_windows);
+        #[cfg(feature = "scripting")]
+        let has_script = self.script.is_some();
+        #[cfg(not(feature = "scripting"))]
+        let has_script = false;
+        if self.hooks.is_empty() && !has_script {
+            return;
+        }
+        let focused = self.get_active_window().map(|w| {
+            (
+                *w.id(),
+                self.system.app_name(w).ok().flatten(),
+                self.system.title(w).ok(),
+            )
+        });
+        let state = HookState {
+            mode: self.mode.name(),
+            focused,
+            active_display_id: self.active_display_idx.map(|idx| self.display_ids[idx]),
+            active_groups: self
+                .displays
+                .iter()
+                .filter_map(|(d_id, ds)| ds.active_group.map(|g_id| (*d_id, g_id)))
+                .collect(),
+            layouts: self
+                .displays
+                .iter()
+                .flat_map(|(d_id, ds)| {
+                    ds.groups
+                        .iter()
+                        .map(move |(g_id, g)| ((*d_id, *g_id), g.layout.to_string()))
+                })
+                .collect(),
+            display_ids: self.display_ids.clone(),
+            created,
+        };
+        let events = self.hooks.update(state);
+        #[cfg(feature = "scripting")]
+        self.run_script_handlers(&events);
+        #[cfg(not(feature = "scripting"))]
+        let _ = events;
+    }
+
+    /// Perform the actions the script queues for `events`. Actions for an
+    /// event about a window act on that window. Changes they make are seen by
+    /// hooks the next time they run, so that handlers can't set each other
+    /// off forever.
+    #[cfg(feature = "scripting")]
+    fn run_script_handlers(&mut self, events: &[HookEvent]) {
+        let script = match &mut self.script {
+            Some(script) => script,
+            None => return,
+        };
+        let mut batches = vec![];
+        for event in events {
+            match script.handle(event) {
+                Ok(actions) if actions.is_empty() => (),
+                Ok(actions) => batches.push((event.window_id(), actions)),
+                Err(e) => eprintln!("While running script for {}: {:?}", event.name(), e),
+            }
+        }
+        if batches.is_empty() {
+            return;
+        }
+        for (w_id, actions) in batches {
+            let result = match w_id {
+                Some(w_id) => self.perform_batch_on_window(w_id, &actions),
+                None => self.perform_batch(&actions),
+            };
+            result.unwrap_or_else(|e| eprintln!("While performing script actions: {:?}", e));
+        }
+        self.update_status_window_content();
+        self.update_menu_bar();
+    }
+
+    /// Make the window with id `w_id` active while `actions` are performed,
+    /// then make the previously active window active again.
+    #[cfg(feature = "scripting")]
+    fn perform_batch_on_window(&mut self, w_id: uuid::Uuid, actions: &[Action]) -> Result<()> {
+        let (display_id, g_id, w_idx) = match self.find_window_by(|w| *w.id() == w_id) {
+            Some(location) => location,
+            None => return Ok(()),
+        };
+        let prev_id = self.get_active_window().map(|w| *w.id());
+        self.set_active_window_location(display_id, g_id, w_idx);
+        let result = self.perform_batch(actions);
+        if let Some(prev_id) = prev_id {
+            if let Some((display_id, g_id, w_idx)) = self.find_window_by(|w| *w.id() == prev_id) {
+                self.set_active_window_location(display_id, g_id, w_idx);
+            }
+        }
+        result
+    }
+
+    #[cfg(feature = "scripting")]
+    fn run_script_function(&mut self, name: &str) -> Result<()> {
+        let actions = match &mut self.script {
+            Some(script) => script.call(name)?,
+            None => return Err(anyhow!("No script is loaded")),
+        };
+        actions.iter().try_for_each(|a| self.perform_action(a))
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    fn run_script_function(&mut self, _name: &str) -> Result<()> {
+        Err(anyhow!("Built without the scripting feature"))
+    }
+
+    /// Each display's active group.
+    fn shown_groups(&self) -> HashMap<DisplayID, Option<u8>> {
+        self.displays
+            .iter()
+            .map(|(display_id, ds)| (*display_id, ds.active_group))
+            .collect()
+    }
+
+    /// With `spaces.enabled`, put each window on its group's Space, and
+    /// switch to the Space of each display's group if it was shown since
+    /// `shown_before`.
+    #[cfg(feature = "spaces")]
+    fn sync_spaces(&mut self, shown_before: &HashMap<DisplayID, Option<u8>>) {
+        if !self.config.spaces.enabled {
+            return;
+        }
+        // Moving windows between Spaces and switching Space change the
+        // desktop, which read-only and dry-run modes never do.
+        if self.system.is_read_only() {
+            println!("[read-only] sync Spaces");
+            return;
+        }
+        let all = match display_spaces() {
+            Ok(all) => all,
+            Err(e) => {
+                eprintln!("While listing Spaces: {:?}", e);
+                return;
+            }
+        };
+        for (display_id, ds) in self.displays.iter() {
+            let spaces = match spaces_of_display(&all, ds.uuid.as_deref()) {
+                Some(spaces) => spaces,
+                None => continue,
+            };
+            let space_of_group =
+                |g_id: u8| spaces.spaces.get(SpacesConfig::space_index(g_id)).copied();
+            for (g_id, g) in ds.groups.iter() {
+                let sid = match space_of_group(*g_id) {
+                    Some(sid) => sid,
+                    None => continue,
+                };
+                for w in g.windows.iter() {
+                    if self.space_of_window.insert(*w.id(), sid) == Some(sid) {
+                        continue;
+                    }
+                    // Not retried until the window changes group again.
+                    self.system
+                        .window_number(w)
+                        .and_then(|wid| move_window_to_space(wid, sid))
+                        .unwrap_or_else(|e| {
+                            eprintln!("While moving window {:?} to Space {}: {:?}", w.id(), sid, e)
+                        });
+                }
+            }
+            if shown_before.get(display_id) == Some(&ds.active_group) {
+                continue;
+            }
+            if let Some(sid) = ds.active_group.and_then(space_of_group) {
+                if sid != spaces.current {
+                    switch_to_space(spaces, sid)
+                        .unwrap_or_else(|e| eprintln!("While switching Space: {:?}", e));
+                }
+            }
+        }
+    }
+
+    #[cfg(not(feature = "spaces"))]
+    fn sync_spaces(&mut self, _shown_before: &HashMap<DisplayID, Option<u8>>) {}
+
+    /// Managed windows that are only on Spaces not being shown.
+    #[cfg(feature = "spaces")]
+    fn windows_on_hidden_spaces(&self) -> HashSet<uuid::Uuid> {
+        if !self.config.spaces.enabled {
+            return HashSet::new();
+        }
+        let current: HashSet<SpaceID> = match display_spaces() {
+            Ok(all) => all.iter().map(|spaces| spaces.current).collect(),
+            Err(e) => {
+                eprintln!("While listing Spaces: {:?}", e);
+                return HashSet::new();
+            }
+        };
+        self.displays
+            .values()
+            .flat_map(|ds| ds.groups.values())
+            .flat_map(|g| g.windows.iter())
+            .filter(|w| {
+                let sids = self.system.window_number(w).and_then(window_spaces);
+                sids.map_or(false, |sids| {
+                    !sids.is_empty() && !sids.iter().any(|sid| current.contains(sid))
+                })
+            })
+            .map(|w| *w.id())
+            .collect()
+    }
+
+    #[cfg(not(feature = "spaces"))]
+    fn windows_on_hidden_spaces(&self) -> HashSet<uuid::Uuid> {
+        HashSet::new()
+    }
+
+    /// The user switched Space: with `spaces.enabled`, show the group living
+    /// on the Space each display now shows.
+    #[cfg(feature = "spaces")]
+    pub fn space_changed(&mut self) -> Result<()> {
+        if !self.config.spaces.enabled {
+            return Ok(());
+        }
+        let all = display_spaces()?;
+        let mut changed = false;
+        for ds in self.displays.values_mut() {
+            let g_id = spaces_of_display(&all, ds.uuid.as_deref())
+                .and_then(|spaces| spaces.current_index())
+                .and_then(SpacesConfig::group_of_space);
+            if let Some(g_id) = g_id {
+                if ds.active_group != Some(g_id) {
+                    ds.set_active_group(g_id);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return Ok(());
+        }
+        // The new Space's windows may not have been listed before.
+        self.refresh_window_list()?;
+        self.relayout_all_displays()?;
+        self.update_status_window_content();
+        self.update_menu_bar();
+        self.notify_hooks();
+        Ok(())
+    }
+
+    #[cfg(not(feature = "spaces"))]
+    pub fn space_changed(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn set_menu_bar(&mut self, menu_bar: MenuBar) {
+        self.menu_bar = Some(menu_bar);
+        self.update_menu_bar();
+        self.notify_hooks();
+    }
+
+    /// Show the mode and active group in the menu bar, and list the groups
+    /// and their windows in its menu.
+    fn update_menu_bar(&self) {
+        let menu_bar = match &self.menu_bar {
+            Some(menu_bar) => menu_bar,
+            None => return,
+        };
+        let mode = match self.mode {
+            Mode::Insert => "\u{1f158}",
+            Mode::Normal | Mode::InsertNormal => "\u{1f15d}",
+            Mode::Switcher { .. } => "\u{1f162}",
+            Mode::Palette => "\u{1f15f}",
+            Mode::Passthrough => "\u{23f8}",
+        };
+        let active_group = self.get_active_display().and_then(|ds| ds.active_group);
+        let mut title = match active_group {
+            Some(g_id) => format!("{} {}", mode, self.group_label(g_id)),
+            None => mode.to_string(),
+        };
+        if self.event_tap_warning.is_some() {
+            title.insert_str(0, "\u{26a0} ");
+        }
+        if !self.urgent_windows.is_empty() {
+            title.push_str(" !");
+        }
+
+        let active_display_id = self.active_display_idx.map(|idx| self.display_ids[idx]);
+        let mut groups = vec![];
+        for display_id in self.display_ids.iter() {
+            let ds = match self.displays.get(display_id) {
+                Some(ds) => ds,
+                None => continue,
+            };
+            for g_id in 0..=9 {
+                if let Some(g) = ds.groups.get(&g_id) {
+                    groups.push(MenuGroup {
+                        display_id: *display_id,
+                        group: g_id,
+                        name: self.group_names.get(&g_id).cloned(),
+                        is_active: Some(*display_id) == active_display_id
+                            && ds.active_group == Some(g_id),
+                        windows: g
+                            .windows
+                            .iter()
+                            .map(|w| {
+                                let title = self.system.title(w).unwrap_or("<Unknown>".to_string());
+                                if self.urgent_windows.contains(w.id()) {
+                                    (*w.id(), format!("! {}", title))
+                                } else {
+                                    (*w.id(), title)
+                                }
+                            })
+                            .collect(),
+                    });
+                }
+            }
+        }
+        menu_bar.update(&title, &groups);
+    }
+
+    /// Focus a group or window picked from the menu bar.
+    pub fn focus_menu_target(&mut self, target: &MenuTarget) -> Result<()> {
+        match target {
+            MenuTarget::Group { display_id, group } => {
+                self.focus_location(*display_id, *group, None)
+            }
+            MenuTarget::Window {
+                display_id,
+                group,
+                window_id,
+            } => self.focus_location(*display_id, *group, Some(*window_id)),
+        }
+    }
+
+    /// Show a group and focus one of its windows, or its active window.
+    fn focus_location(
+        &mut self,
+        display_id: DisplayID,
+        g_id: u8,
+        window_id: Option<uuid::Uuid>,
+    ) -> Result<()> {
+        let w_idx = window_id.and_then(|window_id| {
+            self.displays
+                .get(&display_id)
+                .and_then(|ds| ds.groups.get(&g_id))
+                .and_then(|g| g.windows.iter().position(|w| *w.id() == window_id))
+        });
+        match w_idx {
+            Some(w_idx) => self.set_active_window_location(display_id, g_id, w_idx),
+            None => {
+                if let Some(ds) = self.displays.get_mut(&display_id) {
+                    ds.set_active_group(g_id);
+                }
+                self.active_display_idx =
+                    self.display_ids.iter().position(|d_id| *d_id == display_id);
+            }
+        }
+        self.bring_active_display_group_to_front()?;
+        self.activate_active_window()?;
+        self.relayout_active_display()?;
+        self.record_focus();
+        self.update_menu_bar();
+        self.update_status_tasklist();
+        self.notify_hooks();
+        if self.mode != Mode::Insert {
+            self.update_status_window_content();
+            self.highlight_active_window()?;
+        }
+        Ok(())
+    }
+
+    fn open_switcher(&mut self, all_groups: bool) {
+        let locations: Vec<(DisplayID, u8)> = if all_groups {
+            self.display_ids
+                .iter()
+                .flat_map(|display_id| (0..=9).map(move |g_id| (*display_id, g_id)))
+                .collect()
+        } else {
+            match self.get_active_display() {
+                Some(ds) => ds
+                    .active_group
+                    .map(|g_id| (ds.display_id, g_id))
+                    .into_iter()
+                    .collect(),
+                None => vec![],
+            }
+        };
+
+        let entries = self.switcher_entries(locations);
+        if entries.is_empty() {
+            return;
+        }
+
+        // Start on the window after the active one, like alt-tab.
+        let active_id = self.get_active_window().map(|w| *w.id());
+        let active_idx = entries
+            .iter()
+            .position(|e| Some(e.window_id) == active_id)
+            .unwrap_or(0);
+        let selected = (active_idx + 1) % entries.len();
+        self.switcher_restores = false;
+        self.show_switcher(entries, selected);
+    }
+
+    /// Open the switcher on the minimized windows, each listed under the
+    /// group it was minimized from, to restore the one picked.
+    fn open_minimized_switcher(&mut self) {
+        let fallback = self
+            .get_active_display()
+            .map(|ds| (ds.display_id, ds.active_group.unwrap_or(1)));
+        let mut entries = vec![];
+        for w in self.minimized_windows.clone() {
+            let (display_id, g_id) = match self.minimized_from.get(w.id()).copied().or(fallback) {
+                Some(origin) => origin,
+                None => continue,
+            };
+            entries.push(SwitcherEntry {
+                display_id,
+                group: g_id,
+                window_id: *w.id(),
+                title: self.system.title(&w).unwrap_or("<Unknown>".to_string()),
+                // Only if it was captured before it was minimized.
+                image: self.thumbnails.get(&*self.system, &w),
+            });
+        }
+        if entries.is_empty() {
+            return;
+        }
+        // Start on the last one minimized.
+        let selected = entries.len() - 1;
+        self.switcher_restores = true;
+        self.show_switcher(entries, selected);
+    }
+
+    fn show_switcher(&mut self, entries: Vec<SwitcherEntry>, selected: usize) {
+        self.mode_before_switcher = self.mode;
+        self.set_mode(Mode::Switcher {
+            release_to_select: self.mode == Mode::Insert,
+        });
+        self.switcher = Some(Switcher::open(
+            entries,
+            selected,
+            &self.active_display_bounds(),
+        ));
+    }
+
+    /// Show the windows of a group on the active display, to switch to it
+    /// when transient mode ends.
+    fn preview_group(&mut self, g_id: u8) {
+        // Close the previous preview before opening another.
+        self.group_preview = None;
+        let (display_id, selected) = match self.get_active_display() {
+            Some(ds) => (
+                ds.display_id,
+                ds.groups
+                    .get(&g_id)
+                    .and_then(|g| g.active_window_idx)
+                    .unwrap_or(0),
+            ),
+            None => return,
+        };
+        let entries = self.switcher_entries(vec![(display_id, g_id)]);
+        let preview = if entries.is_empty() {
+            None
+        } else {
+            Some(Switcher::open(
+                entries,
+                selected,
+                &self.active_display_bounds(),
+            ))
+        };
+        self.group_preview = Some((g_id, preview));
+    }
+
+    fn show_group(&mut self, g_id: u8) -> Result<()> {
+        self.set_active_display_group(g_id);
+        self.bring_active_display_group_to_front()?;
+        self.activate_active_window()?;
+        self.relayout_active_display()?;
+        self.update_status_window_content();
+        self.highlight_active_window()
+    }
+
+    /// The windows of each group in `locations`, with their thumbnails.
+    fn switcher_entries(&mut self, locations: Vec<(DisplayID, u8)>) -> Vec<SwitcherEntry> {
+        let mut entries = vec![];
+        for (display_id, g_id) in locations {
+            let g = match self
+                .displays
+                .get(&display_id)
+                .and_then(|ds| ds.groups.get(&g_id))
+            {
+                Some(g) => g,
+                None => continue,
+            };
+            for w in g.windows.iter() {
+                let image = self.thumbnails.get(&*self.system, w);
+                entries.push(SwitcherEntry {
+                    display_id,
+                    group: g_id,
+                    window_id: *w.id(),
+                    title: self.system.title(w).unwrap_or("<Unknown>".to_string()),
+                    image,
+                });
+            }
+        }
+        entries
+    }
+
+    /// Close the switcher and go back to the previous mode, returning the
+    /// location of the selected window.
+    fn close_switcher(&mut self) -> Option<(DisplayID, u8, uuid::Uuid)> {
+        let switcher = self.switcher.take()?;
+        self.set_mode(self.mode_before_switcher);
+        switcher
+            .selected()
+            .map(|e| (e.display_id, e.group, e.window_id))
+    }
+
+    /// Show each display's index and name on it for a couple of seconds.
+    fn identify_displays(&mut self) {
+        // Close the previous labels before showing new ones.
+        self.display_labels = None;
+        let labels: Vec<_> = self
+            .display_ids
+            .iter()
+            .enumerate()
+            .map(|(idx, display_id)| {
+                let name =
+                    display_name(*display_id).unwrap_or_else(|| format!("Display {}", display_id));
+                (
+                    self.system.display_bounds(*display_id),
+                    idx.to_string(),
+                    name,
+                )
+            })
+            .collect();
+        self.display_labels = Some(DisplayLabels::show(&labels, DISPLAY_LABEL_DURATION));
     }
 
-    fn get_active_display(&self) -> Option<&DisplayState> {
-        self.active_display_idx.and_then(|idx| {
-            let display_id = self.display_ids[idx];
-            self.displays.get(&display_id)
-        })
+    /// Feed a key press to the leader-key chords. Chords start in normal
+    /// mode, and in insert mode too if the leader has modifiers.
+    pub fn chord_key_down(&mut self, event: &CGEvent) -> ChordKey {
+        let can_start = match self.mode {
+            Mode::Normal => true,
+            Mode::Insert => self.chords.leader_has_modifiers(),
+            _ => false,
+        };
+        self.chords.key_down(KeyPress::of_event(event), can_start)
     }
 
-    fn get_active_display_mut(&mut self) -> Option<&mut DisplayState> {
-        self.active_display_idx.and_then(|idx| {
-            let display_id = self.display_ids[idx];
-            self.displays.get_mut(&display_id)
-        })
+    /// Feed a modifier change to the double-tap bindings, returning the
+    /// action to run if it completes a double tap.
+    pub fn double_tap_flags_changed(&mut self, event: &CGEvent) -> Option<Action> {
+        self.double_taps.flags_changed(event)
     }
 
-    fn get_active_window(&self) -> Option<&WindowWrapper<AXUIElement>> {
-        self.get_active_display()
-            .and_then(|ds| ds.get_active_window())
+    /// Note a key press or click, which interrupts a double tap.
+    pub fn interrupt_double_tap(&mut self) {
+        self.double_taps.interrupt();
     }
 
-    /// Create a window slightly larger than and behind the active window.
-    fn highlight_active_window(&mut self) -> Result<()> {
-        if let Some(w) = self.get_active_window() {
-            let f = w.frame()?;
-            let outset = 7.;
-            let pos = position_to_origin(&w)?;
-            let size = unsafe { mem::transmute::<CGSize, NSSize>(f.size) };
-            let rect = NSRect::new(pos, size).inset(-outset, -outset);
-            match self.highlight_overlay_window {
-                None => unsafe {
-                    let overlay = NSWindow::alloc(nil);
-                    overlay.initWithContentRect_styleMask_backing_defer_(
-                        rect,
-                        NSWindowStyleMask::empty(),
-                        NSBackingStoreBuffered,
-                        false,
-                    );
-                    overlay.setBackgroundColor_(NSColor::systemRedColor(nil));
-                    overlay.setAlphaValue_(0.7);
-                    overlay.makeKeyAndOrderFront_(nil);
-                    self.highlight_overlay_window = Some(overlay);
-                },
-                Some(overlay) => {
-                    unsafe {
-                        overlay.setContentSize_(rect.size);
-                        overlay.setFrameOrigin_(rect.origin);
-                        overlay.setContentSize_(rect.size);
-                        overlay.makeKeyAndOrderFront_(nil);
-                    };
-                }
-            }
-        }
-        self.bring_status_window_to_front();
-        Ok(())
+    /// The action bound to this key press in `bindings.keys`, if any.
+    pub fn key_binding(&self, event: &CGEvent) -> Option<Action> {
+        self.key_bindings
+            .action(KeyPress::of_event(event), self.mode)
     }
 
-    fn close_highlight_window(&mut self) {
-        if let Some(window) = self.highlight_overlay_window {
-            unsafe {
-                window.close();
-            };
-            self.highlight_overlay_window = None;
+    fn open_palette(&mut self) {
+        if self.palette.is_none() {
+            self.mode_before_palette = self.mode;
         }
+        self.set_mode(Mode::Palette);
+        self.palette = Some(Palette::open(&self.active_display_bounds()));
     }
 
-    fn describe_displays(&self) -> String {
-        let mut content = String::new();
+    fn close_palette(&mut self) {
+        if self.palette.take().is_some() {
+            self.set_mode(self.mode_before_palette);
+        }
+    }
 
-        let mut is_first = true;
+    /// In passthrough mode, events should reach apps untouched.
+    pub fn is_passthrough(&self) -> bool {
+        self.mode == Mode::Passthrough
+    }
 
-        for (display_id, display) in self.displays.iter() {
-            let display_is_active = self
-                .active_display_idx
-                .map_or(false, |idx| self.display_ids[idx] == *display_id);
-            if is_first {
-                is_first = false;
-            } else {
-                content.push('\n');
-            }
-            if display_is_active {
-                content.push_str("[x] ");
-            } else {
-                content.push_str("[ ] ");
+    /// Whether the frontmost app has a `passthrough` rule, so key presses
+    /// should reach it untouched. Rules are only matched again when the
+    /// frontmost app changes.
+    pub fn frontmost_app_passes_through(&self) -> bool {
+        if self.config.rules.is_empty() {
+            return false;
+        }
+        unsafe {
+            let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+            let app: id = msg_send![workspace, frontmostApplication];
+            if app == nil {
+                return false;
             }
-            content.push_str(&format!("Display {}", display_id));
-            for group_id in 0..=9 {
-                if let Some(group) = display.groups.get(&group_id) {
-                    content.push_str("\n  ");
-                    let group_is_active = display.active_group.map_or(false, |id| id == group_id);
-                    if display_is_active && group_is_active {
-                        content.push_str("[x] ");
-                    } else {
-                        content.push_str("[ ] ");
-                    }
-                    content.push_str(&format!("Group {} ({})", group_id, group.layout));
-                    let iter = group.windows.iter().enumerate();
-                    let iter: Box<dyn Iterator<Item = _>> = match group.layout {
-                        Layout::TileHorizontal(_) => Box::new(iter),
-                        Layout::Cascade | Layout::Floating => Box::new(iter.rev()),
-                    };
-                    for (i, window) in iter {
-                        content.push_str("\n    ");
-                        let window_is_active =
-                            group.active_window_idx.map_or(false, |idx| idx == i);
-                        if display_is_active && group_is_active && window_is_active {
-                            content.push_str("[x] ");
-                        } else {
-                            content.push_str("[ ] ");
-                        }
-                        let title = window
-                            .element()
-                            .title()
-                            .map(|cfstring| cfstring.to_string())
-                            .unwrap_or("<Unkown>".to_string());
-                        let title: String = title.chars().take(45).collect();
-                        content.push_str(&format!("{}", title));
-                    }
-                } else if Some(group_id) == display.active_group {
-                    // Group is active, but contains no windows
-                    content.push_str(&format!("\n  [x] Group {}", group_id));
+            let pid: i32 = msg_send![app, processIdentifier];
+            match self.passthrough_app.get() {
+                Some((last_pid, passes_through)) if last_pid == pid => passes_through,
+                _ => {
+                    let bundle_id: id = msg_send![app, bundleIdentifier];
+                    let name: id = msg_send![app, localizedName];
+                    let passes_through = self.config.passthrough_for(
+                        nsstring_to_string(bundle_id).as_deref(),
+                        nsstring_to_string(name).as_deref(),
+                    );
+                    self.passthrough_app.set(Some((pid, passes_through)));
+                    passes_through
                 }
             }
         }
-        content
     }
 
-    fn update_status_window_content(&self) {
-        if let Some((_window, text_field)) = self.status_window {
-            unsafe {
-                let text = NSString::alloc(nil).init_str(&self.describe_displays());
-                text_field.setStringValue_(text);
-            }
-        }
+    /// Whether key presses that aren't bound to an action should still be
+    /// kept from the focused app.
+    pub fn captures_keyboard(&self) -> bool {
+        self.mode == Mode::Palette
     }
 
     fn open_status_window(&mut self) {
         self.close_status_window();
 
-        let rect = NSRect::new(NSPoint::new(0., 0.), NSSize::new(300., 300.));
+        let bounds = self.active_display_bounds();
+        let scale = overlay_scale(&bounds);
+        let rect = NSRect::new(
+            NSPoint::new(0., 0.),
+            NSSize::new(STATUS_WINDOW_WIDTH * scale, STATUS_WINDOW_HEIGHT * scale),
+        );
         unsafe {
-            let window = NSWindow::alloc(nil);
-            window.initWithContentRect_styleMask_backing_defer_(
+            // A non-activating panel never takes key status from the window
+            // being manipulated.
+            let window: id = msg_send![class!(NSPanel), alloc];
+            let window = window.initWithContentRect_styleMask_backing_defer_(
                 rect,
-                NSWindowStyleMask::NSTitledWindowMask | NSWindowStyleMask::NSClosableWindowMask,
+                NSWindowStyleMask::NSTitledWindowMask
+                    | NSWindowStyleMask::NSClosableWindowMask
+                    | NSWindowStyleMask::NSNonactivatingPanelMask,
                 NSBackingStoreBuffered,
-                false,
+                NO,
             );
+            let () = msg_send![window, setBecomesKeyOnlyIfNeeded: YES];
+            let () = msg_send![window, setHidesOnDeactivate: NO];
+            window.setLevel_(FLOATING_WINDOW_LEVEL);
             let title = NSString::alloc(nil).init_str("Window Manager");
             window.setTitle_(title);
             window.setAlphaValue_(0.7);
-            window.center();
+            center_on(window, &bounds);
 
+            let tasklist_height = match self.tasklist_handler {
+                Some(_) => STATUS_TASKLIST_HEIGHT * scale,
+                None => 0.,
+            };
             let text_field = NSTextField::alloc(nil);
-            NSTextField::initWithFrame_(text_field, rect);
+            NSTextField::initWithFrame_(
+                text_field,
+                NSRect::new(
+                    NSPoint::new(0., tasklist_height),
+                    NSSize::new(rect.size.width, rect.size.height - tasklist_height),
+                ),
+            );
             text_field.setEditable_(false);
-            window.contentView().addSubview_(text_field);
+            let font: id = msg_send![class!(NSFont), systemFontOfSize: STATUS_FONT_SIZE * scale];
+            let () = msg_send![text_field, setFont: font];
+            let window = OverlayWindow::new(window);
+            window.content_view().addSubview_(text_field);
+            let () = msg_send![text_field, release];
+
+            self.status_tasklist = self.tasklist_handler.as_ref().map(|handler| {
+                let tasklist = Tasklist::new(
+                    NSRect::new(
+                        NSPoint::new(0., 0.),
+                        NSSize::new(rect.size.width, tasklist_height),
+                    ),
+                    scale,
+                    handler,
+                );
+                window.content_view().addSubview_(tasklist.view());
+                tasklist
+            });
+            window.order_front();
 
             self.status_window = Some((window, text_field));
             self.update_status_window_content();
+            self.update_status_tasklist();
+        }
+    }
+
+    /// Clicking a thumbnail in the status window calls `handler`, which
+    /// should focus the window with `focus_menu_target`.
+    pub fn set_tasklist_handler(&mut self, handler: TasklistHandler) {
+        self.tasklist_handler = Some(handler);
+    }
+
+    /// Show the active group's windows in the status window, capturing
+    /// their thumbnails again if they are old.
+    pub fn update_status_tasklist(&mut self) {
+        if self.status_tasklist.is_none() {
+            return;
+        }
+        let (display_id, g_id, windows, active_id) = match self.get_active_display() {
+            Some(ds) => match ds
+                .active_group
+                .and_then(|g_id| Some((g_id, ds.groups.get(&g_id)?)))
+            {
+                Some((g_id, g)) => (
+                    ds.display_id,
+                    g_id,
+                    g.windows.clone(),
+                    g.get_active_window().map(|w| *w.id()),
+                ),
+                None => return,
+            },
+            None => return,
+        };
+        let entries = windows
+            .iter()
+            .map(|w| TasklistEntry {
+                target: MenuTarget::Window {
+                    display_id,
+                    group: g_id,
+                    window_id: *w.id(),
+                },
+                title: self.system.title(w).unwrap_or_default(),
+                image: self.thumbnails.get(&*self.system, w),
+                is_active: Some(*w.id()) == active_id,
+            })
+            .collect();
+        if let Some(tasklist) = &self.status_tasklist {
+            tasklist.update(entries);
         }
     }
 
     fn bring_status_window_to_front(&self) {
-        if let Some((window, _)) = self.status_window {
-            unsafe {
-                window.orderFrontRegardless();
-            };
+        if let Some((window, _)) = &self.status_window {
+            window.order_front();
         }
     }
 
     fn close_status_window(&mut self) {
-        match self.status_window {
-            Some((window, _)) => {
-                unsafe {
-                    window.close();
-                };
-                self.status_window = None;
-            }
-            None => (),
-        }
+        self.status_tasklist = None;
+        self.status_window = None;
     }
 
     fn activate_active_window(&self) -> Result<()> {
+        if let Some(deferred) = &self.deferred_updates {
+            deferred.activate.set(true);
+            return Ok(());
+        }
+        self.pending_activation.set(None);
         if let Some(w) = self.get_active_window() {
             eprintln!("Activate window {:?}", w);
-            w.activate()?;
+            activate_window(&*self.system, w, &self.config)?;
         }
         Ok(())
     }
 
+    /// Activate and highlight the active window once there have been no
+    /// more window motions for `activation.debounce` seconds.
+    fn activate_active_window_debounced(&mut self) -> Result<()> {
+        if self.last_repeat.is_some() {
+            // Done once the key is released, in `end_key_repeat`.
+            return Ok(());
+        }
+        let debounce = self.config.activation.debounce;
+        if debounce <= 0. || self.deferred_updates.is_some() {
+            self.activate_active_window()?;
+            return self.highlight_active_window();
+        }
+        self.pending_activation
+            .set(Some(Instant::now() + Duration::from_secs_f64(debounce)));
+        self.schedule_wake_up();
+        Ok(())
+    }
+
+    /// `wake_up` calls `handler` to arrange for it to be called again, when
+    /// the work put off by debouncing is due.
+    pub fn set_wake_handler(&mut self, handler: WakeHandler) {
+        self.wake_handler = Some(handler);
+    }
+
+    /// When the work put off by debouncing, or a refresh, is next due.
+    fn next_wake_up(&self) -> Option<Instant> {
+        [
+            self.pending_activation.get(),
+            self.relayouts.due.get(),
+            self.last_repeat.map(|at| at + KEY_REPEAT_TIMEOUT),
+            self.next_refresh_poll(),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Arrange for `wake_up` to be called when the work put off by
+    /// debouncing, or a refresh, is next due, replacing the call arranged
+    /// before.
+    fn schedule_wake_up(&self) {
+        let handler = match &self.wake_handler {
+            Some(handler) => handler,
+            None => return,
+        };
+        let due = self.next_wake_up();
+        let mut wake_timer = self.wake_timer.borrow_mut();
+        if matches!((&*wake_timer, due), (Some((at, _)), Some(due)) if *at == due) {
+            return;
+        }
+        if let Some((_, timer)) = wake_timer.take() {
+            timer.invalidate();
+        }
+        if let Some(due) = due {
+            let delay = due.saturating_duration_since(Instant::now());
+            *wake_timer = Some((due, (handler.0)(delay.as_secs_f64())));
+        }
+    }
+
+    /// Do the activation, relayouts and catching up after held keys that
+    /// are due, and check on a refresh. Called by the timer
+    /// `schedule_wake_up` arranges.
+    pub fn wake_up(&mut self) {
+        *self.wake_timer.get_mut() = None;
+        self.poll_refresh()
+            .unwrap_or_else(|e| eprintln!("While refreshing window list: {:?}", e));
+        self.activate_pending_window()
+            .unwrap_or_else(|e| eprintln!("While activating window: {:?}", e));
+        self.relayout_pending_displays()
+            .unwrap_or_else(|e| eprintln!("While laying out displays: {:?}", e));
+        self.schedule_wake_up();
+    }
+
+    /// Do the activation put off by `activate_active_window_debounced`, once
+    /// it is due.
+    fn activate_pending_window(&mut self) -> Result<()> {
+        if self
+            .last_repeat
+            .map_or(false, |at| at.elapsed() >= KEY_REPEAT_TIMEOUT)
+        {
+            // We missed the key's release.
+            self.end_key_repeat()?;
+        }
+        match self.pending_activation.get() {
+            Some(due) if Instant::now() >= due => {
+                self.activate_active_window()?;
+                self.highlight_active_window()
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn bring_active_display_group_to_front(&self) -> Result<()> {
         if let Some(d) = self.get_active_display() {
-            d.bring_active_group_to_front()?;
+            d.bring_active_group_to_front(&self.config)?;
         }
         Ok(())
     }
@@ -928,7 +4168,12 @@ impl WindowManager {
     fn next_group_id_with_windows(&self) -> Option<u8> {
         if let Some(ds) = self.get_active_display() {
             if let Some(active_gid) = ds.active_group {
-                let mut g_ids: Vec<_> = ds.groups.keys().collect();
+                let mut g_ids: Vec<_> = ds
+                    .groups
+                    .iter()
+                    .filter(|(_, g)| !g.windows.is_empty())
+                    .map(|(g_id, _)| g_id)
+                    .collect();
                 g_ids.sort();
                 return match g_ids.iter().skip_while(|&&&id| id <= active_gid).next() {
                     Some(&&next_g_id) => Some(next_g_id),
@@ -957,7 +4202,12 @@ impl WindowManager {
     fn prev_group_id_with_windows(&self) -> Option<u8> {
         if let Some(ds) = self.get_active_display() {
             if let Some(active_gid) = ds.active_group {
-                let mut g_ids: Vec<_> = ds.groups.keys().collect();
+                let mut g_ids: Vec<_> = ds
+                    .groups
+                    .iter()
+                    .filter(|(_, g)| !g.windows.is_empty())
+                    .map(|(g_id, _)| g_id)
+                    .collect();
                 g_ids.sort();
                 return match g_ids
                     .iter()
@@ -995,131 +4245,594 @@ impl WindowManager {
         }
     }
 
-    fn toggle_active_window_in_group(&mut self, g_id: u8) {
-        if let Some(ds) = self.get_active_display_mut() {
-            ds.toggle_active_window_in_group(g_id)
+    fn toggle_active_window_in_group(&mut self, g_id: u8) {
+        if let Some(ds) = self.get_active_display_mut() {
+            ds.toggle_active_window_in_group(g_id)
+        }
+    }
+
+    fn move_active_window_to_next_display(&mut self) {
+        match self.next_display_idx() {
+            Some(next_display_idx) => self.move_active_window_to_display_idx(next_display_idx),
+            None => (),
+        }
+    }
+
+    fn move_active_window_to_prev_display(&mut self) {
+        match self.prev_display_idx() {
+            Some(prev_display_idx) => self.move_active_window_to_display_idx(prev_display_idx),
+            None => (),
+        }
+    }
+
+    /// Swap the active window with the window at the same index in the active
+    /// group of the next display. Both groups keep their layouts.
+    fn swap_active_window_with_next_display(&mut self) {
+        let (display_idx, next_display_idx) =
+            match (self.active_display_idx, self.next_display_idx()) {
+                (Some(idx), Some(next_idx)) if idx != next_idx => (idx, next_idx),
+                _ => return,
+            };
+        let display_id = self.display_ids[display_idx];
+        let next_display_id = self.display_ids[next_display_idx];
+
+        let w_idx = match self
+            .get_active_display()
+            .and_then(|ds| ds.get_active_group())
+            .and_then(|g| g.active_window_idx)
+        {
+            Some(w_idx) => w_idx,
+            None => return,
+        };
+        let other = match self
+            .displays
+            .get(&next_display_id)
+            .and_then(|ds| ds.get_active_group_window(w_idx))
+        {
+            Some(w) => w.clone(),
+            None => return,
+        };
+
+        let this = self
+            .displays
+            .get_mut(&display_id)
+            .and_then(|ds| ds.replace_active_group_window(w_idx, other));
+        if let Some(this) = this {
+            if let Some(ds) = self.displays.get_mut(&next_display_id) {
+                ds.replace_active_group_window(w_idx, this);
+                if let Some(g) = ds.get_active_group_mut() {
+                    g.active_window_idx = Some(w_idx);
+                }
+            }
+        }
+    }
+
+    /// Move the active group to the display at `display_idx` and show it
+    /// there, keeping its layout and window order. It keeps its number
+    /// unless that display already has windows in a group with that number.
+    fn move_active_group_to_display_idx(&mut self, display_idx: usize) {
+        let display_id = match self.display_ids.get(display_idx) {
+            Some(display_id) => *display_id,
+            None => return,
+        };
+        let (from_display_id, g_id) = match self.get_active_display() {
+            Some(ds) if ds.display_id != display_id => {
+                match ds.active_group.filter(|_| ds.get_active_window().is_some()) {
+                    Some(g_id) => (ds.display_id, g_id),
+                    None => return,
+                }
+            }
+            _ => return,
+        };
+        let (system, group_defaults) = (&self.system, &self.group_defaults);
+        let to_g_id = match self
+            .displays
+            .entry(display_id)
+            .or_insert_with(|| {
+                DisplayState::new(display_id, system.clone(), group_defaults.clone())
+            })
+            .free_group_id(g_id)
+        {
+            Some(g_id) => g_id,
+            None => return,
+        };
+        let g = self
+            .displays
+            .get_mut(&from_display_id)
+            .and_then(|ds| ds.take_active_group());
+        if let (Some(g), Some(ds)) = (g, self.displays.get_mut(&display_id)) {
+            ds.put_group(to_g_id, g);
+        }
+    }
+
+    fn move_active_group_to_next_display(&mut self) {
+        if let Some(next_display_idx) = self.next_display_idx() {
+            self.move_active_group_to_display_idx(next_display_idx)
+        }
+    }
+
+    fn move_active_group_to_prev_display(&mut self) {
+        if let Some(prev_display_idx) = self.prev_display_idx() {
+            self.move_active_group_to_display_idx(prev_display_idx)
+        }
+    }
+
+    /// Swap the active groups of the active display and the next display.
+    /// Each display keeps its group numbers; the groups' windows, layouts and
+    /// settings change places.
+    fn swap_active_group_with_next_display(&mut self) {
+        let (display_id, next_display_id) = match (self.active_display_idx, self.next_display_idx())
+        {
+            (Some(idx), Some(next_idx)) if idx != next_idx => {
+                (self.display_ids[idx], self.display_ids[next_idx])
+            }
+            _ => return,
+        };
+        let active_group = |ds: &DisplayState| ds.active_group;
+        let (g_id, next_g_id) = match (
+            self.displays.get(&display_id).and_then(active_group),
+            self.displays.get(&next_display_id).and_then(active_group),
+        ) {
+            (Some(g_id), Some(next_g_id)) => (g_id, next_g_id),
+            _ => return,
+        };
+        let g = self
+            .displays
+            .get_mut(&display_id)
+            .and_then(|ds| ds.take_active_group());
+        let next_g = self
+            .displays
+            .get_mut(&next_display_id)
+            .and_then(|ds| ds.take_active_group());
+        if let (Some(g), Some(ds)) = (g, self.displays.get_mut(&next_display_id)) {
+            ds.put_group(next_g_id, g);
+        }
+        if let (Some(next_g), Some(ds)) = (next_g, self.displays.get_mut(&display_id)) {
+            ds.put_group(g_id, next_g);
+        }
+    }
+
+    /// The active window's app's windows on every display, starting with the
+    /// active window.
+    fn active_app_window_ids(&self) -> Vec<uuid::Uuid> {
+        let active = match self.get_active_window() {
+            Some(w) => w,
+            None => return vec![],
+        };
+        let pid = match self.system.pid(active) {
+            Ok(pid) => pid,
+            Err(_) => return vec![*active.id()],
+        };
+        let mut ids = vec![*active.id()];
+        for ds in self.displays.values() {
+            for g in ds.groups.values() {
+                for w in g.windows.iter() {
+                    if !ids.contains(w.id()) && self.system.pid(w).map_or(false, |p| p == pid) {
+                        ids.push(*w.id());
+                    }
+                }
+            }
+        }
+        ids
+    }
+
+    /// The active app's next (or previous) window after the active window, in
+    /// display, group and window order.
+    fn app_window_after(&self, forward: bool) -> Option<uuid::Uuid> {
+        let active = self.get_active_window()?;
+        let pid = self.system.pid(active).ok()?;
+        let mut ids = vec![];
+        for display_id in self.display_ids.iter() {
+            let ds = match self.displays.get(display_id) {
+                Some(ds) => ds,
+                None => continue,
+            };
+            let mut g_ids: Vec<_> = ds.groups.keys().copied().collect();
+            g_ids.sort();
+            for g_id in g_ids {
+                for w in ds.groups[&g_id].windows.iter() {
+                    if self.system.pid(w).map_or(false, |p| p == pid) {
+                        ids.push(*w.id());
+                    }
+                }
+            }
+        }
+        let idx = ids.iter().position(|id| id == active.id())?;
+        let next = if forward {
+            (idx + 1) % ids.len()
+        } else {
+            (idx + ids.len() - 1) % ids.len()
+        };
+        Some(ids[next]).filter(|id| id != active.id())
+    }
+
+    /// Take the windows with these ids out of every group on every display.
+    fn take_windows(&mut self, ids: &[uuid::Uuid]) -> Vec<WindowHandle> {
+        ids.iter()
+            .filter_map(|w_id| {
+                self.displays
+                    .values_mut()
+                    .filter_map(|ds| ds.remove_window(w_id))
+                    .last()
+            })
+            .collect()
+    }
+
+    /// Move the active window's app's windows into group `g_id` of the active
+    /// display, or its active group if `g_id` is `None`.
+    fn move_active_app_to_group(&mut self, g_id: Option<u8>, follow: bool) {
+        let ids = self.active_app_window_ids();
+        let windows = self.take_windows(&ids);
+        if let Some(ds) = self.get_active_display_mut() {
+            let g_id = match g_id.or(ds.active_group) {
+                Some(g_id) => g_id,
+                None => return,
+            };
+            ds.insert_windows_in_group(g_id, windows);
+            if follow {
+                ds.set_active_group(g_id);
+            }
+        }
+    }
+
+    /// Float the active window's app's windows, or tile them all again if
+    /// they already all float.
+    fn toggle_active_app_floating(&mut self) {
+        let ids = self.active_app_window_ids();
+        if ids.iter().all(|w_id| self.floating_windows.contains(w_id)) {
+            for w_id in ids.iter() {
+                self.floating_windows.remove(w_id);
+            }
+        } else {
+            self.floating_windows.extend(ids);
+        }
+    }
+
+    /// Change the active window's frame with `f`, if the window floats.
+    fn adjust_active_floating_frame(&mut self, f: impl FnOnce(&mut CGRect)) -> Result<()> {
+        let floats = matches!(
+            self.get_active_display().and_then(|ds| ds.layout()),
+            Some(Layout::Floating)
+        );
+        let w = match self.get_active_window() {
+            Some(w) if floats || self.floating_windows.contains(w.id()) => w.clone(),
+            _ => return Ok(()),
+        };
+        let mut frame = self.system.frame(&w)?;
+        f(&mut frame);
+        self.system.set_frame(&w, frame)?;
+        // Otherwise the next relayout would put it back.
+        self.floating_frames.remember(&*self.system, &w)
+    }
+
+    fn nudge_active_window(&mut self, direction: Direction) -> Result<()> {
+        let step = self.config.floating.nudge_step;
+        self.adjust_active_floating_frame(|frame| match direction {
+            Direction::Left => frame.origin.x -= step,
+            Direction::Right => frame.origin.x += step,
+            Direction::Up => frame.origin.y -= step,
+            Direction::Down => frame.origin.y += step,
+        })
+    }
+
+    /// Move the active window's right or bottom edge in `direction`.
+    fn resize_active_floating_window(&mut self, direction: Direction) -> Result<()> {
+        let step = self.config.floating.resize_step;
+        self.adjust_active_floating_frame(|frame| match direction {
+            Direction::Left => frame.size.width = f64::max(frame.size.width - step, step),
+            Direction::Right => frame.size.width += step,
+            Direction::Up => frame.size.height = f64::max(frame.size.height - step, step),
+            Direction::Down => frame.size.height += step,
+        })
+    }
+
+    fn set_active_window_full(&self) -> Result<()> {
+        if let Some(window) = self.get_active_window() {
+            let display_id = self.system.display_of(window)?;
+            self.system
+                .set_frame(window, self.system.display_bounds(display_id))?;
+        }
+        Ok(())
+    }
+
+    /// Move the active window to `region` of its display. Snapping a window
+    /// to the same region again steps it through `snap.cycle`'s sizes, then
+    /// pushes it past the display edge the region is against, into the
+    /// opposite region of the next display over. With no display there, the
+    /// cycle starts again.
+    fn snap_active_window(&mut self, region: SnapRegion) -> Result<()> {
+        let window = match self.get_active_window() {
+            Some(window) => window,
+            None => return Ok(()),
+        };
+        let w_id = *window.id();
+        let sizes = &self.config.snap.cycle;
+        let d = self.system.display_bounds(self.system.display_of(window)?);
+        let w = self.system.frame(window)?;
+        let current = self
+            .snapped_windows
+            .get(&w_id)
+            .filter(|(r, idx)| {
+                *r == region && *idx < sizes.len() && same_frame(&w, &r.frame(&d, &w, sizes[*idx]))
+            })
+            .map(|(_, idx)| *idx);
+        let mut idx = current.map_or(0, |idx| idx + 1);
+        if region == SnapRegion::Center {
+            idx = 0;
+        }
+        if idx < sizes.len() {
+            self.system
+                .set_frame(window, region.frame(&d, &w, sizes[idx]))?;
+            self.snapped_windows.insert(w_id, (region, idx));
+            self.remember_floating_frame(&w_id);
+            return Ok(());
+        }
+
+        let next_display = match region.edge() {
+            Some(edge) => {
+                let (mid_x, mid_y) = (
+                    d.origin.x + d.size.width / 2.,
+                    d.origin.y + d.size.height / 2.,
+                );
+                let pos = match edge {
+                    Direction::Left => CGPoint::new(d.origin.x - 1., mid_y),
+                    Direction::Right => CGPoint::new(d.origin.x + d.size.width + 1., mid_y),
+                    Direction::Up => CGPoint::new(mid_x, d.origin.y - 1.),
+                    Direction::Down => CGPoint::new(mid_x, d.origin.y + d.size.height + 1.),
+                };
+                self.system.display_at_point(&pos)?
+            }
+            None => None,
+        };
+        match next_display {
+            Some(display_id) => {
+                let d = self.system.display_bounds(display_id);
+                let region = region.opposite();
+                self.system
+                    .set_frame(window, region.frame(&d, &w, sizes[0]))?;
+                self.snapped_windows.insert(w_id, (region, 0));
+                if let Some(ds) = self.get_active_display_mut() {
+                    if let Some(w) = ds.pop_active_window() {
+                        let display_id = self.system.display_of(&w)?;
+                        self.insert_open_window(w, display_id);
+                        self.active_display_idx =
+                            self.display_ids.iter().position(|d_id| *d_id == display_id);
+                    }
+                }
+            }
+            None => {
+                self.system
+                    .set_frame(window, region.frame(&d, &w, sizes[0]))?;
+                self.snapped_windows.insert(w_id, (region, 0));
+            }
+        }
+        self.remember_floating_frame(&w_id);
+        Ok(())
+    }
+
+    /// Minimize `window` into the Dock, with its animation. There is no API
+    /// to skip the animation for one window, so with
+    /// `minimize.suppress_animation` the window is parked in the bottom-right
+    /// corner of its display instead, remembering where it was.
+    fn minimize_window(&mut self, window: &WindowHandle) -> Result<()> {
+        if !self.config.minimize.suppress_animation {
+            return self.system.set_minimized(window, true);
         }
+        let frame = self.system.frame(window)?;
+        let d = self.system.display_bounds(self.system.display_of(window)?);
+        // macOS keeps part of every window on screen: leave it a corner.
+        let corner = CGPoint::new(
+            d.origin.x + d.size.width - 1.,
+            d.origin.y + d.size.height - 1.,
+        );
+        self.system.set_position(window, corner)?;
+        self.parked_windows.insert(*window.id(), frame);
+        Ok(())
     }
 
-    fn move_active_window_to_next_display(&mut self) {
-        match self.next_display_idx() {
-            Some(next_display_idx) => self.move_active_window_to_display_idx(next_display_idx),
-            None => (),
+    /// Undo `minimize_window`.
+    fn restore_window(&mut self, window: &WindowHandle) -> Result<()> {
+        match self.parked_windows.remove(window.id()) {
+            Some(frame) => self.system.set_frame(window, frame),
+            None => self.system.set_minimized(window, false),
         }
     }
 
-    fn move_active_window_to_prev_display(&mut self) {
-        match self.prev_display_idx() {
-            Some(prev_display_idx) => self.move_active_window_to_display_idx(prev_display_idx),
-            None => (),
+    /// Put every parked window back where it was, e.g. before handing over
+    /// to another instance, which wouldn't know to.
+    fn unpark_windows(&mut self) {
+        for (w_id, frame) in mem::take(&mut self.parked_windows) {
+            if let Some(w) = self.minimized_windows.iter().find(|w| *w.id() == w_id) {
+                self.system
+                    .set_frame(w, frame)
+                    .unwrap_or_else(|e| eprintln!("While unparking window: {:?}", e));
+            }
         }
     }
 
-    fn set_active_window_full(&self) -> Result<()> {
-        if let Some(window) = self.get_active_window() {
-            let display = window.display()?;
-            window.set_frame(display.bounds())?;
+    fn minimize_active_window(&mut self) -> Result<()> {
+        let origin = self
+            .get_active_display()
+            .and_then(|ds| ds.active_group.map(|g_id| (ds.display_id, g_id)));
+        let window = self
+            .get_active_display_mut()
+            .and_then(|ds| ds.pop_active_window());
+        match window {
+            Some(window) => {
+                self.minimize_window(&window)?;
+                if let Some(origin) = origin {
+                    self.minimized_from.insert(*window.id(), origin);
+                }
+                self.minimized_windows.push(window);
+                Ok(())
+            }
+            None => Ok(()),
         }
-        Ok(())
     }
 
-    fn set_active_window_left(&mut self) -> Result<()> {
-        if let Some(window) = self.get_active_window() {
-            let d = window.display()?.bounds();
-            let w = window.frame()?;
-            if d.origin.x > 0. && w.origin.x == d.origin.x && w.size.width == d.size.width / 2. {
-                // Already at left: move to previous display.
-                let pos = CGPoint::new(d.origin.x - 1.0, d.origin.y);
-                let (displays, _) =
-                    CGDisplay::displays_with_point(pos, 1).map_err(|e| CGErrorWrapper(e))?;
-
-                if let Some(display_id) = displays.first() {
-                    let d = CGDisplay::new(*display_id).bounds();
-                    window.set_frame(CGRect::new(
-                        &CGPoint::new(d.origin.x + d.size.width / 2., d.origin.y),
-                        &CGSize::new(d.size.width / 2., d.size.height),
-                    ))?;
-                    if let Some(ds) = self.get_active_display_mut() {
-                        if let Some(w) = ds.pop_active_window() {
-                            let display_id = w.display()?.id;
-                            self.insert_open_window(w, display_id);
-                            self.active_display_idx =
-                                self.display_ids.iter().position(|d_id| *d_id == display_id);
-                        }
+    /// Minimize every window in the active group. They are restored in the
+    /// same order by `restore_active_group`.
+    fn minimize_active_group(&mut self) -> Result<()> {
+        let (origin, windows) = match self.get_active_display_mut() {
+            Some(ds) => {
+                let display_id = ds.display_id;
+                match (ds.active_group, ds.get_active_group_mut()) {
+                    (Some(g_id), Some(g)) => {
+                        g.active_window_idx = None;
+                        ((display_id, g_id), mem::take(&mut g.windows))
                     }
+                    _ => return Ok(()),
+                }
+            }
+            None => return Ok(()),
+        };
+        let mut kept = vec![];
+        for window in windows {
+            match self.minimize_window(&window) {
+                Ok(()) => {
+                    self.minimized_from.insert(*window.id(), origin);
+                    self.minimized_windows.push(window);
+                }
+                Err(e) => {
+                    eprintln!("While minimizing {:?}: {:?}", window, e);
+                    kept.push(window);
                 }
-            } else {
-                window.set_frame(CGRect::new(
-                    &d.origin,
-                    &CGSize::new(d.size.width / 2., d.size.height),
-                ))?;
             }
         }
+        if let Some(g) = self
+            .get_active_display_mut()
+            .and_then(|ds| ds.get_active_group_mut())
+        {
+            g.active_window_idx = if kept.is_empty() { None } else { Some(0) };
+            g.windows = kept;
+        }
         Ok(())
     }
 
-    fn set_active_window_right(&mut self) -> Result<()> {
-        if let Some(window) = self.get_active_window() {
-            let d = window.display()?.bounds();
-            let w = window.frame()?;
-            if w.origin.x == d.origin.x + d.size.width / 2. && w.size.width == d.size.width / 2. {
-                let pos = CGPoint::new(d.origin.x + d.size.width + 1.0, d.origin.y);
-                let (displays, _) =
-                    CGDisplay::displays_with_point(pos, 1).map_err(CGErrorWrapper)?;
-                if let Some(display_id) = displays.first() {
-                    let d = CGDisplay::new(*display_id).bounds();
-                    window.set_frame(CGRect::new(
-                        &d.origin,
-                        &CGSize::new(d.size.width / 2., d.size.height),
-                    ))?;
-                    if let Some(ds) = self.get_active_display_mut() {
-                        if let Some(w) = ds.pop_active_window() {
-                            let display_id = w.display()?.id;
-                            self.insert_open_window(w, display_id);
-                            self.active_display_idx =
-                                self.display_ids.iter().position(|d_id| *d_id == display_id);
-                        }
-                    }
+    /// Restore every window minimized from the active group, in the order
+    /// they had in it, before its other windows.
+    fn restore_active_group(&mut self) -> Result<()> {
+        let origin = match self
+            .get_active_display()
+            .and_then(|ds| ds.active_group.map(|g_id| (ds.display_id, g_id)))
+        {
+            Some(origin) => origin,
+            None => return Ok(()),
+        };
+        let ids: Vec<uuid::Uuid> = self
+            .minimized_windows
+            .iter()
+            .filter(|w| self.minimized_from.get(w.id()) == Some(&origin))
+            .map(|w| *w.id())
+            .collect();
+        // Each is put first in the group.
+        for w_id in ids.iter().rev() {
+            self.unminimize_window(w_id)
+                .unwrap_or_else(|e| eprintln!("While restoring window: {:?}", e));
+        }
+        Ok(())
+    }
+
+    /// The window `WindowRestore` brings back: the last one minimized from
+    /// the active group, or else the last one minimized.
+    fn window_to_restore(&self) -> Option<uuid::Uuid> {
+        let origin = self
+            .get_active_display()
+            .and_then(|ds| ds.active_group.map(|g_id| (ds.display_id, g_id)));
+        self.minimized_windows
+            .iter()
+            .rev()
+            .find(|w| origin.is_some() && self.minimized_from.get(w.id()) == origin.as_ref())
+            .or(self.minimized_windows.last())
+            .map(|w| *w.id())
+    }
+
+    /// Unminimize the window `w_id` and put it back in the group it was
+    /// minimized from.
+    fn unminimize_window(&mut self, w_id: &uuid::Uuid) -> Result<()> {
+        let idx = match self.minimized_windows.iter().position(|w| w.id() == w_id) {
+            Some(idx) => idx,
+            None => return Ok(()),
+        };
+        let window = self.minimized_windows.remove(idx);
+        self.restore_window(&window)?;
+        match self.minimized_from.remove(window.id()) {
+            Some((display_id, g_id)) if self.display_ids.contains(&display_id) => {
+                self.insert_window_in_group(window, display_id, g_id);
+                // Show the restored window where it was.
+                if let Some(ds) = self.displays.get_mut(&display_id) {
+                    ds.set_active_group(g_id);
                 }
-            } else {
-                window.set_frame(CGRect::new(
-                    &CGPoint::new(d.origin.x + d.size.width / 2., d.origin.y),
-                    &CGSize::new(d.size.width / 2., d.size.height),
-                ))?;
+                self.active_display_idx =
+                    self.display_ids.iter().position(|d_id| *d_id == display_id);
+            }
+            _ => {
+                let display_id = self.system.display_of(&window)?;
+                self.insert_open_window(window, display_id);
             }
         }
         Ok(())
     }
 
-    fn minimize_active_window(&mut self) -> Result<()> {
-        if let Some(ds) = self.get_active_display_mut() {
-            match ds.pop_active_window() {
-                Some(window) => {
-                    window.set_minimized(true)?;
-                    self.minimized_windows.push(window);
-                    Ok(())
-                }
-                None => Ok(()),
+    /// Whether `confirmation` was asked for a second time to confirm it. The
+    /// first time the status window asks for confirmation with `prompt`.
+    fn confirmed(&mut self, confirmation: Confirmation, prompt: String) -> Result<bool> {
+        if let Some((pending, deadline, _)) = self.pending_confirmation.take() {
+            if pending == confirmation && Instant::now() < deadline {
+                return Ok(true);
             }
-        } else {
-            Ok(())
         }
+        self.pending_confirmation =
+            Some((confirmation, Instant::now() + CONFIRMATION_TIMEOUT, prompt));
+        self.maybe_enter_normal_mode()?;
+        Ok(false)
     }
 
-    fn unminimize_window(&mut self) -> Result<()> {
-        if let Some(window) = self.minimized_windows.pop() {
-            window.set_minimized(false)?;
-            let display_id = window.display()?.id;
-            self.insert_open_window(window, display_id);
-            Ok(())
-        } else {
-            Ok(())
+    /// Press the close button of every window in a display's group.
+    /// Windows that fail to close stay in the group.
+    fn close_group_windows(&mut self, display_id: DisplayID, g_id: u8) -> Result<()> {
+        let windows = match self
+            .displays
+            .get(&display_id)
+            .and_then(|ds| ds.groups.get(&g_id))
+        {
+            Some(g) => g.windows.clone(),
+            None => return Ok(()),
+        };
+        let mut closed = vec![];
+        for window in windows {
+            match self.system.close(&window) {
+                Ok(()) => closed.push(*window.id()),
+                Err(e) => eprintln!("While closing {:?}: {:?}", window, e),
+            }
         }
+        self.take_windows(&closed);
+        Ok(())
+    }
+
+    /// Forget every window of the app with `pid`, e.g. once it has been
+    /// killed: while it was hung, its windows were kept where they were.
+    fn remove_app_windows(&mut self, pid: i32) {
+        let system = self.system.clone();
+        let is_app_window = |w: &WindowHandle| system.pid(w).map_or(false, |p| p == pid);
+        let ids: Vec<uuid::Uuid> = self
+            .displays
+            .values()
+            .flat_map(|ds| ds.groups.values())
+            .flat_map(|g| g.windows.iter())
+            .filter(|w| is_app_window(w))
+            .map(|w| *w.id())
+            .collect();
+        self.take_windows(&ids);
+        self.minimized_windows.retain(|w| !is_app_window(w));
     }
 
     fn close_active_window(&mut self) -> Result<()> {
-        match self.get_active_display_mut() {
-            Some(ds) => ds.close_active_window(),
+        match self
+            .get_active_display_mut()
+            .and_then(|ds| ds.pop_active_window())
+        {
+            Some(window) => self.system.close(&window),
             None => Ok(()),
         }
     }
@@ -1140,23 +4853,267 @@ impl WindowManager {
         }
     }
 
+    fn set_layout_custom(&mut self, name: &str) -> Result<()> {
+        if !is_registered_layout(name) {
+            return Err(anyhow!("No layout named {:?}", name));
+        }
+        if let Some(ds) = self.get_active_display_mut() {
+            ds.set_layout(Layout::Custom(name.to_string()))
+        }
+        Ok(())
+    }
+
     fn set_layout_tile_horizontal(&mut self) {
         if let Some(ds) = self.get_active_display_mut() {
             ds.set_layout_tile_horizontal()
         }
     }
 
+    fn remembered_floating_frames(&self) -> Option<&FloatingFrames> {
+        if self.config.floating.remember_frames {
+            Some(&self.floating_frames)
+        } else {
+            None
+        }
+    }
+
+    /// The active group's windows, their frames and its layout settings.
+    fn snapshot_active_group(&self) -> Option<LayoutSnapshot> {
+        let ds = self.get_active_display()?;
+        let g = ds.get_active_group()?;
+        let d = self.system.display_bounds(ds.display_id);
+        let windows = g
+            .windows
+            .iter()
+            .filter_map(|w| {
+                let frame = self.system.frame(w).ok()?;
+                Some(SnapshotWindow {
+                    bundle_id: self.system.bundle_identifier(w).ok().flatten(),
+                    app_name: self.system.app_name(w).ok().flatten(),
+                    title: self.system.title(w).unwrap_or_default(),
+                    x: frame.origin.x - d.origin.x,
+                    y: frame.origin.y - d.origin.y,
+                    width: frame.size.width,
+                    height: frame.size.height,
+                    floating: self.floating_windows.contains(w.id()),
+                })
+            })
+            .collect();
+        Some(LayoutSnapshot {
+            layout: g.layout.clone(),
+            primary_column_max_windows: g.primary_column_max_windows,
+            primary_column_pct: g.primary_column_pct,
+            gap: g.gap,
+            windows,
+        })
+    }
+
+    /// Arrange the active group as `snapshot` has it: the windows matching
+    /// its windows move into the group from wherever they are, in its order
+    /// and ahead of the group's other windows, and the group takes its
+    /// layout settings. A window matches if it belongs to the same app,
+    /// preferably with the same title, since relaunched apps may title their
+    /// windows differently. Returns how many windows were found.
+    fn restore_layout_snapshot(&mut self, snapshot: &LayoutSnapshot) -> Result<usize> {
+        let candidates: Vec<(uuid::Uuid, Option<String>, Option<String>, String)> = self
+            .displays
+            .values()
+            .flat_map(|ds| ds.groups.values())
+            .flat_map(|g| g.windows.iter())
+            .map(|w| {
+                (
+                    *w.id(),
+                    self.system.bundle_identifier(w).ok().flatten(),
+                    self.system.app_name(w).ok().flatten(),
+                    self.system.title(w).unwrap_or_default(),
+                )
+            })
+            .collect();
+        let mut matched: Vec<(uuid::Uuid, &SnapshotWindow)> = vec![];
+        for sw in snapshot.windows.iter() {
+            let found = candidates
+                .iter()
+                .filter(|(w_id, ..)| !matched.iter().any(|(id, _)| id == w_id))
+                .filter(|(_, bundle_id, app_name, _)| match &sw.bundle_id {
+                    Some(_) => *bundle_id == sw.bundle_id,
+                    None => *app_name == sw.app_name,
+                })
+                .min_by_key(|(.., title)| *title != sw.title);
+            if let Some((w_id, ..)) = found {
+                matched.push((*w_id, sw));
+            }
+        }
+
+        let ids: Vec<uuid::Uuid> = matched.iter().map(|(w_id, _)| *w_id).collect();
+        let windows = self.take_windows(&ids);
+        let ds = match self.get_active_display_mut() {
+            Some(ds) => ds,
+            None => return Ok(0),
+        };
+        let g_id = ds.active_group.unwrap_or(DEFAULT_GROUP);
+        ds.insert_windows_in_group(g_id, windows);
+        ds.set_active_group(g_id);
+        let d = ds.system.display_bounds(ds.display_id);
+        if let Some(g) = ds.get_active_group_mut() {
+            g.set_layout(snapshot.layout.clone());
+            g.primary_column_max_windows = snapshot.primary_column_max_windows;
+            g.primary_column_pct = snapshot.primary_column_pct;
+            g.gap = snapshot.gap;
+            g.split_tree = None;
+            g.active_window_idx = (!g.windows.is_empty()).then_some(0);
+        }
+        let floats = matches!(snapshot.layout, Layout::Floating);
+        for (w_id, sw) in matched.iter() {
+            if sw.floating {
+                self.floating_windows.insert(*w_id);
+            } else {
+                self.floating_windows.remove(w_id);
+            }
+            if !floats && !sw.floating {
+                continue;
+            }
+            if let Some(w) = self.window_by_id(w_id) {
+                let frame = CGRect::new(
+                    &CGPoint::new(d.origin.x + sw.x, d.origin.y + sw.y),
+                    &CGSize::new(sw.width, sw.height),
+                );
+                self.system
+                    .set_frame(w, frame)
+                    .unwrap_or_else(|e| eprintln!("While restoring frame: {:?}", e));
+            }
+        }
+        // So the frames just put back win over older ones.
+        self.remember_floating_frames();
+        Ok(matched.len())
+    }
+
+    /// Remember the frame of the window `w_id` if it is floating, or in a
+    /// floating layout, e.g. once it has been dragged or snapped there, so
+    /// that the next relayout doesn't put it back.
+    fn remember_floating_frame(&mut self, w_id: &uuid::Uuid) {
+        let (display_id, g_id, w_idx) = match self.find_window_by(|w| w.id() == w_id) {
+            Some(location) => location,
+            None => return,
+        };
+        let g = &self.displays[&display_id].groups[&g_id];
+        if !matches!(g.layout, Layout::Floating) && !self.floating_windows.contains(w_id) {
+            return;
+        }
+        self.floating_frames
+            .remember(&*self.system, &g.windows[w_idx])
+            .unwrap_or_else(|e| eprintln!("While remembering floating frame: {:?}", e));
+    }
+
+    /// Remember the frames of windows that are floating, or in a floating
+    /// layout, for the current display sizes.
+    fn remember_floating_frames(&mut self) {
+        for ds in self.displays.values() {
+            for g in ds.groups.values() {
+                let is_floating_layout = matches!(g.layout, Layout::Floating);
+                for w in g.windows.iter() {
+                    if is_floating_layout || self.floating_windows.contains(w.id()) {
+                        self.floating_frames
+                            .remember(&*self.system, w)
+                            .unwrap_or_else(|e| {
+                                eprintln!("While remembering floating frame: {:?}", e)
+                            });
+                    }
+                }
+            }
+        }
+    }
+
     fn relayout_active_display(&self) -> Result<()> {
+        if let Some(deferred) = &self.deferred_updates {
+            deferred.relayout.set(true);
+            return Ok(());
+        }
         if let Some(ds) = self.get_active_display() {
-            ds.relayout()
-        } else {
-            Ok(())
+            self.relayouts.request([ds.display_id]);
+            self.schedule_wake_up();
         }
+        Ok(())
     }
 
     fn relayout_all_displays(&self) -> Result<()> {
-        for ds in self.displays.values() {
-            ds.relayout()?;
+        if let Some(deferred) = &self.deferred_updates {
+            deferred.relayout.set(true);
+            return Ok(());
+        }
+        self.relayouts.request(self.displays.keys().copied());
+        self.schedule_wake_up();
+        Ok(())
+    }
+
+    /// Lay out the displays asked for by `relayout_active_display` and
+    /// `relayout_all_displays`, once requests have stopped coming.
+    fn relayout_pending_displays(&mut self) -> Result<()> {
+        let display_ids = match self.relayouts.take_due() {
+            Some(display_ids) => display_ids,
+            None => return Ok(()),
+        };
+        for display_id in display_ids {
+            if let Some(g) = self
+                .displays
+                .get_mut(&display_id)
+                .and_then(|ds| ds.get_active_group_mut())
+            {
+                g.sync_split_tree(&self.floating_windows);
+            }
+            if let Some(ds) = self.displays.get(&display_id) {
+                ds.relayout(&self.floating_windows, self.remembered_floating_frames())?;
+            }
+        }
+        self.apply_zoom()?;
+        if self.relayouts.highlight.replace(false) {
+            self.highlight_active_window()?;
+        }
+        Ok(())
+    }
+
+    /// Zoom the active window, or put it back in its tile if it is zoomed.
+    /// Floating windows can't be zoomed.
+    fn toggle_zoom(&mut self) -> Result<()> {
+        let (w_id, is_tiled) = match self.get_active_window() {
+            Some(w) => (
+                *w.id(),
+                !self.floating_windows.contains(w.id())
+                    && !matches!(
+                        self.get_active_display().and_then(|ds| ds.layout()),
+                        Some(Layout::Floating)
+                    ),
+            ),
+            None => return Ok(()),
+        };
+        if self.zoomed_window == Some(w_id) {
+            self.zoomed_window = None;
+            return self.relayout_active_display();
+        }
+        if is_tiled {
+            self.zoomed_window = Some(w_id);
+            self.apply_zoom()?;
+        }
+        Ok(())
+    }
+
+    /// Expand the zoomed window over its display, as a layout of one window
+    /// would. Layouts put it back in its tile, so this follows each relayout.
+    fn apply_zoom(&self) -> Result<()> {
+        let w_id = match self.zoomed_window {
+            Some(w_id) => w_id,
+            None => return Ok(()),
+        };
+        if let Some((display_id, g_id, w_idx)) = self.find_window_by(|w| *w.id() == w_id) {
+            let g = &self.displays[&display_id].groups[&g_id];
+            // The same gaps as the group's layout leaves.
+            let bounds = inset(&self.system.display_bounds(display_id), g.gap / 2.);
+            if let Some(frame) = Layout::tile_horizontal(1, 50)
+                .compute(&bounds, &[1.])
+                .first()
+            {
+                self.system
+                    .set_frame(&g.windows[w_idx], inset(frame, g.gap / 2.))?;
+            }
         }
         Ok(())
     }
@@ -1228,7 +5185,159 @@ impl WindowManager {
     }
 
     pub fn do_action(&mut self, action: &Action) -> Result<()> {
+        if matches!(&self.pending_confirmation, Some((c, _, _)) if c.action_id() != action.id()) {
+            self.pending_confirmation = None;
+        }
+        let shown_before = self.shown_groups();
+        let result = if self.target_mouse_window && !matches!(action, Action::TargetMouseWindow) {
+            self.target_mouse_window = false;
+            get_mouse_location().and_then(|point| self.perform_action_at_point(&point, action))
+        } else {
+            self.perform_action(action)
+        };
+        if self.verbose {
+            match &result {
+                Ok(()) => println!("Performed {:?}", action),
+                Err(e) => println!("Failed to perform {:?}: {:?}", action, e),
+            }
+        }
+        self.last_activity = Instant::now();
+        if result.is_ok() && action.is_repeatable() {
+            self.last_action = Some(action.clone());
+        }
+        self.sync_spaces(&shown_before);
+        self.record_focus();
+        self.update_status_window_content();
+        self.update_status_tasklist();
+        self.update_menu_bar();
+        self.notify_hooks();
+        result
+    }
+
+    /// Perform the action bound to a key press. Motions repeated by holding
+    /// their key are throttled to `bindings.repeat_interval`, and the window
+    /// is only activated, and the status window's thumbnails, the menu bar
+    /// and hooks only updated, once the key is released.
+    pub fn do_key_action(&mut self, action: &Action, event: &CGEvent) -> Result<()> {
+        let repeat = event.get_integer_value_field(EventField::KEYBOARD_EVENT_AUTOREPEAT) != 0;
+        if !(repeat && action.is_motion()) {
+            self.end_key_repeat()?;
+            return self.do_action(action);
+        }
+        let interval = Duration::from_secs_f64(self.config.bindings.repeat_interval);
+        if matches!(self.last_repeat, Some(at) if at.elapsed() < interval) {
+            return Ok(());
+        }
+        self.last_repeat = Some(Instant::now());
+        // In case the key's release is missed.
+        self.schedule_wake_up();
+        let result = self.perform_action(action);
+        self.update_status_window_content();
+        result
+    }
+
+    /// Catch up after motions repeated by holding a key, once it is
+    /// released.
+    pub fn end_key_repeat(&mut self) -> Result<()> {
+        if self.last_repeat.take().is_none() {
+            return Ok(());
+        }
+        self.record_focus();
+        self.update_status_window_content();
+        self.update_status_tasklist();
+        self.update_menu_bar();
+        self.notify_hooks();
+        self.activate_active_window()?;
+        self.highlight_active_window()
+    }
+
+    /// Perform `action` on the window under `point` rather than the active
+    /// window, e.g. to get rid of a popup without focusing it.
+    pub fn do_action_at_point(&mut self, point: &CGPoint, action: &Action) -> Result<()> {
+        let result = self.perform_action_at_point(point, action);
+        self.record_focus();
+        self.update_status_window_content();
+        self.update_menu_bar();
+        self.notify_hooks();
+        result
+    }
+
+    /// Make the window under `point` active while `action` is performed, then
+    /// make the previously active window active again. If it had focus and
+    /// lost it, it is focused again.
+    fn perform_action_at_point(&mut self, point: &CGPoint, action: &Action) -> Result<()> {
+        let window = match self.system.window_at_point(point)? {
+            Some(window) => window,
+            None => return Ok(()),
+        };
+        let (display_id, g_id, w_idx) = match self
+            .find_window_by(|w| self.system.is_same_window(&window, w).unwrap_or(false))
+        {
+            Some(location) => location,
+            None => return Ok(()),
+        };
+        let prev = self.get_active_window().map(|w| {
+            (
+                *w.id(),
+                self.system.is_frontmost_and_main(w).unwrap_or(false),
+            )
+        });
+
+        self.set_active_window_location(display_id, g_id, w_idx);
+        let result = self.perform_action(action);
+
+        if let Some((prev_id, was_focused)) = prev {
+            if let Some((display_id, g_id, w_idx)) = self.find_window_by(|w| *w.id() == prev_id) {
+                self.set_active_window_location(display_id, g_id, w_idx);
+                let still_focused = self.get_active_window().map_or(false, |w| {
+                    self.system.is_frontmost_and_main(w).unwrap_or(false)
+                });
+                if was_focused && !still_focused {
+                    self.activate_active_window()?;
+                }
+                if self.mode != Mode::Insert || self.border_enabled {
+                    self.highlight_active_window()?;
+                }
+            }
+        }
+        result
+    }
+
+    /// Perform `actions` in order, then relayout, activate and highlight once
+    /// rather than after each action. Stops at the first action that fails.
+    pub fn do_actions(&mut self, actions: &[Action]) -> Result<()> {
+        let result = self.perform_batch(actions);
+        self.record_focus();
+        self.update_status_window_content();
+        self.update_menu_bar();
+        self.notify_hooks();
+        result
+    }
+
+    /// Perform `actions`, updating layouts, focus and the highlight once at
+    /// the end.
+    fn perform_batch(&mut self, actions: &[Action]) -> Result<()> {
+        self.deferred_updates = Some(DeferredUpdates::default());
+        let result = actions.iter().try_for_each(|a| self.perform_action(a));
+        if let Some(deferred) = self.deferred_updates.take() {
+            if deferred.relayout.get() {
+                self.relayout_all_displays()?;
+            }
+            if deferred.activate.get() {
+                self.activate_active_window()?;
+            }
+            if deferred.highlight.get() {
+                self.highlight_active_window()?;
+            }
+        }
+        result
+    }
+
+    fn perform_action(&mut self, action: &Action) -> Result<()> {
         use Action::*;
+        if self.mode == Mode::Passthrough && !matches!(action, TogglePassthrough) {
+            return Err(anyhow!("Passthrough mode is on"));
+        }
         match action {
             RelayoutAll => {
                 self.refresh_window_list()?;
@@ -1243,11 +5352,34 @@ impl WindowManager {
                 Ok(())
             }
             ModeInsert => {
+                if let Some((g_id, _)) = self.group_preview.take() {
+                    self.show_group(g_id)?;
+                }
                 self.set_mode(Mode::Insert);
-                self.close_highlight_window();
+                if self.border_enabled {
+                    self.focus_changed()?;
+                } else {
+                    self.close_highlight_window();
+                }
                 self.close_status_window();
                 Ok(())
             }
+            TogglePassthrough => {
+                if self.mode == Mode::Passthrough {
+                    self.set_mode(Mode::Insert);
+                    self.refresh_window_list()?;
+                    if self.border_enabled {
+                        self.focus_changed()?;
+                    }
+                } else {
+                    self.switcher = None;
+                    self.palette = None;
+                    self.set_mode(Mode::Passthrough);
+                    self.close_highlight_window();
+                    self.close_status_window();
+                }
+                Ok(())
+            }
             ModeInsertNormal => {
                 self.set_mode(Mode::InsertNormal);
                 self.refresh_window_list()?;
@@ -1257,6 +5389,8 @@ impl WindowManager {
             }
             LayoutFloating => {
                 self.set_layout_floating();
+                // So the frames just put back win over older ones.
+                self.remember_floating_frames();
                 self.relayout_active_display()?;
                 self.update_status_window_content();
                 self.highlight_active_window()?;
@@ -1269,6 +5403,46 @@ impl WindowManager {
                 self.highlight_active_window()?;
                 Ok(())
             }
+            SaveLayout(name) => {
+                let snapshot = match self.snapshot_active_group() {
+                    Some(snapshot) => snapshot,
+                    None => return Ok(()),
+                };
+                let path = LayoutSnapshot::default_path()
+                    .ok_or_else(|| anyhow!("No home directory to save layouts in"))?;
+                let mut snapshots = LayoutSnapshot::load_all(&path)?;
+                snapshots.insert(name.clone(), snapshot);
+                LayoutSnapshot::save_all(&path, &snapshots)?;
+                println!("Saved layout {:?} to {}", name, path.display());
+                Ok(())
+            }
+            RestoreLayout(name) => {
+                let path = LayoutSnapshot::default_path()
+                    .ok_or_else(|| anyhow!("No home directory to load layouts from"))?;
+                let snapshots = LayoutSnapshot::load_all(&path)?;
+                let snapshot = snapshots
+                    .get(name)
+                    .ok_or_else(|| anyhow!("No layout named {:?} in {}", name, path.display()))?;
+                let found = self.restore_layout_snapshot(snapshot)?;
+                println!(
+                    "Restored layout {:?}: found {} of {} windows",
+                    name,
+                    found,
+                    snapshot.windows.len()
+                );
+                self.relayout_all_displays()?;
+                self.activate_active_window()?;
+                self.update_status_window_content();
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            LayoutCustom(name) => {
+                self.set_layout_custom(name)?;
+                self.relayout_active_display()?;
+                self.update_status_window_content();
+                self.highlight_active_window()?;
+                Ok(())
+            }
             LayoutTiling => {
                 self.set_layout_tile_horizontal();
                 self.relayout_active_display()?;
@@ -1281,27 +5455,173 @@ impl WindowManager {
                 self.highlight_active_window()?;
                 Ok(())
             }
-            WindowLeftHalf => {
-                self.set_active_window_left()?;
+            WindowLeftHalf
+            | WindowRightHalf
+            | WindowTopHalf
+            | WindowBottomHalf
+            | WindowTopLeftQuarter
+            | WindowTopRightQuarter
+            | WindowBottomLeftQuarter
+            | WindowBottomRightQuarter
+            | WindowCenter => {
+                let region = match action {
+                    WindowLeftHalf => SnapRegion::LeftHalf,
+                    WindowRightHalf => SnapRegion::RightHalf,
+                    WindowTopHalf => SnapRegion::TopHalf,
+                    WindowBottomHalf => SnapRegion::BottomHalf,
+                    WindowTopLeftQuarter => SnapRegion::TopLeftQuarter,
+                    WindowTopRightQuarter => SnapRegion::TopRightQuarter,
+                    WindowBottomLeftQuarter => SnapRegion::BottomLeftQuarter,
+                    WindowBottomRightQuarter => SnapRegion::BottomRightQuarter,
+                    _ => SnapRegion::Center,
+                };
+                self.snap_active_window(region)?;
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            WindowMinimize => {
+                self.minimize_active_window()?;
+                self.activate_active_window()?;
+                self.relayout_active_display()?;
                 self.highlight_active_window()?;
                 Ok(())
             }
-            WindowRightHalf => {
-                self.set_active_window_right()?;
+            MinimizeGroup => {
+                self.minimize_active_group()?;
+                self.relayout_active_display()?;
                 self.highlight_active_window()?;
                 Ok(())
             }
-            WindowMinimize => {
-                self.minimize_active_window()?;
+            RestoreGroup => {
+                self.restore_active_group()?;
+                self.bring_active_display_group_to_front()?;
                 self.activate_active_window()?;
                 self.relayout_active_display()?;
                 self.highlight_active_window()?;
                 Ok(())
             }
             WindowRestore => {
-                self.unminimize_window()?;
+                if let Some(w_id) = self.window_to_restore() {
+                    self.unminimize_window(&w_id)?;
+                    self.set_window_active_by_id(w_id)?;
+                }
+                self.activate_active_window()?;
+                self.relayout_active_display()?;
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            WindowZoom => {
+                if let Some(w) = self.get_active_window() {
+                    self.system.zoom(w)?;
+                }
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            TargetMouseWindow => {
+                self.target_mouse_window = true;
+                Ok(())
+            }
+            RepeatLast => match self.last_action.clone() {
+                Some(action) => self.perform_action(&action),
+                None => Ok(()),
+            },
+            Spawn(command) => spawn(command),
+            RunScript(name) => self.run_script_function(name),
+            ToggleZoom => {
+                self.toggle_zoom()?;
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            WindowFullScreen => {
+                if let Some(w) = self.get_active_window() {
+                    self.system.toggle_full_screen(w)?;
+                }
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            TitleBarDoubleClick => match TitleBarDoubleClickAction::from_user_defaults() {
+                TitleBarDoubleClickAction::Minimize => self.do_action(&WindowMinimize),
+                TitleBarDoubleClickAction::Zoom => {
+                    if let Some(w) = self.get_active_window() {
+                        self.system.zoom(w)?;
+                    }
+                    self.highlight_active_window()?;
+                    Ok(())
+                }
+                TitleBarDoubleClickAction::None => Ok(()),
+            },
+            CloseGroupWindows => {
+                let (display_id, g_id, count) = match self.get_active_display().and_then(|ds| {
+                    Some((
+                        ds.display_id,
+                        ds.active_group?,
+                        ds.get_active_group()?.windows.len(),
+                    ))
+                }) {
+                    Some((display_id, g_id, count)) if count > 0 => (display_id, g_id, count),
+                    _ => return Ok(()),
+                };
+                let prompt = format!(
+                    "Close {} windows in group {}",
+                    count,
+                    self.group_label(g_id)
+                );
+                if !self.confirmed(Confirmation::CloseGroupWindows(display_id, g_id), prompt)? {
+                    return Ok(());
+                }
+                self.close_group_windows(display_id, g_id)?;
+                self.relayout_active_display()?;
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            QuitApp => {
+                if let Some(window) = self.get_active_window() {
+                    self.system.quit_app(window)?;
+                }
+                Ok(())
+            }
+            ForceQuitApp => {
+                let (pid, app) = match self.get_active_window() {
+                    Some(window) => (
+                        self.system.pid(window)?,
+                        self.system
+                            .app_name(window)
+                            .ok()
+                            .flatten()
+                            .unwrap_or_default(),
+                    ),
+                    None => return Ok(()),
+                };
+                let prompt = format!("Force quit {} ({})", app, pid);
+                if !self.confirmed(Confirmation::ForceQuitApp(pid), prompt)? {
+                    return Ok(());
+                }
+                self.system.force_quit_app(pid)?;
+                self.remove_app_windows(pid);
+                self.relayout_all_displays()?;
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            WindowRaise => {
+                if let Some(g) = self
+                    .get_active_display_mut()
+                    .and_then(|ds| ds.get_active_group_mut())
+                {
+                    g.raise_active_window();
+                }
+                self.activate_active_window()?;
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            WindowLower => {
+                if let Some(g) = self
+                    .get_active_display_mut()
+                    .and_then(|ds| ds.get_active_group_mut())
+                {
+                    g.lower_active_window();
+                }
+                self.bring_active_display_group_to_front()?;
                 self.activate_active_window()?;
-                self.relayout_active_display()?;
                 self.highlight_active_window()?;
                 Ok(())
             }
@@ -1315,16 +5635,85 @@ impl WindowManager {
             NextWindow => {
                 self.maybe_enter_normal_mode()?;
                 self.set_next_window_active();
-                self.activate_active_window()?;
                 self.update_status_window_content();
-                self.highlight_active_window()?;
-                Ok(())
+                self.activate_active_window_debounced()
             }
             PrevWindow => {
                 self.maybe_enter_normal_mode()?;
                 self.set_prev_window_active();
-                self.activate_active_window()?;
                 self.update_status_window_content();
+                self.activate_active_window_debounced()
+            }
+            FocusLeft | FocusRight | FocusUp | FocusDown => {
+                let direction = match action {
+                    FocusLeft => Direction::Left,
+                    FocusRight => Direction::Right,
+                    FocusUp => Direction::Up,
+                    _ => Direction::Down,
+                };
+                self.maybe_enter_normal_mode()?;
+                self.set_window_in_direction_active(direction)?;
+                self.update_status_window_content();
+                self.activate_active_window_debounced()
+            }
+            FocusLast | FocusOlder | FocusNewer => {
+                self.maybe_enter_normal_mode()?;
+                let mut next = match action {
+                    FocusLast => self.focus_history.last(),
+                    FocusOlder => self.focus_history.older(),
+                    _ => self.focus_history.newer(),
+                };
+                // Step over windows that aren't managed any more, e.g. ones
+                // minimized since.
+                while let Some(w_id) = next {
+                    if self.set_window_active_by_id(w_id)? {
+                        break;
+                    }
+                    next = match action {
+                        FocusOlder => self.focus_history.older(),
+                        FocusNewer => self.focus_history.newer(),
+                        _ => None,
+                    };
+                }
+                self.update_status_window_content();
+                self.activate_active_window_debounced()
+            }
+            FocusUrgent => {
+                while let Some(w_id) = self.urgent_windows.pop() {
+                    if self.set_window_active_by_id(w_id)? {
+                        break;
+                    }
+                }
+                self.update_status_window_content();
+                self.activate_active_window_debounced()
+            }
+            NextAppWindow | PrevAppWindow => {
+                if let Some(w_id) = self.app_window_after(matches!(action, NextAppWindow)) {
+                    self.set_window_active_by_id(w_id)?;
+                }
+                // Like cmd+`, switch straight away in insert mode.
+                if self.mode == Mode::Insert {
+                    return self.activate_active_window();
+                }
+                self.update_status_window_content();
+                self.activate_active_window_debounced()
+            }
+            SwapLeft | SwapRight | SwapUp | SwapDown => {
+                let direction = match action {
+                    SwapLeft => Direction::Left,
+                    SwapRight => Direction::Right,
+                    SwapUp => Direction::Up,
+                    _ => Direction::Down,
+                };
+                if self.swap_window_in_direction(direction)? {
+                    self.relayout_all_displays()?;
+                    self.close_status_window();
+                    self.open_status_window();
+                    self.activate_active_window()?;
+                } else {
+                    self.relayout_active_display()?;
+                    self.update_status_window_content();
+                }
                 self.highlight_active_window()?;
                 Ok(())
             }
@@ -1366,6 +5755,118 @@ impl WindowManager {
                 self.highlight_active_window()?;
                 Ok(())
             }
+            SetPrimaryColWidth(pct) => {
+                if let Some(g) = self
+                    .get_active_display_mut()
+                    .and_then(|ds| ds.get_active_group_mut())
+                {
+                    g.set_primary_column_width(*pct, true);
+                }
+                self.relayout_active_display()?;
+                self.update_status_window_content();
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            Nudge(direction) => {
+                self.nudge_active_window(*direction)?;
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            ResizeFloating(direction) => {
+                self.resize_active_floating_window(*direction)?;
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            SplitHorizontal | SplitVertical => {
+                let direction = match action {
+                    SplitHorizontal => SplitDirection::Horizontal,
+                    _ => SplitDirection::Vertical,
+                };
+                let floating = &self.floating_windows;
+                if let Some(g) = self
+                    .active_display_idx
+                    .and_then(|idx| self.displays.get_mut(&self.display_ids[idx]))
+                    .and_then(|ds| ds.get_active_group_mut())
+                {
+                    g.split_active_window(floating, direction);
+                }
+                self.relayout_active_display()?;
+                self.update_status_window_content();
+                Ok(())
+            }
+            StackWindow | UnstackWindow => {
+                let floating = &self.floating_windows;
+                if let Some(g) = self
+                    .active_display_idx
+                    .and_then(|idx| self.displays.get_mut(&self.display_ids[idx]))
+                    .and_then(|ds| ds.get_active_group_mut())
+                {
+                    if matches!(action, StackWindow) {
+                        g.stack_active_window(floating);
+                    } else {
+                        g.unstack_active_window();
+                    }
+                }
+                self.relayout_active_display()?;
+                self.activate_active_window()?;
+                self.update_status_window_content();
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            StackNext | StackPrev => {
+                if let Some(g) = self
+                    .get_active_display_mut()
+                    .and_then(|ds| ds.get_active_group_mut())
+                {
+                    g.set_next_stacked_window_active(matches!(action, StackNext));
+                }
+                self.update_status_window_content();
+                self.activate_active_window_debounced()
+            }
+            GrowTile | ShrinkTile => {
+                let factor = if matches!(action, GrowTile) {
+                    TILE_WEIGHT_STEP
+                } else {
+                    1. / TILE_WEIGHT_STEP
+                };
+                let floating = &self.floating_windows;
+                if let Some(g) = self
+                    .active_display_idx
+                    .and_then(|idx| self.displays.get_mut(&self.display_ids[idx]))
+                    .and_then(|ds| ds.get_active_group_mut())
+                {
+                    g.scale_active_tile(factor, floating);
+                }
+                self.relayout_active_display()?;
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            Balance => {
+                let floating = &self.floating_windows;
+                if let Some(g) = self
+                    .active_display_idx
+                    .and_then(|idx| self.displays.get_mut(&self.display_ids[idx]))
+                    .and_then(|ds| ds.get_active_group_mut())
+                {
+                    g.balance(floating);
+                }
+                self.relayout_active_display()?;
+                self.update_status_window_content();
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            SetPrimaryColWindows(n) => {
+                if let Some(g) = self
+                    .get_active_display_mut()
+                    .and_then(|ds| ds.get_active_group_mut())
+                {
+                    g.set_primary_column_max_windows(*n, true);
+                }
+                self.relayout_active_display()?;
+                self.update_status_window_content();
+                self.highlight_active_window()?;
+                Ok(())
+            }
             NextDisplay => {
                 self.maybe_enter_normal_mode()?;
                 self.set_next_display_active();
@@ -1416,15 +5917,57 @@ impl WindowManager {
                 self.highlight_active_window()?;
                 Ok(())
             }
-            ShowGroup(g_idx) => {
-                self.set_active_display_group(*g_idx);
-                self.bring_active_display_group_to_front()?;
+            SwapWindowWithNextDisplay => {
+                self.swap_active_window_with_next_display();
+                self.relayout_all_displays()?;
+                self.activate_active_window()?;
+                self.update_status_window_content();
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            MoveGroupToNextDisplay { follow } => {
+                self.move_active_group_to_next_display();
+                if *follow {
+                    self.set_next_display_active();
+                }
+                self.relayout_all_displays()?;
+                self.close_status_window();
+                self.open_status_window();
+                self.activate_active_window()?;
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            MoveGroupToPrevDisplay { follow } => {
+                self.move_active_group_to_prev_display();
+                if *follow {
+                    self.set_prev_display_active();
+                }
+                self.relayout_all_displays()?;
+                self.close_status_window();
+                self.open_status_window();
+                self.activate_active_window()?;
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            SwapGroupWithNextDisplay => {
+                self.swap_active_group_with_next_display();
+                self.relayout_all_displays()?;
                 self.activate_active_window()?;
-                self.relayout_active_display()?;
                 self.update_status_window_content();
                 self.highlight_active_window()?;
                 Ok(())
             }
+            IdentifyDisplays => {
+                self.identify_displays();
+                Ok(())
+            }
+            ShowGroup(g_idx) => {
+                if self.mode == Mode::InsertNormal && self.config.groups.preview {
+                    self.preview_group(*g_idx);
+                    return Ok(());
+                }
+                self.show_group(*g_idx)
+            }
             MoveWindowToGroup { id: g_id, follow } => {
                 self.move_active_window_to_group(*g_id);
                 if *follow {
@@ -1446,7 +5989,13 @@ impl WindowManager {
                 Ok(())
             }
             NextGroup => {
-                if self.set_active_display_group_next_with_windows() {
+                let changed = if self.config.groups.skip_empty {
+                    self.set_active_display_group_next_with_windows()
+                } else {
+                    self.set_active_display_group_next();
+                    true
+                };
+                if changed {
                     self.bring_active_display_group_to_front()?;
                     self.activate_active_window()?;
                     self.relayout_active_display()?;
@@ -1456,7 +6005,13 @@ impl WindowManager {
                 Ok(())
             }
             PrevGroup => {
-                if self.set_active_display_group_prev_with_windows() {
+                let changed = if self.config.groups.skip_empty {
+                    self.set_active_display_group_prev_with_windows()
+                } else {
+                    self.set_active_display_group_prev();
+                    true
+                };
+                if changed {
                     self.bring_active_display_group_to_front()?;
                     self.activate_active_window()?;
                     self.relayout_active_display()?;
@@ -1489,6 +6044,444 @@ impl WindowManager {
                 self.highlight_active_window()?;
                 Ok(())
             }
+            MoveAppToGroup { id: g_id, follow } => {
+                self.move_active_app_to_group(Some(*g_id), *follow);
+                if *follow {
+                    self.bring_active_display_group_to_front()?;
+                }
+                self.activate_active_window()?;
+                self.relayout_all_displays()?;
+                self.update_status_window_content();
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            ToggleAppFloating => {
+                self.toggle_active_app_floating();
+                self.relayout_all_displays()?;
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            GatherApp => {
+                self.move_active_app_to_group(None, true);
+                self.bring_active_display_group_to_front()?;
+                self.activate_active_window()?;
+                self.relayout_all_displays()?;
+                self.update_status_window_content();
+                self.highlight_active_window()?;
+                Ok(())
+            }
+            OpenSwitcher { all_groups } => {
+                self.open_switcher(*all_groups);
+                Ok(())
+            }
+            OpenMinimizedSwitcher => {
+                self.open_minimized_switcher();
+                Ok(())
+            }
+            SwitcherNext => {
+                if let Some(switcher) = &mut self.switcher {
+                    switcher.select_next();
+                }
+                Ok(())
+            }
+            SwitcherPrev => {
+                if let Some(switcher) = &mut self.switcher {
+                    switcher.select_prev();
+                }
+                Ok(())
+            }
+            SwitcherSelect => {
+                let location = match self.close_switcher() {
+                    Some((_, _, window_id)) if self.switcher_restores => {
+                        self.unminimize_window(&window_id)?;
+                        self.find_window_by(|w| *w.id() == window_id)
+                            .map(|(display_id, g_id, _)| (display_id, g_id, window_id))
+                    }
+                    location => location,
+                };
+                if let Some((display_id, g_id, window_id)) = location {
+                    self.focus_location(display_id, g_id, Some(window_id))?;
+                }
+                Ok(())
+            }
+            SwitcherCancel => {
+                self.close_switcher();
+                Ok(())
+            }
+            OpenPalette => {
+                self.open_palette();
+                Ok(())
+            }
+            PaletteInput(text) => {
+                if let Some(palette) = &mut self.palette {
+                    palette.input(text);
+                }
+                Ok(())
+            }
+            PaletteBackspace => {
+                if let Some(palette) = &mut self.palette {
+                    palette.backspace();
+                }
+                Ok(())
+            }
+            PaletteNext => {
+                if let Some(palette) = &mut self.palette {
+                    palette.select_next();
+                }
+                Ok(())
+            }
+            PalettePrev => {
+                if let Some(palette) = &mut self.palette {
+                    palette.select_prev();
+                }
+                Ok(())
+            }
+            PaletteExecute => {
+                let command = self.palette.as_ref().and_then(|p| p.command());
+                let (id, args) = match command {
+                    Some(command) => command,
+                    None => return Ok(()),
+                };
+                match Action::parse(id, &args) {
+                    Ok(action) => {
+                        self.close_palette();
+                        self.do_action(&action)
+                    }
+                    Err(e) => {
+                        if let Some(palette) = &mut self.palette {
+                            palette.show_error(e.to_string());
+                        }
+                        Ok(())
+                    }
+                }
+            }
+            PaletteCancel => {
+                self.close_palette();
+                Ok(())
+            }
+            ToggleStats => {
+                self.show_stats = !self.show_stats;
+                self.update_status_window_content();
+                Ok(())
+            }
+            ToggleBorder => {
+                self.border_enabled = !self.border_enabled;
+                println!(
+                    "Insert mode border {}",
+                    if self.border_enabled { "on" } else { "off" }
+                );
+                if self.mode == Mode::Insert && !self.border_enabled {
+                    self.close_highlight_window();
+                } else {
+                    self.focus_changed()?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window_system::InMemoryWindowSystem;
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> CGRect {
+        CGRect::new(&CGPoint::new(x, y), &CGSize::new(width, height))
+    }
+
+    /// A window manager for two displays side by side, 1 and 2, and the
+    /// window system it uses.
+    fn window_manager() -> (WindowManager, InMemoryWindowSystem) {
+        let system = InMemoryWindowSystem::new(&[
+            (1, rect(0., 0., 1000., 800.)),
+            (2, rect(1000., 0., 1000., 800.)),
+        ]);
+        let wm = WindowManager::with_system(Config::default(), Rc::new(system.clone()));
+        (wm, system)
+    }
+
+    /// The pids of the windows in a group, in the group's order.
+    fn group_pids(wm: &WindowManager, display_id: DisplayID, g_id: u8) -> Vec<i32> {
+        wm.displays
+            .get(&display_id)
+            .and_then(|ds| ds.groups.get(&g_id))
+            .map(|g| {
+                g.windows
+                    .iter()
+                    .map(|w| wm.system.pid(w).unwrap())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn window_id(wm: &WindowManager, pid: i32) -> uuid::Uuid {
+        let (display_id, g_id, w_idx) = wm
+            .find_window_by(|w| wm.system.pid(w).ok() == Some(pid))
+            .unwrap();
+        *wm.displays[&display_id].groups[&g_id].windows[w_idx].id()
+    }
+
+    fn assert_frame(actual: Option<CGRect>, expected: CGRect) {
+        match actual {
+            Some(frame) if same_frame(&frame, &expected) => {}
+            _ => panic!("Expected frame {:?}, got {:?}", expected, actual),
         }
     }
+
+    fn sorted(mut pids: Vec<i32>) -> Vec<i32> {
+        pids.sort_unstable();
+        pids
+    }
+
+    #[test]
+    fn new_windows_join_the_shown_group_of_their_display() {
+        let (mut wm, system) = window_manager();
+        let a = system.open_window(rect(100., 100., 600., 400.));
+        let b = system.open_window(rect(200., 100., 600., 400.));
+        let c = system.open_window(rect(1100., 100., 600., 400.));
+        wm.refresh_window_list().unwrap();
+
+        assert_eq!(sorted(group_pids(&wm, 1, DEFAULT_GROUP)), vec![a, b]);
+        assert_eq!(group_pids(&wm, 2, DEFAULT_GROUP), vec![c]);
+    }
+
+    #[test]
+    fn small_windows_float() {
+        let (mut wm, system) = window_manager();
+        wm.config.auto_float.enabled = true;
+        system.open_window(rect(100., 100., 200., 150.));
+        wm.refresh_window_list().unwrap();
+
+        assert_eq!(wm.floating_windows.len(), 1);
+    }
+
+    #[test]
+    fn small_windows_tile_unless_auto_float_is_enabled() {
+        let (mut wm, system) = window_manager();
+        system.open_window(rect(100., 100., 200., 150.));
+        wm.refresh_window_list().unwrap();
+
+        assert!(wm.floating_windows.is_empty());
+    }
+
+    #[test]
+    fn closed_windows_leave_their_group() {
+        let (mut wm, system) = window_manager();
+        let a = system.open_window(rect(100., 100., 600., 400.));
+        let b = system.open_window(rect(200., 100., 600., 400.));
+        wm.refresh_window_list().unwrap();
+        system.close_window(a);
+        wm.refresh_window_list().unwrap();
+
+        assert_eq!(group_pids(&wm, 1, DEFAULT_GROUP), vec![b]);
+    }
+
+    #[test]
+    fn refreshing_keeps_the_windows_in_their_groups() {
+        let (mut wm, system) = window_manager();
+        let a = system.open_window(rect(100., 100., 600., 400.));
+        let b = system.open_window(rect(200., 100., 600., 400.));
+        wm.refresh_window_list().unwrap();
+        let before = group_pids(&wm, 1, DEFAULT_GROUP);
+        wm.refresh_window_list().unwrap();
+
+        assert_eq!(group_pids(&wm, 1, DEFAULT_GROUP), before);
+        assert_eq!(sorted(before), vec![a, b]);
+    }
+
+    #[test]
+    fn tiling_puts_the_primary_column_on_the_left() {
+        let (mut wm, system) = window_manager();
+        system.open_window(rect(100., 100., 600., 400.));
+        system.open_window(rect(200., 100., 600., 400.));
+        wm.refresh_window_list().unwrap();
+        wm.displays[&1]
+            .relayout(&wm.floating_windows, None)
+            .unwrap();
+
+        let pids = group_pids(&wm, 1, DEFAULT_GROUP);
+        // Below the menu bar, half the display each.
+        assert_frame(system.frame_of(pids[0]), rect(0., 38., 500., 762.));
+        assert_frame(system.frame_of(pids[1]), rect(500., 38., 500., 762.));
+    }
+
+    #[test]
+    fn tiling_stays_on_the_groups_display() {
+        let (mut wm, system) = window_manager();
+        let a = system.open_window(rect(1100., 100., 600., 400.));
+        wm.refresh_window_list().unwrap();
+        wm.displays[&2]
+            .relayout(&wm.floating_windows, None)
+            .unwrap();
+
+        assert_frame(system.frame_of(a), rect(1000., 38., 1000., 762.));
+    }
+
+    #[test]
+    fn moving_a_window_to_another_group_keeps_the_group_shown() {
+        let (mut wm, system) = window_manager();
+        let a = system.open_window(rect(100., 100., 600., 400.));
+        let b = system.open_window(rect(200., 100., 600., 400.));
+        wm.refresh_window_list().unwrap();
+        let (display_id, g_id, w_idx) = wm
+            .find_window_by(|w| wm.system.pid(w).ok() == Some(a))
+            .unwrap();
+        wm.set_active_window_location(display_id, g_id, w_idx);
+        wm.move_active_window_to_group(2);
+
+        assert_eq!(group_pids(&wm, 1, DEFAULT_GROUP), vec![b]);
+        assert_eq!(group_pids(&wm, 1, 2), vec![a]);
+        assert_eq!(wm.displays[&1].active_group, Some(DEFAULT_GROUP));
+    }
+
+    #[test]
+    fn resizing_a_floating_window_leaves_the_columns() {
+        let (mut wm, system) = window_manager();
+        system.open_window(rect(100., 100., 600., 400.));
+        system.open_window(rect(200., 100., 600., 400.));
+        wm.refresh_window_list().unwrap();
+        let pids = group_pids(&wm, 1, DEFAULT_GROUP);
+        let floating_id = window_id(&wm, pids[0]);
+        wm.floating_windows.insert(floating_id);
+        let g = wm
+            .displays
+            .get_mut(&1)
+            .and_then(|ds| ds.groups.get_mut(&DEFAULT_GROUP))
+            .unwrap();
+        g.set_tile_width_pct(&floating_id, 30., &wm.floating_windows);
+
+        assert_eq!(g.primary_column_pct, 50);
+    }
+
+    #[test]
+    fn resizing_a_tile_skips_floating_windows() {
+        let (mut wm, system) = window_manager();
+        system.open_window(rect(100., 100., 600., 400.));
+        system.open_window(rect(200., 100., 600., 400.));
+        system.open_window(rect(300., 100., 600., 400.));
+        wm.refresh_window_list().unwrap();
+        let pids = group_pids(&wm, 1, DEFAULT_GROUP);
+        wm.floating_windows.insert(window_id(&wm, pids[0]));
+        // The first tiled window, so in the primary column.
+        let primary_id = window_id(&wm, pids[1]);
+        let g = wm
+            .displays
+            .get_mut(&1)
+            .and_then(|ds| ds.groups.get_mut(&DEFAULT_GROUP))
+            .unwrap();
+        g.set_tile_width_pct(&primary_id, 30., &wm.floating_windows);
+
+        assert_eq!(g.primary_column_pct, 30);
+    }
+
+    #[test]
+    fn dragged_floating_windows_stay_where_they_are_dropped() {
+        let (mut wm, system) = window_manager();
+        wm.config.floating.remember_frames = true;
+        let a = system.open_window(rect(100., 100., 600., 400.));
+        wm.refresh_window_list().unwrap();
+        let (display_id, g_id, w_idx) = wm
+            .find_window_by(|w| wm.system.pid(w).ok() == Some(a))
+            .unwrap();
+        wm.set_active_window_location(display_id, g_id, w_idx);
+        wm.set_layout_floating();
+        wm.remember_floating_frames();
+
+        wm.start_drag(&CGPoint::new(150., 150.)).unwrap();
+        wm.drag_window()
+            .unwrap()
+            .set_position_around(&CGPoint::new(250., 250.))
+            .unwrap();
+        wm.end_drag(&CGPoint::new(250., 250.)).unwrap();
+        wm.displays[&1]
+            .relayout(&wm.floating_windows, wm.remembered_floating_frames())
+            .unwrap();
+
+        assert_frame(system.frame_of(a), rect(200., 200., 600., 400.));
+    }
+
+    #[test]
+    fn resizing_a_split_tile_resizes_its_share_of_the_tree() {
+        let (mut wm, system) = window_manager();
+        system.open_window(rect(100., 100., 600., 400.));
+        system.open_window(rect(200., 100., 600., 400.));
+        wm.refresh_window_list().unwrap();
+        let pids = group_pids(&wm, 1, DEFAULT_GROUP);
+        let a_id = window_id(&wm, pids[0]);
+        let floating = wm.floating_windows.clone();
+        let g = wm
+            .displays
+            .get_mut(&1)
+            .and_then(|ds| ds.groups.get_mut(&DEFAULT_GROUP))
+            .unwrap();
+        g.active_window_idx = Some(0);
+        g.split_active_window(&floating, SplitDirection::Horizontal);
+        g.resize_split_tile(&a_id, &rect(0., 38., 750., 762.), 1, &floating);
+        wm.displays[&1].relayout(&floating, None).unwrap();
+
+        assert_frame(system.frame_of(pids[0]), rect(0., 38., 750., 762.));
+        assert_frame(system.frame_of(pids[1]), rect(750., 38., 250., 762.));
+    }
+
+    #[test]
+    fn minimized_windows_are_restored_to_their_group() {
+        let (mut wm, system) = window_manager();
+        let a = system.open_window(rect(100., 100., 600., 400.));
+        let b = system.open_window(rect(200., 100., 600., 400.));
+        wm.refresh_window_list().unwrap();
+        let a_id = window_id(&wm, a);
+        let (display_id, g_id, w_idx) = wm.find_window_by(|w| *w.id() == a_id).unwrap();
+        wm.set_active_window_location(display_id, g_id, w_idx);
+        wm.minimize_active_window().unwrap();
+
+        assert!(system.is_minimized(a));
+        assert_eq!(group_pids(&wm, 1, DEFAULT_GROUP), vec![b]);
+
+        wm.unminimize_window(&a_id).unwrap();
+
+        assert!(!system.is_minimized(a));
+        assert_eq!(sorted(group_pids(&wm, 1, DEFAULT_GROUP)), vec![a, b]);
+    }
+
+    #[test]
+    fn suppressing_the_animation_parks_minimized_windows() {
+        let (mut wm, system) = window_manager();
+        wm.config.minimize.suppress_animation = true;
+        let a = system.open_window(rect(100., 100., 600., 400.));
+        let b = system.open_window(rect(200., 100., 600., 400.));
+        wm.refresh_window_list().unwrap();
+        let a_id = window_id(&wm, a);
+        let a_frame = system.frame_of(a).unwrap();
+        let (display_id, g_id, w_idx) = wm.find_window_by(|w| *w.id() == a_id).unwrap();
+        wm.set_active_window_location(display_id, g_id, w_idx);
+        wm.minimize_active_window().unwrap();
+
+        assert!(!system.is_minimized(a));
+        assert_frame(system.frame_of(a), rect(999., 799., 600., 400.));
+        // Still listed as open, but not taken for a new window.
+        wm.refresh_window_list().unwrap();
+        assert_eq!(group_pids(&wm, 1, DEFAULT_GROUP), vec![b]);
+
+        wm.unminimize_window(&a_id).unwrap();
+
+        assert_frame(system.frame_of(a), a_frame);
+        assert_eq!(sorted(group_pids(&wm, 1, DEFAULT_GROUP)), vec![a, b]);
+    }
+
+    #[test]
+    fn closing_the_active_window_closes_it() {
+        let (mut wm, system) = window_manager();
+        let a = system.open_window(rect(100., 100., 600., 400.));
+        let b = system.open_window(rect(200., 100., 600., 400.));
+        wm.refresh_window_list().unwrap();
+        let (display_id, g_id, w_idx) = wm
+            .find_window_by(|w| wm.system.pid(w).ok() == Some(a))
+            .unwrap();
+        wm.set_active_window_location(display_id, g_id, w_idx);
+        wm.close_active_window().unwrap();
+
+        assert!(system.frame_of(a).is_none());
+        assert_eq!(group_pids(&wm, 1, DEFAULT_GROUP), vec![b]);
+    }
 }
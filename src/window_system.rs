@@ -0,0 +1,864 @@
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    ffi::c_void,
+    rc::Rc,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use accessibility::{AXUIElement, AXUIElementAttributes};
+use accessibility_sys::{kAXWindowRole, AXUIElementSetMessagingTimeout};
+use anyhow::{anyhow, Result};
+use core_foundation::{
+    array::CFArray,
+    base::{FromVoid, ItemRef, TCFType, ToVoid},
+    dictionary::CFDictionary,
+    number::CFNumber,
+    string::CFString,
+};
+use core_graphics::{
+    display::{kCGWindowListExcludeDesktopElements, kCGWindowListOptionOnScreenOnly, CGDisplay},
+    geometry::{CGPoint, CGRect},
+    window::{kCGWindowLayer, kCGWindowOwnerPID, CGWindowID},
+};
+
+use crate::{
+    capture::element_window_id,
+    display_watcher::display_uuid,
+    dry_run::FrameChange,
+    window::{self, ActivationStep, CGErrorWrapper, Window, WindowWrapper},
+};
+
+/// What a `WindowHandle` holds: a window system's own kind of window.
+trait BackendWindow: Any + std::fmt::Debug {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Any + std::fmt::Debug> BackendWindow for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A window, as listed by a `WindowSystem`. The window manager doesn't look
+/// inside: it tells windows apart by `id` and hands them back to the window
+/// system to read or change them. Clones are the same window.
+#[derive(Clone)]
+pub struct WindowHandle {
+    id: uuid::Uuid,
+    window: Rc<dyn BackendWindow>,
+}
+
+impl WindowHandle {
+    pub fn new(window: impl Any + std::fmt::Debug) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4(),
+            window: Rc::new(window),
+        }
+    }
+
+    pub fn id(&self) -> &uuid::Uuid {
+        &self.id
+    }
+
+    /// The window system's own window, if it is a `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        BackendWindow::as_any(&*self.window).downcast_ref()
+    }
+}
+
+impl std::fmt::Debug for WindowHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WindowHandle")
+            .field("id", &self.id)
+            .field("window", &self.window)
+            .finish()
+    }
+}
+
+/// The platform's windows and displays, as the window manager sees them.
+/// `AXWindowSystem` is the macOS implementation, using the Accessibility and
+/// Core Graphics APIs. Another implementation, e.g. the in-memory one the
+/// tests use, can stand in for it where that API isn't available.
+pub trait WindowSystem: std::fmt::Debug {
+    type Window: Clone;
+
+    /// Open windows and minimized windows, apps with recently used windows
+    /// first.
+    fn windows(&self) -> Result<(Vec<Self::Window>, Vec<Self::Window>)>;
+    /// Like `windows`, but without blocking: `finish_windows` picks up the
+    /// result. By default the windows are listed at once.
+    fn start_windows(&self) -> Result<PendingWindows<Self::Window>> {
+        Ok(PendingWindows::ready(self.windows()))
+    }
+    /// The windows listed by `pending`, or `None` if they aren't ready yet.
+    #[allow(clippy::type_complexity)]
+    fn finish_windows(
+        &self,
+        pending: &PendingWindows<Self::Window>,
+    ) -> Option<Result<(Vec<Self::Window>, Vec<Self::Window>)>> {
+        pending.take_ready()
+    }
+    /// Apps left out of `windows` because they aren't responding. Their
+    /// windows haven't necessarily closed.
+    fn unresponsive_pids(&self) -> HashSet<i32>;
+    /// The window with keyboard focus.
+    fn focused_window(&self) -> Result<Self::Window>;
+    fn window_at_point(&self, point: &CGPoint) -> Result<Option<Self::Window>>;
+    /// Whether `a` and `b` are the same window, e.g. as listed by two calls
+    /// to `windows`.
+    fn is_same_window(&self, a: &Self::Window, b: &Self::Window) -> Result<bool>;
+    fn frame(&self, window: &Self::Window) -> Result<CGRect>;
+    fn set_frame(&self, window: &Self::Window, frame: CGRect) -> Result<()>;
+    /// Move the window, keeping its size.
+    fn set_position(&self, window: &Self::Window, position: CGPoint) -> Result<()> {
+        let frame = self.frame(window)?;
+        self.set_frame(window, CGRect::new(&position, &frame.size))
+    }
+    fn activate(&self, window: &Self::Window, steps: &[ActivationStep]) -> Result<()>;
+
+    /// The pid of the window's app.
+    fn pid(&self, window: &Self::Window) -> Result<i32>;
+    fn title(&self, window: &Self::Window) -> Result<String>;
+    /// The bundle identifier of the window's app, if it has one.
+    fn bundle_identifier(&self, window: &Self::Window) -> Result<Option<String>>;
+    /// The localized name of the window's app.
+    fn app_name(&self, window: &Self::Window) -> Result<Option<String>>;
+    /// The window's role, e.g. "AXStandardWindow" or "AXDialog".
+    fn subrole(&self, window: &Self::Window) -> Option<String>;
+    /// Whether the window is modal, i.e. blocks the rest of its app.
+    fn is_modal(&self, window: &Self::Window) -> bool;
+    /// Whether the window is its app's main window.
+    fn is_main(&self, window: &Self::Window) -> Result<bool>;
+    /// Whether the window is its app's main window and the app is frontmost.
+    fn is_frontmost_and_main(&self, window: &Self::Window) -> Result<bool>;
+    /// The window server's id for the window, as used for screen captures
+    /// and Spaces.
+    fn window_number(&self, window: &Self::Window) -> Result<CGWindowID>;
+
+    /// Close the window, as its close button would.
+    fn close(&self, window: &Self::Window) -> Result<()>;
+    /// Zoom the window, as its zoom (green) button would.
+    fn zoom(&self, window: &Self::Window) -> Result<()>;
+    /// Move the window into, or out of, its own full-screen space.
+    fn toggle_full_screen(&self, window: &Self::Window) -> Result<()>;
+    fn set_minimized(&self, window: &Self::Window, minimized: bool) -> Result<()>;
+    /// Ask the window's app to quit, so that it can offer to save its
+    /// documents first.
+    fn quit_app(&self, window: &Self::Window) -> Result<()>;
+    /// Kill the app with `pid`, for when it has hung. Unsaved documents are
+    /// lost.
+    fn force_quit_app(&self, pid: i32) -> Result<()>;
+
+    /// Whether changes to windows are only logged, as in spectator and
+    /// dry-run modes.
+    fn is_read_only(&self) -> bool {
+        false
+    }
+    /// Whether the frames windows are given are read back without moving
+    /// them, as in dry-run mode.
+    fn is_dry_run(&self) -> bool {
+        false
+    }
+    /// The frames windows were given since the last call, in dry-run mode.
+    fn take_frame_changes(&self) -> Vec<FrameChange> {
+        vec![]
+    }
+
+    /// The ids of the connected displays.
+    fn display_ids(&self) -> Result<Vec<u32>>;
+    /// The display with the menu bar.
+    fn main_display_id(&self) -> u32;
+    /// What identifies the display from one connection to the next, if
+    /// anything does.
+    fn display_uuid(&self, display_id: u32) -> Option<String>;
+    fn display_bounds(&self, display_id: u32) -> CGRect;
+    /// The display the window's top-left corner is on.
+    fn display_of(&self, window: &Self::Window) -> Result<u32>;
+    fn display_at_point(&self, point: &CGPoint) -> Result<Option<u32>>;
+}
+
+/// The window system the window manager works with.
+pub type DynWindowSystem = dyn WindowSystem<Window = WindowHandle>;
+
+/// How long to wait for an app to answer an AX call. A hung app would
+/// otherwise block us for several seconds per call.
+const AX_MESSAGING_TIMEOUT: f32 = 0.5;
+/// How long to skip an app that didn't answer before asking it again.
+const UNRESPONSIVE_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+/// How many apps to ask for their windows at once.
+const MAX_QUERY_THREADS: usize = 8;
+
+/// Windows and displays on macOS.
+#[derive(Debug, Default)]
+pub struct AXWindowSystem {
+    /// Apps that didn't answer in time, and when to try them again.
+    unresponsive: RefCell<HashMap<i32, Instant>>,
+}
+
+impl WindowSystem for AXWindowSystem {
+    type Window = WindowHandle;
+
+    fn windows(&self) -> Result<(Vec<Self::Window>, Vec<Self::Window>)> {
+        self.get_all_windows()
+    }
+
+    fn start_windows(&self) -> Result<PendingWindows<Self::Window>> {
+        let pids = self.pids_to_query()?;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // The receiver may have been dropped.
+            let _ = sender.send(query_apps(&pids));
+        });
+        Ok(PendingWindows(Pending::Querying(receiver)))
+    }
+
+    fn finish_windows(
+        &self,
+        pending: &PendingWindows<Self::Window>,
+    ) -> Option<Result<(Vec<Self::Window>, Vec<Self::Window>)>> {
+        let receiver = match &pending.0 {
+            Pending::Querying(receiver) => receiver,
+            Pending::Ready(_) => return pending.take_ready(),
+        };
+        match receiver.try_recv() {
+            Ok(queries) => Some(self.collect_windows(queries)),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                Some(Err(anyhow!("The thread listing windows stopped")))
+            }
+        }
+    }
+
+    fn unresponsive_pids(&self) -> HashSet<i32> {
+        self.unresponsive.borrow().keys().copied().collect()
+    }
+
+    fn focused_window(&self) -> Result<Self::Window> {
+        WindowWrapper::focused().map(WindowHandle::new)
+    }
+
+    fn window_at_point(&self, point: &CGPoint) -> Result<Option<Self::Window>> {
+        Ok(WindowWrapper::at_point(point)?.map(WindowHandle::new))
+    }
+
+    fn is_same_window(&self, a: &Self::Window, b: &Self::Window) -> Result<bool> {
+        ax(a)?.is_same_window(ax(b)?)
+    }
+
+    fn frame(&self, window: &Self::Window) -> Result<CGRect> {
+        ax(window)?.frame()
+    }
+
+    fn set_frame(&self, window: &Self::Window, frame: CGRect) -> Result<()> {
+        ax(window)?.set_frame(frame)
+    }
+
+    fn set_position(&self, window: &Self::Window, position: CGPoint) -> Result<()> {
+        ax(window)?.set_position(position)
+    }
+
+    fn activate(&self, window: &Self::Window, steps: &[ActivationStep]) -> Result<()> {
+        ax(window)?.activate_with(steps)
+    }
+
+    fn pid(&self, window: &Self::Window) -> Result<i32> {
+        Ok(ax(window)?.element().pid()?)
+    }
+
+    fn title(&self, window: &Self::Window) -> Result<String> {
+        Ok(ax(window)?.element().title()?.to_string())
+    }
+
+    fn bundle_identifier(&self, window: &Self::Window) -> Result<Option<String>> {
+        ax(window)?.bundle_identifier()
+    }
+
+    fn app_name(&self, window: &Self::Window) -> Result<Option<String>> {
+        ax(window)?.app_name()
+    }
+
+    fn subrole(&self, window: &Self::Window) -> Option<String> {
+        ax(window).ok()?.subrole()
+    }
+
+    fn is_modal(&self, window: &Self::Window) -> bool {
+        ax(window).map_or(false, |w| w.is_modal())
+    }
+
+    fn is_main(&self, window: &Self::Window) -> Result<bool> {
+        Ok(ax(window)?.element().main()?.into())
+    }
+
+    fn is_frontmost_and_main(&self, window: &Self::Window) -> Result<bool> {
+        ax(window)?.frontmost_and_main()
+    }
+
+    fn window_number(&self, window: &Self::Window) -> Result<CGWindowID> {
+        element_window_id(ax(window)?.element())
+    }
+
+    fn close(&self, window: &Self::Window) -> Result<()> {
+        ax(window)?.close()
+    }
+
+    fn zoom(&self, window: &Self::Window) -> Result<()> {
+        ax(window)?.zoom()
+    }
+
+    fn toggle_full_screen(&self, window: &Self::Window) -> Result<()> {
+        ax(window)?.toggle_full_screen()
+    }
+
+    fn set_minimized(&self, window: &Self::Window, minimized: bool) -> Result<()> {
+        ax(window)?.set_minimized(minimized)
+    }
+
+    fn quit_app(&self, window: &Self::Window) -> Result<()> {
+        ax(window)?.quit_app()
+    }
+
+    fn force_quit_app(&self, pid: i32) -> Result<()> {
+        window::force_quit_app(pid)
+    }
+
+    fn display_ids(&self) -> Result<Vec<u32>> {
+        CGDisplay::active_displays()
+            .map_err(|e| anyhow!(format!("CGDisplay::active_displays {:?}", e)))
+    }
+
+    fn main_display_id(&self) -> u32 {
+        CGDisplay::main().id
+    }
+
+    fn display_uuid(&self, display_id: u32) -> Option<String> {
+        display_uuid(display_id)
+    }
+
+    fn display_bounds(&self, display_id: u32) -> CGRect {
+        CGDisplay::new(display_id).bounds()
+    }
+
+    fn display_of(&self, window: &Self::Window) -> Result<u32> {
+        Ok(ax(window)?.display()?.id)
+    }
+
+    fn display_at_point(&self, point: &CGPoint) -> Result<Option<u32>> {
+        let (displays, _) = CGDisplay::displays_with_point(*point, 1).map_err(CGErrorWrapper)?;
+        Ok(displays.first().copied())
+    }
+}
+
+/// The Accessibility window behind a handle `AXWindowSystem` listed.
+fn ax(window: &WindowHandle) -> Result<&WindowWrapper<AXUIElement>> {
+    window
+        .downcast_ref()
+        .ok_or_else(|| anyhow!("Not an Accessibility window: {:?}", window))
+}
+
+fn ax_handle(element: AXUIElement) -> WindowHandle {
+    WindowHandle::new(WindowWrapper::new(element))
+}
+
+fn get_window_pids(on_screen_only: bool) -> Result<Vec<i64>> {
+    let opts = kCGWindowListExcludeDesktopElements;
+    let opts = if on_screen_only {
+        opts | kCGWindowListOptionOnScreenOnly
+    } else {
+        opts
+    };
+    let window_list: CFArray<*const c_void> =
+        CGDisplay::window_list_info(opts, None).ok_or(anyhow!("no window_list_info"))?;
+
+    let iter = window_list
+        .iter()
+        .map(|w| unsafe { CFDictionary::from_void(*w) })
+        .filter(|d: &ItemRef<CFDictionary>| {
+            // Keep only windows at layer 0
+            let l: CFString = unsafe { CFString::wrap_under_create_rule(kCGWindowLayer) };
+            let layer_void: ItemRef<'_, *const c_void> = d.get(l.to_void());
+            let layer = unsafe { CFNumber::from_void(*layer_void) };
+            layer.to_i32() == Some(0)
+        })
+        .filter_map(|d| {
+            // eprintln!("{:?}", d);
+            let k: CFString = unsafe { CFString::wrap_under_create_rule(kCGWindowOwnerPID) };
+            let pid = d.get(k.to_void());
+            let pid = unsafe { CFNumber::from_void(*pid) };
+            pid.to_i64()
+        })
+        .collect::<Vec<i64>>();
+    Ok(iter)
+}
+
+/// The windows of one app, queried on a worker thread.
+struct AppQuery {
+    pid: i32,
+    /// Open and minimized windows.
+    result: Result<(Vec<AXUIElement>, Vec<AXUIElement>), accessibility::Error>,
+    elapsed: Duration,
+}
+
+// AX elements are CF objects, which may be used from any thread.
+unsafe impl Send for AppQuery {}
+
+/// Open and minimized windows of the app with `pid`. Fails with
+/// `kAXErrorCannotComplete` if the app doesn't answer in time.
+fn app_windows(pid: i32) -> Result<(Vec<AXUIElement>, Vec<AXUIElement>), accessibility::Error> {
+    let app = AXUIElement::application(pid);
+    set_messaging_timeout(&app);
+    let mut open_windows = vec![];
+    let mut minimized_windows = vec![];
+    for w in app.windows()?.iter() {
+        if w.role()? == kAXWindowRole {
+            set_messaging_timeout(&w);
+            // WindowWrapper::new(w.clone()).debug_attributes()?;
+            let minimized: bool = w.minimized()?.into();
+            if minimized {
+                minimized_windows.push(w.clone());
+            } else {
+                open_windows.push(w.clone());
+            }
+        }
+    }
+    Ok((open_windows, minimized_windows))
+}
+
+/// Query each app's windows, several apps at a time, so that slow apps don't
+/// hold up the rest. Results are in the order of `pids`.
+fn query_apps(pids: &[i32]) -> Vec<AppQuery> {
+    if pids.is_empty() {
+        return vec![];
+    }
+    let chunk_size = pids.len().div_ceil(MAX_QUERY_THREADS);
+    thread::scope(|scope| {
+        let workers: Vec<_> = pids
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|&pid| {
+                            let started = Instant::now();
+                            let result = app_windows(pid);
+                            AppQuery {
+                                pid,
+                                result,
+                                elapsed: started.elapsed(),
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        // A worker that panicked would otherwise leave its apps out, as if
+        // their windows had closed: fail the whole query instead, so the
+        // refresh fails and the windows stay where they are.
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    })
+}
+
+/// Windows being listed, from `WindowSystem::start_windows`.
+#[derive(Debug)]
+pub struct PendingWindows<W>(Pending<W>);
+
+#[derive(Debug)]
+enum Pending<W> {
+    /// Listed already, until `finish_windows` takes them.
+    Ready(RefCell<Option<Result<(Vec<W>, Vec<W>)>>>),
+    /// Being listed by the Accessibility API on a background thread.
+    Querying(mpsc::Receiver<Vec<AppQuery>>),
+}
+
+impl<W> PendingWindows<W> {
+    pub fn ready(windows: Result<(Vec<W>, Vec<W>)>) -> Self {
+        Self(Pending::Ready(RefCell::new(Some(windows))))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn take_ready(&self) -> Option<Result<(Vec<W>, Vec<W>)>> {
+        match &self.0 {
+            Pending::Ready(windows) => windows.borrow_mut().take(),
+            Pending::Querying(_) => None,
+        }
+    }
+}
+
+impl AXWindowSystem {
+    /// The apps to ask for windows: apps with recently used windows first,
+    /// leaving out hung apps that aren't due to be tried again.
+    fn pids_to_query(&self) -> Result<Vec<i32>> {
+        let mut window_pids_deduped = vec![];
+        // First use onScreenOnly to get apps with recent windows first
+        for &pid in get_window_pids(true)?.iter() {
+            if !window_pids_deduped.contains(&pid) {
+                window_pids_deduped.push(pid);
+            }
+        }
+        // Then get everything else to get apps with minimized
+        for &pid in get_window_pids(false)?.iter() {
+            if !window_pids_deduped.contains(&pid) {
+                window_pids_deduped.push(pid);
+            }
+        }
+        let pids: Vec<i32> = window_pids_deduped
+            .into_iter()
+            .map(|pid| pid as i32)
+            .collect();
+
+        let now = Instant::now();
+        let mut unresponsive = self.unresponsive.borrow_mut();
+        unresponsive.retain(|pid, _| pids.contains(pid));
+        Ok(pids
+            .into_iter()
+            .filter(|pid| !matches!(unresponsive.get(pid), Some(retry_at) if *retry_at > now))
+            .collect())
+    }
+
+    /// Gather the windows found by `queries`, noting which apps didn't
+    /// answer in time.
+    fn collect_windows(
+        &self,
+        queries: Vec<AppQuery>,
+    ) -> Result<(Vec<WindowHandle>, Vec<WindowHandle>)> {
+        // Windows may have been moved without us being told.
+        window::forget_all_frames();
+        let mut unresponsive = self.unresponsive.borrow_mut();
+        let mut open_windows = vec![];
+        let mut minimized_windows = vec![];
+        for AppQuery {
+            pid,
+            result,
+            elapsed,
+        } in queries
+        {
+            match result {
+                Ok((open, minimized)) => {
+                    if unresponsive.remove(&pid).is_some() {
+                        println!("App {} is responding again", pid);
+                    }
+                    open_windows.extend(open.into_iter().map(ax_handle));
+                    minimized_windows.extend(minimized.into_iter().map(ax_handle));
+                }
+                Err(accessibility::Error::Ax(accessibility_sys::kAXErrorCannotComplete))
+                    if elapsed.as_secs_f32() >= AX_MESSAGING_TIMEOUT =>
+                {
+                    eprintln!(
+                        "App {} is not responding, skipping it for {:?}",
+                        pid, UNRESPONSIVE_RETRY_INTERVAL
+                    );
+                    unresponsive.insert(pid, Instant::now() + UNRESPONSIVE_RETRY_INTERVAL);
+                }
+                Err(accessibility::Error::Ax(accessibility_sys::kAXErrorCannotComplete)) => {
+                    // e.g. kCGWindowOwnerName="Window Server" kCGWindowName=StatusIndicator
+                    ()
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        // eprintln!("open windows: {:?}", open_windows);
+        // eprintln!("minimized windows: {:?}", minimized_windows);
+
+        Ok((open_windows, minimized_windows))
+    }
+
+    fn get_all_windows(&self) -> Result<(Vec<WindowHandle>, Vec<WindowHandle>)> {
+        let pids = self.pids_to_query()?;
+        self.collect_windows(query_apps(&pids))
+    }
+}
+
+/// Make AX calls to `element` give up after `AX_MESSAGING_TIMEOUT` rather
+/// than the system's default of several seconds.
+fn set_messaging_timeout(element: &AXUIElement) {
+    unsafe {
+        AXUIElementSetMessagingTimeout(element.as_concrete_TypeRef(), AX_MESSAGING_TIMEOUT);
+    }
+}
+
+/// Windows and displays kept in memory, for tests. Each window is made up
+/// as an app of its own, so windows are told apart by pid. Clones share
+/// the same windows, so a test can keep one to open and close windows
+/// while the window manager owns another.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct InMemoryWindowSystem(Rc<RefCell<InMemoryState>>);
+
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct InMemoryState {
+    /// Each display's id and bounds, the main display first.
+    displays: Vec<(u32, CGRect)>,
+    /// Each open window's pid and frame, most recently used first.
+    windows: Vec<(i32, CGRect)>,
+    /// Minimized windows' pids and the frames they had.
+    minimized: Vec<(i32, CGRect)>,
+    focused: Option<i32>,
+    next_pid: i32,
+}
+
+/// A window of `InMemoryWindowSystem`.
+#[cfg(test)]
+#[derive(Debug)]
+struct InMemoryWindow {
+    pid: i32,
+}
+
+#[cfg(test)]
+impl InMemoryWindowSystem {
+    /// Above any real pid, so no real app is ever asked about the windows.
+    const FIRST_PID: i32 = 1_000_000;
+
+    pub fn new(displays: &[(u32, CGRect)]) -> Self {
+        Self(Rc::new(RefCell::new(InMemoryState {
+            displays: displays.to_vec(),
+            next_pid: Self::FIRST_PID,
+            ..InMemoryState::default()
+        })))
+    }
+
+    /// Open a window at `frame` and focus it. Returns its pid.
+    pub fn open_window(&self, frame: CGRect) -> i32 {
+        let mut state = self.0.borrow_mut();
+        let pid = state.next_pid;
+        state.next_pid += 1;
+        state.windows.insert(0, (pid, frame));
+        state.focused = Some(pid);
+        pid
+    }
+
+    pub fn close_window(&self, pid: i32) {
+        let mut state = self.0.borrow_mut();
+        state.windows.retain(|(p, _)| *p != pid);
+        state.minimized.retain(|(p, _)| *p != pid);
+        if state.focused == Some(pid) {
+            state.focused = None;
+        }
+    }
+
+    pub fn frame_of(&self, pid: i32) -> Option<CGRect> {
+        let state = self.0.borrow();
+        state
+            .windows
+            .iter()
+            .find(|(p, _)| *p == pid)
+            .map(|(_, f)| *f)
+    }
+
+    pub fn is_minimized(&self, pid: i32) -> bool {
+        self.0.borrow().minimized.iter().any(|(p, _)| *p == pid)
+    }
+
+    fn window(pid: i32) -> WindowHandle {
+        WindowHandle::new(InMemoryWindow { pid })
+    }
+
+    fn pid_of(window: &WindowHandle) -> Result<i32> {
+        window
+            .downcast_ref::<InMemoryWindow>()
+            .map(|w| w.pid)
+            .ok_or_else(|| anyhow!("Not an in-memory window: {:?}", window))
+    }
+}
+
+#[cfg(test)]
+impl WindowSystem for InMemoryWindowSystem {
+    type Window = WindowHandle;
+
+    fn windows(&self) -> Result<(Vec<Self::Window>, Vec<Self::Window>)> {
+        let state = self.0.borrow();
+        Ok((
+            state
+                .windows
+                .iter()
+                .map(|(pid, _)| Self::window(*pid))
+                .collect(),
+            state
+                .minimized
+                .iter()
+                .map(|(pid, _)| Self::window(*pid))
+                .collect(),
+        ))
+    }
+
+    fn unresponsive_pids(&self) -> HashSet<i32> {
+        HashSet::new()
+    }
+
+    fn focused_window(&self) -> Result<Self::Window> {
+        let focused = self.0.borrow().focused;
+        focused
+            .map(Self::window)
+            .ok_or_else(|| anyhow!("No focused window"))
+    }
+
+    fn window_at_point(&self, point: &CGPoint) -> Result<Option<Self::Window>> {
+        let state = self.0.borrow();
+        Ok(state
+            .windows
+            .iter()
+            .find(|(_, frame)| frame.contains(point))
+            .map(|(pid, _)| Self::window(*pid)))
+    }
+
+    fn is_same_window(&self, a: &Self::Window, b: &Self::Window) -> Result<bool> {
+        Ok(Self::pid_of(a)? == Self::pid_of(b)?)
+    }
+
+    fn frame(&self, window: &Self::Window) -> Result<CGRect> {
+        let pid = Self::pid_of(window)?;
+        self.frame_of(pid)
+            .ok_or_else(|| anyhow!("No window with pid {}", pid))
+    }
+
+    fn set_frame(&self, window: &Self::Window, frame: CGRect) -> Result<()> {
+        let pid = Self::pid_of(window)?;
+        let mut state = self.0.borrow_mut();
+        match state.windows.iter_mut().find(|(p, _)| *p == pid) {
+            Some((_, f)) => {
+                *f = frame;
+                Ok(())
+            }
+            None => Err(anyhow!("No window with pid {}", pid)),
+        }
+    }
+
+    fn activate(&self, window: &Self::Window, _steps: &[ActivationStep]) -> Result<()> {
+        let pid = Self::pid_of(window)?;
+        let mut state = self.0.borrow_mut();
+        let idx = state
+            .windows
+            .iter()
+            .position(|(p, _)| *p == pid)
+            .ok_or_else(|| anyhow!("No window with pid {}", pid))?;
+        let w = state.windows.remove(idx);
+        state.windows.insert(0, w);
+        state.focused = Some(pid);
+        Ok(())
+    }
+
+    fn pid(&self, window: &Self::Window) -> Result<i32> {
+        Self::pid_of(window)
+    }
+
+    fn title(&self, window: &Self::Window) -> Result<String> {
+        Ok(format!("Window {}", Self::pid_of(window)?))
+    }
+
+    fn bundle_identifier(&self, _window: &Self::Window) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn app_name(&self, _window: &Self::Window) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn subrole(&self, _window: &Self::Window) -> Option<String> {
+        Some("AXStandardWindow".to_string())
+    }
+
+    fn is_modal(&self, _window: &Self::Window) -> bool {
+        false
+    }
+
+    fn is_main(&self, _window: &Self::Window) -> Result<bool> {
+        // Each window is the only one of its app.
+        Ok(true)
+    }
+
+    fn is_frontmost_and_main(&self, window: &Self::Window) -> Result<bool> {
+        Ok(self.0.borrow().focused == Some(Self::pid_of(window)?))
+    }
+
+    fn window_number(&self, window: &Self::Window) -> Result<CGWindowID> {
+        Ok(Self::pid_of(window)? as CGWindowID)
+    }
+
+    fn close(&self, window: &Self::Window) -> Result<()> {
+        self.close_window(Self::pid_of(window)?);
+        Ok(())
+    }
+
+    fn zoom(&self, _window: &Self::Window) -> Result<()> {
+        Ok(())
+    }
+
+    fn toggle_full_screen(&self, _window: &Self::Window) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_minimized(&self, window: &Self::Window, minimized: bool) -> Result<()> {
+        let pid = Self::pid_of(window)?;
+        let mut state = self.0.borrow_mut();
+        let state = &mut *state;
+        let (from, to) = if minimized {
+            (&mut state.windows, &mut state.minimized)
+        } else {
+            (&mut state.minimized, &mut state.windows)
+        };
+        if let Some(idx) = from.iter().position(|(p, _)| *p == pid) {
+            let w = from.remove(idx);
+            to.insert(0, w);
+        }
+        if minimized && state.focused == Some(pid) {
+            state.focused = None;
+        }
+        Ok(())
+    }
+
+    fn quit_app(&self, window: &Self::Window) -> Result<()> {
+        self.close(window)
+    }
+
+    fn force_quit_app(&self, pid: i32) -> Result<()> {
+        self.close_window(pid);
+        Ok(())
+    }
+
+    fn display_ids(&self) -> Result<Vec<u32>> {
+        Ok(self.0.borrow().displays.iter().map(|(id, _)| *id).collect())
+    }
+
+    fn main_display_id(&self) -> u32 {
+        self.0.borrow().displays.first().map_or(0, |(id, _)| *id)
+    }
+
+    fn display_uuid(&self, display_id: u32) -> Option<String> {
+        Some(format!("display-{}", display_id))
+    }
+
+    fn display_bounds(&self, display_id: u32) -> CGRect {
+        let state = self.0.borrow();
+        state
+            .displays
+            .iter()
+            .find(|(id, _)| *id == display_id)
+            .map_or(CGRect::default(), |(_, bounds)| *bounds)
+    }
+
+    fn display_of(&self, window: &Self::Window) -> Result<u32> {
+        let origin = self.frame(window)?.origin;
+        self.display_at_point(&origin)?
+            .ok_or_else(|| anyhow!("Window is off-screen"))
+    }
+
+    fn display_at_point(&self, point: &CGPoint) -> Result<Option<u32>> {
+        let state = self.0.borrow();
+        Ok(state
+            .displays
+            .iter()
+            .find(|(_, bounds)| bounds.contains(point))
+            .map(|(id, _)| *id))
+    }
+}